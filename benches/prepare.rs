@@ -0,0 +1,32 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use clickhouse::Client;
+
+// Doesn't send queries, so only building SQL is measured.
+fn prepare(c: &mut Criterion) {
+    const TEMPLATE: &str = "SELECT no, name FROM some WHERE no BETWEEN ? AND ? \
+                            AND name != ? AND kind IN ? ORDER BY no LIMIT ?";
+
+    let client = Client::default();
+    let prepared = client.prepare(TEMPLATE);
+
+    let mut group = c.benchmark_group("prepare");
+    group.bench_function("ad-hoc", |b| {
+        b.iter(|| {
+            let query = client.query(black_box(TEMPLATE));
+            let query = query.bind(1).bind(100).bind("foo").bind([1, 2]).bind(10);
+            black_box(query.sql_display().unwrap())
+        })
+    });
+    group.bench_function("prepared", |b| {
+        b.iter(|| {
+            let query = black_box(&prepared).query();
+            let query = query.bind(1).bind(100).bind("foo").bind([1, 2]).bind(10);
+            black_box(query.sql_display().unwrap())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, prepare);
+criterion_main!(benches);
@@ -2,7 +2,7 @@ use proc_macro2::TokenStream;
 use quote::quote;
 use serde_derive_internals::{attr::get_serde_meta_items, Ctxt};
 use syn::{
-    parse_macro_input, Attribute, Data, DataStruct, DeriveInput, Fields, Ident, Lit, Meta,
+    parse_macro_input, Data, DataEnum, DataStruct, DeriveInput, Fields, Ident, Lit, Meta,
     NestedMeta,
 };
 
@@ -49,34 +49,63 @@ fn serde_rename(cx: &Ctxt, field: &syn::Field) -> Option<String> {
     None
 }
 
+/// Parses `#[clickhouse(rename = "..")]`, which takes precedence over `serde(rename)`.
+fn clickhouse_rename(field: &syn::Field) -> syn::Result<Option<String>> {
+    for attr in field.attrs.iter().filter(|a| a.path.is_ident("clickhouse")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(syn::Error::new_spanned(meta, "expected `clickhouse(..)`")),
+        };
+
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("rename") => {
+                    match nv.lit {
+                        Lit::Str(lit) => return Ok(Some(lit.value())),
+                        lit => return Err(syn::Error::new_spanned(lit, "expected a string")),
+                    }
+                }
+                other => return Err(syn::Error::new_spanned(other, "unknown attribute")),
+            }
+        }
+    }
+
+    Ok(None)
+}
+
 fn unraw(ident: &Ident) -> String {
     ident.to_string().trim_start_matches("r#").to_owned()
 }
 
-fn column_names(data: &DataStruct) -> TokenStream {
+fn column_names(data: &DataStruct) -> syn::Result<TokenStream> {
     match &data.fields {
         Fields::Named(fields) => {
             let cx = Ctxt::new();
-            let column_names_iter = fields
+            let mut column_names = Vec::new();
+            for f in fields
                 .named
                 .iter()
                 .filter(|f| !serde_skipped(&cx, &f.attrs))
-                .map(|f| match serde_rename(&cx, f) {
+            {
+                let name = match clickhouse_rename(f)? {
                     Some(name) => name,
-                    None => unraw(f.ident.as_ref().unwrap()),
-                });
+                    None => match serde_rename(&cx, f) {
+                        Some(name) => name,
+                        None => unraw(f.ident.as_ref().unwrap()),
+                    },
+                };
+                column_names.push(name);
+            }
 
             let tokens = quote! {
-                &[#( #column_names_iter,)*]
+                &[#( #column_names,)*]
             };
 
             // TODO: do something more clever?
             let _ = cx.check();
-            tokens
-        }
-        Fields::Unnamed(_) => {
-            quote! { &[] }
+            Ok(tokens)
         }
+        Fields::Unnamed(_) => Ok(quote! { &[] }),
         Fields::Unit => panic!("`Row` cannot be derived for unit structs"),
     }
 }
@@ -115,7 +144,10 @@ pub fn row(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let name = input.ident;
 
     let column_names = match &input.data {
-        Data::Struct(data) => column_names(data),
+        Data::Struct(data) => match column_names(data) {
+            Ok(column_names) => column_names,
+            Err(err) => return err.to_compile_error().into(),
+        },
         Data::Enum(_) | Data::Union(_) => panic!("`Row` can be derived only for structs"),
     };
 
@@ -129,3 +161,90 @@ pub fn row(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 
     proc_macro::TokenStream::from(expanded)
 }
+
+/// Derives `Serialize` and `Deserialize` for a fieldless enum mapped to `Enum8`.
+#[proc_macro_derive(Enum8)]
+pub fn enum8(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    proc_macro::TokenStream::from(enum_repr(input, "Enum8", quote!(i8)))
+}
+
+/// Derives `Serialize` and `Deserialize` for a fieldless enum mapped to `Enum16`.
+#[proc_macro_derive(Enum16)]
+pub fn enum16(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    proc_macro::TokenStream::from(enum_repr(input, "Enum16", quote!(i16)))
+}
+
+fn variants(data: &DataEnum, ch_type: &str) -> Vec<Ident> {
+    data.variants
+        .iter()
+        .map(|variant| match variant.fields {
+            Fields::Unit => variant.ident.clone(),
+            _ => panic!("`{}` can be derived only for fieldless enums", ch_type),
+        })
+        .collect()
+}
+
+// Values are mapped by discriminants, which must fit into `repr`.
+fn enum_repr(input: DeriveInput, ch_type: &str, repr: TokenStream) -> TokenStream {
+    let name = input.ident;
+
+    let variants = match &input.data {
+        Data::Enum(data) => variants(data, ch_type),
+        Data::Struct(_) | Data::Union(_) => panic!("`{}` can be derived only for enums", ch_type),
+    };
+
+    if !input.generics.params.is_empty() {
+        panic!("`{}` cannot be derived for generic enums", ch_type);
+    }
+
+    let name_str = name.to_string();
+    let overflow_msgs = variants
+        .iter()
+        .map(|v| format!("the discriminant of `{name}::{v}` doesn't fit into {ch_type}"));
+
+    quote! {
+        const _: () = {
+            #(
+                assert!(
+                    #name::#variants as i64 >= #repr::MIN as i64
+                        && #name::#variants as i64 <= #repr::MAX as i64,
+                    #overflow_msgs,
+                );
+            )*
+        };
+
+        impl ::clickhouse::serde::Serialize for #name {
+            fn serialize<S>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error>
+            where
+                S: ::clickhouse::serde::Serializer,
+            {
+                let value: #repr = match self {
+                    #( #name::#variants => #name::#variants as #repr, )*
+                };
+                ::clickhouse::serde::Serialize::serialize(&value, serializer)
+            }
+        }
+
+        impl<'de> ::clickhouse::serde::Deserialize<'de> for #name {
+            fn deserialize<D>(deserializer: D) -> ::core::result::Result<Self, D::Error>
+            where
+                D: ::clickhouse::serde::Deserializer<'de>,
+            {
+                let value: #repr = ::clickhouse::serde::Deserialize::deserialize(deserializer)?;
+                #(
+                    if value == #name::#variants as #repr {
+                        return ::core::result::Result::Ok(#name::#variants);
+                    }
+                )*
+                ::core::result::Result::Err(::clickhouse::serde::__private::unknown_discriminant(
+                    #name_str,
+                    #ch_type,
+                    value.into(),
+                    &[#( #name::#variants as i64 ),*],
+                ))
+            }
+        }
+    }
+}
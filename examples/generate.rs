@@ -0,0 +1,43 @@
+use std::time::Instant;
+
+use serde::Deserialize;
+
+use clickhouse::{error::Result, Client, Row};
+
+#[derive(Debug, Row, Deserialize)]
+struct SyntheticRow {
+    id: u64,
+    user_id: u32,
+    score: f64,
+    name: String,
+    tags: Vec<String>,
+    parent: Option<u64>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let client = Client::default().with_url("http://localhost:8123");
+
+    let start = Instant::now();
+    let mut cursor = client.generate::<SyntheticRow>(1_000_000)?;
+
+    let mut count = 0u64;
+    let mut checksum = 0u64;
+    while let Some(row) = cursor.next().await? {
+        count += 1;
+        checksum = checksum
+            .wrapping_add(row.id)
+            .wrapping_add(u64::from(row.user_id))
+            .wrapping_add(row.score as u64)
+            .wrapping_add(row.name.len() as u64)
+            .wrapping_add(row.tags.len() as u64)
+            .wrapping_add(row.parent.unwrap_or_default());
+    }
+
+    println!(
+        "consumed {count} rows in {:?}, checksum: {checksum}",
+        start.elapsed()
+    );
+
+    Ok(())
+}
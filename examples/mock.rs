@@ -19,6 +19,14 @@ async fn make_select(client: &Client) -> Result<Vec<SomeRow>> {
         .await
 }
 
+async fn make_select_since(client: &Client, no: u32) -> Result<Vec<SomeRow>> {
+    client
+        .query("SELECT ?fields FROM test WHERE no > ?")
+        .bind(no)
+        .fetch_all::<SomeRow>()
+        .await
+}
+
 async fn make_insert(client: &Client, data: &[SomeRow]) -> Result<()> {
     let mut insert = client.insert("who cares")?;
     for row in data {
@@ -60,10 +68,25 @@ async fn main() {
     let rows = make_select(&client).await.unwrap();
     assert_eq!(rows, list);
 
+    // How to check sent requests.
+    mock.add(test::handlers::provide(stream::iter(list.clone())));
+    let rows = make_select_since(&client, 42).await.unwrap();
+    assert_eq!(rows, list);
+    // `requests()` returns all requests since the previous call, the last one is ours.
+    let request = mock.requests().pop().unwrap();
+    assert_eq!(request.method, "GET");
+    assert_eq!(
+        request.sql(),
+        "SELECT `no` FROM test WHERE no > 42 FORMAT RowBinary"
+    );
+
     // How to test failures.
     mock.add(test::handlers::failure(test::status::FORBIDDEN));
     let reason = make_select(&client).await;
-    assert_eq!(format!("{reason:?}"), r#"Err(BadResponse("Forbidden"))"#);
+    assert_eq!(
+        format!("{reason:?}"),
+        r#"Err(AuthenticationFailed("403 Forbidden"))"#
+    );
 
     // How to test INSERT.
     let recording = mock.add(test::handlers::record());
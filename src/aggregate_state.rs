@@ -0,0 +1,123 @@
+use std::fmt;
+
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    ser::SerializeTuple,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/// An opaque state of an `AggregateFunction(..)` column, e.g. produced by `uniqState()`,
+/// to move states between tables without decoding them.
+///
+/// States are written to RowBinary as is, without any length, so the end of a state
+/// can be found only by the aggregate function itself. Therefore, it's asymmetric:
+/// * states are inserted into `AggregateFunction(..)` columns directly;
+/// * states must be selected as `String`, e.g. `SELECT toString(state) ..`,
+///   which contains the same bytes with the length prefix.
+///
+/// For example:
+///
+/// ```
+/// # use clickhouse::{aggregate_state::AggregateState, Row};
+/// # use serde::{Deserialize, Serialize};
+/// // CREATE TABLE test (key String, users AggregateFunction(uniq, String)) ...
+/// // SELECT key, toString(users) AS users FROM test
+/// #[derive(Row, Serialize, Deserialize)]
+/// struct MyRow {
+///     key: String,
+///     users: AggregateState,
+/// }
+/// ```
+///
+/// `SimpleAggregateFunction(f, T)` columns are stored as `T`, so it isn't needed for them.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct AggregateState(pub Vec<u8>);
+
+impl AggregateState {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for AggregateState {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<AggregateState> for Vec<u8> {
+    fn from(state: AggregateState) -> Self {
+        state.0
+    }
+}
+
+impl Serialize for AggregateState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.serialize_bytes(&self.0);
+        }
+
+        // Tuples are encoded without the length in RowBinary.
+        let mut tuple = serializer.serialize_tuple(self.0.len())?;
+        for byte in &self.0 {
+            tuple.serialize_element(byte)?;
+        }
+        tuple.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for AggregateState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StateVisitor;
+
+        impl<'de> Visitor<'de> for StateVisitor {
+            type Value = AggregateState;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a state of AggregateFunction selected as String")
+            }
+
+            fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Self::Value, E> {
+                Ok(AggregateState(value.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(AggregateState(value))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element::<u8>()? {
+                    bytes.push(byte);
+                }
+                Ok(AggregateState(bytes))
+            }
+        }
+
+        deserializer.deserialize_byte_buf(StateVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rowbinary;
+
+    #[test]
+    fn it_writes_states_as_is() {
+        let state = AggregateState(vec![1, 2, 3]);
+        let mut buffer = Vec::new();
+        rowbinary::serialize_into(&mut buffer, &(&state, 42u8)).unwrap();
+        assert_eq!(buffer, b"\x01\x02\x03\x2a");
+    }
+
+    #[test]
+    fn it_reads_states_selected_as_strings() {
+        let mut input = &b"\x03\x01\x02\x03\x2a"[..];
+        let (state, rest): (AggregateState, u8) =
+            rowbinary::deserialize_from(&mut input, &mut []).unwrap();
+        assert_eq!(state.as_bytes(), [1, 2, 3]);
+        assert_eq!(rest, 42);
+        assert!(input.is_empty());
+    }
+}
@@ -32,6 +32,12 @@ impl<T: Buf> BufList<T> {
         self.next_buf.is_some() as usize + self.bufs.len()
     }
 
+    /// The number of bytes advanced since the last commit or rollback.
+    #[inline]
+    pub(crate) fn uncommitted(&self) -> usize {
+        self.cursor
+    }
+
     #[inline]
     pub(crate) fn commit(&mut self) {
         while self.cursor > 0 {
@@ -116,6 +122,23 @@ fn it_advances() {
     assert_eq!(list.bufs_cnt(), 0);
 }
 
+#[test]
+fn it_counts_uncommitted() {
+    let mut list = BufList::<&[_]>::default();
+    list.push(&[1, 2, 3]);
+    list.push(&[4, 5, 6]);
+
+    list.advance(2);
+    assert_eq!(list.uncommitted(), 2);
+    list.rollback();
+    assert_eq!(list.uncommitted(), 0);
+
+    list.advance(4);
+    assert_eq!(list.uncommitted(), 4);
+    list.commit();
+    assert_eq!(list.uncommitted(), 0);
+}
+
 #[test]
 fn it_copies_to_slice() {
     let mut list = BufList::<&[_]>::default();
@@ -21,6 +21,7 @@ pub(crate) struct Lz4Decoder<S> {
     chunks: BufList<Bytes>,
     meta: Option<Lz4Meta>,
     buffer: Vec<u8>,
+    verify_checksums: bool,
 }
 
 impl<S> Stream for Lz4Decoder<S>
@@ -30,6 +31,8 @@ where
     type Item = Result<Bytes>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Blocks are split into chunks arbitrarily, so wait for the whole block.
+        // The meta is kept until its data is received, even across `Pending`.
         let meta = loop {
             let size = self.chunks.remaining();
             let required_size = self.meta.as_ref().map_or(LZ4_META_SIZE, |m| {
@@ -116,12 +119,13 @@ impl Lz4Meta {
 }
 
 impl<S> Lz4Decoder<S> {
-    pub(crate) fn new(stream: S) -> Self {
+    pub(crate) fn new(stream: S, verify_checksums: bool) -> Self {
         Self {
             stream,
             chunks: BufList::default(),
             meta: None,
             buffer: Vec::new(),
+            verify_checksums,
         }
     }
 
@@ -140,9 +144,14 @@ impl<S> Lz4Decoder<S> {
         self.chunks
             .copy_to_slice(&mut self.buffer[LZ4_HEADER_SIZE..]);
 
-        let actual_checksum = calc_checksum(&self.buffer);
-        if actual_checksum != meta.checksum {
-            return Err(Error::Decompression("checksum mismatch".into()));
+        if self.verify_checksums {
+            let actual = calc_checksum(&self.buffer);
+            if actual != meta.checksum {
+                return Err(Error::ChecksumMismatch {
+                    expected: meta.checksum,
+                    actual,
+                });
+            }
         }
 
         let mut uncompressed = vec![0u8; meta.uncompressed_size as usize];
@@ -238,7 +247,7 @@ async fn it_decompresses() {
                 .map(Ok::<_, Error>)
                 .collect::<Vec<_>>(),
         );
-        let mut decoder = Lz4Decoder::new(stream);
+        let mut decoder = Lz4Decoder::new(stream, true);
         let actual = decoder.try_next().await.unwrap();
         assert_eq!(actual.as_deref(), Some(expected));
     }
@@ -259,6 +268,27 @@ async fn it_decompresses() {
     }
 }
 
+#[tokio::test]
+async fn it_decompresses_frames_split_into_bytes() {
+    use futures::stream::{self, StreamExt, TryStreamExt};
+
+    let frames = [&b"first frame"[..], &[0; 1000], b"", b"the last"];
+    let source = frames
+        .iter()
+        .flat_map(|frame| compress(frame, Compression::Lz4).unwrap())
+        .collect::<Vec<_>>();
+
+    // Every byte is a separate chunk, which is pending at first.
+    let stream = stream::iter(source).then(|byte| async move {
+        tokio::task::yield_now().await;
+        Ok::<_, Error>(Bytes::copy_from_slice(&[byte]))
+    });
+    let decoder = Lz4Decoder::new(Box::pin(stream), true);
+
+    let actual = decoder.try_collect::<Vec<_>>().await.unwrap();
+    assert_eq!(actual, frames);
+}
+
 #[test]
 fn it_compresses() {
     let source = vec![
@@ -275,3 +305,34 @@ fn it_compresses() {
     let actual = compress(&source, Compression::Lz4).unwrap();
     assert_eq!(actual, expected);
 }
+
+#[tokio::test]
+async fn it_verifies_checksums() {
+    use futures::stream::{self, TryStreamExt};
+
+    let source = b"some data, which is compressed by LZ4".to_vec();
+    let mut compressed = compress(&source, Compression::Lz4).unwrap().to_vec();
+    let expected = (&compressed[..]).get_u128_le();
+
+    // Flip the last byte, which is always a literal in LZ4 blocks.
+    *compressed.last_mut().unwrap() ^= 0xff;
+
+    let decode = |verify_checksums| {
+        let stream = stream::iter([Ok::<_, Error>(Bytes::from(compressed.clone()))]);
+        let mut decoder = Lz4Decoder::new(stream, verify_checksums);
+        async move { decoder.try_next().await }
+    };
+
+    let err = decode(true).await.unwrap_err();
+    assert!(
+        matches!(err, Error::ChecksumMismatch { expected: e, actual } if e == expected && actual != e),
+        "{:?}",
+        err
+    );
+
+    // Without verification, the corrupted block is silently decoded.
+    let actual = decode(false).await.unwrap().unwrap();
+    assert_eq!(actual.len(), source.len());
+    assert_eq!(actual[..source.len() - 1], source[..source.len() - 1]);
+    assert_eq!(actual[source.len() - 1], source[source.len() - 1] ^ 0xff);
+}
@@ -1,13 +1,22 @@
-use std::marker::PhantomData;
+use std::{
+    convert::TryFrom,
+    marker::PhantomData,
+    mem,
+    sync::Arc,
+    task::{Context, Poll},
+};
 
-use bytes::Bytes;
-use futures::TryStreamExt;
+use bytes::{Buf, Bytes};
+use futures::{future, ready, StreamExt, TryStreamExt};
+use hyper::{Body, HeaderMap};
 use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::{mpsc, Semaphore};
 
 use crate::{
     buflist::BufList,
     error::{Error, Result},
-    response::Response,
+    response::{Chunks, Response},
     rowbinary,
 };
 
@@ -18,6 +27,12 @@ const INITIAL_BUFFER_SIZE: usize = 1024;
 struct RawCursor {
     response: Response,
     pending: BufList<Bytes>,
+    last_size: usize,
+    // Zero if chunks aren't prefetched.
+    prefetch_bytes: usize,
+    prefetch: Option<Prefetch>,
+    // A limit of pending bytes of an incomplete value, see `BufferCapacity::max_row`.
+    max_pending: usize,
 }
 
 impl RawCursor {
@@ -25,27 +40,53 @@ impl RawCursor {
         Self {
             response,
             pending: BufList::default(),
+            last_size: 0,
+            prefetch_bytes: 0,
+            prefetch: None,
+            max_pending: usize::MAX,
         }
     }
 
+    // The size of the last yielded value, excluding skipped data.
+    fn last_size(&self) -> usize {
+        self.last_size
+    }
+
+    async fn headers(&mut self) -> Result<&HeaderMap> {
+        self.response.headers().await
+    }
+
     #[inline(always)]
     async fn next<T>(
         &mut self,
         mut f: impl FnMut(&mut BufList<Bytes>) -> ControlFlow<T>,
     ) -> Result<Option<T>> {
+        future::poll_fn(|cx| self.poll_next(cx, &mut f)).await
+    }
+
+    #[inline(always)]
+    fn poll_next<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        mut f: impl FnMut(&mut BufList<Bytes>) -> ControlFlow<T>,
+    ) -> Poll<Result<Option<T>>> {
         let chunks = if let Some(chunks) = self.response.chunks() {
             chunks
         } else {
-            self.response.chunks_slow().await?
+            ready!(self.response.poll_chunks_slow(cx))?
         };
 
+        if self.prefetch_bytes > 0 && self.prefetch.is_none() {
+            self.prefetch = Some(Prefetch::spawn(chunks.take(), self.prefetch_bytes));
+        }
+
         loop {
             match f(&mut self.pending) {
                 ControlFlow::Yield(value) => {
+                    self.last_size = self.pending.uncommitted();
                     self.pending.commit();
-                    return Ok(Some(value));
+                    return Poll::Ready(Ok(Some(value)));
                 }
-                #[cfg(feature = "watch")]
                 ControlFlow::Skip => {
                     self.pending.commit();
                     continue;
@@ -56,22 +97,99 @@ impl RawCursor {
                 }
                 ControlFlow::Err(Error::NotEnoughData) => {
                     self.pending.rollback();
+
+                    // The value is incomplete, so all pending bytes belong to it.
+                    let pending = self.pending.remaining();
+                    if pending > self.max_pending {
+                        return Poll::Ready(Err(Error::RowTooLarge(pending)));
+                    }
                 }
-                ControlFlow::Err(err) => return Err(err),
+                ControlFlow::Err(err) => return Poll::Ready(Err(err)),
             }
 
-            match chunks.try_next().await? {
+            // Data is committed only on yielding, so it's fine to return `Pending` here,
+            // the next call begins with the same state.
+            let next = match &mut self.prefetch {
+                Some(prefetch) => prefetch.poll_next(cx),
+                None => chunks.try_poll_next_unpin(cx),
+            };
+
+            match ready!(next).transpose()? {
                 Some(chunk) => self.pending.push(chunk),
-                None if self.pending.bufs_cnt() > 0 => return Err(Error::NotEnoughData),
-                None => return Ok(None),
+                None if self.pending.bufs_cnt() > 0 => {
+                    return Poll::Ready(Err(Error::NotEnoughData))
+                }
+                None => return Poll::Ready(Ok(None)),
             }
         }
     }
 }
 
+// === Prefetch ===
+
+/// Reads chunks in a separate task ahead of the cursor, so receiving and
+/// decompressing overlap with processing of rows. The task waits while
+/// more than `max_bytes` (at most `u32::MAX`) are received, but not read.
+struct Prefetch {
+    rx: mpsc::UnboundedReceiver<Result<Bytes>>,
+    budget: Arc<Semaphore>,
+    max_bytes: usize,
+}
+
+impl Prefetch {
+    fn spawn(mut chunks: Chunks<Body>, max_bytes: usize) -> Self {
+        let max_bytes = max_bytes.min(u32::MAX as usize);
+        let budget = Arc::new(Semaphore::new(max_bytes));
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let task_budget = budget.clone();
+        tokio::spawn(async move {
+            while let Some(result) = chunks.next().await {
+                let size = result.as_ref().map_or(0, |chunk| chunk.len());
+                let is_err = result.is_err();
+
+                // The budget is closed if the cursor is dropped.
+                match task_budget.acquire_many(permits(size, max_bytes)).await {
+                    Ok(permit) => permit.forget(),
+                    Err(_) => break,
+                }
+
+                if tx.send(result).is_err() || is_err {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            rx,
+            budget,
+            max_bytes,
+        }
+    }
+
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes>>> {
+        let result = ready!(self.rx.poll_recv(cx));
+        if let Some(Ok(chunk)) = &result {
+            let permits = permits(chunk.len(), self.max_bytes);
+            self.budget.add_permits(permits as usize);
+        }
+        Poll::Ready(result)
+    }
+}
+
+impl Drop for Prefetch {
+    fn drop(&mut self) {
+        self.budget.close();
+    }
+}
+
+// Chunks bigger than the budget take all of it.
+fn permits(size: usize, max_bytes: usize) -> u32 {
+    u32::try_from(size.min(max_bytes)).expect("clamped above")
+}
+
 enum ControlFlow<T> {
     Yield(T),
-    #[cfg(feature = "watch")]
     Skip,
     Retry,
     Err(Error),
@@ -83,40 +201,325 @@ fn workaround_51132<'a, T>(ptr: &mut T) -> &'a mut T {
     unsafe { &mut *(ptr as *mut T) }
 }
 
+// === RowBuffer ===
+
+/// Sizes of [`RowBuffer`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BufferCapacity {
+    pub(crate) initial: usize,
+    pub(crate) max_retained: usize,
+    // A limit of the buffer, reached only by rows split between chunks.
+    pub(crate) max_row: usize,
+}
+
+impl Default for BufferCapacity {
+    fn default() -> Self {
+        Self {
+            initial: INITIAL_BUFFER_SIZE,
+            max_retained: usize::MAX,
+            max_row: usize::MAX,
+        }
+    }
+}
+
+/// A temporary buffer for values split between chunks.
+///
+/// It grows to fit the largest value. If it has grown beyond `max_retained`,
+/// it's reset to the initial size by [`RowBuffer::reclaim`], so one huge row
+/// doesn't inflate the buffer of a long-lived cursor forever.
+///
+/// Rows can borrow the buffer, so it's never reallocated under them: growing
+/// retires the old allocation, which is freed only by [`RowBuffer::reclaim`].
+pub(crate) struct RowBuffer {
+    buffer: Vec<u8>,
+    retired: Vec<Vec<u8>>,
+    capacity: BufferCapacity,
+    is_oversized: bool,
+}
+
+impl RowBuffer {
+    pub(crate) fn new(capacity: BufferCapacity) -> Self {
+        Self {
+            buffer: vec![0; capacity.initial],
+            retired: Vec::new(),
+            capacity,
+            is_oversized: false,
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub(crate) fn capacity(&self) -> BufferCapacity {
+        self.capacity
+    }
+
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.buffer
+    }
+
+    /// Grows the buffer by at least `need` bytes, but not beyond `max_row` bytes,
+    /// otherwise [`Error::RowTooLarge`] is returned instead of panicking on overflow.
+    pub(crate) fn grow(&mut self, need: usize) -> Result<()> {
+        // `Vec` can't be larger than `isize::MAX` bytes anyway.
+        let max_len = self.capacity.max_row.min(isize::MAX as usize);
+        let required = self.buffer.len().saturating_add(need);
+        if required > max_len {
+            return Err(Error::RowTooLarge(required));
+        }
+
+        let new_len = required
+            .checked_next_power_of_two()
+            .unwrap_or(required)
+            .min(max_len);
+
+        // The row is decoded again from scratch, so the content isn't copied.
+        let old = mem::replace(&mut self.buffer, vec![0; new_len]);
+        self.retired.push(old);
+        Ok(())
+    }
+
+    /// Must be called once a row is read.
+    pub(crate) fn mark_row_read(&mut self) {
+        self.is_oversized = self.buffer.len() > self.capacity.max_retained;
+    }
+
+    /// Frees retired allocations and shrinks the buffer if the last row was too large.
+    /// Must be called only when rows don't borrow the buffer, i.e. if they're owned.
+    pub(crate) fn reclaim(&mut self) {
+        self.retired.clear();
+
+        if self.is_oversized {
+            self.buffer = vec![0; self.capacity.initial];
+            self.is_oversized = false;
+        }
+    }
+}
+
 // === RowBinaryCursor ===
 
-pub struct RowBinaryCursor<T> {
+pub(crate) struct RowBinaryCursor<T> {
     raw: RawCursor,
-    buffer: Vec<u8>,
+    buffer: RowBuffer,
+    // `Some` for `RowBinaryWithNamesAndTypes`, which is checked while reading the header.
+    column_count: Option<usize>,
+    // `Some` after reading the header in the lenient mode, see `with_lenient_integers()`.
+    column_types: Option<Vec<Option<rowbinary::ColumnType>>>,
+    lenient: bool,
+    // Names the header must start with and the number of columns after them,
+    // see `with_expected_columns()`.
+    expected_columns: Option<(&'static [&'static str], usize)>,
+    has_read_header: bool,
     _marker: PhantomData<T>,
 }
 
 impl<T> RowBinaryCursor<T> {
     pub(crate) fn new(response: Response) -> Self {
+        Self::with_buffer_capacity(response, BufferCapacity::default())
+    }
+
+    pub(crate) fn with_buffer_capacity(response: Response, capacity: BufferCapacity) -> Self {
+        let mut raw = RawCursor::new(response);
+        raw.max_pending = capacity.max_row;
         Self {
-            raw: RawCursor::new(response),
-            buffer: vec![0; INITIAL_BUFFER_SIZE],
+            raw,
+            buffer: RowBuffer::new(capacity),
+            column_count: None,
+            column_types: None,
+            lenient: false,
+            expected_columns: None,
+            has_read_header: false,
             _marker: PhantomData,
         }
     }
 
+    /// Enables prefetching of up to `max_bytes` of the response, see [`Prefetch`].
+    pub(crate) fn with_prefetch(mut self, max_bytes: usize) -> Self {
+        self.raw.prefetch_bytes = max_bytes;
+        self
+    }
+
+    /// Creates a cursor over `RowBinaryWithNamesAndTypes` having exactly `column_count`
+    /// columns, otherwise [`Error::UnexpectedColumnCount`] is returned before any row.
+    pub(crate) fn with_column_count(response: Response, column_count: usize) -> Self {
+        Self {
+            column_count: Some(column_count),
+            ..Self::new(response)
+        }
+    }
+
+    /// Expects `RowBinaryWithNamesAndTypes` and reads integers as they're typed
+    /// in the header, converting them to widths of `T`, see [`Query::with_lenient_integers`].
+    ///
+    /// [`Query::with_lenient_integers`]: crate::query::Query::with_lenient_integers
+    pub(crate) fn with_lenient_integers(mut self) -> Self {
+        self.lenient = true;
+        self
+    }
+
+    /// Expects `RowBinaryWithNamesAndTypes` of columns named `names` followed by
+    /// exactly `trailing` other columns, otherwise [`Error::SchemaMismatch`]
+    /// is returned before any row.
+    pub(crate) fn with_expected_columns(
+        mut self,
+        names: &'static [&'static str],
+        trailing: usize,
+    ) -> Self {
+        self.expected_columns = Some((names, trailing));
+        self
+    }
+
+    pub(crate) async fn headers(&mut self) -> Result<&HeaderMap> {
+        self.raw.headers().await
+    }
+
+    pub(crate) fn last_row_bytes(&self) -> usize {
+        self.raw.last_size()
+    }
+
     pub(crate) async fn next<'a, 'b: 'a>(&'a mut self) -> Result<Option<T>>
+    where
+        T: Deserialize<'b>,
+    {
+        future::poll_fn(|cx| self.poll_next(cx)).await
+    }
+
+    /// Like [`RowBinaryCursor::next`], but also shrinks the buffer after huge rows.
+    pub(crate) async fn next_owned(&mut self) -> Result<Option<T>>
+    where
+        T: for<'b> Deserialize<'b>,
+    {
+        future::poll_fn(|cx| self.poll_next_owned(cx)).await
+    }
+
+    /// Like [`RowBinaryCursor::poll_next`], but also shrinks the buffer after huge rows,
+    /// which is possible only for owned rows: borrowed ones can outlive `&mut self`.
+    pub(crate) fn poll_next_owned(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<T>>>
+    where
+        T: for<'b> Deserialize<'b>,
+    {
+        self.buffer.reclaim();
+        self.poll_next(cx)
+    }
+
+    pub(crate) fn poll_next<'a, 'b: 'a>(
+        &'a mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<T>>>
     where
         T: Deserialize<'b>,
     {
         let buffer = &mut self.buffer;
+        let column_count = self.column_count;
+        let column_types = &mut self.column_types;
+        let lenient = self.lenient;
+        let expected_columns = self.expected_columns;
+        let has_read_header = &mut self.has_read_header;
+        let has_header = column_count.is_some() || lenient || expected_columns.is_some();
+
+        self.raw.poll_next(cx, |pending| {
+            if has_header && !*has_read_header {
+                // Types are parsed only if required to read integers.
+                let header = if lenient {
+                    rowbinary::read_column_names_and_types(pending)
+                } else {
+                    rowbinary::read_column_names(pending).map(|names| (names, Vec::new()))
+                };
+
+                let (names, types) = match header {
+                    Ok(header) => header,
+                    Err(err) => return ControlFlow::Err(err),
+                };
+
+                if let Some(expected) = column_count {
+                    if names.len() != expected {
+                        return ControlFlow::Err(Error::UnexpectedColumnCount(names.len()));
+                    }
+                }
+
+                if let Some((expected, trailing)) = expected_columns {
+                    if names.len() != expected.len() + trailing
+                        || names.iter().zip(expected).any(|(n, e)| n != e)
+                    {
+                        return ControlFlow::Err(Error::SchemaMismatch {
+                            expected: expected.iter().map(|name| name.to_string()).collect(),
+                            found: names,
+                        });
+                    }
+                }
+
+                if lenient {
+                    *column_types = Some(types);
+                }
+
+                *has_read_header = true;
+                return ControlFlow::Skip;
+            }
+
+            let temp_buf = workaround_51132(buffer).as_mut_slice();
+            let result = match column_types {
+                Some(types) => rowbinary::deserialize_lenient_from(pending, temp_buf, types),
+                None => rowbinary::deserialize_from(pending, temp_buf),
+            };
+
+            match result {
+                Ok(value) => {
+                    buffer.mark_row_read();
+                    ControlFlow::Yield(value)
+                }
+                Err(Error::TooSmallBuffer(need)) => match buffer.grow(need) {
+                    Ok(()) => ControlFlow::Retry,
+                    Err(err) => ControlFlow::Err(err),
+                },
+                Err(err) => ControlFlow::Err(err),
+            }
+        })
+    }
+}
+
+// === NamedRowBinaryCursor ===
+
+pub(crate) struct NamedRowBinaryCursor {
+    raw: RawCursor,
+    columns: Option<Vec<rowbinary::Column>>,
+}
+
+impl NamedRowBinaryCursor {
+    pub(crate) fn new(response: Response) -> Self {
+        Self {
+            raw: RawCursor::new(response),
+            columns: None,
+        }
+    }
+
+    pub(crate) async fn headers(&mut self) -> Result<&HeaderMap> {
+        self.raw.headers().await
+    }
+
+    pub(crate) fn last_row_bytes(&self) -> usize {
+        self.raw.last_size()
+    }
+
+    pub(crate) async fn next(&mut self) -> Result<Option<Vec<(String, Value)>>> {
+        let columns = &mut self.columns;
 
         self.raw
             .next(|pending| {
-                match rowbinary::deserialize_from(pending, &mut workaround_51132(buffer)[..]) {
-                    Ok(value) => ControlFlow::Yield(value),
-                    Err(Error::TooSmallBuffer(need)) => {
-                        let new_len = (buffer.len() + need)
-                            .checked_next_power_of_two()
-                            .expect("oom");
-                        buffer.resize(new_len, 0);
-                        ControlFlow::Retry
-                    }
+                // The header precedes all rows, even if there are no rows at all.
+                let Some(columns) = columns else {
+                    return match rowbinary::read_header(pending) {
+                        Ok(header) => {
+                            *columns = Some(header);
+                            ControlFlow::Skip
+                        }
+                        Err(err) => ControlFlow::Err(err),
+                    };
+                };
+
+                match rowbinary::read_row(pending, columns) {
+                    Ok(row) => ControlFlow::Yield(row),
                     Err(err) => ControlFlow::Err(err),
                 }
             })
@@ -124,6 +527,121 @@ impl<T> RowBinaryCursor<T> {
     }
 }
 
+// === ArrowStreamCursor ===
+
+/// A cursor over `ArrowStream`, which emits record batches.
+///
+/// Messages are split into chunks arbitrarily, so the decoder keeps
+/// incomplete messages until the rest is received.
+#[cfg(feature = "arrow")]
+pub(crate) struct ArrowStreamCursor {
+    response: Response,
+    decoder: arrow_ipc::reader::StreamDecoder,
+    buffer: arrow_buffer::Buffer,
+}
+
+#[cfg(feature = "arrow")]
+impl ArrowStreamCursor {
+    pub(crate) fn new(response: Response) -> Self {
+        Self {
+            response,
+            decoder: arrow_ipc::reader::StreamDecoder::new(),
+            buffer: arrow_buffer::Buffer::from_vec(Vec::<u8>::new()),
+        }
+    }
+
+    pub(crate) async fn headers(&mut self) -> Result<&HeaderMap> {
+        self.response.headers().await
+    }
+
+    pub(crate) async fn next(&mut self) -> Result<Option<arrow_array::RecordBatch>> {
+        future::poll_fn(|cx| self.poll_next(cx)).await
+    }
+
+    pub(crate) fn poll_next(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<arrow_array::RecordBatch>>> {
+        let chunks = if let Some(chunks) = self.response.chunks() {
+            chunks
+        } else {
+            ready!(self.response.poll_chunks_slow(cx))?
+        };
+
+        let to_error = |err: arrow_schema::ArrowError| Error::BadResponse(err.to_string());
+
+        loop {
+            // The schema message is consumed without yielding anything.
+            while !self.buffer.is_empty() {
+                if let Some(batch) = self.decoder.decode(&mut self.buffer).map_err(to_error)? {
+                    return Poll::Ready(Ok(Some(batch)));
+                }
+            }
+
+            match ready!(chunks.try_poll_next_unpin(cx)).transpose()? {
+                // Chunks are shared with the buffer without copying.
+                Some(chunk) => self.buffer = arrow_buffer::Buffer::from_bytes(chunk.into()),
+                None => {
+                    self.decoder.finish().map_err(to_error)?;
+                    return Poll::Ready(Ok(None));
+                }
+            }
+        }
+    }
+}
+
+// === JsonEachRowCursor ===
+
+/// A cursor over `JSONEachRow`, which emits every row as a JSON object.
+pub(crate) struct JsonEachRowCursor {
+    raw: RawCursor,
+    line: String,
+}
+
+impl JsonEachRowCursor {
+    pub(crate) fn new(response: Response) -> Self {
+        Self {
+            raw: RawCursor::new(response),
+            line: String::with_capacity(INITIAL_BUFFER_SIZE),
+        }
+    }
+
+    pub(crate) async fn headers(&mut self) -> Result<&HeaderMap> {
+        self.raw.headers().await
+    }
+
+    pub(crate) fn last_row_bytes(&self) -> usize {
+        self.raw.last_size()
+    }
+
+    pub(crate) async fn next(&mut self) -> Result<Option<Value>> {
+        let line = &mut self.line;
+
+        self.raw
+            .next(|pending| match read_line(pending, line) {
+                Ok(line) => match serde_json::from_str(line) {
+                    Ok(value) => ControlFlow::Yield(value),
+                    Err(err) => ControlFlow::Err(Error::BadResponse(err.to_string())),
+                },
+                Err(err) => ControlFlow::Err(err),
+            })
+            .await
+    }
+}
+
+/// Reads a line without `\n`, or fails with `NotEnoughData` if it isn't complete.
+fn read_line<'a>(pending: &mut BufList<Bytes>, line: &'a mut String) -> Result<&'a str> {
+    use bytes::Buf;
+    use std::io::BufRead;
+
+    line.clear();
+    match pending.reader().read_line(line) {
+        Ok(_) => line.strip_suffix('\n').ok_or(Error::NotEnoughData),
+        // Actually, it's an unreachable branch, because `bytes::buf::Reader` doesn't fail.
+        Err(err) => Err(Error::Custom(err.to_string())),
+    }
+}
+
 // === JsonCursor ===
 
 #[cfg(feature = "watch")]
@@ -157,35 +675,98 @@ impl<T> JsonCursor<T> {
     where
         T: Deserialize<'b>,
     {
-        use bytes::Buf;
-        use std::io::BufRead;
-
         let line = &mut self.line;
 
         self.raw
-            .next(|pending| {
-                line.clear();
-                match pending.reader().read_line(line) {
-                    Ok(_) => {
-                        let line = workaround_51132(line);
-                        if let Some(line) = line.strip_suffix('\n') {
-                            match serde_json::from_str(line) {
-                                Ok(JsonRow::Row(value)) => ControlFlow::Yield(value),
-                                Ok(JsonRow::Progress { .. }) => ControlFlow::Skip,
-                                // TODO: another reason?
-                                Err(err) => ControlFlow::Err(Error::BadResponse(err.to_string())),
-                            }
-                        } else {
-                            ControlFlow::Err(Error::NotEnoughData)
-                        }
-                    }
-                    Err(err) => {
-                        // Actually, it's an unreachable branch, because
-                        // `bytes::buf::Reader` doesn't fail.
-                        ControlFlow::Err(Error::Custom(err.to_string()))
-                    }
-                }
+            .next(|pending| match read_line(pending, workaround_51132(line)) {
+                Ok(line) => match serde_json::from_str(line) {
+                    Ok(JsonRow::Row(value)) => ControlFlow::Yield(value),
+                    Ok(JsonRow::Progress { .. }) => ControlFlow::Skip,
+                    // TODO: another reason?
+                    Err(err) => ControlFlow::Err(Error::BadResponse(err.to_string())),
+                },
+                Err(err) => ControlFlow::Err(err),
             })
             .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reclaims_oversized_buffers() {
+        let mut buffer = RowBuffer::new(BufferCapacity {
+            initial: 16,
+            max_retained: 64,
+            max_row: usize::MAX,
+        });
+
+        buffer.grow(40).unwrap();
+        buffer.mark_row_read();
+        buffer.reclaim();
+        assert_eq!(buffer.len(), 64);
+
+        buffer.grow(100).unwrap();
+        assert_eq!(buffer.len(), 256);
+        // Not read yet, e.g. waiting for more data.
+        buffer.reclaim();
+        assert_eq!(buffer.len(), 256);
+
+        buffer.mark_row_read();
+        assert_eq!(buffer.len(), 256);
+        buffer.reclaim();
+        assert_eq!(buffer.len(), 16);
+    }
+
+    #[test]
+    fn it_retains_buffers_by_default() {
+        let mut buffer = RowBuffer::new(BufferCapacity::default());
+        assert_eq!(buffer.len(), INITIAL_BUFFER_SIZE);
+
+        buffer.grow(1 << 20).unwrap();
+        buffer.mark_row_read();
+        buffer.reclaim();
+        assert_eq!(buffer.len(), 2 << 20);
+    }
+
+    #[test]
+    fn it_never_reallocates_under_rows() {
+        let mut buffer = RowBuffer::new(BufferCapacity {
+            initial: 16,
+            max_retained: 16,
+            max_row: usize::MAX,
+        });
+
+        // A borrowed row can point to the old allocation.
+        let ptr = buffer.as_mut_slice().as_ptr();
+        buffer.grow(100).unwrap();
+        buffer.mark_row_read();
+        assert!(buffer.retired.iter().any(|old| old.as_ptr() == ptr));
+
+        buffer.reclaim();
+        assert!(buffer.retired.is_empty());
+        assert_eq!(buffer.len(), 16);
+    }
+
+    #[test]
+    fn it_limits_buffer_growth() {
+        let capacity = BufferCapacity {
+            initial: 64,
+            max_retained: usize::MAX,
+            max_row: 1000,
+        };
+        let mut buffer = RowBuffer::new(capacity);
+        buffer.grow(100).unwrap();
+        assert_eq!(buffer.len(), 256);
+        buffer.grow(700).unwrap();
+        assert_eq!(buffer.len(), 1000);
+        assert!(matches!(buffer.grow(1), Err(Error::RowTooLarge(1001))));
+
+        // Doesn't overflow.
+        let mut buffer = RowBuffer::new(BufferCapacity::default());
+        let err = buffer.grow(usize::MAX).unwrap_err();
+        assert!(matches!(err, Error::RowTooLarge(usize::MAX)), "{:?}", err);
+    }
+}
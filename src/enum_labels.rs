@@ -0,0 +1,119 @@
+use std::str::FromStr;
+
+use crate::{
+    error::{Error, Result},
+    rowbinary::ColumnType,
+};
+
+/// Labels of an `Enum8` or `Enum16` column, parsed from its type.
+///
+/// It allows decoding discriminants into labels without defining a Rust enum,
+/// e.g. for generic display of tables, which types are known only at runtime:
+///
+/// ```
+/// # use clickhouse::enum_labels::EnumLabels;
+/// let labels = EnumLabels::parse("Enum8('draft' = 1, 'published' = 2)")?;
+/// assert_eq!(labels.label(2), Some("published"));
+/// assert_eq!(labels.label(3), None);
+/// assert_eq!(labels.value("draft"), Some(1));
+/// # Ok::<(), clickhouse::error::Error>(())
+/// ```
+///
+/// `Nullable(..)` and `LowCardinality(..)` wrappers are allowed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumLabels {
+    items: Vec<(String, i16)>,
+}
+
+impl EnumLabels {
+    /// Parses a type, as returned by `toTypeName()` or `system.columns`.
+    pub fn parse(ty: &str) -> Result<Self> {
+        let invalid = || Error::Custom(format!("`{ty}` isn't a valid enum type"));
+
+        let items = match ColumnType::parse(ty).map_err(|_| invalid())? {
+            ColumnType::Nullable(inner) => *inner,
+            other => other,
+        };
+
+        let items = match items {
+            ColumnType::Enum8(items) => items
+                .into_iter()
+                .map(|(name, value)| (name, value.into()))
+                .collect(),
+            ColumnType::Enum16(items) => items,
+            _ => return Err(invalid()),
+        };
+
+        Ok(Self { items })
+    }
+
+    /// Returns a label of the discriminant, if defined.
+    ///
+    /// Discriminants of `Enum8` are `i8`, which can be losslessly converted by `.into()`.
+    pub fn label(&self, value: i16) -> Option<&str> {
+        self.items
+            .iter()
+            .find(|(_, v)| *v == value)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Returns a discriminant of the label, if defined.
+    pub fn value(&self, label: &str) -> Option<i16> {
+        self.items
+            .iter()
+            .find(|(name, _)| name == label)
+            .map(|(_, value)| *value)
+    }
+
+    /// Returns all `(label, discriminant)` pairs in the order of definition.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i16)> + '_ {
+        self.items
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+    }
+}
+
+impl FromStr for EnumLabels {
+    type Err = Error;
+
+    fn from_str(ty: &str) -> Result<Self> {
+        Self::parse(ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_decodes_labels() {
+        let labels = EnumLabels::parse("Enum8('a' = 1, 'b\\'c' = -2)").unwrap();
+        assert_eq!(labels.label(1), Some("a"));
+        assert_eq!(labels.label(-2), Some("b'c"));
+        assert_eq!(labels.label(0), None);
+        assert_eq!(labels.value("b'c"), Some(-2));
+        assert_eq!(
+            labels.iter().collect::<Vec<_>>(),
+            vec![("a", 1), ("b'c", -2)]
+        );
+
+        let labels: EnumLabels = "Nullable(Enum16('x' = 1000))".parse().unwrap();
+        assert_eq!(labels.label(1000), Some("x"));
+
+        let labels = EnumLabels::parse("LowCardinality(Enum8('y' = 5))").unwrap();
+        assert_eq!(labels.label(5i8.into()), Some("y"));
+    }
+
+    #[test]
+    fn it_rejects_other_types() {
+        assert!(matches!(EnumLabels::parse("String"), Err(Error::Custom(_))));
+        assert!(matches!(
+            EnumLabels::parse("Nullable(UInt8)"),
+            Err(Error::Custom(_))
+        ));
+        assert!(matches!(
+            EnumLabels::parse("Enum8('a' = 1"),
+            Err(Error::Custom(_))
+        ));
+    }
+}
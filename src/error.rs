@@ -22,6 +22,10 @@ pub enum Error {
     Decompression(#[source] Box<dyn StdError + Send + Sync>),
     #[error("no rows returned by a query that expected to return at least one row")]
     RowNotFound,
+    #[error("more than one row returned by a query that expected to return exactly one row")]
+    TooManyRows,
+    #[error("{0} columns returned by a query that expected to return exactly one column")]
+    UnexpectedColumnCount(usize),
     #[error("sequences must have a known size ahead of time")]
     SequenceMustHaveLength,
     #[error("`deserialize_any` is not supported")]
@@ -36,8 +40,37 @@ pub enum Error {
     Custom(String),
     #[error("bad response: {0}")]
     BadResponse(String),
+    #[error("server exception (code {code}): {message}")]
+    ServerException { code: u32, message: String },
+    #[error("cannot execute the query in readonly mode: {0}")]
+    ReadOnlyViolation(String),
+    #[error("authentication failed: {0}")]
+    AuthenticationFailed(String),
+    #[error("unexpected response (status {status}), probably not from ClickHouse: {body}")]
+    UnexpectedResponse { status: u16, body: String },
+    #[error("the insert can't be retried, because its body may be partially received: {0}")]
+    Unretryable(#[source] Box<Error>),
     #[error("timeout expired")]
     TimedOut,
+    #[error("the client is shut down")]
+    Shutdown,
+    #[error("duplicate column `{0}` in the result, use aliases to make names unique")]
+    DuplicateColumn(String),
+    #[error("the result exceeds the limit of {0} bytes")]
+    ResultTooLarge(u64),
+    #[error(
+        "checksum mismatch of a compressed block (expected {expected:#x}, actual {actual:#x})"
+    )]
+    ChecksumMismatch { expected: u128, actual: u128 },
+    #[error("value {value} is out of range of `{target}`")]
+    Overflow { value: i128, target: &'static str },
+    #[error("a row requires {0} bytes of the buffer, which exceeds the maximum row size")]
+    RowTooLarge(usize),
+    #[error("columns {found:?} of the response don't match fields {expected:?} of the row")]
+    SchemaMismatch {
+        expected: Vec<String>,
+        found: Vec<String>,
+    },
 
     // Internally handled errors, not part of public API.
     // XXX: move to another error?
@@ -67,6 +100,21 @@ impl de::Error for Error {
 }
 
 impl Error {
+    /// Makes an error from an exception sent by the server,
+    /// mapping codes that are handled specially to their own variants.
+    pub(crate) fn server_exception(code: u32, message: String) -> Self {
+        // `READONLY`, e.g. a mutating query with the `readonly` setting.
+        const READONLY: u32 = 164;
+        // `AUTHENTICATION_FAILED`, e.g. a wrong password or an unknown user.
+        const AUTHENTICATION_FAILED: u32 = 516;
+
+        match code {
+            READONLY => Self::ReadOnlyViolation(message),
+            AUTHENTICATION_FAILED => Self::AuthenticationFailed(message),
+            _ => Self::ServerException { code, message },
+        }
+    }
+
     #[allow(dead_code)]
     pub(crate) fn into_io(self) -> io::Error {
         io::Error::new(io::ErrorKind::Other, self)
@@ -1,36 +1,52 @@
 use core::fmt::Display;
-use serde::{de, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
-use serde_with::{DeserializeAs, SerializeAs};
-use std::{
-    convert::{TryFrom, TryInto},
-    fmt::{Debug, LowerHex},
-    str::FromStr,
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    ser::{self, SerializeTuple},
+    Deserialize, Deserializer, Serialize, Serializer,
 };
+use serde_with::{DeserializeAs, SerializeAs};
+use std::{fmt, fmt::Debug, str::FromStr};
 
-/// Wrapper type for a FixedString type in Clickhouse
-/// Uses custom serializing handling in SerializeStruct impl for RowBinarySerializer
-/// Forgoes the LEB128 encoding and just encodes the raw byte string
-/// For deserializing the type FixedString(n) with a `query()`, wrap toString(...) around the value
-/// For example:
+/// Wrapper type for the `FixedString(N)` type in Clickhouse.
 ///
-/// CREATE TABLE test (
-///     t1 String,
-///     t2 FixedString(50)
-/// ) ...
+/// In RowBinary, it's encoded as exactly `N` raw bytes without the LEB128 length:
+/// shorter strings are padded with `\0`, longer strings fail to serialize.
+/// On deserialization, trailing `\0` bytes are trimmed, so the column can be
+/// selected directly, without any `toString(...)` wrapping.
 ///
-/// query("SELECT t1, toString(t2) FROM test;").fetch...
+/// Trimming is controlled by `TRIM`, which is `true` by default. Embedded `\0` bytes
+/// are always preserved, but if trailing ones are meaningful too, e.g. for padded codes,
+/// use `FixedString<N, false>` to keep all `N` bytes.
+///
+/// Either way, the bytes must be valid UTF-8, otherwise deserialization fails.
+/// Use `[u8; N]` for binary data, e.g. hashes.
+///
+/// For example:
+///
+/// ```
+/// # use clickhouse::{fixed_string::FixedString, Row};
+/// # use serde::{Deserialize, Serialize};
+/// // CREATE TABLE test (t1 String, t2 FixedString(50), t3 FixedString(16)) ...
+/// #[derive(Row, Serialize, Deserialize)]
+/// struct MyRow {
+///     t1: String,
+///     t2: FixedString<50>,
+///     // All 16 bytes, even if they end with `\0`.
+///     t3: FixedString<16, false>,
+/// }
+/// ```
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct FixedString {
+pub struct FixedString<const N: usize, const TRIM: bool = true> {
     pub string: String,
 }
 
-impl FixedString {
+impl<const N: usize, const TRIM: bool> FixedString<N, TRIM> {
     pub fn new(string: String) -> Self {
         FixedString { string }
     }
 }
 
-impl<T> From<T> for FixedString
+impl<T, const N: usize, const TRIM: bool> From<T> for FixedString<N, TRIM>
 where
     String: From<T>,
 {
@@ -40,7 +56,7 @@ where
     }
 }
 
-impl FromStr for FixedString {
+impl<const N: usize, const TRIM: bool> FromStr for FixedString<N, TRIM> {
     type Err = <String as FromStr>::Err;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -50,43 +66,86 @@ impl FromStr for FixedString {
     }
 }
 
-impl Display for FixedString {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<const N: usize, const TRIM: bool> Display for FixedString<N, TRIM> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.string)
     }
 }
 
-impl Serialize for FixedString {
+impl<const N: usize, const TRIM: bool> Serialize for FixedString<N, TRIM> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("FixedString", 1)?;
-        state.serialize_field("FixedString", &self.string)?;
-        state.end()
+        if serializer.is_human_readable() {
+            return serializer.serialize_str(&self.string);
+        }
+
+        let bytes = self.string.as_bytes();
+        if bytes.len() > N {
+            return Err(ser::Error::custom(format!(
+                "string of {} bytes doesn't fit into FixedString({N})",
+                bytes.len()
+            )));
+        }
+
+        // Tuples are encoded without the length in RowBinary.
+        let mut tuple = serializer.serialize_tuple(N)?;
+        for byte in bytes
+            .iter()
+            .chain(std::iter::repeat(&0).take(N - bytes.len()))
+        {
+            tuple.serialize_element(byte)?;
+        }
+        tuple.end()
     }
 }
 
-impl<'de> Deserialize<'de> for FixedString {
+impl<'de, const N: usize, const TRIM: bool> Deserialize<'de> for FixedString<N, TRIM> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let obj = serde_json::Value::deserialize(deserializer).map_err(de::Error::custom)?;
+        struct FixedStringVisitor<const N: usize, const TRIM: bool>;
+
+        impl<'de, const N: usize, const TRIM: bool> Visitor<'de> for FixedStringVisitor<N, TRIM> {
+            type Value = FixedString<N, TRIM>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "FixedString({N})")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(FixedString::new(value.to_string()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut bytes = Vec::with_capacity(N);
+                while let Some(byte) = seq.next_element::<u8>()? {
+                    bytes.push(byte);
+                }
 
-        if obj.is_object() {
-            let fixed_str = obj
-                .get("FixedString")
-                .ok_or_else(|| de::Error::custom("no FixedString field"))?;
+                if TRIM {
+                    let len = bytes.iter().rposition(|&b| b != 0).map_or(0, |pos| pos + 1);
+                    bytes.truncate(len);
+                }
 
-            Ok(FixedString::new(fixed_str.as_str().unwrap().to_string()))
+                String::from_utf8(bytes)
+                    .map(FixedString::new)
+                    .map_err(de::Error::custom)
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(FixedStringVisitor::<N, TRIM>)
         } else {
-            Ok(FixedString::new(obj.as_str().unwrap().to_string()))
+            deserializer.deserialize_tuple(N, FixedStringVisitor::<N, TRIM>)
         }
     }
 }
 
-impl<T> SerializeAs<T> for FixedString
+/// Serializes any `T: Debug` as `FixedString(N)`, e.g. `#[serde_as(as = "FixedString<N>")]`.
+impl<T, const N: usize, const TRIM: bool> SerializeAs<T> for FixedString<N, TRIM>
 where
     T: Debug,
 {
@@ -94,13 +153,12 @@ where
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("FixedString", 1)?;
-        state.serialize_field("FixedString", &format!("{:?}", source))?;
-        state.end()
+        Self::new(format!("{:?}", source)).serialize(serializer)
     }
 }
 
-impl<'de, T> DeserializeAs<'de, T> for FixedString
+/// Deserializes any `T: FromStr` from `FixedString(N)`.
+impl<'de, T, const N: usize, const TRIM: bool> DeserializeAs<'de, T> for FixedString<N, TRIM>
 where
     T: Debug + FromStr,
     T::Err: Display,
@@ -109,20 +167,84 @@ where
     where
         D: serde::Deserializer<'de>,
     {
-        let obj = serde_json::Value::deserialize(deserializer).map_err(de::Error::custom)?;
-
-        if obj.is_object() {
-            let fixed_str = obj
-                .get("FixedString")
-                .ok_or_else(|| de::Error::custom("no FixedString field"))?;
-
-            fixed_str
-                .as_str()
-                .unwrap()
-                .parse()
-                .map_err(de::Error::custom)
-        } else {
-            obj.as_str().unwrap().parse().map_err(de::Error::custom)
-        }
+        Self::deserialize(deserializer)?
+            .string
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rowbinary;
+
+    fn serialize<const N: usize>(value: &str) -> crate::error::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        rowbinary::serialize_into(&mut buffer, &FixedString::<N>::from(value))?;
+        Ok(buffer)
+    }
+
+    fn deserialize<const N: usize>(input: &[u8]) -> String {
+        deserialize_with::<N, true>(input)
+    }
+
+    fn deserialize_with<const N: usize, const TRIM: bool>(mut input: &[u8]) -> String {
+        let value: FixedString<N, TRIM> = rowbinary::deserialize_from(&mut input, &mut []).unwrap();
+        assert!(input.is_empty());
+        value.string
+    }
+
+    #[test]
+    fn it_pads_short_strings() {
+        assert_eq!(serialize::<4>("BT").unwrap(), b"BT\0\0");
+        assert_eq!(deserialize::<4>(b"BT\0\0"), "BT");
+    }
+
+    #[test]
+    fn it_keeps_exact_strings() {
+        assert_eq!(serialize::<4>("BTC!").unwrap(), b"BTC!");
+        assert_eq!(deserialize::<4>(b"BTC!"), "BTC!");
+        assert_eq!(serialize::<0>("").unwrap(), b"");
+    }
+
+    #[test]
+    fn it_trims_only_trailing_nulls() {
+        assert_eq!(deserialize_with::<6, true>(b"a\0b\0\0\0"), "a\0b");
+        assert_eq!(deserialize_with::<6, false>(b"a\0b\0\0\0"), "a\0b\0\0\0");
+
+        let padded = serialize::<4>("BT").unwrap();
+        assert_eq!(deserialize_with::<4, true>(&padded), "BT");
+        assert_eq!(deserialize_with::<4, false>(&padded), "BT\0\0");
+        assert_eq!(deserialize_with::<4, true>(b"\0\0\0\0"), "");
+    }
+
+    #[test]
+    fn it_rejects_invalid_utf8() {
+        let input = b"\xff\xfeA\0";
+        let err = rowbinary::deserialize_from::<FixedString<4, false>>(&mut &input[..], &mut [])
+            .unwrap_err();
+        assert!(err.to_string().contains("invalid utf-8"), "{:?}", err);
+        assert!(rowbinary::deserialize_from::<FixedString<4>>(&mut &input[..], &mut []).is_err());
+
+        // Binary data is read as an array instead.
+        let bytes: [u8; 4] = rowbinary::deserialize_from(&mut &input[..], &mut []).unwrap();
+        assert_eq!(&bytes, input);
+    }
+
+    #[test]
+    fn it_rejects_long_strings() {
+        assert!(serialize::<4>("BTC!!").is_err());
+    }
+
+    #[test]
+    fn it_supports_human_readable_formats() {
+        let value = FixedString::<4>::from("BT");
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(json, r#""BT""#);
+        assert_eq!(
+            serde_json::from_str::<FixedString<4>>(&json).unwrap(),
+            value
+        );
     }
 }
@@ -0,0 +1,300 @@
+use serde::{
+    de::{DeserializeSeed, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::{
+    error::{Error, Result},
+    row::DbRow,
+    sql::escape,
+};
+
+/// Builds `SELECT .. FROM system.numbers LIMIT count` returning rows of `T`.
+///
+/// The shape of `T` is discovered by running its `Deserialize` implementation
+/// against a tracer, which maps every requested primitive to an expression of `number`.
+pub(crate) fn select<T>(count: u64) -> Result<String>
+where
+    T: DbRow + for<'b> Deserialize<'b>,
+{
+    let mut tracer = Tracer::default();
+    T::deserialize(&mut tracer)?;
+
+    if tracer.exprs.is_empty() {
+        return Err(Error::Custom("the row type has no fields".into()));
+    }
+
+    let use_names = T::COLUMN_NAMES.len() == tracer.exprs.len();
+
+    let mut sql = String::from("SELECT ");
+    for (idx, expr) in tracer.exprs.iter().enumerate() {
+        if idx > 0 {
+            sql.push_str(", ");
+        }
+
+        sql.push_str(expr);
+
+        if use_names {
+            sql.push_str(" AS ");
+            escape::identifier(T::COLUMN_NAMES[idx], &mut sql).expect("impossible");
+        }
+    }
+
+    sql.push_str(" FROM system.numbers LIMIT ");
+    sql.push_str(&count.to_string());
+    Ok(sql)
+}
+
+#[derive(Default)]
+struct Tracer {
+    exprs: Vec<String>,
+    depth: usize,
+}
+
+impl Tracer {
+    fn nested(&self) -> Self {
+        Self {
+            exprs: Vec::new(),
+            depth: self.depth + 1,
+        }
+    }
+
+    // Fields of the top-level struct (or tuple) become separate columns.
+    fn compound<'de, V: Visitor<'de>>(
+        &mut self,
+        len: usize,
+        visitor: V,
+        wrap: impl FnOnce(&[String]) -> String,
+    ) -> Result<V::Value> {
+        let mut inner = self.nested();
+        let value = visitor.visit_seq(Access {
+            tracer: &mut inner,
+            len,
+        })?;
+
+        if self.depth == 0 {
+            self.exprs.extend(inner.exprs);
+        } else {
+            self.exprs.push(wrap(&inner.exprs));
+        }
+
+        Ok(value)
+    }
+}
+
+struct Access<'a> {
+    tracer: &'a mut Tracer,
+    len: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for Access<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.len > 0 {
+            self.len -= 1;
+            seed.deserialize(&mut *self.tracer).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+macro_rules! impl_num {
+    ($ty:ty, $deser_method:ident, $visitor_method:ident, $expr:literal) => {
+        fn $deser_method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            self.exprs.push($expr.into());
+            visitor.$visitor_method(<$ty>::default())
+        }
+    };
+}
+
+macro_rules! unsupported {
+    ($deser_method:ident($($arg:ident: $ty:ty),*)) => {
+        fn $deser_method<V: Visitor<'de>>(self, $($arg: $ty,)* _: V) -> Result<V::Value> {
+            Err(Error::Custom(format!(
+                "`{}` isn't supported by `generate()`",
+                &stringify!($deser_method)["deserialize_".len()..]
+            )))
+        }
+    };
+}
+
+impl<'de> Deserializer<'de> for &mut Tracer {
+    type Error = Error;
+
+    impl_num!(i8, deserialize_i8, visit_i8, "toInt8(number)");
+    impl_num!(i16, deserialize_i16, visit_i16, "toInt16(number)");
+    impl_num!(i32, deserialize_i32, visit_i32, "toInt32(number)");
+    impl_num!(i64, deserialize_i64, visit_i64, "toInt64(number)");
+    impl_num!(i128, deserialize_i128, visit_i128, "toInt128(number)");
+    impl_num!(u8, deserialize_u8, visit_u8, "toUInt8(number)");
+    impl_num!(u16, deserialize_u16, visit_u16, "toUInt16(number)");
+    impl_num!(u32, deserialize_u32, visit_u32, "toUInt32(number)");
+    impl_num!(u64, deserialize_u64, visit_u64, "number");
+    impl_num!(u128, deserialize_u128, visit_u128, "toUInt128(number)");
+    impl_num!(f32, deserialize_f32, visit_f32, "toFloat32(number)");
+    impl_num!(f64, deserialize_f64, visit_f64, "toFloat64(number)");
+    impl_num!(bool, deserialize_bool, visit_bool, "number % 2 = 1");
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.exprs.push("toString(number)".into());
+        visitor.visit_borrowed_str("")
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.exprs.push("toString(number)".into());
+        visitor.visit_borrowed_bytes(b"")
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut inner = self.nested();
+        let value = visitor.visit_some(&mut inner)?;
+        self.exprs
+            .extend(inner.exprs.into_iter().map(|e| format!("toNullable({e})")));
+        Ok(value)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut inner = self.nested();
+        let value = visitor.visit_seq(Access {
+            tracer: &mut inner,
+            len: 1,
+        })?;
+        self.exprs.push(format!("[{}]", inner.exprs.join(", ")));
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        self.compound(len, visitor, |exprs| format!("tuple({})", exprs.join(", ")))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    unsupported!(deserialize_any());
+    unsupported!(deserialize_char());
+    unsupported!(deserialize_map());
+    unsupported!(deserialize_identifier());
+    unsupported!(deserialize_ignored_any());
+    unsupported!(deserialize_enum(
+        _name: &'static str,
+        _variants: &'static [&'static str]
+    ));
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    use clickhouse::Row;
+
+    #[test]
+    fn it_builds_select_for_struct() {
+        #[derive(Row, Deserialize)]
+        #[allow(dead_code)]
+        struct Inner(u8, String);
+
+        #[derive(Row, Deserialize)]
+        #[allow(dead_code)]
+        struct MyRow {
+            a: u32,
+            b: Option<i64>,
+            c: String,
+            d: Vec<f64>,
+            e: (bool, u64),
+            f: Inner,
+        }
+
+        assert_eq!(
+            select::<MyRow>(10).unwrap(),
+            "SELECT toUInt32(number) AS `a`, toNullable(toInt64(number)) AS `b`, \
+            toString(number) AS `c`, [toFloat64(number)] AS `d`, \
+            tuple(number % 2 = 1, number) AS `e`, tuple(toUInt8(number), toString(number)) AS `f` \
+            FROM system.numbers LIMIT 10"
+        );
+    }
+
+    #[test]
+    fn it_builds_select_for_primitives() {
+        assert_eq!(
+            select::<u64>(5).unwrap(),
+            "SELECT number FROM system.numbers LIMIT 5"
+        );
+        assert_eq!(
+            select::<(u8, String)>(5).unwrap(),
+            "SELECT toUInt8(number), toString(number) FROM system.numbers LIMIT 5"
+        );
+    }
+
+    #[test]
+    fn it_rejects_unsupported_types() {
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        enum Enum {
+            A,
+        }
+
+        #[derive(Row, Deserialize)]
+        #[allow(dead_code)]
+        struct MyRow {
+            a: Enum,
+        }
+
+        assert!(matches!(select::<MyRow>(1), Err(Error::Custom(_))));
+        assert!(matches!(select::<()>(1), Err(Error::Custom(_))));
+    }
+}
@@ -0,0 +1,192 @@
+//! Geo types: `Point`, `Ring`, `Polygon` and `MultiPolygon`.
+//!
+//! They are aliases of tuples and arrays in ClickHouse, so these wrappers are
+//! encoded exactly as the underlying types and only save hand-rolling nesting:
+//!
+//! ```
+//! # use clickhouse::{geo::{Point, Polygon, Ring}, Row};
+//! # use serde::{Deserialize, Serialize};
+//! // CREATE TABLE zones (name String, area Polygon) ...
+//! #[derive(Row, Serialize, Deserialize)]
+//! struct Zone {
+//!     name: String,
+//!     area: Polygon,
+//! }
+//!
+//! let square = Ring::new([(0., 0.), (0., 10.), (10., 10.), (10., 0.)]);
+//! let hole = Ring::new([(4., 4.), (4., 6.), (6., 6.), (6., 4.)]);
+//! let zone = Zone {
+//!     name: "donut".into(),
+//!     area: Polygon::new(square, [hole]),
+//! };
+//! assert_eq!(zone.area.exterior().map(|ring| ring.points().len()), Some(4));
+//! assert_eq!(zone.area.holes()[0].points()[0], Point::new(4., 4.));
+//! ```
+//!
+//! Older versions of ClickHouse require the `allow_experimental_geo_types` setting
+//! to create tables with these types.
+
+use std::iter::FromIterator;
+
+use serde::{Deserialize, Serialize};
+
+/// `Point`, which is `Tuple(Float64, Float64)`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "(f64, f64)", into = "(f64, f64)")]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+}
+
+impl From<(f64, f64)> for Point {
+    fn from((x, y): (f64, f64)) -> Self {
+        Self { x, y }
+    }
+}
+
+impl From<Point> for (f64, f64) {
+    fn from(point: Point) -> Self {
+        (point.x, point.y)
+    }
+}
+
+/// `Ring`, which is `Array(Point)`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Ring(pub Vec<Point>);
+
+impl Ring {
+    pub fn new(points: impl IntoIterator<Item = impl Into<Point>>) -> Self {
+        points.into_iter().collect()
+    }
+
+    pub fn points(&self) -> &[Point] {
+        &self.0
+    }
+}
+
+/// `Polygon`, which is `Array(Ring)`: the outer ring followed by holes.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Polygon(pub Vec<Ring>);
+
+impl Polygon {
+    pub fn new(exterior: Ring, holes: impl IntoIterator<Item = Ring>) -> Self {
+        Self(std::iter::once(exterior).chain(holes).collect())
+    }
+
+    pub fn rings(&self) -> &[Ring] {
+        &self.0
+    }
+
+    /// Returns the outer ring, which is absent only in empty polygons.
+    pub fn exterior(&self) -> Option<&Ring> {
+        self.0.first()
+    }
+
+    pub fn holes(&self) -> &[Ring] {
+        self.0.get(1..).unwrap_or_default()
+    }
+}
+
+/// `MultiPolygon`, which is `Array(Polygon)`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MultiPolygon(pub Vec<Polygon>);
+
+impl MultiPolygon {
+    pub fn new(polygons: impl IntoIterator<Item = Polygon>) -> Self {
+        Self(polygons.into_iter().collect())
+    }
+
+    pub fn polygons(&self) -> &[Polygon] {
+        &self.0
+    }
+}
+
+macro_rules! impl_collection {
+    ($ty:ty, $item:ty) => {
+        impl From<Vec<$item>> for $ty {
+            fn from(items: Vec<$item>) -> Self {
+                Self(items)
+            }
+        }
+
+        impl From<$ty> for Vec<$item> {
+            fn from(value: $ty) -> Self {
+                value.0
+            }
+        }
+
+        impl<I: Into<$item>> FromIterator<I> for $ty {
+            fn from_iter<T: IntoIterator<Item = I>>(iter: T) -> Self {
+                Self(iter.into_iter().map(Into::into).collect())
+            }
+        }
+    };
+}
+
+impl_collection!(Ring, Point);
+impl_collection!(Polygon, Ring);
+impl_collection!(MultiPolygon, Polygon);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rowbinary;
+
+    fn square(from: f64, to: f64) -> Ring {
+        Ring::new([(from, from), (from, to), (to, to), (to, from)])
+    }
+
+    #[test]
+    fn it_encodes_as_tuples_and_arrays() {
+        let point = Point::new(1., 2.);
+        let mut actual = Vec::new();
+        rowbinary::serialize_into(&mut actual, &point).unwrap();
+        let mut expected = Vec::new();
+        rowbinary::serialize_into(&mut expected, &(1f64, 2f64)).unwrap();
+        assert_eq!(actual, expected);
+
+        let polygon = Polygon::new(square(0., 10.), [square(4., 6.)]);
+        let mut actual = Vec::new();
+        rowbinary::serialize_into(&mut actual, &polygon).unwrap();
+        let nested: Vec<Vec<(f64, f64)>> = polygon
+            .rings()
+            .iter()
+            .map(|ring| ring.points().iter().map(|&p| p.into()).collect())
+            .collect();
+        let mut expected = Vec::new();
+        rowbinary::serialize_into(&mut expected, &nested).unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn it_roundtrips_multi_polygons() {
+        let value = MultiPolygon::new([
+            Polygon::new(square(0., 10.), [square(4., 6.)]),
+            Polygon::new(square(20., 30.), []),
+            Polygon::default(),
+        ]);
+
+        let mut buffer = Vec::new();
+        rowbinary::serialize_into(&mut buffer, &value).unwrap();
+        let mut input = &buffer[..];
+        let actual: MultiPolygon = rowbinary::deserialize_from(&mut input, &mut []).unwrap();
+        assert_eq!(actual, value);
+        assert!(input.is_empty());
+
+        let polygons = actual.polygons();
+        assert_eq!(polygons[0].holes(), [square(4., 6.)]);
+        assert_eq!(polygons[1].exterior(), Some(&square(20., 30.)));
+        assert!(polygons[1].holes().is_empty());
+        assert_eq!(polygons[2].exterior(), None);
+        assert!(polygons[2].holes().is_empty());
+    }
+}
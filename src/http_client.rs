@@ -1,8 +1,26 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, PoisonError, RwLock,
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::Future;
 use hyper::{
     client::{connect::Connect, ResponseFuture},
-    Body, Request,
+    header::LOCATION,
+    Body, HeaderMap, Request, Response, StatusCode, Uri,
 };
 use sealed::sealed;
+use tokio::sync::Notify;
+use url::Url;
+
+use crate::{
+    auth,
+    error::{Error, Result},
+};
 
 #[sealed]
 pub trait HttpClient: Send + Sync + 'static {
@@ -18,3 +36,209 @@ where
         self.request(req)
     }
 }
+
+/// The HTTP client shared by all clones of [`Client`](crate::Client),
+/// which tracks requests in flight to shut down gracefully.
+pub(crate) struct Transport {
+    client: RwLock<Option<Box<dyn HttpClient>>>,
+    in_flight: AtomicUsize,
+    finished: Notify,
+}
+
+impl Transport {
+    pub(crate) fn new(client: impl HttpClient) -> Self {
+        Self {
+            client: RwLock::new(Some(Box::new(client))),
+            in_flight: AtomicUsize::new(0),
+            finished: Notify::new(),
+        }
+    }
+
+    /// Sends a request, which is in flight until the returned guard is dropped.
+    pub(crate) fn request(
+        self: &Arc<Self>,
+        req: Request<Body>,
+    ) -> Result<(ResponseFuture, InFlight)> {
+        let future = self.send(req)?;
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        Ok((future, InFlight(self.clone())))
+    }
+
+    /// Like [`Transport::request`], but follows up to `max_redirects` redirects,
+    /// resending the same method, headers and body to the `Location`.
+    /// Credentials and `custom` headers are kept only if the redirect stays
+    /// on the same origin.
+    pub(crate) fn request_with_redirects(
+        self: &Arc<Self>,
+        req: Request<Bytes>,
+        max_redirects: usize,
+        custom: Vec<String>,
+    ) -> Result<(impl Future<Output = Result<Response<Body>>>, InFlight)> {
+        let (parts, body) = req.into_parts();
+        let mut url = Url::parse(&parts.uri.to_string())
+            .map_err(|err| Error::InvalidParams(Box::new(err)))?;
+        let (method, mut headers) = (parts.method, parts.headers);
+
+        let replay = move |uri: Uri, headers: &HeaderMap| {
+            let mut req = Request::new(Body::from(body.clone()));
+            *req.method_mut() = method.clone();
+            *req.uri_mut() = uri;
+            *req.headers_mut() = headers.clone();
+            req
+        };
+        let first = self.send(replay(parts.uri, &headers))?;
+
+        let transport = self.clone();
+        let future = async move {
+            let mut response = first.await?;
+
+            for _ in 0..max_redirects {
+                let location = match redirect_location(&response, &url)? {
+                    Some(location) => location,
+                    None => break,
+                };
+
+                if location.origin() != url.origin() {
+                    auth::remove_credentials(&mut headers);
+                    for name in &custom {
+                        headers.remove(name.as_str());
+                    }
+                }
+
+                url = location;
+                let uri = url
+                    .as_str()
+                    .parse::<Uri>()
+                    .map_err(|err| Error::BadResponse(format!("invalid redirect: {err}")))?;
+
+                response = transport.send(replay(uri, &headers))?.await?;
+            }
+
+            Ok(response)
+        };
+
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        Ok((future, InFlight(self.clone())))
+    }
+
+    fn send(&self, req: Request<Body>) -> Result<ResponseFuture> {
+        let client = self.client.read().unwrap_or_else(PoisonError::into_inner);
+        let client = client.as_ref().ok_or(Error::Shutdown)?;
+        Ok(client._request(req))
+    }
+
+    pub(crate) async fn shutdown(&self, timeout: Duration) -> Result<()> {
+        // Connections of requests in flight are owned by them, so dropping the pool
+        // closes only idle connections, others are closed once responses are read.
+        let client = self
+            .client
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        drop(client);
+
+        let wait = async {
+            loop {
+                // Created before checking to not miss a notification.
+                let finished = self.finished.notified();
+                if self.in_flight.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                finished.await;
+            }
+        };
+
+        tokio::time::timeout(timeout, wait)
+            .await
+            .map_err(|_| Error::TimedOut)
+    }
+}
+
+/// Returns the target of a redirect, if `response` is one.
+fn redirect_location(response: &Response<Body>, url: &Url) -> Result<Option<Url>> {
+    let status = response.status();
+    let is_redirect = matches!(
+        status,
+        StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT
+    );
+
+    let location = match response.headers().get(LOCATION) {
+        Some(location) if is_redirect => location,
+        _ => return Ok(None),
+    };
+
+    let location = location
+        .to_str()
+        .map_err(|err| Error::BadResponse(format!("invalid redirect: {err}")))?;
+    let location = url
+        .join(location)
+        .map_err(|err| Error::BadResponse(format!("invalid redirect: {err}")))?;
+    Ok(Some(location))
+}
+
+pub(crate) struct InFlight(Arc<Transport>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.finished.notify_waiters();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-util", feature = "lz4"))]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+    use crate::{
+        test::{handlers, Mock},
+        Client,
+    };
+
+    #[tokio::test]
+    async fn it_fails_after_shutdown() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+        let other = client.clone();
+
+        mock.add(handlers::provide(stream::iter([1u8])));
+        assert_eq!(client.query("SELECT 1").fetch_one::<u8>().await.unwrap(), 1);
+
+        client.shutdown(Duration::from_secs(1)).await.unwrap();
+
+        let err = other.query("SELECT 1").execute().await.unwrap_err();
+        assert!(matches!(err, Error::Shutdown));
+        let err = other.ping().await.unwrap_err();
+        assert!(matches!(err, Error::Shutdown));
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_waits_for_requests_in_flight() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        mock.add(handlers::provide(stream::iter([1u8, 2])));
+        let mut cursor = client.query("SELECT 1").fetch::<u8>().unwrap();
+        assert_eq!(cursor.next().await.unwrap(), Some(1));
+
+        let err = client
+            .clone()
+            .shutdown(Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::TimedOut));
+
+        // The cursor is still readable after the timeout.
+        assert_eq!(cursor.next().await.unwrap(), Some(2));
+
+        let shutdown = tokio::spawn(client.shutdown(Duration::from_secs(1)));
+        tokio::task::yield_now().await;
+        drop(cursor);
+        shutdown.await.unwrap().unwrap();
+    }
+}
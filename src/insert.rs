@@ -1,24 +1,39 @@
-use crate::join_column_names_insert;
 use core::marker::PhantomData;
-use std::{future::Future, mem, panic, pin::Pin, time::Duration};
+use std::{
+    future::Future,
+    mem, panic,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::Duration,
+};
 
 use bytes::{Bytes, BytesMut};
+use futures::{FutureExt, Stream, StreamExt, TryStreamExt};
 use hyper::{self, body, Body, Request};
 use serde::Serialize;
+use serde_json::Value;
 use tokio::{
-    task::JoinHandle,
+    sync::mpsc,
+    task::{JoinError, JoinHandle},
     time::{Instant, Sleep},
 };
 use url::Url;
 
 use crate::{
+    auth,
     error::{Error, Result},
-    response::Response,
-    rowbinary, Client, Compression, InsertRow,
+    row::{
+        column_count_insert, join_column_names, join_column_names_insert, join_names, DbRow,
+        InsertRow,
+    },
+    rowbinary,
+    trace::RequestSpan,
+    Client, Compression,
 };
 
-use std::fmt::Debug;
-
 const BUFFER_SIZE: usize = 128 * 1024;
 const MIN_CHUNK_SIZE: usize = BUFFER_SIZE - 1024; // slightly less to avoid extra reallocations
 
@@ -29,18 +44,32 @@ const MIN_CHUNK_SIZE: usize = BUFFER_SIZE - 1024; // slightly less to avoid extr
 pub struct Insert<T: InsertRow + Serialize> {
     client: Client,
     table: String,
+    columns: Option<Vec<String>>,
+    format: &'static str,
     buffer: BytesMut,
     #[cfg(feature = "wa-37420")]
     chunk_count: usize,
     sender: Option<body::Sender>,
     #[cfg(feature = "lz4")]
     compression: Compression,
+    #[cfg(feature = "gzip")]
+    gzip: Option<flate2::write::GzEncoder<Vec<u8>>>,
     send_timeout: Option<Duration>,
     end_timeout: Option<Duration>,
+    flush_interval: Option<Duration>,
+    // `Some` instead of `sender` and `handle` if `flush_interval` is set.
+    flusher: Option<Flusher>,
     // Use boxed `Sleep` to reuse a timer entry, it improves performance.
     // Also, `tokio::time::timeout()` significantly increases a future's size.
     sleep: Pin<Box<Sleep>>,
     handle: Option<JoinHandle<Result<()>>>,
+    // `Some` with all sent chunks if retries are enabled, see `with_retries()`.
+    sent: Option<Vec<Bytes>>,
+    retries: usize,
+    // Fields and whether any part of the body is taken by the connection,
+    // which are required only for retries.
+    fields: String,
+    consumed: Option<Arc<AtomicBool>>,
     _marker: PhantomData<fn() -> T>, // TODO: test contravariance.
 }
 
@@ -63,20 +92,29 @@ where
     T: InsertRow + Serialize,
 {
     pub(crate) fn new(client: Client, table: String) -> Result<Self> {
-        let compression = client.compression;
         Ok(Self {
+            #[cfg(feature = "lz4")]
+            compression: client.compression,
             client,
             table,
+            columns: None,
+            format: "RowBinary",
             buffer: BytesMut::with_capacity(BUFFER_SIZE),
             #[cfg(feature = "wa-37420")]
             chunk_count: 0,
             sender: None,
-            #[cfg(feature = "lz4")]
-            compression,
+            #[cfg(feature = "gzip")]
+            gzip: None,
             send_timeout: None,
             end_timeout: None,
+            flush_interval: None,
+            flusher: None,
             sleep: Box::pin(tokio::time::sleep(Duration::new(0, 0))),
             handle: None,
+            sent: None,
+            retries: 0,
+            fields: String::new(),
+            consumed: None,
             _marker: Default::default(),
         })
     }
@@ -104,6 +142,128 @@ where
         self
     }
 
+    /// Sends buffered rows once `interval` elapses since the last sending, even if
+    /// no rows are written meanwhile, e.g. for streams with rare rows. Otherwise,
+    /// rows are buffered until there are enough of them for a chunk (128 KiB),
+    /// so they can be delayed until the next writes or [`Insert::end`].
+    ///
+    /// A separate task is spawned for sending, which never sends an empty chunk
+    /// on a timer tick. Note that the server commits rows only at the end of `INSERT`
+    /// (or once `max_insert_block_size` rows are received), so it limits only
+    /// how long rows are held by the client.
+    pub fn with_max_flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = Some(interval);
+        self
+    }
+
+    /// Sets columns to insert into instead of field names of `T`, e.g. to insert
+    /// a narrow row into a wide table, filling other columns with defaults.
+    ///
+    /// Fields are matched to columns by position, so they must be declared
+    /// in the same order. The number of columns must be equal to the number
+    /// of fields (`Nested` fields count as many as their subfields), otherwise
+    /// the first [`Insert::write`] fails with [`Error::InvalidParams`].
+    ///
+    /// # Examples
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// #[derive(clickhouse::Row, serde::Serialize)]
+    /// struct Narrow {
+    ///     id: u64,
+    ///     name: String,
+    /// }
+    ///
+    /// # let client = clickhouse::Client::default();
+    /// // INSERT INTO wide(`user_id`,`user_name`) FORMAT RowBinary
+    /// let mut insert = client
+    ///     .insert::<Narrow>("wide")?
+    ///     .with_columns(&["user_id", "user_name"]);
+    /// insert.write(&Narrow { id: 1, name: "foo".into() }).await?;
+    /// insert.end().await?;
+    /// # Ok(()) }
+    /// ```
+    pub fn with_columns(mut self, columns: &[&str]) -> Self {
+        self.columns = Some(columns.iter().map(|&column| column.into()).collect());
+        self
+    }
+
+    /// Similar to [`Client::with_option`], but for this `INSERT` only.
+    pub fn with_option(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.client.options.insert(name.into(), value.into());
+        self
+    }
+
+    /// Waits until data is written to `n` replicas before acknowledging the `INSERT`
+    /// (the `insert_quorum` setting). `0` disables the quorum, it's a default.
+    ///
+    /// It protects acknowledged data from being lost along with a single replica,
+    /// but latency grows to that of the slowest replica in the quorum, and the `INSERT`
+    /// fails if the quorum isn't reached in time. Only `Replicated*` tables are affected.
+    pub fn with_insert_quorum(self, n: u64) -> Self {
+        self.with_option("insert_quorum", n.to_string())
+    }
+
+    /// The time to reach the quorum, in milliseconds (the `insert_quorum_timeout` setting).
+    /// If it expires, the `INSERT` fails, however, data can still be written to some replicas.
+    pub fn with_insert_quorum_timeout(self, timeout: Duration) -> Self {
+        self.with_option("insert_quorum_timeout", timeout.as_millis().to_string())
+    }
+
+    /// If enabled, quorum `INSERT`s can be performed in parallel (the `insert_quorum_parallel`
+    /// setting), otherwise, they're serialized, which makes `select_sequential_consistency`
+    /// reads possible but limits throughput.
+    pub fn with_insert_quorum_parallel(self, enabled: bool) -> Self {
+        self.with_option("insert_quorum_parallel", if enabled { "1" } else { "0" })
+    }
+
+    /// Sets the `insert_deduplication_token` setting, so retrying the `INSERT` with
+    /// the same token is ignored if the previous attempt is already committed,
+    /// e.g. to make at-least-once delivery of batches idempotent.
+    ///
+    /// Deduplication works for `Replicated*` tables and for `MergeTree` ones with
+    /// the `non_replicated_deduplication_window` setting. The token is checked
+    /// per inserted block, so the same batch must be sent with the same token.
+    ///
+    /// For async inserts (see [`Insert::with_async`]), data is deduplicated only
+    /// if the `async_insert_deduplicate` setting is enabled. Otherwise, the token
+    /// is ignored and retries produce duplicates.
+    pub fn with_deduplication_token(self, token: impl Into<String>) -> Self {
+        self.with_option("insert_deduplication_token", token)
+    }
+
+    /// Makes the `INSERT` asynchronous (the `async_insert` setting): the server
+    /// buffers rows of many small `INSERT`s and writes them together, which produces
+    /// far fewer parts than writing each of them.
+    ///
+    /// If `wait` is `true` (the `wait_for_async_insert` setting), [`Insert::end`]
+    /// returns after the buffer is flushed to the table, so errors are reported
+    /// and rows are queryable right after it, but it takes up to
+    /// `async_insert_busy_timeout_ms`.
+    ///
+    /// Otherwise, [`Insert::end`] returns as soon as rows are buffered by the server,
+    /// which is faster, but rows aren't queryable immediately, and rows are lost
+    /// if flushing fails or the server stops before it, with no error reported.
+    pub fn with_async(self, wait: bool) -> Self {
+        self.with_option("async_insert", "1")
+            .with_option("wait_for_async_insert", if wait { "1" } else { "0" })
+    }
+
+    /// Retries the `INSERT` up to `retries` times on transient failures: network errors
+    /// and `502`, `503` or `504` pages of a load balancer ([`Error::UnexpectedResponse`]).
+    /// The connection is established again and the entire body is resent with the same settings,
+    /// including the token of [`Insert::with_deduplication_token`].
+    ///
+    /// It's done only if no part of the body has been taken by the connection,
+    /// e.g. if connecting fails. Otherwise, the server could have inserted some blocks
+    /// already, so [`Error::Unretryable`] is returned instead of duplicating them.
+    ///
+    /// Note that all sent chunks are kept in memory until [`Insert::end`] to be resent.
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self.sent = (retries > 0).then(Vec::new);
+        self
+    }
+
     pub(crate) fn set_timeouts(
         &mut self,
         send_timeout: Option<Duration>,
@@ -114,47 +274,121 @@ where
     }
 
     fn init_client(&mut self, row: &T) -> Result<()> {
+        let fields = match &self.columns {
+            Some(columns) => {
+                let field_count = column_count_insert(row);
+                if field_count != columns.len() {
+                    return Err(Error::InvalidParams(
+                        format!(
+                            "{} columns are specified, but the row has {field_count} fields",
+                            columns.len()
+                        )
+                        .into(),
+                    ));
+                }
+
+                join_names(columns)
+            }
+            None => join_column_names_insert(row)
+                .expect("the row type must be a struct or a wrapper around it"),
+        };
+
+        self.init(&fields)
+    }
+
+    fn init(&mut self, fields: &str) -> Result<()> {
+        let Some(interval) = self.flush_interval else {
+            return self.init_request(fields);
+        };
+
+        // Rows are sent by the same code, but in the task.
+        let mut insert = Insert::<Vec<u8>>::new(self.client.clone(), self.table.clone())?;
+        insert.format = self.format;
+        insert.set_timeouts(self.send_timeout, self.end_timeout);
+        insert = insert.with_retries(self.retries);
+        insert.init_request(fields)?;
+
+        // Rows are shared with the task, which is notified only once there are enough
+        // of them for a chunk. Only one notification is buffered, so sending still
+        // slows down writing.
+        let rows = Arc::new(Mutex::new(BytesMut::with_capacity(BUFFER_SIZE)));
+        let (tx, rx) = mpsc::channel(1);
+        let handle = tokio::spawn(run_flusher(insert, rows.clone(), rx, interval));
+        self.flusher = Some(Flusher { rows, tx, handle });
+        Ok(())
+    }
+
+    fn init_request(&mut self, fields: &str) -> Result<()> {
         let mut url =
             Url::parse(&self.client.url).map_err(|err| Error::InvalidParams(err.into()))?;
         let mut pairs = url.query_pairs_mut();
         pairs.clear();
 
-        if let Some(database) = &self.client.database {
-            pairs.append_pair("database", &database);
+        if let Some(database) = self.client.database.as_ref().filter(|db| !db.is_empty()) {
+            pairs.append_pair("database", database);
         }
-        let fields = join_column_names_insert(row)
-            .expect("the row type must be a struct or a wrapper around it");
 
         // TODO: what about escaping a table name?
         // https://clickhouse.yandex/docs/en/query_language/syntax/#syntax-identifiers
-        let query = format!("INSERT INTO {}({fields}) FORMAT RowBinary", self.table);
+        // Without fields, all columns are inserted or, for JSON, the named ones.
+        let query = if fields.is_empty() {
+            format!("INSERT INTO {} FORMAT {}", self.table, self.format)
+        } else {
+            format!(
+                "INSERT INTO {}({fields}) FORMAT {}",
+                self.table, self.format
+            )
+        };
         pairs.append_pair("query", &query);
 
         if self.client.compression.is_lz4() {
             pairs.append_pair("decompress", "1");
         }
 
+        for (name, value) in &self.client.options {
+            pairs.append_pair(name, value);
+        }
+
         drop(pairs);
 
         let mut builder = Request::post(url.as_str());
 
-        if let Some(user) = &self.client.user {
-            builder = builder.header("X-ClickHouse-User", user);
-        }
+        builder = auth::authorize(builder, &self.client);
 
-        if let Some(password) = &self.client.password {
-            builder = builder.header("X-ClickHouse-Key", password);
+        // LZ4 frames are hardly compressible, so gzip is used only instead of it.
+        #[cfg(feature = "gzip")]
+        if self.client.gzip_requests && !self.client.compression.is_lz4() {
+            builder = builder.header(hyper::header::CONTENT_ENCODING, "gzip");
+            // Retries resend chunks of the same gzip stream, so it's kept.
+            if self.gzip.is_none() {
+                self.gzip = Some(flate2::write::GzEncoder::new(
+                    Vec::new(),
+                    flate2::Compression::default(),
+                ));
+            }
         }
 
-        let (sender, body) = Body::channel();
+        // Rows are streamed while the total size is unknown, so no `Content-Length`
+        // is set and the body is sent with `Transfer-Encoding: chunked`.
+        let (sender, mut body) = Body::channel();
+
+        if self.sent.is_some() {
+            self.fields = fields.into();
+
+            let consumed = Arc::new(AtomicBool::new(false));
+            let flag = consumed.clone();
+            body = Body::wrap_stream(body.inspect_ok(move |_| flag.store(true, Ordering::Relaxed)));
+            self.consumed = Some(consumed);
+        }
 
         let request = builder
             .body(body)
             .map_err(|err| Error::InvalidParams(Box::new(err)))?;
 
-        let future = self.client.client._request(request);
-        let handle =
-            tokio::spawn(async move { Response::new(future, Compression::None).finish().await });
+        let mut response = self
+            .client
+            .send(request, Compression::None, RequestSpan::none())?;
+        let handle = tokio::spawn(async move { response.finish().await });
 
         self.handle = Some(handle);
         self.sender = Some(sender);
@@ -166,40 +400,126 @@ where
     /// # Panics
     /// If called after previous call returned an error.
     pub fn write<'a>(&'a mut self, row: &T) -> impl Future<Output = Result<()>> + 'a + Send {
-        if self.handle.is_none() {
-            self.init_client(row).expect("failed to start client");
-        }
-        assert!(self.sender.is_some(), "write() after error");
-        //let str = serde_json::to_string(row).unwrap();
-        //println!("\n\n\n\nDES: {:?}\n\n\n\n", str);
-        let result = rowbinary::serialize_into(&mut self.buffer, row);
-        if result.is_err() {
-            self.abort();
-        }
+        let result = if self.handle.is_none() && self.flusher.is_none() {
+            self.init_client(row)
+        } else {
+            Ok(())
+        };
+
+        let result = result.and_then(|()| {
+            assert!(
+                self.sender.is_some() || self.flusher.is_some(),
+                "write() after error"
+            );
+            //let str = serde_json::to_string(row).unwrap();
+            //println!("\n\n\n\nDES: {:?}\n\n\n\n", str);
+            let result = rowbinary::serialize_into(&mut self.buffer, row);
+            if result.is_err() {
+                self.abort();
+            }
+            result
+        });
 
         async move {
             result?;
-            if self.buffer.len() >= MIN_CHUNK_SIZE {
-                self.send_chunk().await?;
+            if self.flusher.is_some() {
+                self.hand_off().await?;
+            } else if self.buffer.len() >= MIN_CHUNK_SIZE {
+                self.send_chunk(false).await?;
             }
             Ok(())
         }
     }
 
+    /// Writes all rows of `rows` and ends `INSERT`, e.g. to bridge a channel
+    /// or a consumer of a message broker into the table.
+    ///
+    /// Only one chunk is buffered at a time: the stream isn't polled while
+    /// the chunk is being sent, so slow inserting slows down consuming.
+    /// If the server rejects rows in the middle, the rest of the stream isn't
+    /// consumed and the error is returned. It's usually [`Error::ServerException`],
+    /// but can be [`Error::Network`] if the server closes the connection first.
+    ///
+    /// # Examples
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// # #[derive(clickhouse::Row, serde::Serialize)]
+    /// # struct MyRow { no: u32 }
+    /// # let client = clickhouse::Client::default();
+    /// let (tx, rx) = futures::channel::mpsc::channel::<MyRow>(1024);
+    /// // ... pass `tx` to producers ...
+    /// # drop(tx);
+    /// client.insert::<MyRow>("some")?.stream(rx).await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn stream(mut self, rows: impl Stream<Item = T>) -> Result<()> {
+        futures::pin_mut!(rows);
+
+        while let Some(row) = rows.next().await {
+            self.write(&row).await?;
+        }
+
+        self.end().await
+    }
+
+    /// Writes rows already encoded in RowBinary, e.g. fetched by another query.
+    /// `bytes` aren't required to contain whole rows.
+    pub(crate) async fn write_raw(&mut self, bytes: &[u8]) -> Result<()>
+    where
+        T: DbRow,
+    {
+        if self.handle.is_none() && self.flusher.is_none() {
+            let fields = match &self.columns {
+                Some(columns) => join_names(columns),
+                None => join_column_names::<T>()
+                    .expect("the row type must be a struct or a wrapper around it"),
+            };
+            self.init(&fields)?;
+        }
+
+        self.write_bytes(bytes).await
+    }
+
+    async fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.buffer.extend_from_slice(bytes);
+        if self.flusher.is_some() {
+            self.hand_off().await?;
+        } else if self.buffer.len() >= MIN_CHUNK_SIZE {
+            self.send_chunk(false).await?;
+        }
+        Ok(())
+    }
+
     /// Ends `INSERT`.
     /// Succeeds if the server returns 200.
     ///
     /// If it isn't called, the whole `INSERT` is aborted.
     pub async fn end(mut self) -> Result<()> {
-        if !self.buffer.is_empty() {
-            self.send_chunk().await?;
+        if self.flusher.is_some() {
+            self.send_to_flusher(Message::End).await?;
+            let flusher = self.flusher.take().unwrap(); // checked above
+            return unwrap_join_result(flusher.handle.await);
+        }
+
+        // The gzip stream must be finished even if there are no buffered rows.
+        #[cfg(feature = "gzip")]
+        let is_gzipped = self.gzip.is_some();
+        #[cfg(not(feature = "gzip"))]
+        let is_gzipped = false;
+
+        if !self.buffer.is_empty() || is_gzipped {
+            self.send_chunk(true).await?;
         }
 
         self.sender = None; // terminate the sender successfully
-        self.wait_handle().await
+
+        match self.wait_handle().await {
+            Err(err) if self.sent.is_some() => self.resend(err, true).await,
+            res => res,
+        }
     }
 
-    async fn send_chunk(&mut self) -> Result<()> {
+    async fn send_chunk(&mut self, is_last: bool) -> Result<()> {
         if self.sender.is_none() {
             return Ok(());
         }
@@ -212,6 +532,86 @@ where
         // It's difficult to determine when allocations occur.
         // So, instead we control it manually here and rely on the system allocator.
         let chunk = self.take_and_prepare_chunk()?;
+        #[cfg(feature = "gzip")]
+        let chunk = self.gzip_chunk(chunk, is_last)?;
+        #[cfg(not(feature = "gzip"))]
+        let _ = is_last;
+
+        if let Some(sent) = &mut self.sent {
+            sent.push(chunk.clone());
+        }
+
+        match self.send_data(chunk).await {
+            Err(err) if self.sent.is_some() => self.resend(err, false).await,
+            res => res,
+        }
+    }
+
+    /// Resends all chunks sent so far by a new request, if `err` is transient and
+    /// no part of the body is taken by the connection. Also ends the body if `ended`.
+    async fn resend(&mut self, mut err: Error, ended: bool) -> Result<()> {
+        loop {
+            let is_transient = matches!(
+                err,
+                Error::Network(_)
+                    | Error::UnexpectedResponse {
+                        status: 502..=504,
+                        ..
+                    }
+            );
+
+            if !is_transient {
+                return Err(err);
+            }
+
+            if self
+                .consumed
+                .as_ref()
+                .map_or(false, |c| c.load(Ordering::Relaxed))
+            {
+                return Err(Error::Unretryable(Box::new(err)));
+            }
+
+            if self.retries == 0 {
+                return Err(err);
+            }
+
+            self.retries -= 1;
+            self.abort();
+            self.handle = None;
+
+            let fields = mem::take(&mut self.fields);
+            self.init_request(&fields)?;
+
+            let mut result = Ok(());
+            for chunk in self.sent.clone().unwrap_or_default() {
+                result = self.send_data(chunk).await;
+                if result.is_err() {
+                    break;
+                }
+            }
+
+            if result.is_ok() && ended {
+                self.sender = None;
+                result = self.wait_handle().await;
+            }
+
+            match result {
+                Ok(()) => return Ok(()),
+                Err(next) => err = next,
+            }
+        }
+    }
+
+    async fn send_data(&mut self, chunk: Bytes) -> Result<()> {
+        // The server responds before the end of the body only on errors (e.g. rejected
+        // rows), so stop sending data that is going to be discarded anyway.
+        if let Some(res) = self.handle.as_mut().and_then(|h| h.now_or_never()) {
+            self.handle = None;
+            self.abort();
+            unwrap_join_result(res)?;
+            return Err(Error::Network("channel closed".into()));
+        }
 
         let sender = self.sender.as_mut().unwrap(); // checked above
 
@@ -236,15 +636,43 @@ where
         }
     }
 
+    /// Moves written rows to the task, notifying it if there are enough for a chunk.
+    async fn hand_off(&mut self) -> Result<()> {
+        let flusher = self.flusher.as_ref().unwrap(); // checked by callers
+
+        let is_full = {
+            let mut rows = flusher.rows.lock().unwrap_or_else(PoisonError::into_inner);
+            rows.extend_from_slice(&self.buffer);
+            rows.len() >= MIN_CHUNK_SIZE
+        };
+        self.buffer.clear();
+
+        if is_full {
+            self.send_to_flusher(Message::Full).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_to_flusher(&mut self, message: Message) -> Result<()> {
+        let flusher = self.flusher.as_mut().unwrap(); // checked by callers
+
+        if flusher.tx.send(message).await.is_ok() {
+            return Ok(());
+        }
+
+        // The task is finished only on errors before `end()`.
+        let flusher = self.flusher.take().unwrap();
+        unwrap_join_result(flusher.handle.await)?;
+        Err(Error::Network("channel closed".into()))
+    }
+
     async fn wait_handle(&mut self) -> Result<()> {
         let Some(handle) = self.handle.as_mut() else {
             return Ok(());
         };
 
         match timeout!(self, end_timeout, handle) {
-            Some(Ok(res)) => res,
-            Some(Err(err)) if err.is_panic() => panic::resume_unwind(err.into_panic()),
-            Some(Err(err)) => Err(Error::Custom(format!("unexpected error: {err}"))),
+            Some(res) => unwrap_join_result(res),
             None => {
                 // We can do nothing useful here, so just shut down the background task.
                 self.handle.as_ref().unwrap().abort();
@@ -260,8 +688,7 @@ where
             self.buffer.clear();
             compressed
         } else {
-            let b = mem::replace(&mut self.buffer, BytesMut::with_capacity(BUFFER_SIZE)).freeze();
-            b
+            mem::replace(&mut self.buffer, BytesMut::with_capacity(BUFFER_SIZE)).freeze()
         })
     }
 
@@ -270,6 +697,29 @@ where
         Ok(mem::replace(&mut self.buffer, BytesMut::with_capacity(BUFFER_SIZE)).freeze())
     }
 
+    // Every chunk is flushed to be sent immediately, the last one also finishes the stream.
+    #[cfg(feature = "gzip")]
+    fn gzip_chunk(&mut self, chunk: Bytes, is_last: bool) -> Result<Bytes> {
+        use std::io::Write;
+
+        let Some(encoder) = &mut self.gzip else {
+            return Ok(chunk);
+        };
+
+        encoder
+            .write_all(&chunk)
+            .and_then(|_| {
+                if is_last {
+                    encoder.try_finish()
+                } else {
+                    encoder.flush()
+                }
+            })
+            .map_err(|err| Error::Compression(Box::new(err)))?;
+
+        Ok(mem::take(encoder.get_mut()).into())
+    }
+
     #[cfg(feature = "wa-37420")]
     fn prepend_bom(&mut self) {
         if self.chunk_count == 0 && self.buffer.starts_with(&[0xef, 0xbb, 0xbf]) {
@@ -286,6 +736,114 @@ where
         if let Some(sender) = self.sender.take() {
             sender.abort();
         }
+
+        // The task aborts its `INSERT` once the channel is closed.
+        self.flusher = None;
+    }
+}
+
+struct Flusher {
+    // Written rows, which aren't taken by the task yet.
+    rows: Arc<Mutex<BytesMut>>,
+    tx: mpsc::Sender<Message>,
+    handle: JoinHandle<Result<()>>,
+}
+
+enum Message {
+    // There are enough rows for a chunk.
+    Full,
+    End,
+}
+
+async fn run_flusher(
+    mut insert: Insert<Vec<u8>>,
+    rows: Arc<Mutex<BytesMut>>,
+    mut rx: mpsc::Receiver<Message>,
+    interval: Duration,
+) -> Result<()> {
+    // The buffer of `insert` is empty here, so rows are taken without copying.
+    let take_rows = |insert: &mut Insert<Vec<u8>>| {
+        debug_assert!(insert.buffer.is_empty());
+        let mut rows = rows.lock().unwrap_or_else(PoisonError::into_inner);
+        mem::swap(&mut insert.buffer, &mut rows);
+    };
+
+    let mut deadline = Instant::now() + interval;
+
+    loop {
+        let should_send = tokio::select! {
+            message = rx.recv() => match message {
+                // Rows could be taken on a tick after the notification.
+                Some(Message::Full) => {
+                    take_rows(&mut insert);
+                    !insert.buffer.is_empty()
+                }
+                Some(Message::End) => {
+                    take_rows(&mut insert);
+                    return insert.end().await;
+                }
+                // `Insert` is dropped without `end()`, so this one is aborted too.
+                None => return Ok(()),
+            },
+            _ = tokio::time::sleep_until(deadline) => {
+                take_rows(&mut insert);
+
+                // Empty chunks are never sent, hyper could end the body with them.
+                if insert.buffer.is_empty() {
+                    deadline = Instant::now() + interval;
+                }
+                !insert.buffer.is_empty()
+            }
+        };
+
+        if should_send {
+            insert.send_chunk(false).await?;
+            deadline = Instant::now() + interval;
+        }
+    }
+}
+
+/// Inserts `rows` as `JSONEachRow`, see [`Client::insert_json`].
+/// Rows are buffered and sent exactly as by [`Insert`], only encoded differently.
+pub(crate) async fn insert_json(
+    client: &Client,
+    table: String,
+    rows: impl Stream<Item = Value>,
+) -> Result<()> {
+    futures::pin_mut!(rows);
+
+    // The row type is unused, because rows are written already encoded.
+    let mut insert = Insert::<Vec<u8>>::new(client.clone(), table)?;
+    insert.format = "JSONEachRow";
+
+    let mut line = Vec::new();
+    while let Some(row) = rows.next().await {
+        // Checked before the row is written, so the request isn't even started
+        // if the first row is invalid. Otherwise, dropping `insert` aborts it.
+        if !row.is_object() {
+            let reason = format!("JSONEachRow rows must be objects, got `{row}`");
+            return Err(Error::InvalidParams(reason.into()));
+        }
+
+        // Compact, so each row takes exactly one line.
+        line.clear();
+        serde_json::to_writer(&mut line, &row).map_err(|err| Error::Custom(err.to_string()))?;
+        line.push(b'\n');
+
+        if insert.handle.is_none() && insert.flusher.is_none() {
+            insert.init("")?;
+        }
+        insert.write_bytes(&line).await?;
+    }
+
+    insert.end().await
+}
+
+fn unwrap_join_result(res: Result<Result<()>, JoinError>) -> Result<()> {
+    match res {
+        Ok(res) => res,
+        Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+        Err(err) => Err(Error::Custom(format!("unexpected error: {err}"))),
     }
 }
 
@@ -294,3 +852,400 @@ impl<T: InsertRow + Serialize> Drop for Insert<T> {
         self.abort();
     }
 }
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::test::{handlers, Mock};
+
+    use clickhouse::Row;
+
+    #[derive(Debug, PartialEq, Row, Serialize, Deserialize)]
+    struct Narrow {
+        id: u32,
+        name: String,
+    }
+
+    #[tokio::test]
+    async fn it_inserts_into_specified_columns() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+        let recording = mock.add(handlers::record());
+
+        let mut insert = client
+            .insert::<Narrow>("wide")
+            .unwrap()
+            .with_columns(&["user_id", "user`name"]);
+        let row = Narrow {
+            id: 1,
+            name: "foo".into(),
+        };
+        insert.write(&row).await.unwrap();
+        insert.end().await.unwrap();
+
+        assert_eq!(recording.collect::<Vec<Narrow>>().await, [row]);
+        assert_eq!(
+            mock.requests()[0].sql(),
+            "INSERT INTO wide(`user_id`,`user``name`) FORMAT RowBinary"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_inserts_json() {
+        use futures::stream;
+        use serde_json::json;
+
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::None);
+
+        let rows = [
+            json!({"id": 1, "name": "multi\nline"}),
+            json!({"id": 2, "tags": ["a", "b"]}),
+            json!({}),
+        ];
+        mock.add(handlers::provide(stream::iter(Vec::<u8>::new())));
+        client
+            .insert_json("some", stream::iter(rows.clone()))
+            .await
+            .unwrap();
+
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(request.sql(), "INSERT INTO some FORMAT JSONEachRow");
+        let body = String::from_utf8(request.body.to_vec()).unwrap();
+        assert_eq!(
+            body,
+            "{\"id\":1,\"name\":\"multi\\nline\"}\n{\"id\":2,\"tags\":[\"a\",\"b\"]}\n{}\n"
+        );
+        let parsed = body
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(parsed, rows);
+
+        // Nothing is sent if the first row is invalid.
+        let rows = stream::iter([json!(42), json!({"id": 1})]);
+        let err = client.insert_json("some", rows).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::InvalidParams(reason) if reason.to_string().contains("`42`")),
+            "{:?}",
+            err
+        );
+        assert!(mock.requests().is_empty());
+    }
+
+    // Passes the request as it's received, without responding.
+    async fn serve_receiving() -> (String, mpsc::UnboundedReceiver<Vec<u8>>) {
+        use tokio::{io::AsyncReadExt, net::TcpListener};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 4096];
+            while let Ok(len @ 1..) = socket.read(&mut buf).await {
+                let _ = tx.send(buf[..len].to_vec());
+            }
+        });
+
+        (format!("http://{addr}"), rx)
+    }
+
+    #[tokio::test]
+    async fn it_flushes_on_interval() {
+        const INTERVAL: Duration = Duration::from_millis(100);
+
+        let (url, mut received) = serve_receiving().await;
+        let client = Client::default()
+            .with_url(url)
+            .with_compression(Compression::None);
+
+        let row = Narrow {
+            id: 1,
+            name: "foo".into(),
+        };
+        let mut encoded = Vec::new();
+        rowbinary::serialize_into(&mut encoded, &row).unwrap();
+
+        let mut insert = client
+            .insert::<Narrow>("some")
+            .unwrap()
+            .with_max_flush_interval(INTERVAL);
+        let start = Instant::now();
+        insert.write(&row).await.unwrap();
+
+        // The row is sent without `end()`.
+        let mut request = Vec::new();
+        while !request.windows(encoded.len()).any(|w| w == encoded) {
+            let chunk = tokio::time::timeout(INTERVAL * 10, received.recv());
+            request.extend(chunk.await.unwrap().unwrap());
+        }
+        assert!(start.elapsed() >= INTERVAL, "{:?}", start.elapsed());
+
+        // Empty chunks aren't sent on next ticks, one would end the body.
+        tokio::time::sleep(INTERVAL * 3).await;
+        assert!(received.try_recv().is_err());
+
+        // Next rows are sent after the interval as well.
+        insert.write(&row).await.unwrap();
+        let chunk = tokio::time::timeout(INTERVAL * 10, received.recv());
+        assert!(!chunk.await.unwrap().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_sends_full_chunks_before_flush_interval() {
+        let (url, mut received) = serve_receiving().await;
+        let client = Client::default()
+            .with_url(url)
+            .with_compression(Compression::None);
+
+        let mut insert = client
+            .insert::<Narrow>("some")
+            .unwrap()
+            .with_max_flush_interval(Duration::from_secs(60));
+        let row = Narrow {
+            id: 1,
+            name: "x".repeat(1000),
+        };
+        for _ in 0..MIN_CHUNK_SIZE / 1000 + 1 {
+            insert.write(&row).await.unwrap();
+        }
+
+        // Rows are accumulated by the writer, but sent once there are enough of them.
+        let mut size = 0;
+        while size < MIN_CHUNK_SIZE {
+            let chunk = tokio::time::timeout(Duration::from_secs(10), received.recv());
+            size += chunk.await.unwrap().unwrap().len();
+        }
+    }
+
+    #[tokio::test]
+    async fn it_ends_with_flush_interval() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+        let recording = mock.add(handlers::record());
+
+        let mut insert = client
+            .insert::<Narrow>("some")
+            .unwrap()
+            .with_max_flush_interval(Duration::from_secs(60));
+        let rows = (0..3)
+            .map(|id| Narrow {
+                id,
+                name: "foo".into(),
+            })
+            .collect::<Vec<_>>();
+        for row in &rows {
+            insert.write(row).await.unwrap();
+        }
+        insert.end().await.unwrap();
+
+        assert_eq!(recording.collect::<Vec<Narrow>>().await, rows);
+        assert_eq!(
+            mock.requests()[0].sql(),
+            "INSERT INTO some(`id`,`name`) FORMAT RowBinary"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_checks_the_number_of_columns() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        let row = Narrow {
+            id: 1,
+            name: "foo".into(),
+        };
+
+        for columns in [&["id"][..], &["id", "name", "extra"]] {
+            let mut insert = client
+                .insert::<Narrow>("wide")
+                .unwrap()
+                .with_columns(columns);
+            let err = insert.write(&row).await.unwrap_err();
+            assert!(matches!(err, Error::InvalidParams(_)));
+        }
+
+        assert!(mock.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_sends_deduplication_tokens() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+        let recording = mock.add(handlers::record());
+
+        let mut insert = client
+            .insert::<Narrow>("some")
+            .unwrap()
+            .with_deduplication_token("batch-1");
+        let row = Narrow {
+            id: 1,
+            name: "foo".into(),
+        };
+        insert.write(&row).await.unwrap();
+        insert.end().await.unwrap();
+
+        assert_eq!(recording.collect::<Vec<Narrow>>().await, [row]);
+        assert_eq!(
+            mock.requests()[0]
+                .param("insert_deduplication_token")
+                .as_deref(),
+            Some("batch-1")
+        );
+    }
+
+    #[tokio::test]
+    async fn it_sends_async_settings() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        for (wait, expected) in [(true, "1"), (false, "0")] {
+            let recording = mock.add(handlers::record());
+            let mut insert = client.insert::<Narrow>("some").unwrap().with_async(wait);
+            let row = Narrow {
+                id: 1,
+                name: "foo".into(),
+            };
+            insert.write(&row).await.unwrap();
+            insert.end().await.unwrap();
+            assert_eq!(recording.collect::<Vec<Narrow>>().await, [row]);
+
+            let request = mock.requests().pop().unwrap();
+            assert_eq!(request.param("async_insert").as_deref(), Some("1"));
+            let wait = request.param("wait_for_async_insert");
+            assert_eq!(wait.as_deref(), Some(expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_retries_failed_connects() {
+        use std::{
+            error::Error as StdError,
+            sync::atomic::AtomicUsize,
+            task::{Context, Poll},
+        };
+
+        use hyper::{client::HttpConnector, service::Service, Uri};
+
+        // Fails to connect the specified number of times.
+        #[derive(Clone)]
+        struct FlakyConnector {
+            inner: HttpConnector,
+            failures: Arc<AtomicUsize>,
+        }
+
+        type BoxError = Box<dyn StdError + Send + Sync>;
+
+        impl Service<Uri> for FlakyConnector {
+            type Response = <HttpConnector as Service<Uri>>::Response;
+            type Error = BoxError;
+            type Future =
+                Pin<Box<dyn Future<Output = std::result::Result<Self::Response, BoxError>> + Send>>;
+
+            fn poll_ready(
+                &mut self,
+                cx: &mut Context<'_>,
+            ) -> Poll<std::result::Result<(), BoxError>> {
+                self.inner.poll_ready(cx).map_err(Into::into)
+            }
+
+            fn call(&mut self, uri: Uri) -> Self::Future {
+                let fail = self
+                    .failures
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                    .is_ok();
+
+                let future = self.inner.call(uri);
+                Box::pin(async move {
+                    if fail {
+                        return Err("connection refused".into());
+                    }
+                    future.await.map_err(Into::into)
+                })
+            }
+        }
+
+        let client = |mock: &Mock, failures| {
+            let connector = FlakyConnector {
+                inner: HttpConnector::new(),
+                failures: Arc::new(AtomicUsize::new(failures)),
+            };
+            Client::with_http_client(hyper::Client::builder().build(connector)).with_url(mock.url())
+        };
+
+        let rows = (0..3)
+            .map(|id| Narrow {
+                id,
+                name: format!("row {id}"),
+            })
+            .collect::<Vec<_>>();
+
+        let mock = Mock::new();
+        let recording = mock.add(handlers::record());
+        let mut insert = client(&mock, 1)
+            .insert::<Narrow>("some")
+            .unwrap()
+            .with_deduplication_token("batch-1")
+            .with_retries(2);
+        for row in &rows {
+            insert.write(row).await.unwrap();
+        }
+        insert.end().await.unwrap();
+
+        // Exactly one copy of the batch.
+        assert_eq!(recording.collect::<Vec<Narrow>>().await, rows);
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].param("insert_deduplication_token").as_deref(),
+            Some("batch-1")
+        );
+
+        // Retries are exhausted.
+        let mut insert = client(&mock, 2)
+            .insert::<Narrow>("some")
+            .unwrap()
+            .with_retries(1);
+        insert.write(&rows[0]).await.unwrap();
+        let err = insert.end().await.unwrap_err();
+        assert!(matches!(err, Error::Network(_)), "{:?}", err);
+        assert!(mock.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_refuses_to_retry_after_sending_bodies() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        // A page of a proxy, which is ended by the mock only once the body is read.
+        mock.add(handlers::respond(|_req| {
+            hyper::Response::builder()
+                .status(crate::test::status::BAD_GATEWAY)
+                .header("Content-Type", "text/html")
+                .body("<html>502 Bad Gateway</html>".into())
+                .unwrap()
+        }));
+        let mut insert = client.insert::<Narrow>("some").unwrap().with_retries(3);
+        let row = Narrow {
+            id: 1,
+            name: "foo".into(),
+        };
+        insert.write(&row).await.unwrap();
+        let err = insert.end().await.unwrap_err();
+        assert!(
+            matches!(&err, Error::Unretryable(err) if matches!(
+                **err,
+                Error::UnexpectedResponse { status: 502, .. }
+            )),
+            "{:?}",
+            err
+        );
+        assert_eq!(mock.requests().len(), 1);
+    }
+}
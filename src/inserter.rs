@@ -75,6 +75,27 @@ where
         self
     }
 
+    /// See [`Insert::with_option()`], applied to all `INSERT`s.
+    pub fn with_option(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.client.options.insert(name.into(), value.into());
+        self
+    }
+
+    /// See [`Insert::with_insert_quorum()`].
+    pub fn with_insert_quorum(self, n: u64) -> Self {
+        self.with_option("insert_quorum", n.to_string())
+    }
+
+    /// See [`Insert::with_insert_quorum_timeout()`].
+    pub fn with_insert_quorum_timeout(self, timeout: Duration) -> Self {
+        self.with_option("insert_quorum_timeout", timeout.as_millis().to_string())
+    }
+
+    /// See [`Insert::with_insert_quorum_parallel()`].
+    pub fn with_insert_quorum_parallel(self, enabled: bool) -> Self {
+        self.with_option("insert_quorum_parallel", if enabled { "1" } else { "0" })
+    }
+
     /// The maximum number of rows in one `INSERT` statement.
     ///
     /// Note: ClickHouse inserts batches atomically only if all rows fit in the same partition
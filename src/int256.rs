@@ -0,0 +1,145 @@
+//! `UInt256` and `Int256` types.
+//!
+//! `(U)Int128` columns map to `u128` and `i128` directly, but Rust has no wider
+//! integers, so these wrappers hold 32 bytes in little-endian order, exactly as
+//! they are encoded in RowBinary:
+//!
+//! ```
+//! # use clickhouse::{int256::{I256, U256}, Row};
+//! # use serde::{Deserialize, Serialize};
+//! // CREATE TABLE balances (account UInt256, delta Int256) ...
+//! #[derive(Row, Serialize, Deserialize)]
+//! struct Balance {
+//!     account: U256,
+//!     delta: I256,
+//! }
+//!
+//! let balance = Balance {
+//!     account: U256::from(u128::MAX),
+//!     delta: I256::from(-42),
+//! };
+//! assert_eq!(balance.account.to_string(), "340282366920938463463374607431768211455");
+//! assert_eq!(balance.delta.to_string(), "-42");
+//! ```
+//!
+//! Arithmetic isn't provided, use bytes to convert them to types of other crates.
+
+use std::{convert::TryInto, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{row::Primitive, rowbinary::int256_to_string};
+
+macro_rules! impl_int256 {
+    ($(#[$attr:meta])* $ty:ident, $signed:expr) => {
+        $(#[$attr])*
+        #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        pub struct $ty(pub [u8; 32]);
+
+        impl $ty {
+            pub const fn from_le_bytes(bytes: [u8; 32]) -> Self {
+                Self(bytes)
+            }
+
+            pub const fn to_le_bytes(self) -> [u8; 32] {
+                self.0
+            }
+
+            fn limbs(&self) -> [u64; 4] {
+                let mut limbs = [0; 4];
+                for (limb, bytes) in limbs.iter_mut().zip(self.0.chunks_exact(8)) {
+                    *limb = u64::from_le_bytes(bytes.try_into().unwrap());
+                }
+                limbs
+            }
+        }
+
+        impl From<[u8; 32]> for $ty {
+            fn from(bytes: [u8; 32]) -> Self {
+                Self(bytes)
+            }
+        }
+
+        impl From<$ty> for [u8; 32] {
+            fn from(value: $ty) -> Self {
+                value.0
+            }
+        }
+
+        impl Primitive for $ty {}
+
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&int256_to_string(self.limbs(), $signed))
+            }
+        }
+    };
+}
+
+impl_int256!(
+    /// `UInt256`, which is 32 bytes in little-endian order.
+    U256,
+    false
+);
+
+impl_int256!(
+    /// `Int256`, which is 32 bytes in little-endian order, using two's complement.
+    I256,
+    true
+);
+
+impl From<u128> for U256 {
+    fn from(value: u128) -> Self {
+        let mut bytes = [0; 32];
+        bytes[..16].copy_from_slice(&value.to_le_bytes());
+        Self(bytes)
+    }
+}
+
+impl From<i128> for I256 {
+    fn from(value: i128) -> Self {
+        // Extend the sign for negative values.
+        let mut bytes = if value < 0 { [0xff; 32] } else { [0; 32] };
+        bytes[..16].copy_from_slice(&value.to_le_bytes());
+        Self(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rowbinary;
+
+    #[test]
+    fn it_encodes_in_little_endian() {
+        // 2^128 + 0x0102
+        let mut bytes = [0; 32];
+        bytes[0] = 0x02;
+        bytes[1] = 0x01;
+        bytes[16] = 0x01;
+        let value = U256::from_le_bytes(bytes);
+        assert_eq!(value.to_string(), "340282366920938463463374607431768211714");
+
+        let mut buffer = Vec::new();
+        rowbinary::serialize_into(&mut buffer, &(value, 42u8)).unwrap();
+        assert_eq!(buffer.len(), 33);
+        assert_eq!(buffer[..32], bytes);
+
+        let actual: (U256, u8) = rowbinary::deserialize_from(&buffer[..], &mut []).unwrap();
+        assert_eq!(actual, (value, 42));
+    }
+
+    #[test]
+    fn it_extends_signs() {
+        assert_eq!(I256::from(-1).0, [0xff; 32]);
+        assert_eq!(I256::from(-1).to_string(), "-1");
+        assert_eq!(I256::from(i128::MIN).to_string(), i128::MIN.to_string());
+        assert_eq!(I256::from(i128::MAX).to_string(), i128::MAX.to_string());
+        assert_eq!(U256::from(u128::MAX).to_string(), u128::MAX.to_string());
+        assert_eq!(U256::from(u128::MAX).0[16..], [0; 16]);
+        assert_eq!(
+            U256([0xff; 32]).to_string(),
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935"
+        );
+    }
+}
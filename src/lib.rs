@@ -5,23 +5,40 @@
 
 #[macro_use]
 extern crate static_assertions;
+// Derives emit absolute `::clickhouse::` paths, which must resolve in tests of the crate.
+#[cfg(test)]
+extern crate self as clickhouse;
 
-use ::serde::Serialize;
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use ::serde::{Deserialize, Serialize};
+use std::{collections::HashMap, ffi::OsString, sync::Arc, time::Duration};
 
-use hyper::client::connect::HttpConnector;
+use bytes::Bytes;
+use futures::{stream, Stream, StreamExt};
+use hyper::{
+    client::connect::HttpConnector,
+    header::{HeaderName, HeaderValue},
+    HeaderMap,
+};
 #[cfg(feature = "tls")]
 use hyper_tls::HttpsConnector;
 
-pub use clickhouse_derive::Row;
+pub use clickhouse_derive::{Enum16, Enum8, Row};
 
-pub use self::{compression::Compression, row::*};
-use self::{error::Result, http_client::HttpClient};
+pub use self::{auth::AuthScheme, compression::Compression, row::*, version::ServerVersion};
+use self::{
+    error::Result,
+    http_client::{HttpClient, Transport},
+    response::{BodyOptions, Response},
+};
 
+pub mod aggregate_state;
+pub mod enum_labels;
 pub mod error;
 pub mod fixed_string;
+pub mod geo;
 pub mod insert;
 pub mod inserter;
+pub mod int256;
 pub mod query;
 pub mod serde;
 pub mod sql;
@@ -37,15 +54,23 @@ pub mod uuid {
     pub use crate::serde::uuid::*;
 }
 
+mod auth;
 mod buflist;
 mod compression;
 mod cursor;
+mod generate;
 mod http_client;
+mod nested;
+mod ping;
 pub mod remote_cursor;
 mod response;
 pub mod row;
 mod rowbinary;
 mod ticks;
+mod trace;
+#[cfg(all(unix, feature = "unix"))]
+mod unix;
+mod version;
 
 const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
 
@@ -57,14 +82,24 @@ const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(2);
 /// Can be created by using `Client::default()` or [`Client::with_http_client`].
 #[derive(Clone)]
 pub struct Client {
-    client: Arc<dyn HttpClient>,
+    client: Arc<Transport>,
 
     url: String,
     database: Option<String>,
     user: Option<String>,
     password: Option<String>,
+    auth_scheme: AuthScheme,
     compression: Compression,
+    compression_threshold: u64,
+    #[cfg(feature = "gzip")]
+    gzip_requests: bool,
     options: HashMap<String, String>,
+    // Names are lowercased, because headers are case-insensitive.
+    headers: HashMap<String, String>,
+    max_result_bytes: Option<u64>,
+    verify_checksums: bool,
+    max_redirects: usize,
+    settings_in_query: bool,
 }
 
 impl Default for Client {
@@ -94,6 +129,7 @@ impl Client {
             database: self.database.clone(),
             user: self.user.clone(),
             password: self.password.clone(),
+            auth_scheme: self.auth_scheme,
             compression: self.compression,
             options: self.options.clone(),
         }
@@ -103,16 +139,48 @@ impl Client {
     /// Now only [`hyper::Client`] is supported.
     pub fn with_http_client(client: impl HttpClient) -> Self {
         Self {
-            client: Arc::new(client),
+            client: Arc::new(Transport::new(client)),
             url: String::new(),
             database: None,
             user: None,
             password: None,
+            auth_scheme: AuthScheme::default(),
             compression: Compression::default(),
+            compression_threshold: 0,
+            #[cfg(feature = "gzip")]
+            gzip_requests: false,
             options: HashMap::new(),
+            headers: HashMap::new(),
+            max_result_bytes: None,
+            verify_checksums: true,
+            max_redirects: 0,
+            settings_in_query: false,
         }
     }
 
+    /// Creates a client sending HTTP requests over the Unix domain socket at `path`,
+    /// e.g. of a proxy running next to the app, instead of TCP connections.
+    ///
+    /// `base_url` is used as the URL of requests, see [`Client::with_url`],
+    /// but only its path and authority matter: the latter is sent as `Host`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use clickhouse::Client;
+    /// let client = Client::with_unix_socket("/run/clickhouse/http.sock", "http://localhost");
+    /// ```
+    #[cfg(all(unix, feature = "unix"))]
+    pub fn with_unix_socket(
+        path: impl Into<std::path::PathBuf>,
+        base_url: impl Into<String>,
+    ) -> Self {
+        let client = hyper::Client::builder()
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .build(unix::UnixConnector::new(path));
+
+        Self::with_http_client(client).with_url(base_url)
+    }
+
     /// Specifies ClickHouse's url. Should point to HTTP endpoint.
     ///
     /// # Examples
@@ -137,6 +205,23 @@ impl Client {
         self
     }
 
+    /// Returns a copy of the client with another database, like
+    /// `client.clone().with_database(..)`, to route workloads to databases.
+    ///
+    /// It's cheap: the HTTP pool is shared by all copies, so connections
+    /// are reused rather than a new pool is created.
+    ///
+    /// # Examples
+    /// ```
+    /// # use clickhouse::Client;
+    /// let client = Client::default().with_url("http://localhost:8123");
+    /// let metrics = client.clone_with_database("metrics");
+    /// let logs = client.clone_with_database("logs");
+    /// ```
+    pub fn clone_with_database(&self, database: impl Into<String>) -> Self {
+        self.clone().with_database(database)
+    }
+
     /// Specifies a user.
     ///
     /// # Examples
@@ -161,6 +246,23 @@ impl Client {
         self
     }
 
+    /// Specifies how credentials are sent. See [`AuthScheme`] for details.
+    /// By default, `X-ClickHouse-User` and `X-ClickHouse-Key` headers are used.
+    ///
+    /// # Examples
+    /// ```
+    /// # use clickhouse::{AuthScheme, Client};
+    /// # #[cfg(feature = "basic-auth")]
+    /// let client = Client::default()
+    ///     .with_user("test")
+    ///     .with_password("secret")
+    ///     .with_auth_scheme(AuthScheme::Basic);
+    /// ```
+    pub fn with_auth_scheme(mut self, scheme: AuthScheme) -> Self {
+        self.auth_scheme = scheme;
+        self
+    }
+
     /// Specifies a compression mode. See [`Compression`] for details.
     /// By default, `Lz4` is used.
     ///
@@ -175,7 +277,74 @@ impl Client {
         self
     }
 
-    /// Used to specify options that will be passed to all queries.
+    /// Disables compression of responses to queries that are expected to return
+    /// less than `rows` rows, because LZ4 framing only adds overhead to tiny responses.
+    ///
+    /// The size of a result isn't known in advance, so it's estimated by the outermost
+    /// `LIMIT n` (or `LIMIT m, n`) clause: the response isn't compressed if `n < rows`.
+    /// Queries without such a clause, e.g. with `LIMIT n BY` only or `UNION`s,
+    /// are always compressed. Doesn't affect `INSERT`s.
+    ///
+    /// `0` disables the heuristic, it's a default.
+    ///
+    /// # Examples
+    /// ```
+    /// # use clickhouse::Client;
+    /// let client = Client::default().with_compression_threshold(100);
+    /// ```
+    pub fn with_compression_threshold(mut self, rows: u64) -> Self {
+        self.compression_threshold = rows;
+        self
+    }
+
+    /// Enables gzip compression of request bodies, i.e. long queries and `INSERT`s,
+    /// marked by the `Content-Encoding: gzip` header.
+    ///
+    /// It's independent of [`Client::with_compression`], which compresses responses.
+    /// However, `INSERT`s already compressed by LZ4 aren't compressed again.
+    /// Queries sent by GET (read-only ones shorter than 8KiB) have no body,
+    /// and tiny bodies aren't compressed as well.
+    ///
+    /// # Examples
+    /// ```
+    /// # use clickhouse::Client;
+    /// let client = Client::default().with_gzip_requests(true);
+    /// ```
+    #[cfg(feature = "gzip")]
+    pub fn with_gzip_requests(mut self, enabled: bool) -> Self {
+        self.gzip_requests = enabled;
+        self
+    }
+
+    /// Binds all queries and inserts to the HTTP session `id`, which is created if
+    /// it doesn't exist, and lives `timeout` (rounded to seconds) after the last request.
+    /// It allows using a state shared between requests, e.g. temporary tables.
+    ///
+    /// Sessions exist only on one server, hence it shouldn't be used behind
+    /// a round-robin load balancer. Also, ClickHouse rejects concurrent requests
+    /// in the same session.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::time::Duration;
+    /// # use clickhouse::Client;
+    /// let client = Client::default().with_session("my-session", Duration::from_secs(60));
+    /// ```
+    pub fn with_session(self, id: impl Into<String>, timeout: Duration) -> Self {
+        self.with_option("session_id", id)
+            .with_option("session_timeout", timeout.as_secs().to_string())
+    }
+
+    /// Sets the default `quota_key` of all queries and inserts, which ClickHouse uses
+    /// to track quotas keyed by `client_key`, e.g. per tenant of a shared user.
+    /// It can be overridden by [`Query::with_quota_key`](query::Query::with_quota_key).
+    ///
+    /// It's sent as any other option, so `with_option("quota_key", ..)` is the same.
+    pub fn with_quota_key(self, key: impl Into<String>) -> Self {
+        self.with_option("quota_key", key)
+    }
+
+    /// Used to specify options that will be passed to all queries and inserts.
     ///
     /// # Example
     /// ```
@@ -187,20 +356,188 @@ impl Client {
         self
     }
 
+    /// Adds a header to all requests, e.g. required by a gateway in front of ClickHouse.
+    /// Headers with the same name (case-insensitively) are replaced.
+    ///
+    /// Headers set by the client, e.g. `Content-Length` or credentials, can be
+    /// overridden too. Invalid names and values are reported by requests
+    /// as [`Error::InvalidParams`](error::Error::InvalidParams).
+    ///
+    /// # Example
+    /// ```
+    /// # use clickhouse::Client;
+    /// Client::default()
+    ///     .with_header("X-Api-Token", "secret")
+    ///     .with_header("X-Tenant-Id", "42");
+    /// ```
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into().to_ascii_lowercase();
+        self.headers.insert(name, value.into());
+        self
+    }
+
+    /// Limits the size of every response, after decompression, to `limit` bytes.
+    /// Reading a bigger result fails with [`Error::ResultTooLarge`](error::Error::ResultTooLarge)
+    /// as soon as the limit is exceeded, e.g. to prevent `fetch_all()` from
+    /// allocating without bound because of a buggy query.
+    ///
+    /// Responses aren't limited by default.
+    ///
+    /// # Examples
+    /// ```
+    /// # use clickhouse::Client;
+    /// let client = Client::default().with_max_result_bytes(100 * 1024 * 1024);
+    /// ```
+    pub fn with_max_result_bytes(mut self, limit: u64) -> Self {
+        self.max_result_bytes = Some(limit);
+        self
+    }
+
+    /// Enables or disables verification of checksums of LZ4 blocks in responses.
+    /// A corrupted block, e.g. by a flaky proxy, fails with
+    /// [`Error::ChecksumMismatch`](error::Error::ChecksumMismatch), instead of
+    /// being decoded into garbage rows.
+    ///
+    /// Checksums are verified by default, disabling saves CPU only.
+    ///
+    /// # Examples
+    /// ```
+    /// # use clickhouse::Client;
+    /// let client = Client::default().with_checksum_verification(false);
+    /// ```
+    pub fn with_checksum_verification(mut self, enabled: bool) -> Self {
+        self.verify_checksums = enabled;
+        self
+    }
+
+    /// Follows up to `max` redirects (`301`, `302`, `307` and `308`) of read-only
+    /// queries, e.g. issued by load balancers in front of replicas.
+    /// The same request, including its method and body, is sent to `Location`,
+    /// but credentials and headers added by [`Client::with_header`] are dropped
+    /// if the redirect leads to another origin (scheme, host and port).
+    /// Responses beyond the limit fail as bad ones.
+    ///
+    /// Only fetching queries are redirected: inserts and
+    /// [`Query::execute()`](query::Query::execute) can have side effects,
+    /// so they aren't resent. Redirects aren't followed by default.
+    ///
+    /// # Examples
+    /// ```
+    /// # use clickhouse::Client;
+    /// let client = Client::default().with_redirects(3);
+    /// ```
+    pub fn with_redirects(mut self, max: usize) -> Self {
+        self.max_redirects = max;
+        self
+    }
+
+    /// Sends settings among options of fetching queries in the `SETTINGS` clause
+    /// appended to the query instead of URL params, e.g. if many or large settings
+    /// exceed limits of proxies on the URL length. The query is sent in the body
+    /// if it's long, see [`Query::with_method`](query::Query::with_method).
+    ///
+    /// Other options, e.g. `session_id`, `query_id` or `param_*`, aren't settings,
+    /// so they're still sent in the URL. So are settings of the HTTP interface
+    /// itself, e.g. `enable_http_compression` or `http_*`, which are ignored
+    /// in the clause. Inserts and [`Query::execute()`](query::Query::execute)
+    /// aren't affected, because the clause can't be appended to any statement.
+    /// Disabled by default.
+    ///
+    /// # Examples
+    /// ```
+    /// # use clickhouse::Client;
+    /// let client = Client::default()
+    ///     .with_option("log_comment", "a very long comment")
+    ///     .with_settings_in_query(true);
+    /// ```
+    pub fn with_settings_in_query(mut self, enabled: bool) -> Self {
+        self.settings_in_query = enabled;
+        self
+    }
+
+    /// Applies options from environment variables named `{prefix}{OPTION}`,
+    /// where the rest of a name is lowercased to get the option's name.
+    /// Values are passed as is, the server is responsible for parsing them.
+    /// Variables with non-unicode names or values are ignored.
+    ///
+    /// For instance, with the `CLICKHOUSE_SETTING_` prefix,
+    /// `CLICKHOUSE_SETTING_MAX_THREADS=8` is the same as `with_option("max_threads", "8")`.
+    ///
+    /// # Example
+    /// ```
+    /// # use clickhouse::Client;
+    /// let client = Client::default().with_settings_from_env("CLICKHOUSE_SETTING_");
+    /// ```
+    pub fn with_settings_from_env(self, prefix: &str) -> Self {
+        self.with_settings_from_vars(prefix, std::env::vars_os())
+    }
+
+    fn with_settings_from_vars(
+        mut self,
+        prefix: &str,
+        vars: impl IntoIterator<Item = (OsString, OsString)>,
+    ) -> Self {
+        for (name, value) in vars {
+            let (Some(name), Some(value)) = (name.to_str(), value.to_str()) else {
+                continue;
+            };
+
+            match name.strip_prefix(prefix) {
+                Some(option) if !option.is_empty() => {
+                    self.options.insert(option.to_lowercase(), value.into());
+                }
+                _ => {}
+            }
+        }
+
+        self
+    }
+
     /// Starts a new INSERT statement.
     ///
     /// # Panics
     /// If `T` has unnamed fields, e.g. tuples.
-    pub fn insert<T: InsertRow + Serialize>(&self, table: String) -> Result<insert::Insert<T>> {
-        insert::Insert::new(self.clone(), table)
+    pub fn insert<T: InsertRow + Serialize>(
+        &self,
+        table: impl Into<String>,
+    ) -> Result<insert::Insert<T>> {
+        insert::Insert::new(self.clone(), table.into())
+    }
+
+    /// Inserts `rows` into `table` as `JSONEachRow`, e.g. to ingest data that is
+    /// already JSON without declaring a [`Row`] for it. Each row must be an object,
+    /// which keys are column names; omitted columns get their default values.
+    ///
+    /// Rows are streamed and flushed by size as by [`Client::insert`], so
+    /// neither the whole stream nor its length are required ahead.
+    /// A non-object row fails the call before it's sent and aborts the `INSERT`,
+    /// so no rows of the stream are inserted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// use futures::stream;
+    /// use serde_json::json;
+    ///
+    /// let rows = stream::iter([json!({"no": 1, "name": "foo"}), json!({"no": 2})]);
+    /// clickhouse::Client::default().insert_json("some", rows).await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn insert_json(
+        &self,
+        table: impl Into<String>,
+        rows: impl Stream<Item = serde_json::Value>,
+    ) -> Result<()> {
+        insert::insert_json(self, table.into(), rows).await
     }
 
     /// Creates an inserter to perform multiple INSERTs.
     pub fn inserter<T: InsertRow + Serialize>(
         &self,
-        table: String,
+        table: impl Into<String>,
     ) -> Result<inserter::Inserter<T>> {
-        inserter::Inserter::new(self, table)
+        inserter::Inserter::new(self, table.into())
     }
 
     /// Starts a new SELECT/DDL query.
@@ -208,6 +545,291 @@ impl Client {
         query::Query::new(self, query)
     }
 
+    /// Creates a template to start many queries, see [`PreparedQuery`](query::PreparedQuery).
+    pub fn prepare(&self, template: &str) -> query::PreparedQuery {
+        query::PreparedQuery::new(self, template)
+    }
+
+    /// Returns the number of rows of a SELECT query,
+    /// see [`Query::fetch_row_count`](query::Query::fetch_row_count).
+    /// Use the latter to bind arguments.
+    pub async fn query_row_count(&self, query: &str) -> Result<u64> {
+        self.query(query).fetch_row_count().await
+    }
+
+    /// Generates `count` synthetic rows of `T` from `system.numbers`.
+    ///
+    /// Every field is filled with the row number converted to the field's type,
+    /// e.g. `toUInt32(number)` for `u32` or `toString(number)` for `String`,
+    /// so the stream is deterministic. `Option<_>` fields are never `NULL`,
+    /// sequences contain exactly one element. Enums and maps aren't supported.
+    ///
+    /// It's useful for load testing of code consuming rows without preparing any data.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// #[derive(clickhouse::Row, serde::Deserialize)]
+    /// struct MyRow {
+    ///     no: u32,
+    ///     name: String,
+    /// }
+    ///
+    /// let mut cursor = clickhouse::Client::default().generate::<MyRow>(1_000_000)?;
+    ///
+    /// while let Some(MyRow { no, name }) = cursor.next().await? {
+    ///     assert_eq!(no.to_string(), name);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn generate<T>(&self, count: u64) -> Result<query::RowCursor<T>>
+    where
+        T: DbRow + for<'b> Deserialize<'b>,
+    {
+        query::Query::literal(self, generate::select::<T>(count)?).fetch()
+    }
+
+    /// Executes independent statements concurrently, at most 8 at a time.
+    ///
+    /// Returns results in the same order as `statements`. With [`OnError::Stop`],
+    /// statements after the first failed one aren't started and have no results,
+    /// so the returned `Vec` can be shorter. However, some of them can already be
+    /// in progress at that moment, so don't rely on the order of execution.
+    ///
+    /// Unlike multi-statement scripts, statements don't share a session, unless
+    /// [`Client::with_session`] is used, but then ClickHouse rejects concurrent requests.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// use clickhouse::query::OnError;
+    ///
+    /// let results = clickhouse::Client::default()
+    ///     .execute_batch(
+    ///         &[
+    ///             "CREATE TABLE a(no UInt32) ENGINE = MergeTree ORDER BY no",
+    ///             "CREATE TABLE b(no UInt32) ENGINE = MergeTree ORDER BY no",
+    ///         ],
+    ///         OnError::Stop,
+    ///     )
+    ///     .await;
+    ///
+    /// for result in results {
+    ///     result?;
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub async fn execute_batch(
+        &self,
+        statements: &[&str],
+        on_error: query::OnError,
+    ) -> Vec<Result<()>> {
+        self.execute_many(statements, on_error, query::BATCH_CONCURRENCY)
+            .await
+    }
+
+    /// Executes statements one by one, e.g. `CREATE TABLE`s followed by `ALTER`s
+    /// to bootstrap a schema.
+    ///
+    /// Returns results in the same order as `statements`, so the index of a failed
+    /// statement is the index of its result. With [`OnError::Stop`], the remaining
+    /// statements aren't executed after the first failure, and the failed one
+    /// has the last result.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// use clickhouse::query::OnError;
+    ///
+    /// let results = clickhouse::Client::default()
+    ///     .execute_all(
+    ///         &[
+    ///             "CREATE TABLE a(no UInt32) ENGINE = MergeTree ORDER BY no",
+    ///             "ALTER TABLE a ADD COLUMN name String",
+    ///         ],
+    ///         OnError::Stop,
+    ///     )
+    ///     .await;
+    ///
+    /// for (index, result) in results.into_iter().enumerate() {
+    ///     if let Err(err) = result {
+    ///         eprintln!("statement #{index} failed: {err}");
+    ///         return Err(err);
+    ///     }
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub async fn execute_all(
+        &self,
+        statements: &[&str],
+        on_error: query::OnError,
+    ) -> Vec<Result<()>> {
+        self.execute_many(statements, on_error, 1).await
+    }
+
+    async fn execute_many(
+        &self,
+        statements: &[&str],
+        on_error: query::OnError,
+        concurrency: usize,
+    ) -> Vec<Result<()>> {
+        // Futures are lazy, so requests are sent only when `buffered()` polls them.
+        let mut stream = stream::iter(statements)
+            .map(|sql| self.query(sql).execute())
+            .buffered(concurrency);
+
+        let mut results = Vec::with_capacity(statements.len());
+        while let Some(result) = stream.next().await {
+            let is_err = result.is_err();
+            results.push(result);
+
+            if is_err && on_error == query::OnError::Stop {
+                break;
+            }
+        }
+
+        results
+    }
+
+    /// Checks that the server is reachable and healthy by `GET /ping`,
+    /// e.g. before adding a node to the rotation.
+    ///
+    /// The URL, credentials and the HTTP client are reused, but the database
+    /// and options aren't sent, so the database isn't required to exist.
+    /// Returns an error if the server doesn't respond with `Ok.`.
+    ///
+    /// # Examples
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// # let client = clickhouse::Client::default();
+    /// client.ping().await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn ping(&self) -> Result<()> {
+        ping::ping(self).await
+    }
+
+    /// Returns the version of the server by `SELECT version()`,
+    /// e.g. to enable features depending on it.
+    ///
+    /// # Examples
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// # let client = clickhouse::Client::default();
+    /// if client.server_version().await? >= (23, 1, 0, 0) {
+    ///     // ...
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub async fn server_version(&self) -> Result<ServerVersion> {
+        let version = self.query("SELECT version()").fetch_one::<String>().await?;
+        version.parse()
+    }
+
+    /// Cancels a running query by `KILL QUERY`, e.g. with the id set by
+    /// [`Query::with_query_id`](query::Query::with_query_id).
+    /// Returns whether such a query was found.
+    ///
+    /// Unlike dropping a cursor, which only closes the connection, it stops work
+    /// on the server. However, the query is only marked to be cancelled
+    /// and can finish a bit later.
+    ///
+    /// # Examples
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// # let client = clickhouse::Client::default();
+    /// let found = client.kill_query("my-query-id").await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn kill_query(&self, query_id: &str) -> Result<bool> {
+        self.query("KILL QUERY WHERE query_id = {query_id:String}")
+            .kill(query_id)
+            .await
+    }
+
+    /// Shuts down the client: closes idle pooled connections and waits for
+    /// requests in flight, e.g. unfinished cursors and inserts, to complete.
+    /// Returns [`Error::TimedOut`](error::Error::TimedOut) if they aren't
+    /// completed within `timeout`, but they aren't aborted in that case.
+    ///
+    /// All clones of the client share the pool, so they become unusable:
+    /// new requests fail with [`Error::Shutdown`](error::Error::Shutdown).
+    ///
+    /// # Examples
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// # use std::time::Duration;
+    /// # let client = clickhouse::Client::default();
+    /// client.shutdown(Duration::from_secs(10)).await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn shutdown(self, timeout: Duration) -> Result<()> {
+        self.client.shutdown(timeout).await
+    }
+
+    pub(crate) fn send(
+        &self,
+        mut request: hyper::Request<hyper::Body>,
+        compression: Compression,
+        span: trace::RequestSpan,
+    ) -> Result<Response> {
+        self.add_headers(request.headers_mut())?;
+        let (future, in_flight) = self.client.request(request)?;
+        Ok(Response::new(
+            future,
+            compression,
+            self.body_options(),
+            Some(in_flight),
+            span,
+        ))
+    }
+
+    /// Like [`Client::send`], but follows redirects if they're enabled.
+    pub(crate) fn send_replayable(
+        &self,
+        mut request: hyper::Request<Bytes>,
+        compression: Compression,
+        span: trace::RequestSpan,
+    ) -> Result<Response> {
+        if self.max_redirects == 0 {
+            return self.send(request.map(hyper::Body::from), compression, span);
+        }
+
+        self.add_headers(request.headers_mut())?;
+        let custom = self.headers.keys().cloned().collect();
+        let (future, in_flight) =
+            self.client
+                .request_with_redirects(request, self.max_redirects, custom)?;
+        Ok(Response::new(
+            future,
+            compression,
+            self.body_options(),
+            Some(in_flight),
+            span,
+        ))
+    }
+
+    fn add_headers(&self, headers: &mut HeaderMap) -> Result<()> {
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_lowercase(name.as_bytes())
+                .map_err(|err| error::Error::InvalidParams(Box::new(err)))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|err| error::Error::InvalidParams(Box::new(err)))?;
+            headers.insert(name, value);
+        }
+        Ok(())
+    }
+
+    fn body_options(&self) -> BodyOptions {
+        BodyOptions {
+            max_bytes: self.max_result_bytes,
+            verify_checksums: self.verify_checksums,
+        }
+    }
+
     /// Starts a new WATCH query.
     #[cfg(feature = "watch")]
     pub fn watch(&self, query: &str) -> watch::Watch {
@@ -216,11 +838,133 @@ impl Client {
 }
 
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct Credentials {
     pub url: String,
     pub database: Option<String>,
     pub user: Option<String>,
     pub password: Option<String>,
+    pub auth_scheme: AuthScheme,
     pub compression: Compression,
     pub options: HashMap<String, String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_settings_from_env() {
+        // The environment isn't touched, because tests run in parallel.
+        let vars = [
+            ("CH_SETTING_MAX_THREADS", "8"),
+            ("CH_SETTING_join_algorithm", "hash,parallel_hash"),
+            ("CH_SETTING_", "ignored"),
+            ("CH_OTHER", "ignored"),
+        ]
+        .map(|(name, value)| (name.into(), value.into()));
+
+        let client = Client::default()
+            .with_option("max_threads", "1")
+            .with_settings_from_vars("CH_SETTING_", vars);
+        let options = client.credentials().options;
+
+        assert_eq!(options.len(), 2);
+        assert_eq!(options["max_threads"], "8");
+        assert_eq!(options["join_algorithm"], "hash,parallel_hash");
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn it_executes_all_sequentially() {
+        use crate::{
+            query::OnError,
+            test::{handlers, status, Mock},
+        };
+
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+        let statements = ["CREATE TABLE a", "ALTER TABLE b", "CREATE TABLE c"];
+
+        mock.add(handlers::record_ddl());
+        mock.add(handlers::failure(status::BAD_REQUEST));
+        let results = client.execute_all(&statements, OnError::Stop).await;
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(error::Error::BadResponse(_))));
+
+        let sqls = mock.requests().iter().map(|r| r.sql()).collect::<Vec<_>>();
+        assert_eq!(sqls, ["CREATE TABLE a", "ALTER TABLE b"]);
+
+        mock.add(handlers::record_ddl());
+        mock.add(handlers::failure(status::BAD_REQUEST));
+        mock.add(handlers::record_ddl());
+        let results = client.execute_all(&statements, OnError::Continue).await;
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(error::Error::BadResponse(_))));
+        assert!(results[2].is_ok());
+
+        let sqls = mock.requests().iter().map(|r| r.sql()).collect::<Vec<_>>();
+        assert_eq!(sqls, statements);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn it_clones_with_databases() {
+        use crate::test::{handlers, Mock};
+
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_option("max_threads", "1");
+        let metrics = client.clone_with_database("metrics");
+        let logs = client.clone_with_database("logs");
+
+        assert!(Arc::ptr_eq(&client.client, &metrics.client));
+        assert!(Arc::ptr_eq(&client.client, &logs.client));
+        assert_eq!(client.database, None);
+
+        mock.add(handlers::record_ddl());
+        mock.add(handlers::record_ddl());
+        metrics.query("SELECT 1").execute().await.unwrap();
+        logs.query("SELECT 1").execute().await.unwrap();
+
+        let requests = mock.requests();
+        let databases = requests
+            .iter()
+            .map(|r| r.param("database").unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(databases, ["metrics", "logs"]);
+        assert!(requests
+            .iter()
+            .all(|r| r.param("max_threads").as_deref() == Some("1")));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn it_fetches_server_versions() {
+        use futures::stream;
+
+        use crate::test::{handlers, Mock};
+
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::None);
+
+        mock.add(handlers::provide(stream::iter([
+            "23.8.11.29.altinitystable",
+        ])));
+        let version = client.server_version().await.unwrap();
+        assert_eq!(version, ServerVersion::new(23, 8, 11, 29));
+        assert_eq!(
+            mock.requests().pop().unwrap().sql(),
+            "SELECT version() FORMAT RowBinary"
+        );
+
+        mock.add(handlers::provide(stream::iter(["unknown"])));
+        let err = client.server_version().await.unwrap_err();
+        assert!(matches!(err, error::Error::BadResponse(_)), "{:?}", err);
+    }
+}
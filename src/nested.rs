@@ -0,0 +1,1124 @@
+//! Helpers behind [`crate::serde::nested`].
+//!
+//! `Nested(a T1, b T2)` columns are flattened by ClickHouse into `n.a Array(T1)`
+//! and `n.b Array(T2)`, so rows are transposed into parallel arrays on the wire.
+//! The transposed value is serialized as a struct named [`MARKER`], which keeps
+//! RowBinary untouched (struct names and keys aren't encoded) and lets
+//! [`column_names`] expand the field into `n.a, n.b` while building `INSERT`.
+//! While deserializing, the parallel arrays are zipped back into items.
+
+use std::{fmt, marker::PhantomData, vec};
+
+use serde::{
+    de::{
+        self, value::SeqDeserializer, DeserializeOwned, DeserializeSeed, Deserializer,
+        IntoDeserializer, SeqAccess, Visitor,
+    },
+    ser::{self, Impossible, Serialize, SerializeSeq, SerializeStruct, Serializer},
+};
+
+use crate::{
+    error::Error,
+    row::{DbRow, InsertRow},
+};
+
+pub(crate) const MARKER: &str = "__ClickHouseNested";
+
+// === Serialization ===
+
+/// Serializes the `idx`-th field of every item as an array.
+pub(crate) struct Column<'a, T> {
+    pub(crate) items: &'a [T],
+    pub(crate) idx: usize,
+}
+
+impl<T: Serialize> Serialize for Column<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.items.len()))?;
+        for item in self.items {
+            seq.serialize_element(&Field {
+                item,
+                idx: self.idx,
+            })?;
+        }
+        seq.end()
+    }
+}
+
+struct Field<'a, T> {
+    item: &'a T,
+    idx: usize,
+}
+
+impl<T: Serialize> Serialize for Field<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.item.serialize(FieldPicker {
+            inner: serializer,
+            idx: self.idx,
+        })
+    }
+}
+
+/// Passes only the `idx`-th field of a struct to the inner serializer.
+struct FieldPicker<S> {
+    inner: S,
+    idx: usize,
+}
+
+struct PickedField<S: Serializer> {
+    inner: Option<S>,
+    idx: usize,
+    pos: usize,
+    result: Option<S::Ok>,
+}
+
+fn not_a_struct<E: ser::Error>() -> E {
+    E::custom("items of a nested column must be structs")
+}
+
+macro_rules! reject {
+    ($result:expr; $($method:ident($($ty:ty),*)),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $ty),*) -> Result<Self::Ok, Self::Error> {
+                $result
+            }
+        )*
+    };
+}
+
+macro_rules! reject_compound {
+    ($($method:ident($($ty:ty),*) -> $compound:ident),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $ty),*) -> Result<Self::$compound, Self::Error> {
+                Err(not_a_struct())
+            }
+        )*
+    };
+}
+
+macro_rules! reject_all {
+    ($result:expr) => {
+        reject!($result;
+            serialize_bool(bool),
+            serialize_i8(i8),
+            serialize_i16(i16),
+            serialize_i32(i32),
+            serialize_i64(i64),
+            serialize_i128(i128),
+            serialize_u8(u8),
+            serialize_u16(u16),
+            serialize_u32(u32),
+            serialize_u64(u64),
+            serialize_u128(u128),
+            serialize_f32(f32),
+            serialize_f64(f64),
+            serialize_char(char),
+            serialize_str(&str),
+            serialize_bytes(&[u8]),
+            serialize_none(),
+            serialize_unit(),
+            serialize_unit_struct(&'static str),
+            serialize_unit_variant(&'static str, u32, &'static str),
+        );
+
+        reject_compound!(
+            serialize_seq(Option<usize>) -> SerializeSeq,
+            serialize_tuple(usize) -> SerializeTuple,
+            serialize_tuple_struct(&'static str, usize) -> SerializeTupleStruct,
+            serialize_tuple_variant(&'static str, u32, &'static str, usize) -> SerializeTupleVariant,
+            serialize_map(Option<usize>) -> SerializeMap,
+            serialize_struct_variant(&'static str, u32, &'static str, usize) -> SerializeStructVariant,
+        );
+
+        fn serialize_some<V: Serialize + ?Sized>(self, _: &V) -> Result<Self::Ok, Self::Error> {
+            $result
+        }
+
+        fn serialize_newtype_variant<V: Serialize + ?Sized>(
+            self,
+            _: &'static str,
+            _: u32,
+            _: &'static str,
+            _: &V,
+        ) -> Result<Self::Ok, Self::Error> {
+            $result
+        }
+    };
+}
+
+impl<S: Serializer> Serializer for FieldPicker<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Impossible<S::Ok, S::Error>;
+    type SerializeTuple = Impossible<S::Ok, S::Error>;
+    type SerializeTupleStruct = Impossible<S::Ok, S::Error>;
+    type SerializeTupleVariant = Impossible<S::Ok, S::Error>;
+    type SerializeMap = Impossible<S::Ok, S::Error>;
+    type SerializeStruct = PickedField<S>;
+    type SerializeStructVariant = Impossible<S::Ok, S::Error>;
+
+    reject_all!(Err(not_a_struct()));
+
+    fn serialize_newtype_struct<V: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &V,
+    ) -> Result<S::Ok, S::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<PickedField<S>, S::Error> {
+        Ok(PickedField {
+            inner: Some(self.inner),
+            idx: self.idx,
+            pos: 0,
+            result: None,
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+impl<S: Serializer> SerializeStruct for PickedField<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<V: Serialize + ?Sized>(
+        &mut self,
+        _: &'static str,
+        value: &V,
+    ) -> Result<(), S::Error> {
+        if self.pos == self.idx {
+            if let Some(inner) = self.inner.take() {
+                self.result = Some(value.serialize(inner)?);
+            }
+        }
+
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.result
+            .ok_or_else(|| ser::Error::custom("an item of a nested column has too few fields"))
+    }
+}
+
+/// Returns column names of `row` if it contains fields serialized by
+/// [`crate::serde::nested`], expanding them into `field.subfield`.
+///
+/// Fields are named as in [`DbRow::COLUMN_NAMES`], falling back to serde's names.
+pub(crate) fn column_names<R: InsertRow + Serialize + ?Sized>(row: &R) -> Option<Vec<String>> {
+    row.serialize(TopLevel(row.get_column_names()))
+        .ok()
+        .flatten()
+}
+
+struct TopLevel(&'static [&'static str]);
+
+struct TopLevelStruct {
+    columns: &'static [&'static str],
+    names: Vec<String>,
+    fields: usize,
+    has_nested: bool,
+}
+
+impl Serializer for TopLevel {
+    type Ok = Option<Vec<String>>;
+    type Error = Error;
+    type SerializeSeq = Impossible<Self::Ok, Error>;
+    type SerializeTuple = Impossible<Self::Ok, Error>;
+    type SerializeTupleStruct = Impossible<Self::Ok, Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Error>;
+    type SerializeMap = Impossible<Self::Ok, Error>;
+    type SerializeStruct = TopLevelStruct;
+    type SerializeStructVariant = Impossible<Self::Ok, Error>;
+
+    reject_all!(Ok(None));
+
+    fn serialize_newtype_struct<V: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &V,
+    ) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_struct(self, _: &'static str, len: usize) -> Result<TopLevelStruct, Error> {
+        Ok(TopLevelStruct {
+            columns: self.0,
+            names: Vec::with_capacity(len),
+            fields: 0,
+            has_nested: false,
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl SerializeStruct for TopLevelStruct {
+    type Ok = Option<Vec<String>>;
+    type Error = Error;
+
+    fn serialize_field<V: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &V,
+    ) -> Result<(), Error> {
+        // Skipped fields are absent in both, so they're matched by position.
+        let key = self.columns.get(self.fields).copied().unwrap_or(key);
+        self.fields += 1;
+
+        match value.serialize(Detector).ok().flatten() {
+            Some(subfields) => {
+                self.has_nested = true;
+                let names = subfields.into_iter().map(|sub| format!("{key}.{sub}"));
+                self.names.extend(names);
+            }
+            None => self.names.push(key.into()),
+        }
+
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(if self.has_nested {
+            Some(self.names)
+        } else {
+            None
+        })
+    }
+}
+
+/// Returns subfields if a value is serialized by [`crate::serde::nested`].
+struct Detector;
+
+struct DetectorStruct {
+    subfields: Vec<&'static str>,
+}
+
+impl Serializer for Detector {
+    type Ok = Option<Vec<&'static str>>;
+    type Error = Error;
+    type SerializeSeq = Impossible<Self::Ok, Error>;
+    type SerializeTuple = Impossible<Self::Ok, Error>;
+    type SerializeTupleStruct = Impossible<Self::Ok, Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Error>;
+    type SerializeMap = Impossible<Self::Ok, Error>;
+    type SerializeStruct = DetectorStruct;
+    type SerializeStructVariant = Impossible<Self::Ok, Error>;
+
+    reject_all!(Ok(None));
+
+    fn serialize_newtype_struct<V: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: &V,
+    ) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<DetectorStruct, Error> {
+        if name != MARKER {
+            return Err(not_a_struct());
+        }
+
+        Ok(DetectorStruct {
+            subfields: Vec::with_capacity(len),
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl SerializeStruct for DetectorStruct {
+    type Ok = Option<Vec<&'static str>>;
+    type Error = Error;
+
+    fn serialize_field<V: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        _: &V,
+    ) -> Result<(), Error> {
+        self.subfields.push(key);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(Some(self.subfields))
+    }
+}
+
+// === Deserialization ===
+
+/// Reads parallel arrays of a `Nested(..)` column into items.
+///
+/// Subcolumns are read one after another, but an item can be built only from all
+/// its fields. So values are recorded, while `T` drives reading them, and replayed
+/// to build items. To read the `idx`-th field of an item, recorded values of previous
+/// fields are replayed to `T` and reading is stopped after the `idx`-th one.
+pub(crate) fn deserialize<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DbRow + DeserializeOwned,
+{
+    deserializer.deserialize_tuple(T::COLUMN_NAMES.len(), NestedVisitor(PhantomData))
+}
+
+/// A recorded value of a subcolumn.
+#[derive(Clone)]
+enum Value {
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    String(String),
+    Bytes(Vec<u8>),
+    None,
+    Some(Box<Value>),
+    Unit,
+    Newtype(Box<Value>),
+    Seq(Vec<Value>),
+}
+
+// All subcolumns are compared with the first one, which is read entirely first.
+fn ragged<E: de::Error>(names: &[&str], idx: usize, len: impl fmt::Display, expected: usize) -> E {
+    E::custom(format!(
+        "subcolumns of a nested column have different lengths: `{}` has {len} values, \
+         but `{}` has {expected}",
+        names[idx], names[0],
+    ))
+}
+
+fn unrecorded<E: de::Error>() -> E {
+    E::custom("a value of a nested column isn't read")
+}
+
+struct NestedVisitor<T>(PhantomData<T>);
+
+impl<'de, T: DbRow + DeserializeOwned> Visitor<'de> for NestedVisitor<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("parallel arrays of a nested column")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<T>, A::Error> {
+        let count = T::COLUMN_NAMES.len();
+        let mut columns = Vec::with_capacity(count);
+
+        for idx in 0..count {
+            let seed = ColumnSeed::<T> {
+                names: T::COLUMN_NAMES,
+                columns: &columns,
+                _marker: PhantomData,
+            };
+
+            let column = seq
+                .next_element_seed(seed)?
+                .ok_or_else(|| de::Error::invalid_length(idx, &self))?;
+            columns.push(column);
+        }
+
+        let len = columns.first().map_or(0, Vec::len);
+        let mut columns = columns.into_iter().map(Vec::into_iter).collect::<Vec<_>>();
+
+        (0..len)
+            .map(|_| {
+                let fields = columns.iter_mut().filter_map(Iterator::next).collect();
+                T::deserialize(ValueDeserializer::<A::Error>::new(Value::Seq(fields)))
+            })
+            .collect()
+    }
+}
+
+/// Reads the next subcolumn, previous ones are already read.
+struct ColumnSeed<'a, T> {
+    names: &'static [&'static str],
+    columns: &'a [Vec<Value>],
+    _marker: PhantomData<T>,
+}
+
+impl<'de, T: DeserializeOwned> DeserializeSeed<'de> for ColumnSeed<'_, T> {
+    type Value = Vec<Value>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Vec<Value>, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, T: DeserializeOwned> Visitor<'de> for ColumnSeed<'_, T> {
+    type Value = Vec<Value>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an array of a nested column")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<Value>, A::Error> {
+        let idx = self.columns.len();
+        let expected = self.columns.first().map(Vec::len);
+        if let (Some(expected), Some(len)) = (expected, seq.size_hint()) {
+            if len != expected {
+                return Err(ragged(self.names, idx, len, expected));
+            }
+        }
+
+        let mut values = Vec::with_capacity(expected.or_else(|| seq.size_hint()).unwrap_or(0));
+
+        loop {
+            let seed = FieldSeed::<T> {
+                names: self.names,
+                columns: self.columns,
+                row: values.len(),
+                _marker: PhantomData,
+            };
+
+            match seq.next_element_seed(seed)? {
+                Some(value) => values.push(value),
+                None => break,
+            }
+        }
+
+        if let Some(expected) = expected.filter(|&expected| expected != values.len()) {
+            return Err(ragged(self.names, idx, values.len(), expected));
+        }
+
+        Ok(values)
+    }
+}
+
+/// Reads the field of the `row`-th item, which follows fields in `columns`.
+struct FieldSeed<'a, T> {
+    names: &'static [&'static str],
+    columns: &'a [Vec<Value>],
+    row: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<'de, T: DeserializeOwned> DeserializeSeed<'de> for FieldSeed<'_, T> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        let prev = self
+            .columns
+            .iter()
+            .map(|column| {
+                column.get(self.row).cloned().ok_or_else(|| {
+                    let len = format!("more than {}", column.len());
+                    ragged(self.names, self.columns.len(), len, column.len())
+                })
+            })
+            .collect::<Result<Vec<_>, D::Error>>()?;
+
+        let mut slot = None;
+        let result = T::deserialize(FieldProbe {
+            inner: deserializer,
+            prev,
+            slot: &mut slot,
+        });
+
+        // `T::deserialize()` fails after the field is read, because reading is stopped.
+        match (slot, result) {
+            (Some(value), _) => Ok(value),
+            (None, Err(err)) => Err(err),
+            (None, Ok(_)) => Err(de::Error::custom(
+                "an item of a nested column has too few fields",
+            )),
+        }
+    }
+}
+
+/// Provides recorded `prev` fields of a struct, then records the next one from `inner`.
+struct FieldProbe<'a, D> {
+    inner: D,
+    prev: Vec<Value>,
+    slot: &'a mut Option<Value>,
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for FieldProbe<'_, D> {
+    type Error = D::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, D::Error> {
+        Err(de::Error::custom(
+            "items of a nested column must be structs",
+        ))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        visitor.visit_seq(ProbeSeq {
+            inner: Some(self.inner),
+            prev: self.prev.into_iter(),
+            slot: self.slot,
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+struct ProbeSeq<'a, D> {
+    inner: Option<D>,
+    prev: vec::IntoIter<Value>,
+    slot: &'a mut Option<Value>,
+}
+
+impl<'de, D: Deserializer<'de>> SeqAccess<'de> for ProbeSeq<'_, D> {
+    type Error = D::Error;
+
+    fn next_element_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, D::Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        if let Some(value) = self.prev.next() {
+            return seed.deserialize(ValueDeserializer::new(value)).map(Some);
+        }
+
+        match self.inner.take() {
+            Some(inner) => seed
+                .deserialize(Recorder {
+                    inner,
+                    slot: &mut *self.slot,
+                })
+                .map(Some),
+            // Other fields aren't needed, it stops `T::deserialize()`.
+            None => Ok(None),
+        }
+    }
+}
+
+/// Passes a value from `inner` through, recording it into `slot`.
+struct Recorder<'a, D> {
+    inner: D,
+    slot: &'a mut Option<Value>,
+}
+
+macro_rules! forward_recorded {
+    ($($method:ident($($arg:ident: $ty:ty),*)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                $($arg: $ty,)*
+                visitor: V,
+            ) -> Result<V::Value, D::Error> {
+                self.inner.$method($($arg,)* Recording {
+                    inner: visitor,
+                    slot: self.slot,
+                })
+            }
+        )*
+    };
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for Recorder<'_, D> {
+    type Error = D::Error;
+
+    forward_recorded!(
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_i128(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_u128(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    );
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+struct Recording<'a, V> {
+    inner: V,
+    slot: &'a mut Option<Value>,
+}
+
+macro_rules! record_primitives {
+    ($($method:ident($ty:ty) => $variant:ident),* $(,)?) => {
+        $(
+            fn $method<E: de::Error>(self, value: $ty) -> Result<V::Value, E> {
+                *self.slot = Some(Value::$variant(value));
+                self.inner.$method(value)
+            }
+        )*
+    };
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for Recording<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    record_primitives!(
+        visit_bool(bool) => Bool,
+        visit_i8(i8) => I8,
+        visit_i16(i16) => I16,
+        visit_i32(i32) => I32,
+        visit_i64(i64) => I64,
+        visit_i128(i128) => I128,
+        visit_u8(u8) => U8,
+        visit_u16(u16) => U16,
+        visit_u32(u32) => U32,
+        visit_u64(u64) => U64,
+        visit_u128(u128) => U128,
+        visit_f32(f32) => F32,
+        visit_f64(f64) => F64,
+        visit_char(char) => Char,
+    );
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<V::Value, E> {
+        *self.slot = Some(Value::String(value.into()));
+        self.inner.visit_str(value)
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, value: &'de str) -> Result<V::Value, E> {
+        *self.slot = Some(Value::String(value.into()));
+        self.inner.visit_borrowed_str(value)
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<V::Value, E> {
+        *self.slot = Some(Value::String(value.clone()));
+        self.inner.visit_string(value)
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<V::Value, E> {
+        *self.slot = Some(Value::Bytes(value.into()));
+        self.inner.visit_bytes(value)
+    }
+
+    fn visit_borrowed_bytes<E: de::Error>(self, value: &'de [u8]) -> Result<V::Value, E> {
+        *self.slot = Some(Value::Bytes(value.into()));
+        self.inner.visit_borrowed_bytes(value)
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<V::Value, E> {
+        *self.slot = Some(Value::Bytes(value.clone()));
+        self.inner.visit_byte_buf(value)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+        *self.slot = Some(Value::None);
+        self.inner.visit_none()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        let mut inner = None;
+        let value = self.inner.visit_some(Recorder {
+            inner: deserializer,
+            slot: &mut inner,
+        })?;
+
+        *self.slot = Some(Value::Some(Box::new(inner.ok_or_else(unrecorded)?)));
+        Ok(value)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+        *self.slot = Some(Value::Unit);
+        self.inner.visit_unit()
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<V::Value, D::Error> {
+        let mut inner = None;
+        let value = self.inner.visit_newtype_struct(Recorder {
+            inner: deserializer,
+            slot: &mut inner,
+        })?;
+
+        *self.slot = Some(Value::Newtype(Box::new(inner.ok_or_else(unrecorded)?)));
+        Ok(value)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+        let mut items = Vec::new();
+        let value = self.inner.visit_seq(RecordingSeq {
+            inner: seq,
+            items: &mut items,
+        })?;
+
+        *self.slot = Some(Value::Seq(items));
+        Ok(value)
+    }
+}
+
+struct RecordingSeq<'a, A> {
+    inner: A,
+    items: &'a mut Vec<Value>,
+}
+
+impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for RecordingSeq<'_, A> {
+    type Error = A::Error;
+
+    fn next_element_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, A::Error>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        let mut slot = None;
+        let value = self.inner.next_element_seed(RecordingSeed {
+            inner: seed,
+            slot: &mut slot,
+        })?;
+
+        if value.is_some() {
+            self.items.push(slot.ok_or_else(unrecorded)?);
+        }
+
+        Ok(value)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+struct RecordingSeed<'a, S> {
+    inner: S,
+    slot: &'a mut Option<Value>,
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for RecordingSeed<'_, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<S::Value, D::Error> {
+        self.inner.deserialize(Recorder {
+            inner: deserializer,
+            slot: self.slot,
+        })
+    }
+}
+
+/// Replays a recorded value.
+struct ValueDeserializer<E> {
+    value: Value,
+    _marker: PhantomData<E>,
+}
+
+impl<E> ValueDeserializer<E> {
+    fn new(value: Value) -> Self {
+        Self {
+            value,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, E: de::Error> IntoDeserializer<'de, E> for Value {
+    type Deserializer = ValueDeserializer<E>;
+
+    fn into_deserializer(self) -> ValueDeserializer<E> {
+        ValueDeserializer::new(self)
+    }
+}
+
+impl<'de, E: de::Error> Deserializer<'de> for ValueDeserializer<E> {
+    type Error = E;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, E> {
+        match self.value {
+            Value::Bool(v) => visitor.visit_bool(v),
+            Value::I8(v) => visitor.visit_i8(v),
+            Value::I16(v) => visitor.visit_i16(v),
+            Value::I32(v) => visitor.visit_i32(v),
+            Value::I64(v) => visitor.visit_i64(v),
+            Value::I128(v) => visitor.visit_i128(v),
+            Value::U8(v) => visitor.visit_u8(v),
+            Value::U16(v) => visitor.visit_u16(v),
+            Value::U32(v) => visitor.visit_u32(v),
+            Value::U64(v) => visitor.visit_u64(v),
+            Value::U128(v) => visitor.visit_u128(v),
+            Value::F32(v) => visitor.visit_f32(v),
+            Value::F64(v) => visitor.visit_f64(v),
+            Value::Char(v) => visitor.visit_char(v),
+            Value::String(v) => visitor.visit_string(v),
+            Value::Bytes(v) => visitor.visit_byte_buf(v),
+            Value::None => visitor.visit_none(),
+            Value::Some(v) => visitor.visit_some(ValueDeserializer::new(*v)),
+            Value::Unit => visitor.visit_unit(),
+            Value::Newtype(v) => visitor.visit_newtype_struct(ValueDeserializer::new(*v)),
+            Value::Seq(items) => {
+                let mut seq = SeqDeserializer::new(items.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::rowbinary;
+
+    use clickhouse::Row;
+
+    #[derive(Debug, PartialEq, Row, Serialize, Deserialize)]
+    struct Item {
+        name: String,
+        count: u32,
+    }
+
+    #[derive(Debug, PartialEq, Row, Serialize, Deserialize)]
+    struct MyRow {
+        no: i32,
+        #[serde(with = "crate::serde::nested")]
+        items: Vec<Item>,
+    }
+
+    fn roundtrip<T: Serialize + DeserializeOwned>(value: &T) -> T {
+        let mut buffer = Vec::new();
+        rowbinary::serialize_into(&mut buffer, value).unwrap();
+        let mut input = &buffer[..];
+        let actual = rowbinary::deserialize_from(&mut input, &mut []).unwrap();
+        assert!(input.is_empty());
+        actual
+    }
+
+    #[test]
+    fn it_serializes_parallel_arrays() {
+        let row = MyRow {
+            no: 1,
+            items: vec![
+                Item {
+                    name: "foo".into(),
+                    count: 1,
+                },
+                Item {
+                    name: "bar".into(),
+                    count: 5,
+                },
+            ],
+        };
+
+        let mut buffer = Vec::new();
+        rowbinary::serialize_into(&mut buffer, &row).unwrap();
+        assert_eq!(
+            buffer,
+            b"\x01\0\0\0\x02\x03foo\x03bar\x02\x01\0\0\0\x05\0\0\0"
+        );
+
+        let names = column_names(&row).unwrap();
+        assert_eq!(names, ["no", "items.name", "items.count"]);
+    }
+
+    #[test]
+    fn it_serializes_empty_arrays() {
+        let row = MyRow {
+            no: 1,
+            items: Vec::new(),
+        };
+
+        let mut buffer = Vec::new();
+        rowbinary::serialize_into(&mut buffer, &row).unwrap();
+        assert_eq!(buffer, b"\x01\0\0\0\0\0");
+
+        let names = column_names(&row).unwrap();
+        assert_eq!(names, ["no", "items.name", "items.count"]);
+    }
+
+    #[test]
+    fn it_renames_nested_columns() {
+        #[derive(Row, Serialize)]
+        struct Renamed {
+            #[clickhouse(rename = "row.no")]
+            no: i32,
+            #[serde(skip_serializing)]
+            _skipped: u8,
+            #[clickhouse(rename = "events")]
+            #[serde(with = "crate::serde::nested")]
+            items: Vec<Item>,
+        }
+
+        let row = Renamed {
+            no: 1,
+            _skipped: 0,
+            items: Vec::new(),
+        };
+        let names = column_names(&row).unwrap();
+        assert_eq!(names, ["row.no", "events.name", "events.count"]);
+    }
+
+    #[test]
+    fn it_ignores_rows_without_nested() {
+        #[derive(Row, Serialize)]
+        struct Plain {
+            no: i32,
+            items: Vec<u32>,
+            inner: (u8, String),
+        }
+
+        let row = Plain {
+            no: 1,
+            items: vec![1],
+            inner: (1, "a".into()),
+        };
+        assert_eq!(column_names(&row), None);
+        assert_eq!(column_names(&42u32), None);
+    }
+
+    #[test]
+    fn it_deserializes_parallel_arrays() {
+        let mut input = &b"\x01\0\0\0\x02\x03foo\x03bar\x02\x01\0\0\0\x05\0\0\0"[..];
+        let row: MyRow = rowbinary::deserialize_from(&mut input, &mut []).unwrap();
+        assert!(input.is_empty());
+        assert_eq!(
+            row,
+            MyRow {
+                no: 1,
+                items: vec![
+                    Item {
+                        name: "foo".into(),
+                        count: 1,
+                    },
+                    Item {
+                        name: "bar".into(),
+                        count: 5,
+                    },
+                ],
+            }
+        );
+
+        let row = MyRow {
+            no: 2,
+            items: Vec::new(),
+        };
+        assert_eq!(roundtrip(&row), row);
+    }
+
+    #[test]
+    fn it_deserializes_compound_subcolumns() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Id(u64);
+
+        #[derive(Debug, PartialEq, Row, Serialize, Deserialize)]
+        struct Event {
+            id: Id,
+            flag: bool,
+            tag: Option<String>,
+            pair: (u8, i16),
+            labels: Vec<String>,
+            score: f64,
+        }
+
+        #[derive(Debug, PartialEq, Row, Serialize, Deserialize)]
+        struct Log {
+            #[serde(with = "crate::serde::nested")]
+            events: Vec<Event>,
+            rest: u128,
+        }
+
+        let log = Log {
+            events: vec![
+                Event {
+                    id: Id(1),
+                    flag: true,
+                    tag: None,
+                    pair: (1, -1),
+                    labels: vec!["a".into(), "b".into()],
+                    score: 0.5,
+                },
+                Event {
+                    id: Id(2),
+                    flag: false,
+                    tag: Some("tag".into()),
+                    pair: (2, -2),
+                    labels: Vec::new(),
+                    score: 1.5,
+                },
+            ],
+            rest: 42,
+        };
+        assert_eq!(roundtrip(&log), log);
+    }
+
+    #[test]
+    fn it_fails_on_ragged_arrays() {
+        fn check(input: &[u8], lengths: &str) {
+            let mut input = input;
+            let err = rowbinary::deserialize_from::<MyRow>(&mut input, &mut []).unwrap_err();
+            assert!(
+                err.to_string().contains("different lengths") && err.to_string().contains(lengths),
+                "unexpected error: {}",
+                err
+            );
+        }
+
+        // Two names, but one count.
+        check(
+            b"\x01\0\0\0\x02\x03foo\x03bar\x01\x01\0\0\0",
+            "`count` has 1 values, but `name` has 2",
+        );
+        // One name, but two counts.
+        check(
+            b"\x01\0\0\0\x01\x03foo\x02\x01\0\0\0\x05\0\0\0",
+            "`count` has 2 values, but `name` has 1",
+        );
+    }
+}
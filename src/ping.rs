@@ -0,0 +1,105 @@
+use futures::TryStreamExt;
+use hyper::{header::CONTENT_LENGTH, Body, Method, Request};
+use url::Url;
+
+use crate::{
+    auth,
+    error::{Error, Result},
+    trace::RequestSpan,
+    Client, Compression,
+};
+
+const EXPECTED_BODY: &[u8] = b"Ok.\n";
+
+/// Sends `GET /ping`, which is handled by ClickHouse before any query processing,
+/// so neither the database nor settings of `client` are involved.
+pub(crate) async fn ping(client: &Client) -> Result<()> {
+    let mut url = Url::parse(&client.url).map_err(|err| Error::InvalidParams(Box::new(err)))?;
+    // Keep a path prefix, e.g. used by a reverse proxy.
+    let path = format!("{}/ping", url.path().trim_end_matches('/'));
+    url.set_path(&path);
+    url.set_query(None);
+
+    let builder = Request::builder()
+        .method(Method::GET)
+        .uri(url.as_str())
+        .header(CONTENT_LENGTH, "0");
+
+    let request = auth::authorize(builder, client)
+        .body(Body::empty())
+        .map_err(|err| Error::InvalidParams(Box::new(err)))?;
+
+    let mut response = client.send(request, Compression::None, RequestSpan::none())?;
+    let chunks = response.chunks_slow().await?;
+
+    let mut body = Vec::new();
+    while let Some(chunk) = chunks.try_next().await? {
+        body.extend_from_slice(&chunk);
+
+        if body.len() > EXPECTED_BODY.len() {
+            break;
+        }
+    }
+
+    if body != EXPECTED_BODY {
+        let body = String::from_utf8_lossy(&body);
+        return Err(Error::BadResponse(format!(
+            "unexpected response to ping: {body:?}"
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+    use crate::test::{handlers, status, Mock};
+
+    #[tokio::test]
+    async fn it_pings() {
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(format!("{}/prefix/", mock.url()))
+            .with_database("unknown")
+            .with_option("max_threads", "1")
+            .with_user("alice");
+
+        mock.add(handlers::provide(stream::iter(EXPECTED_BODY.to_vec())));
+        client.ping().await.unwrap();
+
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.uri.path(), "/prefix/ping");
+        assert_eq!(request.uri.query(), None);
+        assert_eq!(request.headers["X-ClickHouse-User"], "alice");
+    }
+
+    #[tokio::test]
+    async fn it_fails_on_unexpected_responses() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        mock.add(handlers::provide(stream::iter(b"Nope.\n".to_vec())));
+        let err = client.ping().await.unwrap_err();
+        assert!(matches!(err, Error::BadResponse(reason) if reason.contains("Nope.")));
+
+        mock.add(handlers::failure(status::SERVICE_UNAVAILABLE));
+        let err = client.ping().await.unwrap_err();
+        assert!(matches!(err, Error::BadResponse(_)));
+    }
+
+    #[tokio::test]
+    async fn it_fails_on_unreachable_servers() {
+        // Take a free port and release it, so nothing listens there.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let client = Client::default().with_url(format!("http://{addr}"));
+        let err = client.ping().await.unwrap_err();
+        assert!(matches!(err, Error::Network(_)));
+    }
+}
@@ -1,25 +1,53 @@
-use bytes::BufMut;
-use futures::StreamExt;
-use hyper::{header::CONTENT_LENGTH, Body, Method, Request};
-use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    error::Error as StdError,
+    fmt, mem,
+    ops::Range,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{BufMut, Bytes};
+use futures::{future, ready, stream, Stream, StreamExt};
+use hyper::{
+    header::{CONTENT_ENCODING, CONTENT_LENGTH},
+    Body, HeaderMap, Method, Request,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use url::Url;
 
 use crate::{
-    cursor::RowBinaryCursor,
+    auth,
+    cursor::{BufferCapacity, JsonEachRowCursor, NamedRowBinaryCursor, RowBinaryCursor},
     error::{Error, Result},
-    response::{Chunks, Response},
-    row::DbRow,
-    sql::{Bind, SqlBuilder},
-    Client,
+    insert::Insert,
+    response::Response,
+    row::{DbRow, InsertRow},
+    sql::{self, Bind, SqlBuilder},
+    trace::RequestSpan,
+    Client, Compression,
 };
 
+#[cfg(feature = "arrow")]
+use crate::cursor::ArrowStreamCursor;
+
 const MAX_QUERY_LEN_TO_USE_GET: usize = 8192;
+#[cfg(feature = "gzip")]
+const MIN_QUERY_LEN_TO_GZIP: usize = 1024;
+pub(crate) const BATCH_CONCURRENCY: usize = 8;
+const QUERY_ID: &str = "query_id";
 
 #[must_use]
 #[derive(Clone)]
 pub struct Query {
     client: Client,
     sql: SqlBuilder,
+    buffer_capacity: BufferCapacity,
+    prefetch_bytes: usize,
+    method: Option<Method>,
+    lenient_integers: bool,
+    validate_schema: bool,
 }
 
 impl Query
@@ -30,6 +58,19 @@ where
         Self {
             client: client.clone(),
             sql: SqlBuilder::new(template),
+            buffer_capacity: BufferCapacity::default(),
+            prefetch_bytes: 0,
+            method: None,
+            lenient_integers: false,
+            validate_schema: false,
+        }
+    }
+
+    // Used for generated SQL, which can contain `?` inside identifiers.
+    pub(crate) fn literal(client: &Client, sql: String) -> Self {
+        Self {
+            sql: SqlBuilder::literal(sql),
+            ..Self::new(client, "")
         }
     }
 
@@ -37,21 +78,321 @@ where
     ///
     /// The `value`, which must either implement [`Serialize`](serde::Serialize)
     /// or be an [`Identifier`], will be appropriately escaped.
+    /// `None` is bound as `NULL` and `Some(value)` as `value`, but note that
+    /// `a = NULL` is never true, so nulls must be matched by `a IS NULL` instead.
     ///
     /// WARNING: This means that the query must not have any extra `?`, even if
-    /// they are in a string literal!
+    /// they are in a string literal! The number of bound values is checked
+    /// before sending the query, so a mismatch is reported as [`Error::InvalidParams`].
     pub fn bind(mut self, value: impl Bind) -> Self {
         self.sql.bind_arg(value);
         self
     }
 
+    /// Binds `value` to every `{name}` placeholder in the query, escaped like [`Query::bind`].
+    /// It's substituted on the client side, unlike server-side `{name:Type}` parameters,
+    /// which are kept as is.
+    ///
+    /// All `{name}` placeholders in the query must be bound, even if this method isn't
+    /// called at all, and all bound names must be used, otherwise [`Error::InvalidParams`]
+    /// is returned before sending the query. Use `{{` to write a literal `{`,
+    /// e.g. `'{{id}'` for `'{id}'`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let query = clickhouse::Client::default()
+    ///     .query("SELECT * FROM some WHERE a = {id} OR b = {id} AND c > ?")
+    ///     .bind_named("id", 42)
+    ///     .bind(5);
+    /// assert_eq!(
+    ///     query.sql_display().unwrap(),
+    ///     "SELECT * FROM some WHERE a = 42 OR b = 42 AND c > 5"
+    /// );
+    /// ```
+    pub fn bind_named(mut self, name: &str, value: impl Bind) -> Self {
+        self.sql.bind_named(name, value);
+        self
+    }
+
+    /// Binds `fragment` to the next `?` in the query verbatim, without any escaping,
+    /// e.g. `now() - INTERVAL 1 DAY` or a table function.
+    /// Any `?` in `fragment` is kept as is and doesn't consume bound values.
+    ///
+    /// WARNING: The caller is responsible for the fragment being valid and safe SQL!
+    /// Never pass untrusted input here, it allows SQL injections.
+    /// Prefer [`Query::bind`], which escapes values and identifiers.
+    pub fn bind_raw(mut self, fragment: impl Into<String>) -> Self {
+        self.sql.bind_arg(sql::Raw(fragment.into()));
+        self
+    }
+
+    /// Overrides the database of [`Client::with_database`] for this query only.
+    ///
+    /// An empty string means the default database of the server (or the user).
+    pub fn with_database(mut self, database: impl Into<String>) -> Self {
+        self.client.database = Some(database.into());
+        self
+    }
+
+    /// Overrides the compression of [`Client::with_compression`] for this query only,
+    /// e.g. `Compression::None` to get an uncompressed response.
+    ///
+    /// [`Client::with_compression_threshold`] isn't applied to such queries.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.client.compression = compression;
+        self.client.compression_threshold = 0;
+        self
+    }
+
+    /// Sets the id of the query (the `query_id` setting), e.g. to cancel it
+    /// by [`Client::kill_query`]. Otherwise, ClickHouse generates a random one.
+    pub fn with_query_id(self, query_id: impl Into<String>) -> Self {
+        self.with_option(QUERY_ID, query_id)
+    }
+
+    /// Sets the `quota_key` of the query, overriding [`Client::with_quota_key`],
+    /// to account it in quotas keyed by `client_key`, e.g. per tenant.
+    ///
+    /// It's sent as any other option, so the last of this method and
+    /// `with_option("quota_key", ..)` wins.
+    pub fn with_quota_key(self, key: impl Into<String>) -> Self {
+        self.with_option("quota_key", key)
+    }
+
+    /// Sets the `max_block_size` setting, which limits the number of rows in blocks
+    /// processed and sent by the server, e.g. to receive results in smaller portions.
+    /// See [`RowCursor::next_block`] to process received rows as batches.
+    pub fn with_max_block_size(self, rows: u64) -> Self {
+        self.with_option("max_block_size", rows.to_string())
+    }
+
+    /// Configures the temporary buffer of [`RowCursor`] used for rows split between
+    /// chunks of the response. It starts with `initial` bytes (1 KiB by default)
+    /// and grows to fit the largest row. If a row requires more than `max_retained`
+    /// bytes (unlimited by default), the buffer is shrunk back to `initial` bytes
+    /// afterwards, so occasional huge rows don't inflate memory of long-lived cursors.
+    ///
+    /// Rows borrowing the buffer, e.g. `&str` fields, must stay valid, so it's shrunk
+    /// only if rows are owned and read by [`RowCursor`] as a `Stream`, by
+    /// [`RowCursor::next_block`], [`RowCursor::peek`] or `fetch_all*()` methods.
+    pub fn with_buffer_capacity(mut self, initial: usize, max_retained: usize) -> Self {
+        self.buffer_capacity = BufferCapacity {
+            initial,
+            max_retained,
+            ..self.buffer_capacity
+        };
+        self
+    }
+
+    /// Limits rows of [`RowCursor`] to `max_bytes`, so a row split between chunks
+    /// that doesn't fit fails with [`Error::RowTooLarge`] instead of allocating
+    /// more, e.g. if a length in the response is corrupted. It applies both to
+    /// the temporary buffer and to received chunks of an incomplete row.
+    ///
+    /// Unlimited by default.
+    pub fn with_max_row_size(mut self, max_bytes: usize) -> Self {
+        self.buffer_capacity.max_row = max_bytes;
+        self
+    }
+
+    /// Makes [`RowCursor`] read the response ahead in a separate task, holding
+    /// at most `max_bytes` of chunks, which aren't read by the cursor yet.
+    /// Thus, receiving and decompressing the next chunks overlap with processing
+    /// of rows by the caller, e.g. if it awaits something for every row.
+    ///
+    /// The cursor reads chunks only on demand by default.
+    pub fn with_prefetch(mut self, max_bytes: usize) -> Self {
+        self.prefetch_bytes = max_bytes;
+        self
+    }
+
+    /// Sends the query by `GET` or `POST` regardless of its length, which is useful
+    /// if proxies handle them differently. By default, `SELECT`s shorter than 8 KiB
+    /// are sent by `GET` in the URL, others are sent by `POST` in the body.
+    ///
+    /// Read-only queries sent by `POST` are still executed with `readonly=1`.
+    /// Only read-only queries can be sent by `GET`, otherwise, and for other methods,
+    /// [`Error::InvalidParams`] is returned by executing.
+    pub fn with_method(mut self, method: Method) -> Self {
+        self.method = Some(method);
+        self
+    }
+
+    /// Similar to [`Client::with_max_result_bytes`], but for this query only.
+    pub fn with_max_result_bytes(mut self, limit: u64) -> Self {
+        self.client = self.client.with_max_result_bytes(limit);
+        self
+    }
+
+    /// Similar to [`Client::with_header`], but for this query only.
+    /// It overrides a header of the client with the same name.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.client = self.client.with_header(name, value);
+        self
+    }
+
+    /// Similar to [`Client::with_option`], but for this query only.
+    pub fn with_option(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.client.options.insert(name.into(), value.into());
+        self
+    }
+
+    /// Makes the query see all data acknowledged by `INSERT`s with a quorum
+    /// (the `select_sequential_consistency` setting), e.g. to read own writes
+    /// from any replica of a `Replicated*` table.
+    ///
+    /// It works only along with [`Insert::with_insert_quorum`]: data inserted without
+    /// a quorum isn't guaranteed to be seen. Replicas without all quorum inserts reject
+    /// the query, so it's slower and can fail while replicas catch up.
+    ///
+    /// [`Insert::with_insert_quorum`]: crate::insert::Insert::with_insert_quorum
+    pub fn with_sequential_consistency(self, enabled: bool) -> Self {
+        self.with_option(
+            "select_sequential_consistency",
+            if enabled { "1" } else { "0" },
+        )
+    }
+
+    /// Tolerates integer columns whose width differs from fields of the row type,
+    /// e.g. if a column is `UInt8` in one schema version and `UInt16` in another.
+    ///
+    /// Rows are fetched in `RowBinaryWithNamesAndTypes`, so integers
+    /// (and `Bool`) are read as typed in the response and converted to the requested
+    /// type, failing with [`Error::Overflow`] if a value doesn't fit.
+    /// Types of fields must still match in everything except the integer width.
+    ///
+    /// By default, it's disabled: values must be exactly of the field types.
+    pub fn with_lenient_integers(mut self, enabled: bool) -> Self {
+        self.lenient_integers = enabled;
+        self
+    }
+
+    /// Checks names of columns in the response against fields of the row type
+    /// before decoding rows, returning [`Error::SchemaMismatch`] if they differ,
+    /// e.g. if fields are declared in another order than columns are selected.
+    /// Otherwise, such rows are decoded positionally into garbage or fail randomly.
+    ///
+    /// Rows are fetched in `RowBinaryWithNamesAndTypes`, which starts with
+    /// the header of names and types. Only names are compared, exactly as
+    /// they're produced by `derive(Row)`, i.e. with respect to `rename`,
+    /// and the response must have no other columns. Columns after the fields,
+    /// e.g. `u64` of `(MyRow, u64)` rows, are only counted, not named.
+    /// Rows without fields, e.g. primitives, aren't checked at all.
+    ///
+    /// Note that types aren't validated: a column of another type than the field
+    /// is still decoded as the field's type, unless [`Query::with_lenient_integers`]
+    /// is used to convert integers.
+    ///
+    /// By default, it's disabled: rows are fetched in `RowBinary` without any header.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// # use clickhouse::Row;
+    /// # use serde::Deserialize;
+    /// #[derive(Row, Deserialize)]
+    /// struct MyRow {
+    ///     no: u32,
+    ///     name: String,
+    /// }
+    ///
+    /// # let client = clickhouse::Client::default();
+    /// let rows = client
+    ///     .query("SELECT no, name FROM some")
+    ///     .with_schema_validation(true)
+    ///     .fetch_all::<MyRow>()
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    pub fn with_schema_validation(mut self, enabled: bool) -> Self {
+        self.validate_schema = enabled;
+        self
+    }
+
+    /// Returns the SQL that [`Query::execute`] sends: with bound values,
+    /// but without executing the query, e.g. to log it before executing.
+    ///
+    /// Errors are the same as returned by executing, e.g. because of unbound `?`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let query = clickhouse::Client::default()
+    ///     .query("SELECT * FROM some WHERE name = ?")
+    ///     .bind("foo");
+    /// assert_eq!(query.sql_display().unwrap(), "SELECT * FROM some WHERE name = 'foo'");
+    /// ```
+    pub fn sql_display(&self) -> Result<String> {
+        self.sql.clone().finish()
+    }
+
+    /// Similar to [`Query::sql_display`], but returns the SQL that [`Query::fetch`]
+    /// (and other methods fetching `T`) sends: with `?fields` expanded
+    /// and `FORMAT RowBinary` appended.
+    pub fn sql_display_fetch<T: DbRow>(&self) -> Result<String> {
+        let mut sql = self.sql.clone();
+        sql.bind_fields::<T>();
+        sql.append(self.fetch_format());
+        sql.finish()
+    }
+
+    fn fetch_format(&self) -> &'static str {
+        if self.lenient_integers || self.validate_schema {
+            // The header is required to read integers of any width or to check names.
+            " FORMAT RowBinaryWithNamesAndTypes"
+        } else {
+            " FORMAT RowBinary"
+        }
+    }
+
     /// Executes the query.
     pub async fn execute(self) -> Result<()> {
         self.do_execute(false)?.finish().await
     }
 
+    /// Executes the query, streaming `data` as its input, e.g. for
+    /// `INSERT INTO some FORMAT CSV`. The query is sent in the URL, so it must
+    /// not contain the data itself.
+    ///
+    /// The length of `data` isn't required to be known ahead, because it's sent
+    /// with `Transfer-Encoding: chunked` instead of `Content-Length`.
+    /// Chunks are sent as is: neither `Client::with_gzip_requests` nor LZ4
+    /// is applied to them. An error of the stream aborts the request.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// use futures::{stream, StreamExt};
+    ///
+    /// let lines = stream::iter(0..1_000_000)
+    ///     .map(|no| Ok::<_, std::io::Error>(format!("{no}\n")));
+    /// clickhouse::Client::default()
+    ///     .query("INSERT INTO some(no) FORMAT CSV")
+    ///     .execute_with_body(lines)
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn execute_with_body<S, B, E>(self, data: S) -> Result<()>
+    where
+        S: Stream<Item = Result<B, E>> + Send + 'static,
+        B: Into<Bytes> + 'static,
+        E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+    {
+        self.do_execute_with(false, Some(Body::wrap_stream(data)))?
+            .finish()
+            .await
+    }
+
     /// Executes the query, returning a [`RowCursor`] to obtain results.
     ///
+    /// Note that `RowBinary` has no place for totals and extremes, so the server
+    /// omits them: `WITH TOTALS` and the `extremes` setting don't affect rows.
+    /// Use `WITH ROLLUP` (or `GROUPING SETS`) instead, which returns totals as
+    /// ordinary rows with default values of keys (see the `grouping()` function).
+    ///
     /// # Example
     ///
     /// ```
@@ -71,19 +412,142 @@ where
     /// }
     /// # Ok(()) }
     /// ```
-    pub fn fetch<T>(mut self) -> Result<RowCursor<T>>
-    where
-        T: DbRow + for<'b> Deserialize<'b>,
-    {
+    pub fn fetch<T: DbRow>(mut self) -> Result<RowCursor<T>> {
         self.sql.bind_fields::<T>();
-        self.sql.append(" FORMAT RowBinary");
+        self.sql.append(self.fetch_format());
+
+        let client = self.client.clone();
+        let capacity = self.buffer_capacity;
+        let prefetch_bytes = self.prefetch_bytes;
+        let lenient_integers = self.lenient_integers;
+        let validate_schema = self.validate_schema;
+        let response = self.do_execute(true)?;
+        let mut cursor =
+            RowBinaryCursor::with_buffer_capacity(response, capacity).with_prefetch(prefetch_bytes);
+        if lenient_integers {
+            cursor = cursor.with_lenient_integers();
+        }
+        if validate_schema {
+            cursor = cursor.with_expected_columns(T::COLUMN_NAMES, T::TRAILING_COLUMNS);
+        }
+        Ok(RowCursor(cursor, client, None))
+    }
+
+    /// Executes the query, returning a [`NamedRowCursor`] that emits rows as
+    /// `(column name, value)` pairs.
+    ///
+    /// Unlike [`Query::fetch`], it doesn't require a row type known in advance,
+    /// because column names and types are read from the response
+    /// (`RowBinaryWithNamesAndTypes`). It's useful for ad-hoc queries.
+    ///
+    /// Values are represented as follows:
+    /// * integers up to 64 bits, floats and `Bool` become JSON numbers and booleans,
+    ///   NaN and infinities become `null`;
+    /// * wider integers, `Decimal`, `UUID`, `IPv4`, `IPv6` and `Enum` become strings;
+    /// * `Date` and `DateTime` become strings, always in UTC;
+    /// * `Nullable` becomes `null` or the inner value;
+    /// * `Array` and unnamed `Tuple` become arrays, named `Tuple` and `Map` become objects.
+    ///
+    /// Other types, e.g. `AggregateFunction`, aren't supported and lead to an error.
+    ///
+    /// Columns must have unique names, otherwise [`Error::DuplicateColumn`] is returned.
+    /// Use aliases to disambiguate them or [`Query::fetch`], which decodes positionally.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// let mut cursor = clickhouse::Client::default()
+    ///     .query("SELECT number, toString(number) AS name FROM system.numbers LIMIT 3")
+    ///     .fetch_named_rows()?;
+    ///
+    /// while let Some(row) = cursor.next().await? {
+    ///     for (name, value) in row {
+    ///         println!("{name}: {value}");
+    ///     }
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn fetch_named_rows(mut self) -> Result<NamedRowCursor> {
+        self.sql.append(" FORMAT RowBinaryWithNamesAndTypes");
 
         let response = self.do_execute(true)?;
-        Ok(RowCursor(RowBinaryCursor::new(response)))
+        Ok(NamedRowCursor(NamedRowBinaryCursor::new(response)))
+    }
+
+    /// Executes the query in `JSONEachRow`, returning a [`DynamicRowCursor`] that emits
+    /// every row as a JSON object of columns, e.g. for queries built at runtime.
+    ///
+    /// Unlike [`Query::fetch_named_rows`], columns are converted by ClickHouse,
+    /// so all types are supported, but 64-bit and wider integers and `Decimal`
+    /// are quoted and become strings, which keeps them exact. Other values are
+    /// represented as ClickHouse writes them in JSON.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// let mut cursor = clickhouse::Client::default()
+    ///     .query("SELECT number, toString(number) AS name FROM system.numbers LIMIT 3")
+    ///     .fetch_dynamic()?;
+    ///
+    /// while let Some(row) = cursor.next().await? {
+    ///     println!("{}: {}", row["number"], row["name"]);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn fetch_dynamic(mut self) -> Result<DynamicRowCursor> {
+        self.sql.append(" FORMAT JSONEachRow");
+
+        let response = self
+            .with_option("output_format_json_quote_64bit_integers", "1")
+            .with_option("output_format_json_quote_decimals", "1")
+            .do_execute(true)?;
+        Ok(DynamicRowCursor(JsonEachRowCursor::new(response)))
+    }
+
+    /// Executes the query in `ArrowStream`, returning an [`ArrowCursor`] that emits
+    /// Arrow record batches as ClickHouse encodes them, without decoding rows.
+    ///
+    /// Note that `String` columns are `Binary` in Arrow, unless the
+    /// `output_format_arrow_string_as_string` setting is enabled.
+    ///
+    /// The response is requested without IPC compression (ClickHouse uses
+    /// `lz4_frame` by default), because Arrow's LZ4 and ZSTD codecs aren't enabled.
+    /// Use [`Client::with_compression`] to compress the transfer instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// let mut cursor = clickhouse::Client::default()
+    ///     .query("SELECT number, toString(number) AS name FROM system.numbers LIMIT 3")
+    ///     .with_option("output_format_arrow_string_as_string", "1")
+    ///     .fetch_arrow()?;
+    ///
+    /// while let Some(batch) = cursor.next().await? {
+    ///     println!("{} rows of {:?}", batch.num_rows(), batch.schema());
+    /// }
+    /// # Ok(()) }
+    /// ```
+    #[cfg(feature = "arrow")]
+    pub fn fetch_arrow(mut self) -> Result<ArrowCursor> {
+        self.sql.append(" FORMAT ArrowStream");
+
+        let response = self
+            .with_option("output_format_arrow_compression_method", "none")
+            .do_execute(true)?;
+        Ok(ArrowCursor(ArrowStreamCursor::new(response)))
     }
 
     /// Executes the query and returns just a single row.
     ///
+    /// [`Error::RowNotFound`] is returned only if the response is completed without
+    /// rows. A response cut by a closed connection fails with [`Error::Network`] or,
+    /// if a row is cut, with [`Error::NotEnoughData`] instead. However, a cut can't
+    /// be detected if the response has neither `Content-Length` nor chunked encoding,
+    /// which isn't the case for ClickHouse itself, but possible behind some proxies.
+    ///
     /// Note that `T` must be owned.
     pub async fn fetch_one<T>(self) -> Result<T>
     where
@@ -96,8 +560,104 @@ where
         }
     }
 
+    /// Executes the query and returns a single value, e.g. of `SELECT count() FROM ..`.
+    ///
+    /// Unlike [`Query::fetch_one`], `T` isn't a row but a value of the only column,
+    /// so no wrapping struct is required. The result must consist of exactly one row
+    /// and one column, otherwise [`Error::RowNotFound`], [`Error::TooManyRows`] or
+    /// [`Error::UnexpectedColumnCount`] is returned.
+    ///
+    /// Note that `T` must be owned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// # let client = clickhouse::Client::default();
+    /// let count = client
+    ///     .query("SELECT count() FROM some WHERE no > ?")
+    ///     .bind(500)
+    ///     .fetch_scalar::<u64>()
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn fetch_scalar<T>(mut self) -> Result<T>
+    where
+        T: for<'b> Deserialize<'b> + Send,
+    {
+        // The header is required to check the number of columns.
+        self.sql.append(" FORMAT RowBinaryWithNamesAndTypes");
+
+        let response = self.do_execute(true)?;
+        let mut cursor = RowBinaryCursor::<T>::with_column_count(response, 1);
+
+        let value = cursor.next().await?.ok_or(Error::RowNotFound)?;
+        match cursor.next().await? {
+            Some(_) => Err(Error::TooManyRows),
+            None => Ok(value),
+        }
+    }
+
+    /// Executes `SELECT count() FROM (<query>)` and returns the number of rows
+    /// the query would produce, e.g. to paginate its results.
+    ///
+    /// Arguments are bound as usual, but `?fields` isn't supported.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// # let client = clickhouse::Client::default();
+    /// let total = client
+    ///     .query("SELECT no, name FROM some WHERE no > ?")
+    ///     .bind(500)
+    ///     .fetch_row_count()
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn fetch_row_count(mut self) -> Result<u64> {
+        self.sql.prepend("SELECT count() FROM (");
+        // A line break in case the query ends with a comment.
+        self.sql.append("\n)");
+        self.fetch_scalar().await
+    }
+
+    /// Executes `EXPLAIN <kind> <query>` and returns the output as text,
+    /// e.g. to inspect the plan of an expensive query before running it.
+    ///
+    /// Arguments are bound to the inner query as usual, but `?fields` isn't supported.
+    /// The output is fetched in `TSVRaw`, so it's exactly what ClickHouse produces:
+    /// one line per row, values of [`ExplainKind::Estimate`] separated by tabs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// use clickhouse::query::ExplainKind;
+    ///
+    /// # let client = clickhouse::Client::default();
+    /// let plan = client
+    ///     .query("SELECT no, name FROM some WHERE no > ?")
+    ///     .bind(500)
+    ///     .explain(ExplainKind::Plan)
+    ///     .await?;
+    /// println!("{plan}");
+    /// # Ok(()) }
+    /// ```
+    pub async fn explain(mut self, kind: ExplainKind) -> Result<String> {
+        self.sql.prepend(&format!("EXPLAIN {} ", kind.as_str()));
+        // A line break in case the query ends with a comment.
+        self.sql.append("\nFORMAT TSVRaw");
+
+        let bytes = self.fetch_raw_bytes().await?;
+        String::from_utf8(bytes)
+            .map_err(|err| Error::BadResponse(format!("invalid output of EXPLAIN: {err}")))
+    }
+
     /// Executes the query and returns at most one row.
     ///
+    /// `None` means a completed response without rows, see [`Query::fetch_one`].
+    ///
     /// Note that `T` must be owned.
     pub async fn fetch_optional<T>(self) -> Result<Option<T>>
     where
@@ -106,117 +666,2729 @@ where
         self.fetch()?.next().await
     }
 
-    /// Executes the query and returns all the generated results, collected into a Vec.
+    /// Executes the query and returns at most one row along with the [`Summary`].
+    ///
+    /// See [`Query::fetch_all_with_summary`] for details.
+    pub async fn fetch_optional_with_summary<T>(self) -> Result<(Option<T>, Summary)>
+    where
+        T: DbRow + for<'b> Deserialize<'b> + Send,
+    {
+        let mut cursor = self.fetch_with_summary::<T>()?;
+        let summary = Summary::from_headers(cursor.headers().await?)?;
+        let row = cursor.next().await?;
+        Ok((row, summary))
+    }
+
+    /// Executes the query and returns all the generated results along with the [`Summary`].
+    ///
+    /// The summary is sent by ClickHouse in the `X-ClickHouse-Summary` header.
+    /// To make it describe the whole query rather than its beginning, the
+    /// `wait_end_of_query` option is enabled, so the server buffers the entire
+    /// response before sending it. Thus, it isn't suitable for huge results.
     ///
     /// Note that `T` must be owned.
-    pub async fn fetch_all<T>(self) -> Result<Vec<T>>
+    pub async fn fetch_all_with_summary<T>(self) -> Result<(Vec<T>, Summary)>
     where
         T: DbRow + for<'b> Deserialize<'b> + Send,
     {
-        let mut result = Vec::new();
-        let mut cursor = self.fetch::<T>()?;
+        let mut cursor = self.fetch_with_summary::<T>()?;
+        let summary = Summary::from_headers(cursor.headers().await?)?;
 
-        while let Some(row) = cursor.next().await? {
+        let mut result = Vec::new();
+        while let Some(row) = cursor.next_owned().await? {
             result.push(row);
         }
 
-        Ok(result)
+        Ok((result, summary))
     }
 
-    /// Executes the query and returns the bytes from clickhouse.
-    /// This returns a result as when processing the bytes. we look at them and check
-    /// for clickhouse errors.
-    pub async fn fetch_raw<T>(mut self) -> Result<Vec<u8>>
+    fn fetch_with_summary<T: DbRow>(mut self) -> Result<RowCursor<T>> {
+        self.client
+            .options
+            .insert("wait_end_of_query".into(), "1".into());
+        self.fetch()
+    }
+
+    /// Executes the query and returns all the generated results along with their count,
+    /// e.g. to show progress as "loading X of N rows".
+    ///
+    /// The count is `result_rows` of the [`Summary`] from the `X-ClickHouse-Summary`
+    /// header, not `read_rows`, which also counts filtered out rows. The header is
+    /// received before rows, so no additional `SELECT count()` is sent.
+    /// The `wait_end_of_query` option is enabled for that,
+    /// see [`Query::fetch_all_with_summary`] for details.
+    /// If the server doesn't send the summary or `result_rows` in it (older versions),
+    /// the number of returned rows is used instead.
+    ///
+    /// Note that `T` must be owned.
+    pub async fn fetch_all_with_count<T>(self) -> Result<(Vec<T>, u64)>
     where
-        T: DbRow + for<'b> Deserialize<'b>,
+        T: DbRow + for<'b> Deserialize<'b> + Send,
     {
-        self.sql.bind_fields::<T>();
-        self.sql.append(" FORMAT RowBinary");
+        let mut cursor = self.fetch_with_summary::<T>()?;
+        let summary = Summary::from_headers(cursor.headers().await?).ok();
 
-        let mut res = self.do_execute(true)?;
-        let chunks = res.chunks_slow().await?;
+        let mut result = Vec::new();
+        while let Some(row) = cursor.next_owned().await? {
+            result.push(row);
+        }
+
+        // Absent `result_rows` is zero, which is valid only for empty results.
+        let count = summary
+            .map(|summary| summary.result_rows)
+            .filter(|&count| count > 0 || result.is_empty())
+            .unwrap_or(result.len() as u64);
+
+        Ok((result, count))
+    }
 
+    /// Executes the query and returns all the generated results, collected into a Vec.
+    ///
+    /// Note that `T` must be owned.
+    pub async fn fetch_all<T>(self) -> Result<Vec<T>>
+    where
+        T: DbRow + for<'b> Deserialize<'b> + Send,
+    {
         let mut result = Vec::new();
-        while let Some(next) = chunks.next().await {
-            result.put(next?);
+        let mut cursor = self.fetch::<T>()?;
+
+        while let Some(row) = cursor.next_owned().await? {
+            result.push(row);
         }
 
         Ok(result)
     }
 
+    /// Executes the query as `shards` concurrent queries over contiguous parts of
+    /// `range` and concatenates their results in order, e.g. to scan a huge table
+    /// faster than a single connection allows.
+    ///
+    /// The query must end with two unbound `?` placeholders, which are bound to
+    /// inclusive bounds of every part, e.g. `WHERE id BETWEEN ? AND ?`. Other `?` must
+    /// be bound. Parts differ in length by at most one, and `shards` is reduced to
+    /// the length of `range` if it's longer. An empty `range` gives no rows.
+    ///
+    /// Note that `T` must be owned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// # let client = clickhouse::Client::default();
+    /// let ids = client
+    ///     .query("SELECT id FROM some WHERE kind = ? AND id BETWEEN ? AND ?")
+    ///     .bind("foo")
+    ///     .fetch_all_split::<u64>(0..1_000_000, 4)
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn fetch_all_split<T>(self, range: Range<u64>, shards: usize) -> Result<Vec<T>>
+    where
+        T: DbRow + for<'b> Deserialize<'b> + Send,
+    {
+        let unbound = self.sql.unbound_args()?;
+        if unbound != 2 {
+            return Err(Error::InvalidParams(
+                format!("the query must have two unbound `?` for bounds, but has {unbound}").into(),
+            ));
+        }
+
+        if shards == 0 {
+            return Err(Error::InvalidParams("`shards` must be positive".into()));
+        }
+
+        let queries = split_range(range, shards).into_iter().map(|part| {
+            self.clone()
+                .bind(part.start)
+                .bind(part.end - 1)
+                .fetch_all::<T>()
+        });
+
+        let parts = future::try_join_all(queries).await?;
+        Ok(parts.into_iter().flatten().collect())
+    }
+
+    /// Executes the query and returns the bytes from clickhouse.
+    /// This returns a result as when processing the bytes. we look at them and check
+    /// for clickhouse errors.
+    ///
+    /// The bytes are decompressed as for [`Query::fetch_raw_bytes`].
+    pub async fn fetch_raw<T>(mut self) -> Result<Vec<u8>>
+    where
+        T: DbRow + for<'b> Deserialize<'b>,
+    {
+        self.sql.bind_fields::<T>();
+        self.sql.append(" FORMAT RowBinary");
+        self.fetch_raw_bytes().await
+    }
+
+    /// Executes the query as is and returns the whole response, e.g. to use a format
+    /// other than `RowBinary`, which must be specified in the query then.
+    ///
+    /// Errors are detected as for other methods, but a response isn't parsed at all.
+    ///
+    /// The response is decompressed exactly as by cursors, so the bytes are always
+    /// the output of the `FORMAT`, whichever [`Client::with_compression`] is used
+    /// to transfer it.
+    ///
+    /// # Examples
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// # let client = clickhouse::Client::default();
+    /// let json = client
+    ///     .query("SELECT number FROM system.numbers LIMIT 3 FORMAT JSONEachRow")
+    ///     .fetch_raw_bytes()
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn fetch_raw_bytes(self) -> Result<Vec<u8>> {
+        let mut res = self.do_execute(true)?;
+        let chunks = res.chunks_slow().await?;
+
+        let mut result = Vec::new();
+        while let Some(next) = chunks.next().await {
+            result.put(next?);
+        }
+
+        Ok(result)
+    }
+
+    /// Executes the query with `FORMAT CSVWithNames` if `with_names`, or `FORMAT CSV`
+    /// otherwise, and streams the output, e.g. to hand it off to other tools.
+    ///
+    /// Chunks are passed as received, so they aren't aligned to rows, and a row can be
+    /// split between chunks. Errors are detected as for other methods, and the stream
+    /// ends after the first one.
+    ///
+    /// # Examples
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// use futures::TryStreamExt;
+    ///
+    /// # let client = clickhouse::Client::default();
+    /// let mut csv = client
+    ///     .query("SELECT number, toString(number) FROM system.numbers LIMIT 3")
+    ///     .fetch_csv(true)?;
+    ///
+    /// let mut file = Vec::new();
+    /// while let Some(chunk) = csv.try_next().await? {
+    ///     file.extend_from_slice(&chunk);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn fetch_csv(
+        mut self,
+        with_names: bool,
+    ) -> Result<impl Stream<Item = Result<Bytes>> + Unpin> {
+        if with_names {
+            self.sql.append(" FORMAT CSVWithNames");
+        } else {
+            self.sql.append(" FORMAT CSV");
+        }
+
+        let response = self.do_execute(true)?;
+        let stream = stream::unfold(Some(response), |response| async move {
+            let mut response = response?;
+            let chunk = match response.chunks_slow().await {
+                Ok(chunks) => chunks.next().await?,
+                Err(err) => Err(err),
+            };
+
+            match chunk {
+                Ok(chunk) => Some((Ok(chunk), Some(response))),
+                Err(err) => Some((Err(err), None)),
+            }
+        });
+
+        Ok(Box::pin(stream))
+    }
+
+    /// Streams results of the query into `insert` without deserializing rows,
+    /// e.g. to copy data between tables or servers. `?fields` expands to fields
+    /// of `T`, which are also columns of the `INSERT`.
+    ///
+    /// RowBinary is passed through as is, so the query must produce values of the
+    /// same types as columns of the destination table. The `INSERT` is aborted if
+    /// the query fails, and ended otherwise.
+    ///
+    /// # Examples
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// # #[derive(clickhouse::Row, serde::Serialize)]
+    /// # struct MyRow { no: u32 }
+    /// # let client = clickhouse::Client::default();
+    /// let insert = client.insert::<MyRow>("dst")?;
+    /// client
+    ///     .query("SELECT ?fields FROM src WHERE no > 10")
+    ///     .pipe_into(insert)
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn pipe_into<T>(mut self, mut insert: Insert<T>) -> Result<()>
+    where
+        T: DbRow + InsertRow + Serialize,
+    {
+        self.sql.bind_fields::<T>();
+        self.sql.append(" FORMAT RowBinary");
+
+        let mut res = self.do_execute(true)?;
+        let chunks = res.chunks_slow().await?;
+
+        while let Some(chunk) = chunks.next().await {
+            insert.write_raw(&chunk?).await?;
+        }
+
+        insert.end().await
+    }
+
+    // `KILL QUERY` is forbidden in read-only mode, so it's sent as a writing query.
+    pub(crate) async fn kill(mut self, query_id: &str) -> Result<bool> {
+        self.client.options.remove(QUERY_ID);
+        self.client
+            .options
+            .insert("param_query_id".into(), query_id.into());
+        self.sql.append(" FORMAT RowBinaryWithNamesAndTypes");
+
+        let mut cursor = NamedRowBinaryCursor::new(self.do_execute(false)?);
+        let mut found = false;
+        while cursor.next().await?.is_some() {
+            found = true;
+        }
+
+        Ok(found)
+    }
+
     pub(crate) fn do_execute(self, read_only: bool) -> Result<Response> {
-        let query = self.sql.finish()?;
+        self.do_execute_with(read_only, None)
+    }
+
+    /// Sends the query, either alone or in the URL followed by `data` in the body.
+    fn do_execute_with(self, read_only: bool, data: Option<Body>) -> Result<Response> {
+        let mut query = self.sql.finish()?;
+
+        let compression = response_compression(&self.client, &query);
+
+        let in_query = read_only && self.client.settings_in_query;
+        if in_query {
+            append_settings(&mut query, &self.client.options)?;
+        }
 
         let mut url =
             Url::parse(&self.client.url).map_err(|err| Error::InvalidParams(Box::new(err)))?;
         let mut pairs = url.query_pairs_mut();
         pairs.clear();
 
-        if let Some(database) = &self.client.database {
+        // An empty name means the server's default database.
+        if let Some(database) = self.client.database.as_ref().filter(|db| !db.is_empty()) {
             pairs.append_pair("database", database);
         }
 
-        let use_post = !read_only || query.len() > MAX_QUERY_LEN_TO_USE_GET;
+        let use_post = match self.method {
+            Some(Method::POST) => true,
+            Some(Method::GET) if read_only && data.is_none() => false,
+            Some(method) => {
+                let reason = format!("the query can't be sent by {method}");
+                return Err(Error::InvalidParams(reason.into()));
+            }
+            None => !read_only || data.is_some() || query.len() > MAX_QUERY_LEN_TO_USE_GET,
+        };
         let method = if use_post { Method::POST } else { Method::GET };
 
-        let (body, content_length) = if use_post {
+        #[allow(unused_mut)]
+        let mut gzipped = false;
+
+        let (body, content_length) = if data.is_some() {
+            // The data takes the body, so the query is sent in the URL as by `Insert`.
+            pairs.append_pair("query", &query);
+            (Bytes::new(), 0)
+        } else if use_post {
             if read_only {
                 pairs.append_pair("readonly", "1");
             }
+
+            #[cfg(feature = "gzip")]
+            let query = if self.client.gzip_requests && query.len() >= MIN_QUERY_LEN_TO_GZIP {
+                gzipped = true;
+                gzip(query.as_bytes())?
+            } else {
+                query.into_bytes()
+            };
+
             let len = query.len();
-            (Body::from(query), len)
+            (Bytes::from(query), len)
         } else {
             pairs.append_pair("query", &query);
-            (Body::empty(), 0)
+            (Bytes::new(), 0)
         };
 
-        if self.client.compression.is_lz4() {
+        if compression.is_lz4() {
             pairs.append_pair("compress", "1");
         }
 
         for (name, value) in &self.client.options {
-            pairs.append_pair(name, value);
+            if !(in_query && is_setting(name)) {
+                pairs.append_pair(name, value);
+            }
         }
         drop(pairs);
 
+        let span = RequestSpan::new(&method, &url, content_length);
         let mut builder = Request::builder().method(method).uri(url.as_str());
 
-        if content_length == 0 {
+        if data.is_some() {
+            // The length is unknown, so the body is sent with `Transfer-Encoding: chunked`.
+        } else if content_length == 0 {
             builder = builder.header(CONTENT_LENGTH, "0");
         } else {
             builder = builder.header(CONTENT_LENGTH, content_length.to_string());
         }
 
-        if let Some(user) = &self.client.user {
-            builder = builder.header("X-ClickHouse-User", user);
+        if gzipped {
+            builder = builder.header(CONTENT_ENCODING, "gzip");
         }
 
-        if let Some(password) = &self.client.password {
-            builder = builder.header("X-ClickHouse-Key", password);
-        }
+        builder = auth::authorize(builder, &self.client);
 
         let request = builder
             .body(body)
             .map_err(|err| Error::InvalidParams(Box::new(err)))?;
 
-        let future = self.client.client._request(request);
-        Ok(Response::new(future, self.client.compression))
+        if let Some(data) = data {
+            return self.client.send(request.map(|_| data), compression, span);
+        }
+
+        // Only queries without side effects can be resent to another location.
+        if read_only {
+            self.client.send_replayable(request, compression, span)
+        } else {
+            self.client.send(request.map(Body::from), compression, span)
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder
+        .write_all(data)
+        .and_then(|_| encoder.finish())
+        .map_err(|err| Error::Compression(Box::new(err)))
+}
+
+// Splits `range` into at most `shards` non-empty contiguous parts of almost equal length.
+fn split_range(range: Range<u64>, shards: usize) -> Vec<Range<u64>> {
+    let len = range.end.saturating_sub(range.start);
+    let shards = (shards as u64).min(len);
+    if shards == 0 {
+        return Vec::new();
+    }
+
+    let (base, rest) = (len / shards, len % shards);
+    let mut start = range.start;
+
+    (0..shards)
+        .map(|i| {
+            let end = start + base + u64::from(i < rest);
+            let part = start..end;
+            start = end;
+            part
+        })
+        .collect()
+}
+
+/// A query template to derive queries binding different values,
+/// e.g. to execute the same query many times with different arguments.
+///
+/// Created by [`Client::prepare`]. Derived queries are copies of the template,
+/// so they have the same settings as the client at the moment of preparing,
+/// and can be configured further.
+///
+/// # Example
+///
+/// ```
+/// # async fn example() -> clickhouse::error::Result<()> {
+/// # let client = clickhouse::Client::default();
+/// let prepared = client.prepare("SELECT count() FROM some WHERE no BETWEEN ? AND ?");
+///
+/// for (from, to) in [(0, 10), (10, 20)] {
+///     let count = prepared.bind_all([from, to]).fetch_one::<u64>().await?;
+///     println!("{from}..{to}: {count}");
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct PreparedQuery(Query);
+
+impl PreparedQuery {
+    pub(crate) fn new(client: &Client, template: &str) -> Self {
+        Self(Query::new(client, template))
+    }
+
+    /// Returns a new query without bound values, see [`Query::bind`].
+    pub fn query(&self) -> Query {
+        self.0.clone()
+    }
+
+    /// Returns a new query binding all `values` in order, see [`Query::bind`].
+    ///
+    /// Values of different types are bound by [`PreparedQuery::query`] instead.
+    pub fn bind_all<B: Bind>(&self, values: impl IntoIterator<Item = B>) -> Query {
+        values.into_iter().fold(self.query(), Query::bind)
+    }
+}
+
+/// Defines what [`Client::execute_batch`] and [`Client::execute_all`] do
+/// after a failed statement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnError {
+    /// Doesn't start the remaining statements.
+    Stop,
+    /// Executes all statements regardless of failures.
+    Continue,
+}
+
+/// What [`Query::explain`] shows, see [the docs] for details.
+///
+/// [the docs]: https://clickhouse.com/docs/en/sql-reference/statements/explain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExplainKind {
+    /// `EXPLAIN AST`, the abstract syntax tree.
+    Ast,
+    /// `EXPLAIN SYNTAX`, the query after syntax optimizations.
+    Syntax,
+    /// `EXPLAIN QUERY TREE`, the query tree after passes of the analyzer.
+    QueryTree,
+    /// `EXPLAIN PLAN`, steps of the query plan.
+    Plan,
+    /// `EXPLAIN PIPELINE`, processors of the query pipeline.
+    Pipeline,
+    /// `EXPLAIN ESTIMATE`, numbers of rows, marks and parts to be read per table.
+    Estimate,
+}
+
+impl ExplainKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Ast => "AST",
+            Self::Syntax => "SYNTAX",
+            Self::QueryTree => "QUERY TREE",
+            Self::Plan => "PLAN",
+            Self::Pipeline => "PIPELINE",
+            Self::Estimate => "ESTIMATE",
+        }
+    }
+}
+
+/// Statistics of an executed query, sent by ClickHouse in the `X-ClickHouse-Summary` header.
+///
+/// Fields missing in the header, e.g. on older servers, are zeros.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct Summary {
+    #[serde(default, deserialize_with = "summary_value")]
+    pub read_rows: u64,
+    #[serde(default, deserialize_with = "summary_value")]
+    pub read_bytes: u64,
+    #[serde(default, deserialize_with = "summary_value")]
+    pub written_rows: u64,
+    #[serde(default, deserialize_with = "summary_value")]
+    pub written_bytes: u64,
+    #[serde(default, deserialize_with = "summary_value")]
+    pub total_rows_to_read: u64,
+    #[serde(default, deserialize_with = "summary_value")]
+    pub result_rows: u64,
+    #[serde(default, deserialize_with = "summary_value")]
+    pub result_bytes: u64,
+    #[serde(default, deserialize_with = "summary_value")]
+    pub elapsed_ns: u64,
+}
+
+impl Summary {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Result<Self> {
+        let header = headers
+            .get("X-ClickHouse-Summary")
+            .ok_or_else(|| Error::BadResponse("no X-ClickHouse-Summary header".into()))?;
+
+        serde_json::from_slice(header.as_bytes())
+            .map_err(|err| Error::BadResponse(format!("invalid X-ClickHouse-Summary: {err}")))
+    }
+}
+
+// ClickHouse sends numbers as strings, e.g. `{"read_rows":"1"}`.
+fn summary_value<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+fn response_compression(client: &Client, query: &str) -> Compression {
+    let is_small =
+        sql::limit::outer_limit(query).map_or(false, |limit| limit < client.compression_threshold);
+
+    if is_small {
+        Compression::None
+    } else {
+        client.compression
+    }
+}
+
+// Params of the HTTP interface that aren't settings, see `HTTPHandler::processQuery`.
+const NOT_SETTINGS: &[&str] = &[
+    "buffer_size",
+    "client_protocol_version",
+    "close_session",
+    "compress",
+    "database",
+    "decompress",
+    "default_format",
+    "password",
+    "query",
+    "query_id",
+    "quota_key",
+    "role",
+    "session_check",
+    "session_id",
+    "session_timeout",
+    "stacktrace",
+    "user",
+    "wait_end_of_query",
+];
+
+// Settings of the HTTP interface, which are applied before the query is parsed,
+// so they take effect only in the URL. Also all `http_*` ones.
+const HTTP_SETTINGS: &[&str] = &[
+    "add_http_cors_header",
+    "cancel_http_readonly_queries_on_client_close",
+    "enable_http_compression",
+    "send_progress_in_http_headers",
+];
+
+fn is_setting(name: &str) -> bool {
+    let is_identifier = !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_');
+
+    is_identifier
+        && !name.starts_with("param_")
+        && !name.starts_with("http_")
+        && !NOT_SETTINGS.contains(&name)
+        && !HTTP_SETTINGS.contains(&name)
+}
+
+// Appends `SETTINGS` after `FORMAT`, where it's allowed for any query with output.
+// Names are sorted to make the query deterministic.
+fn append_settings(query: &mut String, options: &HashMap<String, String>) -> Result<()> {
+    let mut settings = options
+        .iter()
+        .filter(|(name, _)| is_setting(name))
+        .collect::<Vec<_>>();
+
+    if settings.is_empty() {
+        return Ok(());
+    }
+
+    settings.sort_unstable();
+
+    let to_error = |err: fmt::Error| Error::InvalidParams(Box::new(err));
+
+    query.push_str(" SETTINGS ");
+    for (idx, (name, value)) in settings.into_iter().enumerate() {
+        if idx > 0 {
+            query.push_str(", ");
+        }
+
+        query.push_str(name);
+        query.push_str(" = ");
+        sql::escape::string(value, &mut *query).map_err(to_error)?;
     }
+
+    Ok(())
 }
 
 /// A cursor that emits rows.
-pub struct RowCursor<T>(RowBinaryCursor<T>);
+// The client is kept to cancel the query, and the row is a peeked one.
+pub struct RowCursor<T>(RowBinaryCursor<T>, Client, Option<T>);
 
 impl<T> RowCursor<T>
 where
     Self: Send,
     T: Send,
 {
+    /// Returns headers of the HTTP response, e.g. `X-ClickHouse-Query-Id`.
+    ///
+    /// Waits for the response if it hasn't been received yet, but doesn't consume the body,
+    /// so rows can still be fetched afterwards.
+    pub async fn headers(&mut self) -> Result<&HeaderMap> {
+        self.0.headers().await
+    }
+
     /// Emits the next row.
     pub async fn next<'a, 'b: 'a>(&'a mut self) -> Result<Option<T>>
     where
         T: Deserialize<'b>,
     {
+        if let Some(row) = self.2.take() {
+            return Ok(Some(row));
+        }
+
+        self.0.next().await
+    }
+
+    // Like `next()`, but for owned rows, which allows shrinking the buffer.
+    pub(crate) async fn next_owned(&mut self) -> Result<Option<T>>
+    where
+        T: for<'b> Deserialize<'b>,
+    {
+        if let Some(row) = self.2.take() {
+            return Ok(Some(row));
+        }
+
+        self.0.next_owned().await
+    }
+
+    /// Returns the next row without consuming it, so the following [`RowCursor::next`]
+    /// emits the same row, e.g. to detect boundaries of groups in ordered results.
+    /// Returns `None` at the end, no matter how many times it's called.
+    ///
+    /// The row is decoded and kept in the cursor, so `T` must be owned.
+    /// [`RowCursor::last_row_bytes`] is updated when the row is peeked.
+    pub async fn peek(&mut self) -> Result<Option<&T>>
+    where
+        T: for<'b> Deserialize<'b>,
+    {
+        if self.2.is_none() {
+            self.2 = self.0.next_owned().await?;
+        }
+
+        Ok(self.2.as_ref())
+    }
+
+    /// Emits the next batch of rows: waits for at least one row, then takes all rows
+    /// already received without waiting for more data. Returns `None` at the end.
+    ///
+    /// RowBinary has no framing of blocks, so batches follow chunks of the response
+    /// rather than blocks exactly. However, the server sends data as blocks are ready,
+    /// so [`Query::with_max_block_size`] effectively limits batches in practice.
+    ///
+    /// If an error occurs, rows of the current batch are dropped.
+    pub async fn next_block(&mut self) -> Result<Option<Vec<T>>>
+    where
+        T: for<'b> Deserialize<'b>,
+    {
+        let mut block: Vec<T> = self.2.take().into_iter().collect();
+
+        future::poll_fn(|cx| loop {
+            match self.0.poll_next_owned(cx) {
+                Poll::Ready(Ok(Some(row))) => block.push(row),
+                Poll::Ready(Ok(None)) | Poll::Pending if !block.is_empty() => {
+                    return Poll::Ready(Ok(Some(mem::take(&mut block))));
+                }
+                Poll::Ready(Ok(None)) => return Poll::Ready(Ok(None)),
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Returns how many bytes of RowBinary (after decompression) the last row
+    /// emitted by [`RowCursor::next`] occupied. It's `0` before the first row.
+    ///
+    /// It's useful to find out which rows dominate the transferred size.
+    pub fn last_row_bytes(&self) -> usize {
+        self.0.last_row_bytes()
+    }
+
+    /// Returns a cursor that lazily applies `f` to rows as they are emitted,
+    /// e.g. to convert them into domain types. Errors are passed as is,
+    /// `f` is applied only to rows.
+    ///
+    /// Unlike [`StreamExt::map`], which is available for owned rows and maps
+    /// `Result`s, it doesn't require `T` to be owned and `futures` to be imported.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// #[derive(clickhouse::Row, serde::Deserialize)]
+    /// struct MyRow<'a> {
+    ///     no: u32,
+    ///     name: &'a str,
+    /// }
+    ///
+    /// let mut names = clickhouse::Client::default()
+    ///     .query("SELECT ?fields FROM some")
+    ///     .fetch::<MyRow<'_>>()?
+    ///     .map_rows(|row| format!("{}: {}", row.no, row.name));
+    ///
+    /// while let Some(name) = names.next().await? {
+    ///     println!("{name}");
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn map_rows<U, F>(self, f: F) -> MappedCursor<T, F>
+    where
+        F: FnMut(T) -> U,
+    {
+        MappedCursor { cursor: self, f }
+    }
+
+    /// Cancels the query by [`Client::kill_query`] and drops the cursor.
+    /// Returns whether the query was still running.
+    ///
+    /// The query id set by [`Query::with_query_id`] is used if any, otherwise it's read
+    /// from the `X-ClickHouse-Query-Id` header, which requires waiting for the response.
+    pub async fn cancel(mut self) -> Result<bool> {
+        let query_id = match self.1.options.get(QUERY_ID) {
+            Some(query_id) => query_id.clone(),
+            None => self
+                .headers()
+                .await?
+                .get("X-ClickHouse-Query-Id")
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| Error::Custom("the response has no query id".into()))?
+                .to_string(),
+        };
+
+        let client = self.1.clone();
+        drop(self);
+        client.kill_query(&query_id).await
+    }
+}
+
+/// Emits rows like [`RowCursor::next`], but it requires `T` to be owned.
+///
+/// It allows using adapters of [`futures::StreamExt`] and [`futures::TryStreamExt`],
+/// e.g. `cursor.try_collect::<Vec<_>>()` or `cursor.ready_chunks(1000)`.
+impl<T> Stream for RowCursor<T>
+where
+    T: for<'b> Deserialize<'b> + Unpin,
+{
+    type Item = Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(row) = this.2.take() {
+            return Poll::Ready(Some(Ok(row)));
+        }
+
+        this.0.poll_next_owned(cx).map(Result::transpose)
+    }
+}
+
+/// A cursor that emits rows of [`RowCursor`] transformed by a function.
+///
+/// See [`RowCursor::map_rows`] for details.
+pub struct MappedCursor<T, F> {
+    cursor: RowCursor<T>,
+    f: F,
+}
+
+impl<T, U, F> MappedCursor<T, F>
+where
+    RowCursor<T>: Send,
+    T: Send,
+    F: FnMut(T) -> U,
+{
+    /// Emits the next row, transformed.
+    pub async fn next<'a, 'b: 'a>(&'a mut self) -> Result<Option<U>>
+    where
+        T: Deserialize<'b>,
+    {
+        Ok(self.cursor.next().await?.map(&mut self.f))
+    }
+}
+
+/// Emits rows like [`MappedCursor::next`], but it requires `T` to be owned.
+impl<T, U, F> Stream for MappedCursor<T, F>
+where
+    T: for<'b> Deserialize<'b> + Unpin,
+    F: FnMut(T) -> U + Unpin,
+{
+    type Item = Result<U>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let row = ready!(Pin::new(&mut this.cursor).poll_next(cx));
+        Poll::Ready(row.map(|row| row.map(&mut this.f)))
+    }
+}
+
+/// A cursor that emits rows as `(column name, value)` pairs.
+///
+/// See [`Query::fetch_named_rows`] for details.
+pub struct NamedRowCursor(NamedRowBinaryCursor);
+
+impl NamedRowCursor {
+    /// Returns headers of the HTTP response, e.g. `X-ClickHouse-Query-Id`.
+    ///
+    /// See [`RowCursor::headers`] for details.
+    pub async fn headers(&mut self) -> Result<&HeaderMap> {
+        self.0.headers().await
+    }
+
+    /// Emits the next row.
+    pub async fn next(&mut self) -> Result<Option<Vec<(String, Value)>>> {
         self.0.next().await
     }
+
+    /// Returns the size of the last emitted row, excluding the header.
+    ///
+    /// See [`RowCursor::last_row_bytes`] for details.
+    pub fn last_row_bytes(&self) -> usize {
+        self.0.last_row_bytes()
+    }
+}
+
+/// A cursor that emits rows as JSON objects.
+///
+/// See [`Query::fetch_dynamic`] for details.
+pub struct DynamicRowCursor(JsonEachRowCursor);
+
+impl DynamicRowCursor {
+    /// Returns headers of the HTTP response, e.g. `X-ClickHouse-Query-Id`.
+    ///
+    /// See [`RowCursor::headers`] for details.
+    pub async fn headers(&mut self) -> Result<&HeaderMap> {
+        self.0.headers().await
+    }
+
+    /// Emits the next row.
+    pub async fn next(&mut self) -> Result<Option<Value>> {
+        self.0.next().await
+    }
+
+    /// Returns the size of the last emitted row in JSON.
+    ///
+    /// See [`RowCursor::last_row_bytes`] for details.
+    pub fn last_row_bytes(&self) -> usize {
+        self.0.last_row_bytes()
+    }
+}
+
+/// A cursor that emits Arrow record batches.
+///
+/// See [`Query::fetch_arrow`] for details.
+#[cfg(feature = "arrow")]
+pub struct ArrowCursor(ArrowStreamCursor);
+
+#[cfg(feature = "arrow")]
+impl ArrowCursor {
+    /// Returns headers of the HTTP response, e.g. `X-ClickHouse-Query-Id`.
+    ///
+    /// See [`RowCursor::headers`] for details.
+    pub async fn headers(&mut self) -> Result<&HeaderMap> {
+        self.0.headers().await
+    }
+
+    /// Emits the next record batch.
+    pub async fn next(&mut self) -> Result<Option<arrow_array::RecordBatch>> {
+        self.0.next().await
+    }
+}
+
+/// Emits record batches like [`ArrowCursor::next`].
+#[cfg(feature = "arrow")]
+impl Stream for ArrowCursor {
+    type Item = Result<arrow_array::RecordBatch>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().0.poll_next(cx).map(Result::transpose)
+    }
+}
+
+#[test]
+fn it_parses_summary() {
+    let mut headers = HeaderMap::new();
+    assert!(matches!(
+        Summary::from_headers(&headers),
+        Err(Error::BadResponse(_))
+    ));
+
+    headers.insert(
+        "X-ClickHouse-Summary",
+        r#"{"read_rows":"10","read_bytes":"80","written_rows":"0","written_bytes":"0","total_rows_to_read":"10","result_rows":"3","result_bytes":"24"}"#
+            .parse()
+            .unwrap(),
+    );
+
+    assert_eq!(
+        Summary::from_headers(&headers).unwrap(),
+        Summary {
+            read_rows: 10,
+            read_bytes: 80,
+            total_rows_to_read: 10,
+            result_rows: 3,
+            result_bytes: 24,
+            ..Summary::default()
+        }
+    );
+
+    headers.insert(
+        "X-ClickHouse-Summary",
+        r#"{"read_rows":"x"}"#.parse().unwrap(),
+    );
+    assert!(matches!(
+        Summary::from_headers(&headers),
+        Err(Error::BadResponse(_))
+    ));
+}
+
+#[cfg(all(test, feature = "test-util", feature = "lz4"))]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+    use crate::test::{handlers, Mock};
+
+    use clickhouse::Row;
+
+    #[tokio::test]
+    async fn it_overrides_compression() {
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::Lz4);
+
+        mock.add(handlers::provide(stream::iter([1u8])));
+        let query = client.query("SELECT 1").with_compression(Compression::None);
+        assert_eq!(query.fetch_one::<u8>().await.unwrap(), 1);
+
+        let requests = mock.requests();
+        assert_eq!(requests[0].param("compress"), None);
+
+        // The client's setting is used by other queries.
+        mock.add(handlers::failure(crate::test::status::NOT_FOUND));
+        let _ = client.query("SELECT 1").execute().await;
+
+        let requests = mock.requests();
+        assert_eq!(requests[0].param("compress").as_deref(), Some("1"));
+    }
+
+    #[tokio::test]
+    async fn it_skips_compression_below_threshold() {
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::Lz4)
+            .with_compression_threshold(100);
+
+        // The mock responds with plain RowBinary, so decoding it proves that
+        // the response wasn't expected to be compressed.
+        mock.add(handlers::provide(stream::iter(0..10u64)));
+        let sql = "SELECT number FROM system.numbers LIMIT 10";
+        let numbers = client.query(sql).fetch_all::<u64>().await.unwrap();
+        assert_eq!(numbers, (0..10).collect::<Vec<_>>());
+
+        let requests = mock.requests();
+        assert_eq!(requests[0].param("compress"), None);
+
+        // Above the threshold, compression is requested as usual.
+        mock.add(handlers::failure(crate::test::status::NOT_FOUND));
+        let sql = "SELECT number FROM system.numbers LIMIT 1000";
+        let _ = client.query(sql).fetch_all::<u64>().await;
+
+        let requests = mock.requests();
+        assert_eq!(requests[0].param("compress").as_deref(), Some("1"));
+    }
+
+    #[tokio::test]
+    async fn it_sends_sequential_consistency() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        mock.add(handlers::provide(stream::iter([1u8])));
+        let query = client.query("SELECT 1").with_sequential_consistency(true);
+        query.fetch_one::<u8>().await.unwrap();
+
+        let requests = mock.requests();
+        let value = requests[0].param("select_sequential_consistency");
+        assert_eq!(value.as_deref(), Some("1"));
+    }
+
+    #[tokio::test]
+    async fn it_sends_quota_keys() {
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_quota_key("tenant-1");
+
+        mock.add(handlers::provide(stream::iter([1u8])));
+        client.query("SELECT 1").fetch_one::<u8>().await.unwrap();
+
+        mock.add(handlers::provide(stream::iter([1u8])));
+        let query = client.query("SELECT 1").with_quota_key("tenant-2");
+        query.fetch_one::<u8>().await.unwrap();
+
+        mock.add(handlers::provide(stream::iter([1u8])));
+        let query = client
+            .query("SELECT 1")
+            .with_quota_key("tenant-2")
+            .with_option("quota_key", "tenant-3");
+        query.fetch_one::<u8>().await.unwrap();
+
+        let keys = mock
+            .requests()
+            .iter()
+            .map(|request| request.param("quota_key").unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["tenant-1", "tenant-2", "tenant-3"]);
+    }
+
+    #[tokio::test]
+    async fn it_sends_settings_in_query() {
+        let mock = Mock::new();
+        let comment = "it's\n".repeat(2000);
+        let mut client = Client::default()
+            .with_url(mock.url())
+            .with_settings_in_query(true)
+            .with_option("log_comment", &comment)
+            .with_option("session_id", "my-session")
+            .with_option("param_no", "42")
+            .with_option("default_format", "TSV")
+            .with_option("wait_end_of_query", "1")
+            .with_option("enable_http_compression", "1")
+            .with_option("send_progress_in_http_headers", "1")
+            .with_option("http_headers_progress_interval_ms", "500");
+        for idx in 0..100 {
+            client = client.with_option(format!("custom_setting_{idx:03}"), "x".repeat(100));
+        }
+
+        mock.add(handlers::provide(stream::iter([1u8])));
+        let query = client.query("SELECT {no:UInt8}").with_query_id("my-id");
+        query.fetch_one::<u8>().await.unwrap();
+
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(request.method, Method::POST);
+        let url = request.uri.to_string();
+        assert!(url.len() < 400, "{}", url);
+        assert!(!url.contains("log_comment") && !url.contains("custom_setting"));
+        assert_eq!(request.param("session_id").as_deref(), Some("my-session"));
+        assert_eq!(request.param("param_no").as_deref(), Some("42"));
+        assert_eq!(request.param("query_id").as_deref(), Some("my-id"));
+        // Options of the HTTP interface take effect only in the URL.
+        for (name, value) in [
+            ("default_format", "TSV"),
+            ("wait_end_of_query", "1"),
+            ("enable_http_compression", "1"),
+            ("send_progress_in_http_headers", "1"),
+            ("http_headers_progress_interval_ms", "500"),
+        ] {
+            assert_eq!(request.param(name).as_deref(), Some(value), "{}", name);
+        }
+
+        let sql = request.sql();
+        let (query, settings) = sql.split_once(" SETTINGS ").unwrap();
+        assert_eq!(query, "SELECT {no:UInt8} FORMAT RowBinary");
+        let settings = settings.split(", ").collect::<Vec<_>>();
+        assert_eq!(settings.len(), 101);
+        let expected = format!("custom_setting_000 = '{}'", "x".repeat(100));
+        assert_eq!(settings[0], expected);
+        let expected = format!("log_comment = '{}'", "it\\'s\\n".repeat(2000));
+        assert_eq!(settings[100], expected);
+
+        // Writing queries aren't affected.
+        mock.add(handlers::record_ddl());
+        client.query("SELECT 1").execute().await.unwrap();
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(request.sql(), "SELECT 1");
+        assert_eq!(request.param("log_comment"), Some(comment));
+    }
+
+    #[tokio::test]
+    async fn it_pipes_into_insert() {
+        #[derive(Debug, PartialEq, Row, Serialize, Deserialize)]
+        struct MyRow {
+            no: u32,
+            name: String,
+        }
+
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        let rows = (0..10_000).map(|no| MyRow {
+            no,
+            name: format!("name-{no}"),
+        });
+        mock.add(handlers::provide(stream::iter(rows)));
+        let recording = mock.add(handlers::record());
+
+        let insert = client.insert::<MyRow>("dst").unwrap();
+        let query = client.query("SELECT ?fields FROM src");
+        query.pipe_into(insert).await.unwrap();
+
+        let recorded: Vec<MyRow> = recording.collect().await;
+        assert_eq!(recorded.len(), 10_000);
+        assert_eq!(recorded[42].name, "name-42");
+
+        let requests = mock.requests();
+        assert_eq!(
+            requests[0].sql(),
+            "SELECT `no`,`name` FROM src FORMAT RowBinary"
+        );
+        assert_eq!(
+            requests[1].sql(),
+            "INSERT INTO dst(`no`,`name`) FORMAT RowBinary"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_fetches_raw_bytes_as_is() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        mock.add(handlers::provide(stream::iter(*b"[1]\n")));
+        let sql = "SELECT 1 FORMAT JSONCompactEachRow";
+        let bytes = client.query(sql).fetch_raw_bytes().await.unwrap();
+        assert_eq!(bytes, b"[1]\n");
+        assert_eq!(mock.requests()[0].sql(), sql);
+
+        mock.add(handlers::failure(crate::test::status::BAD_REQUEST));
+        let result = client.query(sql).fetch_raw_bytes().await;
+        assert!(matches!(result, Err(Error::BadResponse(_))));
+    }
+
+    #[cfg(feature = "lz4")]
+    #[tokio::test]
+    async fn it_fetches_raw_bytes_decompressed() {
+        use crate::compression::lz4;
+
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::Lz4);
+
+        let tsv = b"1\tfoo\n2\tit\\'s\n";
+        let compressed = lz4::compress(tsv, Compression::Lz4).unwrap();
+        assert_ne!(&compressed[..], &tsv[..]);
+
+        mock.add(handlers::raw(compressed));
+        let sql = "SELECT no, name FROM some FORMAT TSV";
+        let bytes = client.query(sql).fetch_raw_bytes().await.unwrap();
+        assert_eq!(bytes, tsv);
+
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(request.param("compress").as_deref(), Some("1"));
+        assert_eq!(request.sql(), sql);
+    }
+
+    #[tokio::test]
+    async fn it_displays_sent_sql() {
+        #[allow(unused)]
+        #[derive(Row, Deserialize)]
+        struct MyRow {
+            no: u32,
+            #[serde(rename = "type")]
+            kind: String,
+        }
+
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        let query = client
+            .query("SELECT ?fields FROM ? WHERE no IN ? AND kind = ?")
+            .bind(sql::Identifier("some"))
+            .bind(sql::InList(&[1, 2]))
+            .bind("it's");
+        let expected = "SELECT `no`,`type` FROM `some` WHERE no IN (1,2) AND kind = 'it\\'s'";
+        assert_eq!(
+            query.sql_display_fetch::<MyRow>().unwrap(),
+            format!("{expected} FORMAT RowBinary")
+        );
+        assert!(matches!(query.sql_display(), Err(Error::InvalidParams(_))));
+
+        mock.add(handlers::provide(stream::iter([0u8])));
+        let rendered = query.sql_display_fetch::<MyRow>().unwrap();
+        let _ = query.fetch_all::<MyRow>().await;
+        assert_eq!(mock.requests()[0].sql(), rendered);
+
+        #[allow(unused)]
+        #[derive(Row, Deserialize)]
+        struct Renamed {
+            #[clickhouse(rename = "user.id")]
+            user_id: u32,
+            #[clickhouse(rename = "count()")]
+            count: u64,
+        }
+
+        let query = client.query("SELECT ?fields FROM (SELECT user.id, count() FROM some)");
+        assert_eq!(
+            query.sql_display_fetch::<Renamed>().unwrap(),
+            "SELECT `user.id`,`count()` FROM (SELECT user.id, count() FROM some) FORMAT RowBinary"
+        );
+
+        let query = client.query("SELECT 1 FROM some WHERE no = ?").bind(42);
+        assert_eq!(
+            query.sql_display().unwrap(),
+            "SELECT 1 FROM some WHERE no = 42"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_fetches_huge_rows_with_limited_buffer() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        let huge = "x".repeat(1 << 20);
+        let small = (0..1000).map(|no| format!("small-{no}"));
+        let rows = std::iter::once(huge.clone()).chain(small);
+        mock.add(handlers::provide(stream::iter(rows)));
+
+        let rows = client
+            .query("SELECT s FROM some")
+            .with_buffer_capacity(64, 4096)
+            .fetch_all::<String>()
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1001);
+        assert_eq!(rows[0], huge);
+        assert_eq!(rows[1000], "small-999");
+    }
+
+    #[tokio::test]
+    async fn it_checks_bound_args_before_sending() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        let result = client.query("SELECT ?, ?").bind(1).execute().await;
+        assert!(matches!(result, Err(Error::InvalidParams(_))));
+
+        let result = client.query("SELECT ?").bind(1).bind(2).execute().await;
+        assert!(matches!(result, Err(Error::InvalidParams(_))));
+
+        assert!(mock.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_binds_raw_fragments() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        let query = client
+            .query("SELECT 1 FROM some WHERE ts > ? AND name = ? AND kind = ?")
+            .bind_raw("now()")
+            .bind("it's")
+            .bind_raw("'a?'");
+        assert_eq!(
+            query.sql_display().unwrap(),
+            "SELECT 1 FROM some WHERE ts > now() AND name = 'it\\'s' AND kind = 'a?'"
+        );
+
+        mock.add(handlers::provide(stream::iter([1u8])));
+        let rendered = query.sql_display_fetch::<u8>().unwrap();
+        assert_eq!(query.fetch_one::<u8>().await.unwrap(), 1);
+        assert_eq!(mock.requests()[0].sql(), rendered);
+    }
+
+    #[tokio::test]
+    async fn it_streams_bodies_of_unknown_length() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        // `unfold` has no upper bound of the size, so the body can't be pre-sized.
+        let lines = stream::unfold(0, |no| async move {
+            (no < 1000).then(|| (Ok::<_, Error>(format!("{no},it's\n")), no + 1))
+        });
+        assert_eq!(lines.size_hint(), (0, None));
+
+        mock.add(handlers::provide(stream::iter(Vec::<u8>::new())));
+        client
+            .query("INSERT INTO some(no, name) FORMAT CSV")
+            .execute_with_body(lines)
+            .await
+            .unwrap();
+
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.sql(), "INSERT INTO some(no, name) FORMAT CSV");
+        assert!(!request.headers.contains_key(CONTENT_LENGTH));
+        assert_eq!(request.headers["Transfer-Encoding"], "chunked");
+        let expected = (0..1000)
+            .map(|no| format!("{no},it's\n"))
+            .collect::<String>();
+        assert_eq!(request.body, expected);
+
+        let lines = stream::iter([Ok::<_, Error>("1\n")]);
+        let result = client
+            .query("INSERT INTO some(no) FORMAT CSV")
+            .with_method(Method::GET)
+            .execute_with_body(lines)
+            .await;
+        assert!(matches!(result, Err(Error::InvalidParams(_))));
+    }
+
+    #[tokio::test]
+    async fn it_reports_readonly_violations() {
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_option("readonly", "1");
+
+        let reason = "Cannot execute query in readonly mode. (READONLY)";
+        mock.add(handlers::exception(164, reason));
+        let err = client
+            .query("INSERT INTO some VALUES (1)")
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::ReadOnlyViolation(message) if message.contains(reason)),
+            "{:?}",
+            err
+        );
+        assert_eq!(mock.requests()[0].param("readonly").as_deref(), Some("1"));
+
+        mock.add(handlers::exception(60, "Table default.some doesn't exist"));
+        let err = client.query("SELECT 1").execute().await.unwrap_err();
+        assert!(matches!(err, Error::ServerException { code: 60, .. }));
+    }
+
+    #[tokio::test]
+    async fn it_kills_queries() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        // RowBinaryWithNamesAndTypes: one `kill_status String` column.
+        let header = b"\x01\x0bkill_status\x06String";
+        let response = [&header[..], b"\x07waiting"].concat();
+        mock.add(handlers::provide(stream::iter(response)));
+        assert!(client.kill_query("some-id").await.unwrap());
+
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.param("param_query_id").as_deref(), Some("some-id"));
+        assert_eq!(
+            request.sql(),
+            "KILL QUERY WHERE query_id = {query_id:String} FORMAT RowBinaryWithNamesAndTypes"
+        );
+
+        mock.add(handlers::provide(stream::iter(header.to_vec())));
+        assert!(!client.kill_query("another-id").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn it_cancels_cursors_by_own_query_id() {
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_option("query_id", "ignored");
+
+        mock.add(handlers::provide(stream::iter([1u8])));
+        let mut cursor = client
+            .query("SELECT 1")
+            .with_query_id("my-id")
+            .fetch::<u8>()
+            .unwrap();
+        assert_eq!(cursor.next().await.unwrap(), Some(1));
+
+        mock.add(handlers::provide(stream::iter(
+            b"\x01\x0bkill_status\x06String".to_vec(),
+        )));
+        assert!(!cursor.cancel().await.unwrap());
+
+        let requests = mock.requests();
+        assert_eq!(requests[0].param("query_id").as_deref(), Some("my-id"));
+        assert_eq!(
+            requests[1].param("param_query_id").as_deref(),
+            Some("my-id")
+        );
+        assert_eq!(requests[1].param("query_id"), None);
+    }
+
+    #[test]
+    #[allow(clippy::single_range_in_vec_init, clippy::reversed_empty_ranges)]
+    fn it_splits_ranges() {
+        assert_eq!(split_range(0..10, 3), [0..4, 4..7, 7..10]);
+        assert_eq!(split_range(10..20, 1), [10..20]);
+        assert_eq!(split_range(5..7, 4), [5..6, 6..7]);
+        assert!(split_range(3..3, 2).is_empty());
+        assert!(split_range(3..1, 2).is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_generates_rows_with_question_marks_in_names() {
+        #[derive(Debug, PartialEq, Row, Deserialize)]
+        struct MyRow {
+            #[serde(rename = "a?")]
+            a: u8,
+        }
+
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        mock.add(handlers::provide(stream::iter([1u8])));
+        let mut cursor = client.generate::<MyRow>(1).unwrap();
+        assert_eq!(cursor.next().await.unwrap(), Some(MyRow { a: 1 }));
+
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(
+            request.sql(),
+            "SELECT toUInt8(number) AS `a?` FROM system.numbers LIMIT 1 FORMAT RowBinary"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_fetches_split_ranges() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        for _ in 0..3 {
+            mock.add(handlers::provide(stream::iter([7u8])));
+        }
+        let rows = client
+            .query("SELECT n FROM some WHERE kind = ? AND n BETWEEN ? AND ?")
+            .bind("foo")
+            .fetch_all_split::<u8>(0..8, 3)
+            .await
+            .unwrap();
+        assert_eq!(rows, [7, 7, 7]);
+
+        let mut sqls: Vec<_> = mock.requests().iter().map(|r| r.sql()).collect();
+        sqls.sort();
+        let expected = ["0 AND 2", "3 AND 5", "6 AND 7"].map(|bounds| {
+            format!("SELECT n FROM some WHERE kind = 'foo' AND n BETWEEN {bounds} FORMAT RowBinary")
+        });
+        assert_eq!(sqls, expected);
+    }
+
+    #[tokio::test]
+    async fn it_checks_split_placeholders() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        let query = client.query("SELECT n FROM some WHERE n BETWEEN ? AND ?");
+        let result = query.clone().bind(1).fetch_all_split::<u8>(0..8, 3).await;
+        assert!(matches!(result, Err(Error::InvalidParams(_))));
+
+        let result = query.clone().fetch_all_split::<u8>(0..8, 0).await;
+        assert!(matches!(result, Err(Error::InvalidParams(_))));
+
+        // A failed binding is reported instead of the number of placeholders.
+        let result = query
+            .bind(std::collections::HashMap::<u32, u32>::new())
+            .fetch_all_split::<u8>(0..8, 3)
+            .await;
+        let err = result.unwrap_err();
+        assert!(matches!(err, Error::InvalidParams(_)));
+        assert!(err.to_string().contains("unsupported"), "{:?}", err);
+
+        let rows = client
+            .query("SELECT n FROM some WHERE n BETWEEN ? AND ?")
+            .fetch_all_split::<u8>(8..8, 3)
+            .await
+            .unwrap();
+        assert!(rows.is_empty());
+
+        assert!(mock.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_sends_custom_headers() {
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_header("X-Api-Token", "token")
+            .with_header("X-Tenant-Id", "client");
+
+        mock.add(handlers::provide(stream::iter([1u8])));
+        let query = client
+            .query("SELECT 1")
+            .with_header("x-tenant-id", "query")
+            .with_header("X-Request-Id", "42");
+        query.fetch_one::<u8>().await.unwrap();
+
+        let headers = mock.requests().pop().unwrap().headers;
+        assert_eq!(headers["X-Api-Token"], "token");
+        assert_eq!(headers["X-Request-Id"], "42");
+        let tenants = headers.get_all("X-Tenant-Id").iter().collect::<Vec<_>>();
+        assert_eq!(tenants, ["query"]);
+
+        // Other requests use headers of the client only.
+        mock.add(handlers::record_ddl());
+        client.query("CREATE TABLE t").execute().await.unwrap();
+        let headers = mock.requests().pop().unwrap().headers;
+        assert_eq!(headers["X-Tenant-Id"], "client");
+        assert!(!headers.contains_key("X-Request-Id"));
+
+        #[derive(Row, Serialize, Deserialize)]
+        struct MyRow {
+            no: u8,
+        }
+
+        let _recording = mock.add(handlers::record::<MyRow>());
+        let mut insert = client.insert::<MyRow>("t").unwrap();
+        insert.write(&MyRow { no: 1 }).await.unwrap();
+        insert.end().await.unwrap();
+        let headers = mock.requests().pop().unwrap().headers;
+        assert_eq!(headers["X-Api-Token"], "token");
+
+        let result = client
+            .query("SELECT 1")
+            .with_header("bad header", "value")
+            .execute()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidParams(_))));
+    }
+
+    // Responds with `response` as is, then closes the connection.
+    async fn serve_raw(response: impl AsRef<[u8]> + Send + 'static) -> String {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let _ = socket.read(&mut [0; 4096]).await;
+            socket.write_all(response.as_ref()).await.unwrap();
+            socket.shutdown().await.unwrap();
+        });
+
+        format!("http://{addr}")
+    }
+
+    // Responds with `chunks` after `delay` each. Unlike sockets here and in `Mock`,
+    // the connection has almost no buffer, so the server is blocked until the
+    // client reads a chunk, like a real server sending results of a huge query.
+    fn serve_slowly(chunks: Vec<Vec<u8>>, delay: std::time::Duration) -> Client {
+        use std::{
+            io,
+            pin::Pin,
+            sync::{Arc, Mutex},
+            task::{Context, Poll},
+        };
+
+        use hyper::client::connect::{Connected, Connection};
+        use tokio::io::{
+            duplex, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf,
+        };
+
+        #[derive(Clone)]
+        struct Connector(Arc<Mutex<Option<DuplexStream>>>);
+
+        impl hyper::service::Service<hyper::Uri> for Connector {
+            type Response = Conn;
+            type Error = io::Error;
+            type Future = future::Ready<io::Result<Conn>>;
+
+            fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn call(&mut self, _uri: hyper::Uri) -> Self::Future {
+                let stream = self.0.lock().unwrap().take().expect("one connection");
+                future::ready(Ok(Conn(stream)))
+            }
+        }
+
+        struct Conn(DuplexStream);
+
+        impl Connection for Conn {
+            fn connected(&self) -> Connected {
+                Connected::new()
+            }
+        }
+
+        impl AsyncRead for Conn {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.0).poll_read(cx, buf)
+            }
+        }
+
+        impl AsyncWrite for Conn {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                Pin::new(&mut self.0).poll_write(cx, buf)
+            }
+
+            fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.0).poll_flush(cx)
+            }
+
+            fn poll_shutdown(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.0).poll_shutdown(cx)
+            }
+        }
+
+        let (client_side, mut server_side) = duplex(1024);
+
+        tokio::spawn(async move {
+            let _ = server_side.read(&mut [0; 1024]).await;
+            let head = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+            server_side.write_all(head).await.unwrap();
+
+            for chunk in chunks {
+                tokio::time::sleep(delay).await;
+                let size = format!("{:x}\r\n", chunk.len());
+                server_side.write_all(size.as_bytes()).await.unwrap();
+                server_side.write_all(&chunk).await.unwrap();
+                server_side.write_all(b"\r\n").await.unwrap();
+            }
+
+            server_side.write_all(b"0\r\n\r\n").await.unwrap();
+        });
+
+        let connector = Connector(Arc::new(Mutex::new(Some(client_side))));
+        let http_client = hyper::Client::builder().build::<_, hyper::Body>(connector);
+        Client::with_http_client(http_client)
+            .with_url("http://localhost")
+            .with_compression(Compression::None)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_prefetches_chunks() {
+        use std::time::Duration;
+
+        use tokio::time::{sleep, Instant};
+
+        const CHUNKS: u64 = 4;
+        const ROWS_PER_CHUNK: u64 = 8 * 1024;
+        const DELAY: Duration = Duration::from_millis(100);
+
+        let chunks = (0..CHUNKS)
+            .map(|chunk| {
+                let rows = chunk * ROWS_PER_CHUNK..(chunk + 1) * ROWS_PER_CHUNK;
+                rows.flat_map(u64::to_le_bytes).collect()
+            })
+            .collect::<Vec<Vec<u8>>>();
+
+        // Both the server and the caller spend `DELAY` for every chunk.
+        async fn fetch(query: Query) -> Duration {
+            let start = Instant::now();
+            let mut cursor = query.fetch::<u64>().unwrap();
+            let mut expected = 0;
+            while let Some(no) = cursor.next().await.unwrap() {
+                assert_eq!(no, expected);
+                if no % ROWS_PER_CHUNK == 0 {
+                    sleep(DELAY).await;
+                }
+                expected += 1;
+            }
+            assert_eq!(expected, CHUNKS * ROWS_PER_CHUNK);
+            start.elapsed()
+        }
+
+        let client = serve_slowly(chunks.clone(), DELAY);
+        let serial = fetch(client.query("SELECT no FROM some")).await;
+
+        let client = serve_slowly(chunks, DELAY);
+        let query = client
+            .query("SELECT no FROM some")
+            .with_prefetch(1024 * 1024);
+        let prefetched = fetch(query).await;
+
+        // The server waits for the caller without prefetching, but not with it.
+        assert!(serial >= DELAY * 2 * CHUNKS as u32, "{:?}", serial);
+        assert!(prefetched < DELAY * (CHUNKS as u32 + 2), "{:?}", prefetched);
+    }
+
+    #[tokio::test]
+    async fn it_limits_row_size() {
+        use crate::rowbinary;
+
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::None);
+
+        // Rows aren't borrowed, so chunks are only accumulated.
+        let small = vec![1u32; 10];
+        let huge = vec![2u32; 100];
+        let mut response = Vec::new();
+        rowbinary::serialize_into(&mut response, &small).unwrap();
+        rowbinary::serialize_into(&mut response, &huge).unwrap();
+        let chunks = response
+            .chunks(16)
+            .map(Bytes::copy_from_slice)
+            .collect::<Vec<_>>();
+
+        mock.add(handlers::raw_chunks(stream::iter(chunks)));
+        let mut cursor = client
+            .query("SELECT ids FROM some")
+            .with_max_row_size(64)
+            .fetch::<Vec<u32>>()
+            .unwrap();
+        assert_eq!(cursor.next().await.unwrap(), Some(small));
+        let err = cursor.next().await.unwrap_err();
+        assert!(
+            matches!(err, Error::RowTooLarge(size) if size > 64 && size <= 80),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn it_prefetches_within_budget() {
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::None);
+
+        // Chunks bigger than the budget are still passed.
+        let rows = (0..1000u32).collect::<Vec<_>>();
+        mock.add(handlers::provide(stream::iter(rows.clone())));
+        let query = client.query("SELECT no FROM some").with_prefetch(1);
+        assert_eq!(query.fetch_all::<u32>().await.unwrap(), rows);
+
+        // Errors follow prefetched rows.
+        let chunks = [
+            Bytes::copy_from_slice(&1u32.to_le_bytes()),
+            Bytes::from_static(
+                b"Code: 395. DB::Exception: Oops (version 23.8.1.1 (official build))\n",
+            ),
+        ];
+        mock.add(handlers::raw_chunks(stream::iter(chunks)));
+        let query = client.query("SELECT no FROM some").with_prefetch(1024);
+        let mut cursor = query.fetch::<u32>().unwrap();
+        assert_eq!(cursor.next().await.unwrap(), Some(1));
+        let err = cursor.next().await.unwrap_err();
+        assert!(
+            matches!(err, Error::ServerException { code: 395, .. }),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn it_distinguishes_empty_and_truncated_results() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        mock.add(handlers::provide(stream::iter(Vec::<u8>::new())));
+        let err = client
+            .query("SELECT 1")
+            .fetch_one::<u32>()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::RowNotFound));
+
+        mock.add(handlers::provide(stream::iter(Vec::<u8>::new())));
+        let row = client.query("SELECT 1").fetch_optional::<u32>().await;
+        assert!(matches!(row, Ok(None)));
+
+        // A row is cut.
+        mock.add(handlers::provide(stream::iter([1u8, 2])));
+        let err = client
+            .query("SELECT 1")
+            .fetch_one::<u32>()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NotEnoughData));
+
+        // The connection is closed before the end of the chunked body.
+        let heads = [
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"[..],
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\n\x01\0\0\0\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n",
+        ];
+
+        for head in heads {
+            let client = Client::default()
+                .with_url(serve_raw(head).await)
+                .with_compression(Compression::None);
+
+            let err = client
+                .query("SELECT 1")
+                .fetch_all::<u32>()
+                .await
+                .unwrap_err();
+            assert!(matches!(err, Error::Network(_)), "{:?}", err);
+        }
+
+        let client = Client::default()
+            .with_url(serve_raw(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n").await)
+            .with_compression(Compression::None);
+        let err = client
+            .query("SELECT 1")
+            .fetch_one::<u32>()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Network(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn it_overrides_methods() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        mock.add(handlers::provide(stream::iter([1u8])));
+        let query = client.query("SELECT 1").with_method(Method::POST);
+        assert_eq!(query.fetch_one::<u8>().await.unwrap(), 1);
+
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.param("query"), None);
+        assert_eq!(request.param("readonly").as_deref(), Some("1"));
+        assert_eq!(request.sql(), "SELECT 1 FORMAT RowBinary");
+
+        // Long read-only queries can be sent by `GET`.
+        let sql = format!("SELECT '{}'", "a".repeat(MAX_QUERY_LEN_TO_USE_GET));
+        mock.add(handlers::provide(stream::iter([1u8])));
+        let query = client.query(&sql).with_method(Method::GET);
+        assert_eq!(query.fetch_one::<u8>().await.unwrap(), 1);
+
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(request.method, Method::GET);
+        assert_eq!(request.sql(), format!("{sql} FORMAT RowBinary"));
+        assert_eq!(request.param("readonly"), None);
+
+        // But other queries can't.
+        let query = client.query("CREATE TABLE t").with_method(Method::GET);
+        let err = query.execute().await.unwrap_err();
+        assert!(matches!(err, Error::InvalidParams(_)), "{:?}", err);
+
+        let query = client.query("SELECT 1").with_method(Method::PUT);
+        let err = query.execute().await.unwrap_err();
+        assert!(matches!(err, Error::InvalidParams(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn it_follows_redirects() {
+        let primary = Mock::new();
+        let replica = Mock::new();
+        let client = Client::default()
+            .with_url(primary.url())
+            .with_user("alice")
+            .with_password("secret")
+            .with_header("X-Trace", "42")
+            .with_redirects(2);
+
+        // Credentials are kept on the same origin.
+        primary.add(handlers::redirect("/other"));
+        primary.add(handlers::provide(stream::iter([1u8])));
+        let query = client.query("SELECT 1");
+        assert_eq!(query.fetch_one::<u8>().await.unwrap(), 1);
+
+        let request = primary.requests().pop().unwrap();
+        assert_eq!(request.uri.path(), "/other/");
+        assert_eq!(request.sql(), "SELECT 1 FORMAT RowBinary");
+        assert_eq!(request.headers["X-ClickHouse-User"], "alice");
+        assert_eq!(request.headers["X-ClickHouse-Key"], "secret");
+        assert_eq!(request.headers["X-Trace"], "42");
+
+        // But dropped on another one along with custom headers,
+        // unlike the method and the body.
+        let location = format!("{}/replica", replica.url());
+        primary.add(handlers::redirect(location));
+        replica.add(handlers::provide(stream::iter([2u8])));
+        let query = client.query("SELECT 2").with_method(Method::POST);
+        assert_eq!(query.fetch_one::<u8>().await.unwrap(), 2);
+
+        let request = replica.requests().pop().unwrap();
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.uri.path(), "/replica/");
+        assert_eq!(request.sql(), "SELECT 2 FORMAT RowBinary");
+        assert!(!request.headers.contains_key("X-Trace"));
+        assert!(!request.headers.contains_key("X-ClickHouse-User"));
+        assert!(!request.headers.contains_key("X-ClickHouse-Key"));
+
+        // Too many redirects.
+        for _ in 0..3 {
+            primary.add(handlers::redirect("/loop"));
+        }
+        let err = client
+            .query("SELECT 3")
+            .fetch_one::<u8>()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::BadResponse(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn it_ignores_redirects_by_default() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        mock.add(handlers::redirect("/other"));
+        let err = client
+            .query("SELECT 1")
+            .fetch_one::<u8>()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::BadResponse(_)), "{:?}", err);
+
+        // Statements with side effects are never redirected.
+        let client = client.with_redirects(1);
+        mock.add(handlers::redirect("/other"));
+        let err = client.query("CREATE TABLE t").execute().await.unwrap_err();
+        assert!(matches!(err, Error::BadResponse(_)), "{:?}", err);
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn it_explains_queries() {
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::None);
+
+        let plan = "Expression ((Project names + Projection))\n  \
+                    Filter (WHERE)\n    \
+                    ReadFromMergeTree (default.some)\n";
+        mock.add(handlers::provide(stream::iter(plan.bytes())));
+        let actual = client
+            .query("SELECT * FROM some WHERE name = ? -- comment")
+            .bind("a?")
+            .explain(ExplainKind::Plan)
+            .await
+            .unwrap();
+        assert_eq!(actual, plan);
+        assert_eq!(
+            mock.requests().pop().unwrap().sql(),
+            "EXPLAIN PLAN SELECT * FROM some WHERE name = 'a?' -- comment\nFORMAT TSVRaw"
+        );
+
+        mock.add(handlers::provide(stream::iter(b"QUERY id: 0\n".to_vec())));
+        let actual = client
+            .query("SELECT 1")
+            .explain(ExplainKind::QueryTree)
+            .await
+            .unwrap();
+        assert_eq!(actual, "QUERY id: 0\n");
+        assert_eq!(
+            mock.requests().pop().unwrap().sql(),
+            "EXPLAIN QUERY TREE SELECT 1\nFORMAT TSVRaw"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_fetches_row_counts() {
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::None);
+
+        // `RowBinaryWithNamesAndTypes` with the only `count()` column.
+        let mut response = b"\x01\x07count()\x06UInt64".to_vec();
+        response.extend_from_slice(&42u64.to_le_bytes());
+
+        mock.add(handlers::raw(response.clone()));
+        let count = client
+            .query("SELECT * FROM some WHERE name = ? -- comment")
+            .bind("a?")
+            .fetch_row_count()
+            .await
+            .unwrap();
+        assert_eq!(count, 42);
+        assert_eq!(
+            mock.requests().pop().unwrap().sql(),
+            "SELECT count() FROM (SELECT * FROM some WHERE name = 'a?' -- comment\n) \
+             FORMAT RowBinaryWithNamesAndTypes"
+        );
+
+        mock.add(handlers::raw(response));
+        let count = client.query_row_count("SELECT 1").await.unwrap();
+        assert_eq!(count, 42);
+        assert_eq!(
+            mock.requests().pop().unwrap().sql(),
+            "SELECT count() FROM (SELECT 1\n) FORMAT RowBinaryWithNamesAndTypes"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_fetches_dynamic_rows() {
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::None);
+
+        // Rows are split between chunks.
+        let chunks = [
+            &br#"{"big":"18446744073709551615","name":"a","#[..],
+            br#""ratio":1.5,"tags":[1,2],"none":null}"#,
+            b"\n{\"big\":\"1\",\"name\":\"b\",\"ratio\":0,\"tags\":[],\"none\":1}\n",
+        ]
+        .map(Bytes::from_static);
+        mock.add(handlers::raw_chunks(stream::iter(chunks)));
+
+        let mut cursor = client.query("SELECT * FROM some").fetch_dynamic().unwrap();
+
+        let row = cursor.next().await.unwrap().unwrap();
+        assert_eq!(row["big"], "18446744073709551615");
+        assert_eq!(row["name"], "a");
+        assert_eq!(row["ratio"], 1.5);
+        assert_eq!(row["tags"], serde_json::json!([1, 2]));
+        assert!(row["none"].is_null());
+
+        let row = cursor.next().await.unwrap().unwrap();
+        assert_eq!(row["name"], "b");
+        assert_eq!(row["none"], 1);
+        assert_eq!(cursor.next().await.unwrap(), None);
+
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(request.sql(), "SELECT * FROM some FORMAT JSONEachRow");
+        assert_eq!(
+            request
+                .param("output_format_json_quote_64bit_integers")
+                .as_deref(),
+            Some("1")
+        );
+    }
+
+    #[tokio::test]
+    async fn it_fetches_csv() {
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::None);
+
+        let csv = b"\"no\",\"name\"\n1,\"a\nb\"\n2,\"c\"\n".to_vec();
+        mock.add(handlers::raw(csv.clone()));
+        let chunks = client
+            .query("SELECT no, name FROM some")
+            .fetch_csv(true)
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        let actual = chunks
+            .into_iter()
+            .map(Result::unwrap)
+            .fold(Vec::new(), |mut acc, chunk| {
+                acc.extend_from_slice(&chunk);
+                acc
+            });
+        assert_eq!(actual, csv);
+
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(
+            request.sql(),
+            "SELECT no, name FROM some FORMAT CSVWithNames"
+        );
+
+        mock.add(handlers::raw(&b"1,2\n"[..]));
+        let mut csv = client.query("SELECT 1, 2").fetch_csv(false).unwrap();
+        assert_eq!(csv.next().await.unwrap().unwrap(), &b"1,2\n"[..]);
+        assert!(csv.next().await.is_none());
+
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(request.sql(), "SELECT 1, 2 FORMAT CSV");
+
+        // Errors are detected and end the stream.
+        let csv = b"1,2\nCode: 395. DB::Exception: Oops (version 23.8.1.1 (official build))\n";
+        mock.add(handlers::raw(&csv[..]));
+        let results = client
+            .query("SELECT 1, 2")
+            .fetch_csv(false)
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        let err = results.into_iter().find_map(Result::err).unwrap();
+        assert!(
+            matches!(err, Error::ServerException { code: 395, .. }),
+            "{:?}",
+            err
+        );
+
+        mock.add(handlers::failure(crate::test::status::BAD_REQUEST));
+        let mut csv = client.query("SELECT 1").fetch_csv(false).unwrap();
+        assert!(matches!(csv.next().await, Some(Err(Error::BadResponse(_)))));
+        assert!(csv.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn it_limits_result_sizes() {
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_max_result_bytes(10);
+
+        // An endless result is aborted while reading.
+        mock.add(handlers::provide(stream::repeat(1u8)));
+        let err = client
+            .query("SELECT 1")
+            .fetch_all::<u8>()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ResultTooLarge(10)), "{:?}", err);
+
+        mock.add(handlers::provide(stream::repeat(1u8)));
+        let err = client
+            .query("SELECT 1")
+            .fetch_raw_bytes()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ResultTooLarge(10)), "{:?}", err);
+
+        // Exactly the limit is fine.
+        mock.add(handlers::provide(stream::iter([1u8; 10])));
+        let rows = client.query("SELECT 1").fetch_all::<u8>().await.unwrap();
+        assert_eq!(rows, [1; 10]);
+
+        // Queries can override it.
+        mock.add(handlers::provide(stream::iter([1u8; 11])));
+        let query = client.query("SELECT 1").with_max_result_bytes(20);
+        assert_eq!(query.fetch_all::<u8>().await.unwrap().len(), 11);
+    }
+
+    #[tokio::test]
+    async fn it_fetches_counts() {
+        let client = Client::default().with_compression(Compression::None);
+
+        let url = serve_raw(
+            b"HTTP/1.1 200 OK\r\n\
+              X-ClickHouse-Summary: {\"read_rows\":\"10\",\"result_rows\":\"3\"}\r\n\
+              Content-Length: 3\r\n\r\n\x01\x02\x03",
+        )
+        .await;
+        let query = client.clone().with_url(url).query("SELECT n FROM some");
+        let (rows, count) = query.fetch_all_with_count::<u8>().await.unwrap();
+        assert_eq!(rows, [1, 2, 3]);
+        assert_eq!(count, 3);
+        assert_eq!(count, rows.len() as u64);
+
+        // Without `result_rows` and without the summary at all.
+        for head in [
+            &b"HTTP/1.1 200 OK\r\nX-ClickHouse-Summary: {\"read_rows\":\"10\"}\r\n"[..],
+            &b"HTTP/1.1 200 OK\r\n"[..],
+        ] {
+            let mut response = head.to_vec();
+            response.extend_from_slice(b"Content-Length: 2\r\n\r\n\x01\x02");
+            let url = serve_raw(response).await;
+            let query = client.clone().with_url(url).query("SELECT n FROM some");
+            let (rows, count) = query.fetch_all_with_count::<u8>().await.unwrap();
+            assert_eq!(rows, [1, 2]);
+            assert_eq!(count, 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_fetches_blocks() {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        mock.add(handlers::provide(stream::iter(0..100u32)));
+        let mut cursor = client
+            .query("SELECT n FROM some")
+            .with_max_block_size(10)
+            .fetch::<u32>()
+            .unwrap();
+
+        let mut blocks = Vec::new();
+        while let Some(block) = cursor.next_block().await.unwrap() {
+            assert!(!block.is_empty());
+            blocks.push(block);
+        }
+        assert!(cursor.next_block().await.unwrap().is_none());
+
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(request.param("max_block_size").as_deref(), Some("10"));
+
+        mock.add(handlers::provide(stream::iter(0..100u32)));
+        let rows = client.query("SELECT n FROM some").fetch_all::<u32>().await;
+        assert_eq!(blocks.concat(), rows.unwrap());
+
+        // Rows received together are emitted as one batch.
+        let mut response = b"HTTP/1.1 200 OK\r\nContent-Length: 400\r\n\r\n".to_vec();
+        (0..100u32).for_each(|n| response.extend_from_slice(&n.to_le_bytes()));
+        let client = client
+            .with_url(serve_raw(response).await)
+            .with_compression(Compression::None);
+        let mut cursor = client.query("SELECT n FROM some").fetch::<u32>().unwrap();
+
+        let mut blocks = Vec::new();
+        while let Some(block) = cursor.next_block().await.unwrap() {
+            blocks.push(block);
+        }
+        assert!(blocks.iter().any(|block| block.len() > 1));
+        assert_eq!(blocks.concat(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn it_peeks_rows() {
+        use futures::TryStreamExt;
+
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        mock.add(handlers::provide(stream::iter([
+            (1u32, 10u8),
+            (1, 11),
+            (2, 20),
+        ])));
+        let mut cursor = client
+            .query("SELECT grp, n FROM some ORDER BY grp")
+            .fetch::<(u32, u8)>()
+            .unwrap();
+
+        // Rows are grouped by looking at the next one.
+        let mut groups = Vec::new();
+        while let Some((grp, n)) = cursor.next().await.unwrap() {
+            let mut group = vec![n];
+            while let Some(&(next_grp, _)) = cursor.peek().await.unwrap() {
+                if next_grp != grp {
+                    break;
+                }
+                assert_eq!(cursor.peek().await.unwrap(), Some(&(grp, 11)));
+                group.push(cursor.next().await.unwrap().unwrap().1);
+            }
+            groups.push((grp, group));
+        }
+        assert_eq!(groups, [(1, vec![10, 11]), (2, vec![20])]);
+
+        assert_eq!(cursor.peek().await.unwrap(), None);
+        assert_eq!(cursor.peek().await.unwrap(), None);
+        assert_eq!(cursor.next().await.unwrap(), None);
+
+        // Peeked rows are emitted by other methods too.
+        mock.add(handlers::provide(stream::iter([1u32, 2, 3])));
+        let mut cursor = client.query("SELECT n FROM some").fetch::<u32>().unwrap();
+        assert_eq!(cursor.peek().await.unwrap(), Some(&1));
+        let mut rows = Vec::new();
+        while let Some(block) = cursor.next_block().await.unwrap() {
+            rows.extend(block);
+        }
+        assert_eq!(rows, [1, 2, 3]);
+
+        mock.add(handlers::provide(stream::iter([1u32, 2])));
+        let mut cursor = client.query("SELECT n FROM some").fetch::<u32>().unwrap();
+        assert_eq!(cursor.peek().await.unwrap(), Some(&1));
+        let rows = cursor.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(rows, [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn it_maps_rows() {
+        use futures::TryStreamExt;
+
+        #[derive(Row, Deserialize)]
+        struct MyRow<'a> {
+            no: u32,
+            name: &'a str,
+        }
+
+        #[derive(Debug, PartialEq)]
+        struct User {
+            id: u64,
+            name: String,
+        }
+
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+        let rows = [(1u32, "alice"), (2, "bob")];
+
+        mock.add(handlers::provide(stream::iter(rows)));
+        let mut users = client
+            .query("SELECT ?fields FROM some")
+            .fetch::<MyRow<'_>>()
+            .unwrap()
+            .map_rows(|row| User {
+                id: row.no.into(),
+                name: row.name.to_uppercase(),
+            });
+
+        let mut actual = Vec::new();
+        while let Some(user) = users.next().await.unwrap() {
+            actual.push(user);
+        }
+        let expected = [
+            User {
+                id: 1,
+                name: "ALICE".into(),
+            },
+            User {
+                id: 2,
+                name: "BOB".into(),
+            },
+        ];
+        assert_eq!(actual, expected);
+        assert_eq!(users.next().await.unwrap(), None);
+
+        // Errors are passed as is.
+        mock.add(handlers::failure(crate::test::status::BAD_REQUEST));
+        let mut calls = 0;
+        let mut users = client
+            .query("SELECT n FROM some")
+            .fetch::<u32>()
+            .unwrap()
+            .map_rows(|n| {
+                calls += 1;
+                n
+            });
+        assert!(matches!(users.next().await, Err(Error::BadResponse(_))));
+        drop(users);
+        assert_eq!(calls, 0);
+
+        // Also as a stream for owned rows.
+        mock.add(handlers::provide(stream::iter([1u32, 2, 3])));
+        let doubled = client
+            .query("SELECT n FROM some")
+            .fetch::<u32>()
+            .unwrap()
+            .map_rows(|n| n * 2)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(doubled, [2, 4, 6]);
+    }
+
+    #[tokio::test]
+    async fn it_reads_integers_leniently() {
+        #[derive(Debug, PartialEq, Row, Deserialize)]
+        struct MyRow {
+            no: u32,
+            delta: Option<i8>,
+        }
+
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::None);
+
+        // `no UInt16, delta Nullable(Int64)`.
+        let mut response = b"\x02\x02no\x05delta\x06UInt16\x0fNullable(Int64)".to_vec();
+        response.extend_from_slice(&500u16.to_le_bytes());
+        response.push(0);
+        response.extend_from_slice(&(-5i64).to_le_bytes());
+        response.extend_from_slice(&600u16.to_le_bytes());
+        response.push(1);
+
+        mock.add(handlers::raw(response));
+        let rows = client
+            .query("SELECT ?fields FROM some")
+            .with_lenient_integers(true)
+            .fetch_all::<MyRow>()
+            .await
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                MyRow {
+                    no: 500,
+                    delta: Some(-5)
+                },
+                MyRow {
+                    no: 600,
+                    delta: None
+                },
+            ]
+        );
+        assert_eq!(
+            mock.requests().pop().unwrap().sql(),
+            "SELECT `no`,`delta` FROM some FORMAT RowBinaryWithNamesAndTypes"
+        );
+
+        // A value that doesn't fit into the field.
+        let mut response = b"\x01\x02no\x06UInt32".to_vec();
+        response.extend_from_slice(&70_000u32.to_le_bytes());
+
+        mock.add(handlers::raw(response));
+        let err = client
+            .query("SELECT no FROM some")
+            .with_lenient_integers(true)
+            .fetch_one::<u16>()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::Overflow {
+                    value: 70_000,
+                    target: "u16"
+                }
+            ),
+            "{:?}",
+            err
+        );
+
+        // Strict by default.
+        let query = client.query("SELECT ?fields FROM some");
+        assert_eq!(
+            query.sql_display_fetch::<MyRow>().unwrap(),
+            "SELECT `no`,`delta` FROM some FORMAT RowBinary"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_validates_schemas() {
+        #[derive(Debug, PartialEq, Row, Deserialize)]
+        struct MyRow {
+            no: u32,
+            name: String,
+        }
+
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::None);
+
+        // `name String, no UInt32`, i.e. in another order than fields.
+        let mut response = b"\x02\x04name\x02no\x06String\x06UInt32".to_vec();
+        response.extend_from_slice(b"\x03foo");
+        response.extend_from_slice(&42u32.to_le_bytes());
+
+        mock.add(handlers::raw(response.clone()));
+        let err = client
+            .query("SELECT name, no FROM some")
+            .with_schema_validation(true)
+            .fetch_all::<MyRow>()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                Error::SchemaMismatch { expected, found }
+                    if expected == &["no", "name"] && found == &["name", "no"]
+            ),
+            "{:?}",
+            err
+        );
+        assert_eq!(
+            mock.requests().pop().unwrap().sql(),
+            "SELECT name, no FROM some FORMAT RowBinaryWithNamesAndTypes"
+        );
+
+        // `no UInt32, name String`, matching the row.
+        let mut response = b"\x02\x02no\x04name\x06UInt32\x06String".to_vec();
+        response.extend_from_slice(&42u32.to_le_bytes());
+        response.extend_from_slice(b"\x03foo");
+
+        mock.add(handlers::raw(response.clone()));
+        let rows = client
+            .query("SELECT ?fields FROM some")
+            .with_schema_validation(true)
+            .fetch_all::<MyRow>()
+            .await
+            .unwrap();
+        let expected = MyRow {
+            no: 42,
+            name: "foo".into(),
+        };
+        assert_eq!(rows, [expected]);
+
+        // Along with lenient integers, which read the same header.
+        #[derive(Debug, PartialEq, Row, Deserialize)]
+        struct WideRow {
+            no: u64,
+            name: String,
+        }
+
+        mock.add(handlers::raw(response));
+        let rows = client
+            .query("SELECT ?fields FROM some")
+            .with_schema_validation(true)
+            .with_lenient_integers(true)
+            .fetch_all::<WideRow>()
+            .await
+            .unwrap();
+        let expected = WideRow {
+            no: 42,
+            name: "foo".into(),
+        };
+        assert_eq!(rows, [expected]);
+
+        // `no UInt32, name String, extra UInt8`, i.e. with a trailing column.
+        let mut response = b"\x03\x02no\x04name\x05extra\x06UInt32\x06String\x05UInt8".to_vec();
+        response.extend_from_slice(&42u32.to_le_bytes());
+        response.extend_from_slice(b"\x03foo");
+        response.push(7);
+
+        mock.add(handlers::raw(response.clone()));
+        let err = client
+            .query("SELECT ?fields, extra FROM some")
+            .with_schema_validation(true)
+            .fetch_all::<MyRow>()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                Error::SchemaMismatch { expected, found }
+                    if expected == &["no", "name"] && found == &["no", "name", "extra"]
+            ),
+            "{:?}",
+            err
+        );
+
+        // Unless it's expected by the tuple.
+        mock.add(handlers::raw(response));
+        let rows = client
+            .query("SELECT ?fields, extra FROM some")
+            .with_schema_validation(true)
+            .fetch_all::<(MyRow, u8)>()
+            .await
+            .unwrap();
+        let expected = MyRow {
+            no: 42,
+            name: "foo".into(),
+        };
+        assert_eq!(rows, [(expected, 7)]);
+    }
+
+    #[test]
+    fn it_prepares_queries() {
+        let client = Client::default().with_option("max_threads", "1");
+        let template = "SELECT ?fields FROM some WHERE name = ? AND no > ?";
+        let prepared = client.prepare(template);
+
+        #[derive(Row, Deserialize)]
+        #[allow(dead_code)]
+        struct MyRow {
+            no: u32,
+        }
+
+        for (name, no) in [("a?", 1), ("b'", 2), ("c", 3)] {
+            let expected = client.query(template).bind(name).bind(no);
+            let actual = prepared.query().bind(name).bind(no);
+            assert_eq!(
+                actual.sql_display_fetch::<MyRow>().unwrap(),
+                expected.sql_display_fetch::<MyRow>().unwrap()
+            );
+            assert_eq!(actual.client.options, expected.client.options);
+        }
+
+        // Binding doesn't touch the parsed template.
+        assert_eq!(prepared.query().sql.unbound_args().unwrap(), 2);
+
+        let prepared = client.prepare("SELECT * FROM some WHERE no IN (?, ?, ?)");
+        assert_eq!(
+            prepared.bind_all([1, 2, 3]).sql_display().unwrap(),
+            "SELECT * FROM some WHERE no IN (1, 2, 3)"
+        );
+        assert!(prepared.bind_all([1, 2]).sql_display().is_err());
+    }
+
+    #[cfg(feature = "lz4")]
+    #[tokio::test]
+    async fn it_fetches_lz4_blocks_split_into_bytes() {
+        use crate::{compression::lz4, rowbinary};
+
+        let mock = Mock::new();
+        let client = Client::default()
+            .with_url(mock.url())
+            .with_compression(Compression::Lz4);
+
+        // Two blocks, both splitting rows.
+        let mut rows = Vec::new();
+        rowbinary::serialize_into(&mut rows, &[(1u32, "foo"), (2, "bar"), (3, "baz")]).unwrap();
+        let (left, right) = rows.split_at(rows.len() / 2 + 1);
+        let mut compressed = lz4::compress(left, Compression::Lz4).unwrap().to_vec();
+        compressed.extend_from_slice(&lz4::compress(right, Compression::Lz4).unwrap());
+
+        let chunks = compressed.into_iter().map(|byte| Bytes::from(vec![byte]));
+        mock.add(handlers::raw_chunks(stream::iter(chunks)));
+        let rows = client
+            .query("SELECT a, b FROM some")
+            .fetch_all::<(u32, String)>()
+            .await
+            .unwrap();
+        assert_eq!(
+            rows,
+            [(1, "foo".into()), (2, "bar".into()), (3, "baz".into())]
+        );
+    }
+
+    #[cfg(feature = "arrow")]
+    #[tokio::test]
+    async fn it_fetches_arrow_batches() {
+        use std::sync::Arc;
+
+        use arrow_array::{RecordBatch, StringArray, UInt64Array};
+        use arrow_ipc::{
+            writer::{IpcWriteOptions, StreamWriter},
+            CompressionType,
+        };
+        use arrow_schema::{DataType, Field, Schema};
+        use futures::TryStreamExt;
+
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("number", DataType::UInt64, false),
+            Field::new("name", DataType::Utf8, false),
+        ]));
+        let batch = |numbers: Vec<u64>| {
+            let names = numbers.iter().map(u64::to_string).collect::<Vec<_>>();
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(UInt64Array::from(numbers)),
+                    Arc::new(StringArray::from(names)),
+                ],
+            )
+            .unwrap()
+        };
+
+        let mut response = Vec::new();
+        let mut writer = StreamWriter::try_new(&mut response, &schema).unwrap();
+        writer.write(&batch(vec![1, 2, 3])).unwrap();
+        writer.write(&batch(vec![4, 5])).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        // Every byte is a separate chunk, splitting all messages.
+        let chunks = response.into_iter().map(|byte| Bytes::from(vec![byte]));
+        mock.add(handlers::raw_chunks(stream::iter(chunks)));
+        let batches = client
+            .query("SELECT number, toString(number) AS name FROM some")
+            .fetch_arrow()
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(batches.len(), 2);
+        assert!(batches.iter().all(|b| b.schema() == schema));
+        assert_eq!(batches[0].num_rows(), 3);
+        assert_eq!(batches[1], batch(vec![4, 5]));
+        let request = mock.requests().pop().unwrap();
+        assert_eq!(
+            request.sql(),
+            "SELECT number, toString(number) AS name FROM some FORMAT ArrowStream"
+        );
+        assert_eq!(
+            request
+                .param("output_format_arrow_compression_method")
+                .as_deref(),
+            Some("none")
+        );
+
+        // Compressed streams are read, but only empty buffers can be decoded.
+        let mut response = Vec::new();
+        let options = IpcWriteOptions::default()
+            .try_with_compression(Some(CompressionType::LZ4_FRAME))
+            .unwrap();
+        let numbers = Arc::new(Schema::new(vec![Field::new(
+            "number",
+            DataType::UInt64,
+            false,
+        )]));
+        let empty = RecordBatch::new_empty(numbers.clone());
+        let mut writer =
+            StreamWriter::try_new_with_options(&mut response, &numbers, options).unwrap();
+        writer.write(&empty).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        mock.add(handlers::raw(response));
+        let mut cursor = client
+            .query("SELECT number FROM some")
+            .fetch_arrow()
+            .unwrap();
+        assert_eq!(cursor.next().await.unwrap(), Some(empty));
+        assert_eq!(cursor.next().await.unwrap(), None);
+
+        // Truncated streams are reported.
+        mock.add(handlers::raw(vec![0xff, 0xff, 0xff, 0xff, 8, 0]));
+        let mut cursor = client.query("SELECT 1").fetch_arrow().unwrap();
+        let err = cursor.next().await.unwrap_err();
+        assert!(matches!(err, Error::BadResponse(_)), "{:?}", err);
+    }
 }
@@ -1,22 +1,22 @@
 use crate::DbRow;
 use crate::{error::Error, rowbinary};
-use crate::{response::Chunks, Compression};
-use bytes::Bytes;
+use bytes::{Buf, Bytes};
 use futures::{Stream, StreamExt};
-use hyper::Body;
 use serde::Deserialize;
 use std::marker::PhantomData;
-use std::ops::DerefMut;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
-use crate::buflist::BufList;
+use crate::{
+    buflist::BufList,
+    cursor::{BufferCapacity, RowBuffer},
+};
 
 /// A cursor for deserializing using the row binary format from a byte buffer.
 pub struct RemoteCursor<T, S> {
     stream: S,
     pending: BufList<Bytes>,
-    tmp_buf: Vec<u8>,
+    tmp_buf: RowBuffer,
     _p: PhantomData<T>,
 }
 
@@ -26,13 +26,40 @@ where
     T: DbRow + for<'b> Deserialize<'b>,
 {
     pub fn new(stream: S) -> Self {
+        Self::with_capacity(stream, BufferCapacity::default().initial, usize::MAX)
+    }
+
+    /// Creates a cursor with a temporary buffer (used for values split between chunks)
+    /// of `initial_capacity` bytes. The buffer grows to fit the largest value and is
+    /// shrunk back to `initial_capacity` after a row requiring more than `max_retained_capacity`
+    /// bytes, so occasional huge rows don't inflate memory usage forever.
+    pub fn with_capacity(stream: S, initial_capacity: usize, max_retained_capacity: usize) -> Self {
+        let capacity = BufferCapacity {
+            initial: initial_capacity,
+            max_retained: max_retained_capacity,
+            ..BufferCapacity::default()
+        };
+
         Self {
             stream,
-            tmp_buf: vec![0; 1024],
+            tmp_buf: RowBuffer::new(capacity),
             pending: BufList::default(),
             _p: Default::default(),
         }
     }
+
+    /// Limits rows to `max_bytes`, so a larger one, e.g. because of a corrupted
+    /// length, fails with [`Error::RowTooLarge`] instead of buffering more chunks
+    /// or growing the temporary buffer further.
+    ///
+    /// Unlimited by default.
+    pub fn with_max_row_size(mut self, max_bytes: usize) -> Self {
+        self.tmp_buf = RowBuffer::new(BufferCapacity {
+            max_row: max_bytes,
+            ..self.tmp_buf.capacity()
+        });
+        self
+    }
 }
 
 impl<T, S> Stream for RemoteCursor<T, S>
@@ -45,22 +72,30 @@ where
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = self.get_mut();
         loop {
-            match rowbinary::deserialize_from(&mut this.pending, &mut this.tmp_buf[..]) {
+            match rowbinary::deserialize_from(&mut this.pending, this.tmp_buf.as_mut_slice()) {
                 Ok(value) => {
                     this.pending.commit();
+                    // Rows are owned, so the buffer can be shrunk immediately.
+                    this.tmp_buf.mark_row_read();
+                    this.tmp_buf.reclaim();
                     return Poll::Ready(Some(Ok(value)));
                 }
                 Err(Error::TooSmallBuffer(need)) => {
-                    let new_len = (this.tmp_buf.len() + need)
-                        .checked_next_power_of_two()
-                        .expect("oom");
-                    this.tmp_buf.resize(new_len, 0);
-
                     this.pending.rollback();
+
+                    if let Err(err) = this.tmp_buf.grow(need) {
+                        return Poll::Ready(Some(Err(err)));
+                    }
                     continue;
                 }
                 Err(Error::NotEnoughData) => {
                     this.pending.rollback();
+
+                    // The row is incomplete, so all pending bytes belong to it.
+                    let pending = this.pending.remaining();
+                    if pending > this.tmp_buf.capacity().max_row {
+                        return Poll::Ready(Some(Err(Error::RowTooLarge(pending))));
+                    }
                 }
                 Err(e) => return Poll::Ready(Some(Err(e))),
             }
@@ -81,3 +116,65 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_shrinks_buffer_after_huge_rows() {
+        let huge = "x".repeat(100_000);
+        let mut data = Vec::new();
+        rowbinary::serialize_into(&mut data, &huge).unwrap();
+        for idx in 0..1000 {
+            rowbinary::serialize_into(&mut data, &format!("small-{idx}")).unwrap();
+        }
+
+        // Small chunks force values to be copied into the temporary buffer.
+        let chunks = data
+            .chunks(7)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+        let mut cursor = RemoteCursor::<String, _>::with_capacity(stream::iter(chunks), 64, 4096);
+
+        assert_eq!(cursor.next().await.unwrap().unwrap(), huge);
+        assert_eq!(cursor.tmp_buf.len(), 64);
+
+        let mut count = 0;
+        while let Some(row) = cursor.next().await {
+            assert_eq!(row.unwrap(), format!("small-{count}"));
+            assert_eq!(cursor.tmp_buf.len(), 64);
+            count += 1;
+        }
+        assert_eq!(count, 1000);
+    }
+
+    #[tokio::test]
+    async fn it_fails_on_huge_lengths() {
+        // A `String` with a bogus length of `2^63 - 1` bytes.
+        let mut data = vec![0xff; 8];
+        data.push(0x7f);
+        data.extend_from_slice(&[b'x'; 100]);
+
+        let chunks = || {
+            data.chunks(7)
+                .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+                .collect::<Vec<_>>()
+        };
+
+        let mut cursor = RemoteCursor::<String, _>::new(stream::iter(chunks()));
+        let err = cursor.next().await.unwrap().unwrap_err();
+        assert!(matches!(err, Error::NotEnoughData), "{:?}", err);
+
+        let mut cursor =
+            RemoteCursor::<String, _>::new(stream::iter(chunks())).with_max_row_size(64);
+        let err = cursor.next().await.unwrap().unwrap_err();
+        assert!(
+            matches!(err, Error::RowTooLarge(len) if len > 64),
+            "{:?}",
+            err
+        );
+    }
+}
@@ -7,36 +7,101 @@ use std::{
 use bstr::ByteSlice;
 use bytes::{BufMut, Bytes};
 use futures::{
-    future,
+    future, ready,
     stream::{self, Stream, TryStreamExt},
 };
-use hyper::{body, client::ResponseFuture, Body, StatusCode};
+use hyper::{body, header::CONTENT_TYPE, Body, HeaderMap, StatusCode};
 
 #[cfg(feature = "lz4")]
 use crate::compression::lz4::Lz4Decoder;
 use crate::{
     compression::Compression,
     error::{Error, Result},
+    http_client::InFlight,
+    trace::RequestSpan,
 };
 
+const UNEXPECTED_BODY_LEN: usize = 512;
+
 pub(crate) enum Response {
-    Waiting(Pin<Box<dyn Future<Output = Result<Chunks<Body>>> + Send>>),
-    Loading(Chunks<Body>),
+    Waiting(ResponseHead),
+    // The head is boxed to keep the hot `Loading` variant small.
+    Loading(Chunks<Body>, Box<Head>),
+}
+
+#[derive(Default)]
+pub(crate) struct Head {
+    headers: HeaderMap,
+    // The request is in flight until the response is dropped.
+    _in_flight: Option<InFlight>,
+}
+
+type ResponseHead = Pin<Box<dyn Future<Output = Result<(Chunks<Body>, Box<Head>)>> + Send>>;
+
+/// Settings of reading bodies, which are taken from the client.
+#[derive(Clone, Copy)]
+pub(crate) struct BodyOptions {
+    pub(crate) max_bytes: Option<u64>,
+    pub(crate) verify_checksums: bool,
+}
+
+impl Default for BodyOptions {
+    fn default() -> Self {
+        Self {
+            max_bytes: None,
+            verify_checksums: true,
+        }
+    }
 }
 
 impl Response {
-    pub(crate) fn new(response: ResponseFuture, compression: Compression) -> Self {
+    pub(crate) fn new<E>(
+        response: impl Future<Output = Result<hyper::Response<Body>, E>> + Send + 'static,
+        compression: Compression,
+        options: BodyOptions,
+        in_flight: Option<InFlight>,
+        mut span: RequestSpan,
+    ) -> Self
+    where
+        E: Into<Error>,
+    {
         // Boxing here significantly improves performance by reducing the size of `chunks()`.
         Self::Waiting(Box::pin(async move {
-            let response = response.await?;
+            let response = match response.await {
+                Ok(response) => response,
+                Err(err) => {
+                    let err = err.into();
+                    span.finish(Some(&err));
+                    return Err(err);
+                }
+            };
             let status = response.status();
-            let body = response.into_body();
-
-            if status == StatusCode::OK {
-                Ok(Chunks::new(body, compression))
+            let (parts, body) = response.into_parts();
+
+            let code = exception_code(&parts.headers);
+
+            // CH never responds with HTML, unlike misconfigured proxies. Pages without
+            // `Content-Type` are detected by `collect_bad_response()` instead.
+            let err = if code.is_none() && is_auth_failure(status) {
+                // Sent by a proxy, which can echo the request in the body,
+                // so only the status is reported to never leak credentials.
+                Error::AuthenticationFailed(status_reason(status))
+            } else if is_html(&parts.headers) {
+                collect_unexpected_response(status, body).await
+            } else if status == StatusCode::OK {
+                let head = Head {
+                    headers: parts.headers,
+                    _in_flight: in_flight,
+                };
+                let chunks = Chunks::new(body, compression, options, span);
+                return Ok((chunks, Box::new(head)));
             } else {
-                Err(collect_bad_response(status, body, compression).await)
-            }
+                let foreign = !has_clickhouse_headers(&parts.headers);
+                collect_bad_response(status, code, foreign, body, compression).await
+            };
+
+            span.finish(Some(&err));
+            Err(err)
         }))
     }
 
@@ -44,19 +109,51 @@ impl Response {
     pub(crate) fn chunks(&mut self) -> Option<&mut Chunks<Body>> {
         match self {
             Self::Waiting(_) => None,
-            Self::Loading(chunks) => Some(chunks),
+            Self::Loading(chunks, _) => Some(chunks),
         }
     }
 
     #[cold]
     #[inline(never)]
     pub(crate) async fn chunks_slow(&mut self) -> Result<&mut Chunks<Body>> {
-        loop {
-            match self {
-                Self::Waiting(future) => *self = Self::Loading(future.await?),
-                Self::Loading(chunks) => break Ok(chunks),
+        future::poll_fn(|cx| self.poll_head(cx)).await?;
+        Ok(self.chunks().expect("the head must be received"))
+    }
+
+    #[cold]
+    #[inline(never)]
+    pub(crate) fn poll_chunks_slow(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<&mut Chunks<Body>>> {
+        ready!(self.poll_head(cx))?;
+        Poll::Ready(Ok(self.chunks().expect("the head must be received")))
+    }
+
+    fn poll_head(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if let Self::Waiting(future) = self {
+            match ready!(future.as_mut().poll(cx)) {
+                Ok((chunks, head)) => *self = Self::Loading(chunks, head),
+                Err(err) => {
+                    // The future mustn't be polled after completion, so the response
+                    // is turned into an empty one, allowing to poll cursors after errors.
+                    *self = Self::Loading(Chunks(None), Box::default());
+                    return Poll::Ready(Err(err));
+                }
             }
         }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Waits for the response's head and returns its headers without touching the body.
+    pub(crate) async fn headers(&mut self) -> Result<&HeaderMap> {
+        self.chunks_slow().await?;
+
+        match self {
+            Self::Loading(_, head) => Ok(&head.headers),
+            Self::Waiting(_) => unreachable!(),
+        }
     }
 
     pub(crate) async fn finish(&mut self) -> Result<()> {
@@ -66,41 +163,131 @@ impl Response {
     }
 }
 
+fn is_auth_failure(status: StatusCode) -> bool {
+    status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+}
+
+// A standardised reason for the status code, e.g. `502 Bad Gateway`.
+fn status_reason(status: StatusCode) -> String {
+    format!(
+        "{} {}",
+        status.as_str(),
+        status.canonical_reason().unwrap_or("<unknown>"),
+    )
+}
+
+fn is_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| value.trim_start().starts_with("text/html"))
+}
+
+#[cold]
+#[inline(never)]
+async fn collect_unexpected_response(status: StatusCode, mut body: Body) -> Error {
+    // Only the beginning is read, such pages can be arbitrarily large.
+    let mut bytes = Vec::new();
+    while bytes.len() < UNEXPECTED_BODY_LEN {
+        match body.try_next().await {
+            Ok(Some(chunk)) => bytes.extend_from_slice(&chunk),
+            Ok(None) => break,
+            Err(err) => return err.into(),
+        }
+    }
+
+    unexpected_response(status, &bytes)
+}
+
+fn unexpected_response(status: StatusCode, bytes: &[u8]) -> Error {
+    let body = String::from_utf8_lossy(bytes);
+    let mut body = body.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if body.len() > UNEXPECTED_BODY_LEN {
+        let mut len = UNEXPECTED_BODY_LEN;
+        while !body.is_char_boundary(len) {
+            len -= 1;
+        }
+        body.truncate(len);
+        body.push_str("...");
+    }
+
+    Error::UnexpectedResponse {
+        status: status.as_u16(),
+        body,
+    }
+}
+
+// CH sets `X-ClickHouse-Server-Display-Name` and others for any response, even errors.
+fn has_clickhouse_headers(headers: &HeaderMap) -> bool {
+    headers
+        .keys()
+        .any(|name| name.as_str().starts_with("x-clickhouse-"))
+}
+
+fn looks_like_html(bytes: &[u8]) -> bool {
+    bytes.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'<')
+}
+
+// CH sets it for all exceptions occurred before sending the response.
+fn exception_code(headers: &HeaderMap) -> Option<u32> {
+    headers
+        .get("X-ClickHouse-Exception-Code")?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
 #[cold]
 #[inline(never)]
-async fn collect_bad_response(status: StatusCode, body: Body, compression: Compression) -> Error {
+async fn collect_bad_response(
+    status: StatusCode,
+    code: Option<u32>,
+    // No CH headers at all, so the response is likely sent by a proxy.
+    foreign: bool,
+    body: Body,
+    compression: Compression,
+) -> Error {
     // Collect the whole body into one contiguous buffer to simplify handling.
     // Only network errors can occur here and we return them instead of status code
     // because it means the request can be repeated to get a more detailed error.
+    // However, if the exception code is known, the body only provides details.
     //
     // TODO: we don't implement any length checks and a malicious peer (e.g. MITM)
     //       might make us consume arbitrary amounts of memory.
     let raw_bytes = match body::to_bytes(body).await {
         Ok(bytes) => bytes,
+        Err(_) if code.is_some() => Bytes::new(),
         Err(err) => return err.into(),
     };
 
     // Try to decompress the body, because CH compresses any responses, even with errors.
     let stream = stream::once(future::ready(Result::<_>::Ok(raw_bytes.slice(..))));
-    let stream = Decompress::new(stream, compression);
+    let stream = Decompress::new(stream, compression, true);
 
     // We're collecting already fetched chunks, thus only decompression errors can be here.
     // If decompression is failed, we should try the raw body because it can be sent without
     // any compression if some proxy is used, which typically know nothing about CH params.
     let bytes = collect_bytes(stream).await.unwrap_or(raw_bytes);
 
-    let reason = String::from_utf8(bytes.into())
-        .map(|reason| reason.trim().into())
-        .unwrap_or_else(|_| {
-            // If we have a unreadable response, return standardised reason for the status code.
-            format!(
-                "{} {}",
-                status.as_str(),
-                status.canonical_reason().unwrap_or("<unknown>"),
-            )
-        });
+    if foreign && looks_like_html(&bytes) {
+        return unexpected_response(status, &bytes);
+    }
 
-    Error::BadResponse(reason)
+    let reason = String::from_utf8(bytes.into())
+        .ok()
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty())
+        // If we have an empty or unreadable response,
+        // return standardised reason for the status code.
+        .unwrap_or_else(|| status_reason(status));
+
+    match code {
+        Some(code) => Error::server_exception(code, reason),
+        None => Error::BadResponse(reason),
+    }
 }
 
 async fn collect_bytes(stream: impl Stream<Item = Result<Bytes>>) -> Result<Bytes> {
@@ -118,16 +305,35 @@ async fn collect_bytes(stream: impl Stream<Item = Result<Bytes>>) -> Result<Byte
 
 // * Uses `Option<_>` to make this stream fused.
 // * Uses `Box<_>` in order to reduce the size of cursors.
-pub(crate) struct Chunks<S>(Option<Box<DetectDbException<Decompress<ConvertError<S>>>>>);
+pub(crate) struct Chunks<S>(Option<Box<ChunksInner<S>>>);
+
+struct ChunksInner<S> {
+    stream: DetectDbException<Decompress<ConvertError<S>>>,
+    // Decompressed bytes, the limit is `u64::MAX` if responses aren't limited.
+    received: u64,
+    limit: u64,
+    // Zero-sized without the `tracing` feature.
+    span: RequestSpan,
+}
 
 impl<S, E> Chunks<S>
 where
     S: Stream<Item = Result<Bytes, E>> + Unpin + Sync + Send,
     Error: From<E>,
 {
-    fn new(stream: S, compression: Compression) -> Self {
-        let stream = DetectDbException::Stream(Decompress::new(ConvertError(stream), compression));
-        Self(Some(Box::new(stream)))
+    fn new(stream: S, compression: Compression, options: BodyOptions, span: RequestSpan) -> Self {
+        let stream = Decompress::new(ConvertError(stream), compression, options.verify_checksums);
+        Self(Some(Box::new(ChunksInner {
+            stream: DetectDbException::Stream(stream),
+            received: 0,
+            limit: options.max_bytes.unwrap_or(u64::MAX),
+            span,
+        })))
+    }
+
+    /// Moves the rest of chunks out, leaving an ended stream.
+    pub(crate) fn take(&mut self) -> Self {
+        Self(self.0.take())
     }
 }
 
@@ -140,11 +346,25 @@ where
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         // `take()` prevents from use after caught panic.
-        if let Some(mut stream) = self.0.take() {
-            let res = Pin::new(&mut stream).poll_next(cx);
+        if let Some(mut inner) = self.0.take() {
+            let mut res = Pin::new(&mut inner.stream).poll_next(cx);
+
+            if let Poll::Ready(Some(Ok(chunk))) = &res {
+                inner.received += chunk.len() as u64;
+                if inner.received > inner.limit {
+                    res = Poll::Ready(Some(Err(Error::ResultTooLarge(inner.limit))));
+                }
+            }
+
+            match &res {
+                Poll::Pending => {}
+                Poll::Ready(Some(Ok(chunk))) => inner.span.on_chunk(chunk.len()),
+                Poll::Ready(Some(Err(err))) => inner.span.finish(Some(err)),
+                Poll::Ready(None) => inner.span.finish(None),
+            }
 
             if matches!(res, Poll::Pending | Poll::Ready(Some(Ok(_)))) {
-                self.0 = Some(stream);
+                self.0 = Some(inner);
             } else {
                 assert!(self.0.is_none());
             }
@@ -181,11 +401,14 @@ enum Decompress<S> {
 }
 
 impl<S> Decompress<S> {
-    fn new(stream: S, compression: Compression) -> Self {
+    #[cfg_attr(not(feature = "lz4"), allow(unused_variables))]
+    fn new(stream: S, compression: Compression, verify_checksums: bool) -> Self {
         match compression {
             Compression::None => Self::Plain(stream),
             #[cfg(feature = "lz4")]
-            Compression::Lz4 | Compression::Lz4Hc(_) => Self::Lz4(Lz4Decoder::new(stream)),
+            Compression::Lz4 | Compression::Lz4Hc(_) => {
+                Self::Lz4(Lz4Decoder::new(stream, verify_checksums))
+            }
         }
     }
 }
@@ -258,5 +481,180 @@ fn extract_exception_slow(chunk: &mut Bytes) -> Option<Error> {
 
     let exception = chunk.split_off(index);
     let exception = String::from_utf8_lossy(&exception[..exception.len() - 1]);
-    Some(Error::BadResponse(exception.into()))
+
+    let code = exception
+        .strip_prefix("Code: ")
+        .and_then(|rest| rest.split('.').next())
+        .and_then(|code| code.parse().ok());
+
+    Some(match code {
+        Some(code) => Error::server_exception(code, exception.into()),
+        None => Error::BadResponse(exception.into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, net::SocketAddr};
+
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Server,
+    };
+
+    use super::*;
+
+    async fn respond(
+        status: StatusCode,
+        headers: &'static [(&'static str, &'static str)],
+        body: impl Into<Body> + Clone + Send + Sync + 'static,
+    ) -> Error {
+        let make_service = make_service_fn(move |_conn| {
+            let body = body.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_req| {
+                    let body = body.clone();
+                    async move {
+                        let mut builder = hyper::Response::builder().status(status);
+                        for (name, value) in headers {
+                            builder = builder.header(*name, *value);
+                        }
+                        Ok::<_, Infallible>(builder.body(body.into()).unwrap())
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        let future = hyper::Client::new().get(url.parse().unwrap());
+        Response::new(
+            future,
+            Compression::None,
+            BodyOptions::default(),
+            None,
+            RequestSpan::none(),
+        )
+        .finish()
+        .await
+        .unwrap_err()
+    }
+
+    const CODE: &str = "X-ClickHouse-Exception-Code";
+    const CODE_241: &[(&str, &str)] = &[(CODE, "241")];
+    const CODE_60: &[(&str, &str)] = &[(CODE, "60")];
+    const HTML: &[(&str, &str)] = &[("Content-Type", "text/html; charset=utf-8")];
+
+    #[tokio::test]
+    async fn it_uses_exception_code_header() {
+        let err = respond(StatusCode::INTERNAL_SERVER_ERROR, CODE_241, "").await;
+        assert!(matches!(
+            err,
+            Error::ServerException { code: 241, message } if message == "500 Internal Server Error"
+        ));
+
+        let reason = "Code: 60. DB::Exception: Table default.t doesn't exist. (UNKNOWN_TABLE)";
+        let err = respond(StatusCode::NOT_FOUND, CODE_60, reason).await;
+        assert!(matches!(
+            err,
+            Error::ServerException { code: 60, message } if message == reason
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_handles_responses_without_exception_code() {
+        let err = respond(StatusCode::NOT_FOUND, &[], "Not found").await;
+        assert!(matches!(err, Error::BadResponse(reason) if reason == "Not found"));
+
+        let err = respond(StatusCode::BAD_GATEWAY, &[], "").await;
+        assert!(matches!(err, Error::BadResponse(reason) if reason == "502 Bad Gateway"));
+
+        let err = respond(StatusCode::BAD_GATEWAY, &[(CODE, "garbage")], "").await;
+        assert!(matches!(err, Error::BadResponse(_)));
+    }
+
+    #[tokio::test]
+    async fn it_detects_auth_failures() {
+        // Proxies can echo credentials, so their bodies are ignored.
+        let body = "Unauthorized: X-ClickHouse-Key: secret";
+        let err = respond(StatusCode::UNAUTHORIZED, &[], body).await;
+        assert!(matches!(
+            &err,
+            Error::AuthenticationFailed(reason) if reason == "401 Unauthorized"
+        ));
+        assert!(!err.to_string().contains("secret"));
+
+        let page = "<html><body>403 Forbidden, password=secret</body></html>";
+        let err = respond(StatusCode::FORBIDDEN, HTML, page).await;
+        assert!(matches!(
+            &err,
+            Error::AuthenticationFailed(reason) if reason == "403 Forbidden"
+        ));
+        assert!(!err.to_string().contains("secret"));
+
+        let reason = "Code: 516. DB::Exception: alice: Authentication failed: \
+                      password is incorrect, or there is no user with such name. \
+                      (AUTHENTICATION_FAILED)";
+        let err = respond(StatusCode::UNAUTHORIZED, &[(CODE, "516")], reason).await;
+        assert!(matches!(err, Error::AuthenticationFailed(message) if message == reason));
+
+        // The exception code is more specific than the status.
+        let reason = "Code: 164. DB::Exception: Cannot execute query in readonly mode";
+        let err = respond(StatusCode::FORBIDDEN, &[(CODE, "164")], reason).await;
+        assert!(matches!(err, Error::ReadOnlyViolation(_)));
+    }
+
+    #[tokio::test]
+    async fn it_detects_html_pages() {
+        let page = "<html>\n<head><title>502 Bad Gateway</title></head>\n\
+                    <body>\n<center><h1>502 Bad Gateway</h1></center>\n</body>\n</html>\n";
+        let err = respond(StatusCode::BAD_GATEWAY, HTML, page).await;
+        assert!(matches!(
+            err,
+            Error::UnexpectedResponse { status: 502, body } if body == "<html> <head><title>502 Bad Gateway</title></head> \
+                <body> <center><h1>502 Bad Gateway</h1></center> </body> </html>"
+        ));
+
+        // Even with `200 OK`, e.g. a login page, which must not be parsed as rows.
+        let page = format!("<html>{}</html>", "ф".repeat(1000));
+        let err = respond(StatusCode::OK, HTML, page).await;
+        assert!(matches!(
+            err,
+            Error::UnexpectedResponse { status: 200, body }
+                if body.starts_with("<html>фф") && body.ends_with("ф...") && body.len() <= 515
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_detects_html_pages_without_clickhouse_headers() {
+        let page = "\n<!DOCTYPE html>\n<html><body>Service Unavailable</body></html>";
+
+        // Neither `Content-Type` nor any of `X-ClickHouse-*` headers.
+        let err = respond(StatusCode::SERVICE_UNAVAILABLE, &[], page).await;
+        assert!(matches!(
+            err,
+            Error::UnexpectedResponse { status: 503, body }
+                if body == "<!DOCTYPE html> <html><body>Service Unavailable</body></html>"
+        ));
+
+        // The same body is reported as is if sent by CH.
+        let headers = &[("X-ClickHouse-Server-Display-Name", "ch-1")];
+        let err = respond(StatusCode::SERVICE_UNAVAILABLE, headers, page).await;
+        assert!(matches!(err, Error::BadResponse(reason) if reason == page.trim()));
+    }
+
+    #[test]
+    fn it_extracts_exception_code_from_body() {
+        let mut chunk = Bytes::from_static(
+            b"data\nCode: 395. DB::Exception: Value passed to 'throwIf' function is non-zero: \
+            while executing 'FUNCTION throwIf(equals(number, 2) :: 1) -> throwIf(equals(number, 2))\
+            UInt8 : 0'. (FUNCTION_THROW_IF_VALUE_IS_NON_ZERO) (version 23.3.1.2823 (official build))\n",
+        );
+
+        let err = extract_exception(&mut chunk).unwrap();
+        assert_eq!(chunk, &b"data\n"[..]);
+        assert!(matches!(err, Error::ServerException { code: 395, .. }));
+    }
 }
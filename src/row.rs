@@ -1,7 +1,13 @@
-use crate::sql;
+use serde::Serialize;
+
+use crate::{nested, sql};
 
 pub trait DbRow {
     const COLUMN_NAMES: &'static [&'static str];
+
+    /// Columns following `COLUMN_NAMES`, e.g. `P1, P2` of `(SomeRow, P1, P2)`.
+    #[doc(hidden)]
+    const TRAILING_COLUMNS: usize = 0;
 }
 
 /// The InsertRow Trait is a ObjectSafe version of row that allows us to
@@ -42,6 +48,7 @@ macro_rules! impl_row_for_tuple {
         /// `SELECT ?fields, count() FROM .. GROUP BY ?fields`.
         impl<$i: DbRow, $($other: Primitive),+> DbRow for ($i, $($other),+) {
             const COLUMN_NAMES: &'static [&'static str] = $i::COLUMN_NAMES;
+            const TRAILING_COLUMNS: usize = [$(stringify!($other)),+].len();
         }
 
         impl_row_for_tuple!($($other)+);
@@ -68,45 +75,44 @@ pub(crate) fn join_column_names<R: DbRow>() -> Option<String> {
         return None;
     }
 
-    let out = R::COLUMN_NAMES
-        .iter()
-        .enumerate()
-        .fold(String::new(), |mut res, (idx, name)| {
-            if idx > 0 {
-                res.push(',');
-            }
-            sql::escape::identifier(name, &mut res).expect("impossible");
-            res
-        });
-
-    Some(out)
+    Some(join_names(R::COLUMN_NAMES))
 }
 
-pub(crate) fn join_column_names_insert<R: InsertRow>(row: &R) -> Option<String> {
+/// Like [`join_column_names`], but also expands `Nested` columns of `row`.
+pub(crate) fn join_column_names_insert<R: InsertRow + Serialize>(row: &R) -> Option<String> {
+    if let Some(names) = nested::column_names(row) {
+        return Some(join_names(&names));
+    }
+
     let column = row.get_column_names();
     if column.is_empty() {
         return None;
     }
 
-    let out = column
+    Some(join_names(column))
+}
+
+/// Returns the number of columns of `row`, same as [`join_column_names_insert`] emits.
+pub(crate) fn column_count_insert<R: InsertRow + Serialize>(row: &R) -> usize {
+    nested::column_names(row).map_or_else(|| row.get_column_names().len(), |names| names.len())
+}
+
+pub(crate) fn join_names(names: &[impl AsRef<str>]) -> String {
+    names
         .iter()
         .enumerate()
         .fold(String::new(), |mut res, (idx, name)| {
             if idx > 0 {
                 res.push(',');
             }
-            sql::escape::identifier(name, &mut res).expect("impossible");
+            sql::escape::identifier(name.as_ref(), &mut res).expect("impossible");
             res
-        });
-
-    Some(out)
+        })
 }
 
 #[cfg(test)]
 mod tests {
-    // XXX: need for `derive(Row)`. Provide `row(crate = ..)` instead.
-    use crate as clickhouse;
-    use clickhouse::DbRow;
+    use clickhouse::Row;
 
     use super::*;
 
@@ -153,6 +159,29 @@ mod tests {
         assert_eq!(join_column_names::<TopLevel>().unwrap(), "`two`");
     }
 
+    #[test]
+    fn it_supports_clickhouse_renaming() {
+        use serde::Serialize;
+
+        #[derive(Row, Serialize)]
+        #[allow(dead_code)]
+        struct MyRow {
+            #[clickhouse(rename = "user.id")]
+            user_id: u32,
+            #[clickhouse(rename = "count()")]
+            count: u64,
+            // It takes precedence over `serde(rename)`.
+            #[serde(rename = "b")]
+            #[clickhouse(rename = "toString(x)")]
+            x: String,
+        }
+
+        assert_eq!(
+            join_column_names::<MyRow>().unwrap(),
+            "`user.id`,`count()`,`toString(x)`"
+        );
+    }
+
     #[test]
     fn it_skips_serializing() {
         use serde::Serialize;
@@ -171,6 +200,7 @@ mod tests {
     #[test]
     fn it_rejects_other() {
         #[derive(Row)]
+        #[allow(dead_code)]
         struct NamedTuple(u32, u32);
 
         assert_eq!(join_column_names::<u32>(), None);
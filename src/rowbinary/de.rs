@@ -1,4 +1,4 @@
-use std::{convert::TryFrom, mem, str};
+use std::{convert::TryFrom, mem, slice, str};
 
 use bytes::Buf;
 use serde::{
@@ -6,6 +6,7 @@ use serde::{
     Deserialize,
 };
 
+use super::types::ColumnType;
 use crate::error::{Error, Result};
 
 /// Deserializes a value from `buffer` with a message encoded in the RowBinary format.
@@ -13,19 +14,48 @@ pub(crate) fn deserialize_from<'de, T: Deserialize<'de>>(
     input: impl Buf,
     temp_buf: &'de mut [u8],
 ) -> Result<T> {
-    let mut deserializer = RowBinaryDeserializer { input, temp_buf };
+    let mut deserializer = RowBinaryDeserializer {
+        input,
+        temp_buf,
+        columns: &[],
+        hint: None,
+    };
+    T::deserialize(&mut deserializer)
+}
+
+/// Deserializes a value like [`deserialize_from`], but reads integers as they're
+/// typed in `columns` (`None` for unsupported types, which are read strictly),
+/// converting them to the requested width and failing with [`Error::Overflow`]
+/// if a value doesn't fit.
+///
+/// A row is either a tuple (or a struct) of all these columns, or the only column itself.
+pub(crate) fn deserialize_lenient_from<'de, T: Deserialize<'de>>(
+    input: impl Buf,
+    temp_buf: &'de mut [u8],
+    columns: &[Option<ColumnType>],
+) -> Result<T> {
+    let mut deserializer = RowBinaryDeserializer {
+        input,
+        temp_buf,
+        columns,
+        hint: None,
+    };
     T::deserialize(&mut deserializer)
 }
 
 /// A deserializer for the RowBinary format.
 ///
 /// See https://clickhouse.yandex/docs/en/interfaces/formats/#rowbinary for details.
-struct RowBinaryDeserializer<'de, B> {
+struct RowBinaryDeserializer<'de, 'c, B> {
     input: B,
     temp_buf: &'de mut [u8],
+    // Types of top-level columns, which are empty in the strict mode.
+    columns: &'c [Option<ColumnType>],
+    // The type of the next value, if known.
+    hint: Option<&'c ColumnType>,
 }
 
-impl<'de, B: Buf> RowBinaryDeserializer<'de, B> {
+impl<'de, 'c, B: Buf> RowBinaryDeserializer<'de, 'c, B> {
     fn read_vec(&mut self, size: usize) -> Result<Vec<u8>> {
         ensure_size(&mut self.input, size)?;
         let mut vec = vec![0; size];
@@ -53,10 +83,103 @@ impl<'de, B: Buf> RowBinaryDeserializer<'de, B> {
         // TODO: what about another error?
         usize::try_from(size).map_err(|_| Error::NotEnoughData)
     }
+
+    fn take_hint(&mut self) -> Option<&'c ColumnType> {
+        if let Some(hint) = self.hint.take() {
+            return Some(hint);
+        }
+
+        // The row is the only column, e.g. `fetch::<u32>()`.
+        match mem::take(&mut self.columns) {
+            [column] => column.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Reads an integer of the hinted type, if the hint is an integer.
+    fn read_hinted_integer(&mut self) -> Result<Option<i128>> {
+        let value = match self.take_hint() {
+            Some(ColumnType::UInt8 | ColumnType::Bool) => self.read_integer(1, Buf::get_u8)?,
+            Some(ColumnType::UInt16) => self.read_integer(2, Buf::get_u16_le)?,
+            Some(ColumnType::UInt32) => self.read_integer(4, Buf::get_u32_le)?,
+            Some(ColumnType::UInt64) => self.read_integer(8, Buf::get_u64_le)?,
+            Some(ColumnType::Int8) => self.read_integer(1, Buf::get_i8)?,
+            Some(ColumnType::Int16) => self.read_integer(2, Buf::get_i16_le)?,
+            Some(ColumnType::Int32) => self.read_integer(4, Buf::get_i32_le)?,
+            Some(ColumnType::Int64) => self.read_integer(8, Buf::get_i64_le)?,
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+
+    fn read_integer<T: Into<i128>>(&mut self, size: usize, get: fn(&mut B) -> T) -> Result<i128> {
+        ensure_size(&mut self.input, size)?;
+        Ok(get(&mut self.input).into())
+    }
+
+    fn visit_elements<V: Visitor<'de>>(
+        &mut self,
+        len: usize,
+        hints: ElementHints<'c>,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_seq(Access {
+            deserializer: self,
+            hints,
+            len,
+        })
+    }
+}
+
+struct Access<'de, 'c, 'a, B> {
+    deserializer: &'a mut RowBinaryDeserializer<'de, 'c, B>,
+    hints: ElementHints<'c>,
+    len: usize,
+}
+
+impl<'de, B: Buf> SeqAccess<'de> for Access<'de, '_, '_, B> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.len > 0 {
+            self.len -= 1;
+            self.deserializer.hint = self.hints.next();
+            let value = DeserializeSeed::deserialize(seed, &mut *self.deserializer)?;
+            Ok(Some(value))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+/// Hints for elements of a tuple or a sequence.
+enum ElementHints<'c> {
+    None,
+    Columns(slice::Iter<'c, Option<ColumnType>>),
+    Tuple(slice::Iter<'c, (Option<String>, ColumnType)>),
+    Each(&'c ColumnType),
+}
+
+impl<'c> ElementHints<'c> {
+    fn next(&mut self) -> Option<&'c ColumnType> {
+        match self {
+            Self::None => None,
+            Self::Columns(iter) => iter.next().and_then(Option::as_ref),
+            Self::Tuple(iter) => iter.next().map(|(_, ty)| ty),
+            Self::Each(ty) => Some(ty),
+        }
+    }
 }
 
 #[inline]
-fn ensure_size(buffer: impl Buf, size: usize) -> Result<()> {
+pub(crate) fn ensure_size(buffer: impl Buf, size: usize) -> Result<()> {
     if buffer.remaining() < size {
         Err(Error::NotEnoughData)
     } else {
@@ -64,6 +187,25 @@ fn ensure_size(buffer: impl Buf, size: usize) -> Result<()> {
     }
 }
 
+macro_rules! impl_int {
+    ($ty:ty, $deser_method:ident, $visitor_method:ident, $reader_method:ident) => {
+        #[inline]
+        fn $deser_method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            if let Some(value) = self.read_hinted_integer()? {
+                let value = <$ty>::try_from(value).map_err(|_| Error::Overflow {
+                    value,
+                    target: stringify!($ty),
+                })?;
+                return visitor.$visitor_method(value);
+            }
+
+            ensure_size(&mut self.input, mem::size_of::<$ty>())?;
+            let value = self.input.$reader_method();
+            visitor.$visitor_method(value)
+        }
+    };
+}
+
 macro_rules! impl_num {
     ($ty:ty, $deser_method:ident, $visitor_method:ident, $reader_method:ident) => {
         #[inline]
@@ -75,7 +217,7 @@ macro_rules! impl_num {
     };
 }
 
-impl<'de, 'a, B: Buf> Deserializer<'de> for &'a mut RowBinaryDeserializer<'de, B> {
+impl<'de, B: Buf> Deserializer<'de> for &mut RowBinaryDeserializer<'de, '_, B> {
     type Error = Error;
 
     #[inline]
@@ -83,16 +225,16 @@ impl<'de, 'a, B: Buf> Deserializer<'de> for &'a mut RowBinaryDeserializer<'de, B
         Err(Error::DeserializeAnyNotSupported)
     }
 
-    impl_num!(i8, deserialize_i8, visit_i8, get_i8);
-    impl_num!(i16, deserialize_i16, visit_i16, get_i16_le);
-    impl_num!(i32, deserialize_i32, visit_i32, get_i32_le);
-    impl_num!(i64, deserialize_i64, visit_i64, get_i64_le);
-    impl_num!(i128, deserialize_i128, visit_i128, get_i128_le);
-    impl_num!(u8, deserialize_u8, visit_u8, get_u8);
-    impl_num!(u16, deserialize_u16, visit_u16, get_u16_le);
-    impl_num!(u32, deserialize_u32, visit_u32, get_u32_le);
-    impl_num!(u64, deserialize_u64, visit_u64, get_u64_le);
-    impl_num!(u128, deserialize_u128, visit_u128, get_u128_le);
+    impl_int!(i8, deserialize_i8, visit_i8, get_i8);
+    impl_int!(i16, deserialize_i16, visit_i16, get_i16_le);
+    impl_int!(i32, deserialize_i32, visit_i32, get_i32_le);
+    impl_int!(i64, deserialize_i64, visit_i64, get_i64_le);
+    impl_int!(i128, deserialize_i128, visit_i128, get_i128_le);
+    impl_int!(u8, deserialize_u8, visit_u8, get_u8);
+    impl_int!(u16, deserialize_u16, visit_u16, get_u16_le);
+    impl_int!(u32, deserialize_u32, visit_u32, get_u32_le);
+    impl_int!(u64, deserialize_u64, visit_u64, get_u64_le);
+    impl_int!(u128, deserialize_u128, visit_u128, get_u128_le);
     impl_num!(f32, deserialize_f32, visit_f32, get_f32_le);
     impl_num!(f64, deserialize_f64, visit_f64, get_f64_le);
 
@@ -109,12 +251,13 @@ impl<'de, 'a, B: Buf> Deserializer<'de> for &'a mut RowBinaryDeserializer<'de, B
 
     #[inline]
     fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-        ensure_size(&mut self.input, 1)?;
-        match self.input.get_u8() {
-            0 => visitor.visit_bool(false),
-            1 => visitor.visit_bool(true),
-            v => Err(Error::InvalidTagEncoding(v as usize)),
+        if let Some(value) = self.read_hinted_integer()? {
+            return visitor.visit_bool(value != 0);
         }
+
+        ensure_size(&mut self.input, 1)?;
+        // Any nonzero value is `true`, e.g. for legacy `UInt8` flags.
+        visitor.visit_bool(self.input.get_u8() != 0)
     }
 
     #[inline]
@@ -158,44 +301,30 @@ impl<'de, 'a, B: Buf> Deserializer<'de> for &'a mut RowBinaryDeserializer<'de, B
 
     #[inline]
     fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
-        struct Access<'de, 'a, B> {
-            deserializer: &'a mut RowBinaryDeserializer<'de, B>,
-            len: usize,
-        }
-
-        impl<'de, 'a, B: Buf> SeqAccess<'de> for Access<'de, 'a, B> {
-            type Error = Error;
-
-            fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
-            where
-                T: DeserializeSeed<'de>,
-            {
-                if self.len > 0 {
-                    self.len -= 1;
-                    let value = DeserializeSeed::deserialize(seed, &mut *self.deserializer)?;
-                    Ok(Some(value))
-                } else {
-                    Ok(None)
-                }
-            }
-
-            fn size_hint(&self) -> Option<usize> {
-                Some(self.len)
+        let hints = if !self.columns.is_empty() && self.columns.len() == len {
+            ElementHints::Columns(mem::take(&mut self.columns).iter())
+        } else {
+            match self.take_hint() {
+                Some(ColumnType::Tuple(types)) => ElementHints::Tuple(types.iter()),
+                _ => ElementHints::None,
             }
-        }
+        };
 
-        visitor.visit_seq(Access {
-            deserializer: self,
-            len,
-        })
+        self.visit_elements(len, hints, visitor)
     }
 
     #[inline]
     fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let hint = self.take_hint();
         ensure_size(&mut self.input, 1)?;
 
         match self.input.get_u8() {
-            0 => visitor.visit_some(&mut *self),
+            0 => {
+                if let Some(ColumnType::Nullable(ty)) = hint {
+                    self.hint = Some(ty);
+                }
+                visitor.visit_some(&mut *self)
+            }
             1 => visitor.visit_none(),
             v => Err(Error::InvalidTagEncoding(v as usize)),
         }
@@ -203,8 +332,13 @@ impl<'de, 'a, B: Buf> Deserializer<'de> for &'a mut RowBinaryDeserializer<'de, B
 
     #[inline]
     fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let hints = match self.take_hint() {
+            Some(ColumnType::Array(ty)) => ElementHints::Each(ty),
+            _ => ElementHints::None,
+        };
+
         let len = self.read_size()?;
-        self.deserialize_tuple(len, visitor)
+        self.visit_elements(len, hints, visitor)
     }
 
     #[inline]
@@ -266,7 +400,7 @@ impl<'de, 'a, B: Buf> Deserializer<'de> for &'a mut RowBinaryDeserializer<'de, B
     }
 }
 
-fn get_unsigned_leb128(mut buffer: impl Buf) -> Result<u64> {
+pub(crate) fn get_unsigned_leb128(mut buffer: impl Buf) -> Result<u64> {
     let mut value = 0u64;
     let mut shift = 0;
 
@@ -1,7 +1,13 @@
-pub(crate) use de::deserialize_from;
+pub(crate) use de::{deserialize_from, deserialize_lenient_from};
 pub(crate) use ser::serialize_into;
+pub(crate) use types::ColumnType;
+pub(crate) use value::{
+    int256_to_string, read_column_names, read_column_names_and_types, read_header, read_row, Column,
+};
 
 mod de;
 mod ser;
 #[cfg(test)]
 mod tests;
+mod types;
+mod value;
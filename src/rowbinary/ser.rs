@@ -3,7 +3,6 @@ use serde::{
     ser::{Impossible, SerializeSeq, SerializeStruct, SerializeTuple, Serializer},
     Serialize,
 };
-use std::mem;
 
 use crate::error::{Error, Result};
 
@@ -31,7 +30,7 @@ macro_rules! impl_num {
     };
 }
 
-impl<'a, B: BufMut> Serializer for &'a mut RowBinarySerializer<B> {
+impl<B: BufMut> Serializer for &mut RowBinarySerializer<B> {
     type Ok = ();
     type Error = Error;
     type SerializeSeq = Self;
@@ -199,30 +198,13 @@ impl<'a, B: BufMut> Serializer for &'a mut RowBinarySerializer<B> {
     }
 }
 
-impl<'a, B: BufMut> SerializeStruct for &'a mut RowBinarySerializer<B> {
+impl<B: BufMut> SerializeStruct for &mut RowBinarySerializer<B> {
     type Ok = ();
     type Error = Error;
 
-    /// In Clickhouse, when inserting in the RowBinary format:
-    /// -- String is represented as a varint length (unsigned LEB128), followed by the bytes of the string.
-    /// -- FixedString is represented simply as a sequence of bytes.
-    /// When serializing a FixedString, we can simply serialize the String in the wrapper struct FixedString
-    /// Since T is generic and the &str type already has an implementation which encodes it as LEB128,
-    /// we can coerce T to a &str and put the bytes in the buffer without LEB128.
-    /// * This will fail if the length of the underlying string != n for Clickhouse type FixedString(n)
     #[inline]
-    fn serialize_field<T: Serialize + ?Sized>(
-        &mut self,
-        name: &'static str,
-        value: &T,
-    ) -> Result<()> {
-        if name == "FixedString" {
-            let value_str: &&String = unsafe { mem::transmute(&value) };
-            self.buffer.put_slice(value_str.as_bytes());
-            Ok(())
-        } else {
-            value.serialize(&mut **self)
-        }
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, _: &'static str, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
     }
 
     #[inline]
@@ -231,7 +213,7 @@ impl<'a, B: BufMut> SerializeStruct for &'a mut RowBinarySerializer<B> {
     }
 }
 
-impl<'a, B: BufMut> SerializeSeq for &'a mut RowBinarySerializer<B> {
+impl<B: BufMut> SerializeSeq for &mut RowBinarySerializer<B> {
     type Ok = ();
     type Error = Error;
 
@@ -244,7 +226,7 @@ impl<'a, B: BufMut> SerializeSeq for &'a mut RowBinarySerializer<B> {
     }
 }
 
-impl<'a, B: BufMut> SerializeTuple for &'a mut RowBinarySerializer<B> {
+impl<B: BufMut> SerializeTuple for &mut RowBinarySerializer<B> {
     type Ok = ();
     type Error = Error;
 
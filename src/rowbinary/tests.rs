@@ -133,3 +133,147 @@ fn it_deserializes() {
         assert_eq!(actual, sample());
     }
 }
+
+#[test]
+fn it_encodes_ip_addresses() {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Ips {
+        #[serde(with = "crate::serde::ipv4")]
+        ipv4: Ipv4Addr,
+        ipv6: Ipv6Addr,
+    }
+
+    let ips = Ips {
+        ipv4: Ipv4Addr::new(192, 168, 0, 1),
+        ipv6: Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0xafc8, 0x10, 0x1),
+    };
+
+    let expected = vec![
+        // [IPv4] 192.168.0.1, a little-endian `UInt32`
+        0x01, 0x00, 0xa8, 0xc0, /**/
+        // [IPv6] 2001:db8::afc8:10:1, in network byte order
+        0x20, 0x01, 0x0d, 0xb8, 0x00, 0x00, 0x00, 0x00, /**/
+        0x00, 0x00, 0xaf, 0xc8, 0x00, 0x10, 0x00, 0x01, /**/
+    ];
+
+    let mut actual = Vec::new();
+    super::serialize_into(&mut actual, &ips).unwrap();
+    assert_eq!(actual, expected);
+
+    let actual: Ips = super::deserialize_from(&expected[..], &mut []).unwrap();
+    assert_eq!(actual, ips);
+}
+
+#[test]
+fn it_handles_multibyte_string_lengths() {
+    let cases: [(usize, &[u8]); 3] = [(127, b"\x7f"), (128, b"\x80\x01"), (16384, b"\x80\x80\x01")];
+
+    for (len, prefix) in cases {
+        let string = "x".repeat(len);
+        let mut actual = Vec::new();
+        super::serialize_into(&mut actual, &(&string, 42u8)).unwrap();
+        assert!(actual.starts_with(prefix), "length {}", len);
+        assert_eq!(actual.len(), prefix.len() + len + 1);
+
+        let value: (String, u8) = super::deserialize_from(&actual[..], &mut []).unwrap();
+        assert_eq!(value, (string, 42));
+    }
+}
+
+#[test]
+fn it_handles_string_lengths_split_between_chunks() {
+    use bytes::Bytes;
+
+    use crate::{buflist::BufList, error::Error};
+
+    let string = "x".repeat(16384);
+    let mut input = Vec::new();
+    super::serialize_into(&mut input, &(&string, 42u8)).unwrap();
+
+    // Including splits inside the 3-byte length prefix.
+    for split in 1..input.len() {
+        let mut pending = BufList::default();
+        pending.push(Bytes::copy_from_slice(&input[..split]));
+
+        // Like `RawCursor`, rollback and wait for the next chunk.
+        let result = super::deserialize_from::<(String, u8)>(&mut pending, &mut []);
+        assert!(
+            matches!(result, Err(Error::NotEnoughData)),
+            "split {}",
+            split
+        );
+        pending.rollback();
+
+        pending.push(Bytes::copy_from_slice(&input[split..]));
+        let value: (String, u8) = super::deserialize_from(&mut pending, &mut []).unwrap();
+        assert_eq!(value, (string.clone(), 42));
+        pending.commit();
+        assert_eq!(pending.bufs_cnt(), 0);
+    }
+}
+
+#[test]
+fn it_encodes_booleans_as_bytes() {
+    let mut actual = Vec::new();
+    super::serialize_into(&mut actual, &(true, false)).unwrap();
+    assert_eq!(actual, [1, 0]);
+
+    let value: (bool, bool) = super::deserialize_from(&actual[..], &mut []).unwrap();
+    assert_eq!(value, (true, false));
+
+    // E.g. `UInt8` flags.
+    let value: (bool, bool) = super::deserialize_from(&[2, 255][..], &mut []).unwrap();
+    assert_eq!(value, (true, true));
+}
+
+#[test]
+fn it_roundtrips_128_bit_integers() {
+    let value = (i128::MIN, i128::MAX, u128::MIN, u128::MAX);
+    let mut actual = Vec::new();
+    super::serialize_into(&mut actual, &value).unwrap();
+
+    let mut expected = Vec::new();
+    expected.extend_from_slice(&i128::MIN.to_le_bytes());
+    expected.extend_from_slice(&i128::MAX.to_le_bytes());
+    expected.extend_from_slice(&[0; 16]);
+    expected.extend_from_slice(&[0xff; 16]);
+    assert_eq!(actual, expected);
+
+    let actual: (i128, i128, u128, u128) = super::deserialize_from(&actual[..], &mut []).unwrap();
+    assert_eq!(actual, value);
+}
+
+#[test]
+fn it_reads_nested_integers_leniently() {
+    use super::ColumnType;
+
+    let columns = ["Array(Int16)", "Tuple(UInt8, Bool)", "String"]
+        .iter()
+        .map(|ty| ColumnType::parse(ty).ok())
+        .collect::<Vec<_>>();
+
+    let mut input = vec![2];
+    input.extend_from_slice(&(-1i16).to_le_bytes());
+    input.extend_from_slice(&300i16.to_le_bytes());
+    input.extend_from_slice(&[7, 1, 2, b'h', b'i']);
+
+    let value: (Vec<i64>, (u64, u32), String) =
+        super::deserialize_lenient_from(&input[..], &mut [], &columns).unwrap();
+    assert_eq!(value, (vec![-1, 300], (7, 1), "hi".into()));
+
+    // The same columns, but values don't fit.
+    let result = super::deserialize_lenient_from::<(Vec<u16>, (u8, u8), String)>(
+        &input[..],
+        &mut [],
+        &columns,
+    );
+    assert!(matches!(
+        result,
+        Err(crate::error::Error::Overflow {
+            value: -1,
+            target: "u16"
+        })
+    ));
+}
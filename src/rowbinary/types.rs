@@ -0,0 +1,420 @@
+use crate::error::{Error, Result};
+
+/// A column type as reported by `RowBinaryWithNamesAndTypes`.
+///
+/// Only types having a fixed RowBinary representation are supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ColumnType {
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    UInt128,
+    UInt256,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Int128,
+    Int256,
+    Float32,
+    Float64,
+    Bool,
+    String,
+    FixedString(usize),
+    Uuid,
+    Date,
+    Date32,
+    DateTime,
+    DateTime64(u32),
+    Decimal { precision: u32, scale: u32 },
+    Ipv4,
+    Ipv6,
+    Enum8(Vec<(String, i8)>),
+    Enum16(Vec<(String, i16)>),
+    Nullable(Box<ColumnType>),
+    Array(Box<ColumnType>),
+    Tuple(Vec<(Option<String>, ColumnType)>),
+    Map(Box<ColumnType>, Box<ColumnType>),
+}
+
+impl ColumnType {
+    pub(crate) fn parse(src: &str) -> Result<Self> {
+        let mut parser = Parser { src, pos: 0 };
+        let ty = parser.parse_type()?;
+        parser.skip_ws();
+
+        if parser.pos != src.len() {
+            return Err(parser.error());
+        }
+
+        Ok(ty)
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self) -> Error {
+        Error::BadResponse(format!("unsupported column type: {}", self.src))
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        self.skip_ws();
+        if self.rest().starts_with(ch) {
+            self.pos += ch.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, ch: char) -> Result<()> {
+        if self.eat(ch) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_ws();
+        self.rest().chars().next()
+    }
+
+    fn ident(&mut self) -> Result<&'a str> {
+        self.skip_ws();
+
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+
+        if len == 0 {
+            return Err(self.error());
+        }
+
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    // Parses both `name` and `` `quoted name` ``.
+    fn name(&mut self) -> Result<String> {
+        if self.peek() == Some('`') {
+            self.quoted('`')
+        } else {
+            self.ident().map(Into::into)
+        }
+    }
+
+    fn quoted(&mut self, quote: char) -> Result<String> {
+        self.expect(quote)?;
+
+        let mut out = String::new();
+        let mut chars = self.rest().char_indices();
+
+        while let Some((idx, ch)) = chars.next() {
+            match ch {
+                '\\' => match chars.next() {
+                    Some((_, ch)) => out.push(ch),
+                    None => break,
+                },
+                ch if ch == quote => {
+                    self.pos += idx + ch.len_utf8();
+                    return Ok(out);
+                }
+                ch => out.push(ch),
+            }
+        }
+
+        Err(self.error())
+    }
+
+    fn int<T: std::str::FromStr>(&mut self) -> Result<T> {
+        self.skip_ws();
+
+        let rest = self.rest();
+        let len = rest
+            .char_indices()
+            .find(|&(idx, c)| !(c.is_ascii_digit() || (idx == 0 && c == '-')))
+            .map_or(rest.len(), |(idx, _)| idx);
+
+        let value = rest[..len].parse().map_err(|_| self.error())?;
+        self.pos += len;
+        Ok(value)
+    }
+
+    // Skips an arbitrary argument, e.g. a function name with parameters.
+    fn skip_arg(&mut self) -> Result<()> {
+        let mut depth = 0usize;
+
+        for (idx, ch) in self.rest().char_indices() {
+            match ch {
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ',' | ')' if depth == 0 => {
+                    self.pos += idx;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        Err(self.error())
+    }
+
+    fn parse_type(&mut self) -> Result<ColumnType> {
+        let name = self.ident()?;
+
+        Ok(match name {
+            "UInt8" => ColumnType::UInt8,
+            "UInt16" => ColumnType::UInt16,
+            "UInt32" => ColumnType::UInt32,
+            "UInt64" => ColumnType::UInt64,
+            "UInt128" => ColumnType::UInt128,
+            "UInt256" => ColumnType::UInt256,
+            "Int8" => ColumnType::Int8,
+            "Int16" => ColumnType::Int16,
+            "Int32" => ColumnType::Int32,
+            "Int64" => ColumnType::Int64,
+            "Int128" => ColumnType::Int128,
+            "Int256" => ColumnType::Int256,
+            "Float32" => ColumnType::Float32,
+            "Float64" => ColumnType::Float64,
+            "Bool" => ColumnType::Bool,
+            "String" => ColumnType::String,
+            "UUID" => ColumnType::Uuid,
+            "Date" => ColumnType::Date,
+            "Date32" => ColumnType::Date32,
+            "IPv4" => ColumnType::Ipv4,
+            "IPv6" => ColumnType::Ipv6,
+            "FixedString" => {
+                self.expect('(')?;
+                let size = self.int()?;
+                self.expect(')')?;
+                ColumnType::FixedString(size)
+            }
+            "DateTime" => {
+                // The timezone doesn't affect the binary representation.
+                if self.eat('(') {
+                    self.quoted('\'')?;
+                    self.expect(')')?;
+                }
+                ColumnType::DateTime
+            }
+            "DateTime64" => {
+                self.expect('(')?;
+                let precision = self.int()?;
+                if self.eat(',') {
+                    self.quoted('\'')?;
+                }
+                self.expect(')')?;
+                ColumnType::DateTime64(precision)
+            }
+            "Decimal" => {
+                self.expect('(')?;
+                let precision = self.int()?;
+                self.expect(',')?;
+                let scale = self.int()?;
+                self.expect(')')?;
+                ColumnType::Decimal { precision, scale }
+            }
+            "Decimal32" | "Decimal64" | "Decimal128" | "Decimal256" => {
+                self.expect('(')?;
+                let scale = self.int()?;
+                self.expect(')')?;
+                let precision = match name {
+                    "Decimal32" => 9,
+                    "Decimal64" => 18,
+                    "Decimal128" => 38,
+                    _ => 76,
+                };
+                ColumnType::Decimal { precision, scale }
+            }
+            "Enum8" => ColumnType::Enum8(self.parse_enum_items()?),
+            "Enum16" => ColumnType::Enum16(self.parse_enum_items()?),
+            "Nullable" => ColumnType::Nullable(Box::new(self.parse_wrapped()?)),
+            "Array" => ColumnType::Array(Box::new(self.parse_wrapped()?)),
+            // `LowCardinality(T)` is encoded as `T` in RowBinary.
+            "LowCardinality" => self.parse_wrapped()?,
+            "Tuple" => ColumnType::Tuple(self.parse_tuple_items()?),
+            "Map" => {
+                self.expect('(')?;
+                let key = self.parse_type()?;
+                self.expect(',')?;
+                let value = self.parse_type()?;
+                self.expect(')')?;
+                ColumnType::Map(Box::new(key), Box::new(value))
+            }
+            "SimpleAggregateFunction" => {
+                self.expect('(')?;
+                self.skip_arg()?;
+                self.expect(',')?;
+                let ty = self.parse_type()?;
+                self.expect(')')?;
+                ty
+            }
+            "Point" => point(),
+            "Ring" => ColumnType::Array(Box::new(point())),
+            "Polygon" => ColumnType::Array(Box::new(ColumnType::Array(Box::new(point())))),
+            "MultiPolygon" => ColumnType::Array(Box::new(ColumnType::Array(Box::new(
+                ColumnType::Array(Box::new(point())),
+            )))),
+            _ => return Err(self.error()),
+        })
+    }
+
+    fn parse_wrapped(&mut self) -> Result<ColumnType> {
+        self.expect('(')?;
+        let ty = self.parse_type()?;
+        self.expect(')')?;
+        Ok(ty)
+    }
+
+    fn parse_enum_items<T: std::str::FromStr>(&mut self) -> Result<Vec<(String, T)>> {
+        let mut items = Vec::new();
+
+        self.expect('(')?;
+        loop {
+            let name = self.quoted('\'')?;
+            self.expect('=')?;
+            items.push((name, self.int()?));
+
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.expect(')')?;
+
+        Ok(items)
+    }
+
+    fn parse_tuple_items(&mut self) -> Result<Vec<(Option<String>, ColumnType)>> {
+        let mut items = Vec::new();
+
+        self.expect('(')?;
+        loop {
+            // Elements can be named, e.g. `Tuple(a UInt32, b String)`.
+            let start = self.pos;
+            let name = self.name()?;
+            let item = match self.peek() {
+                Some(',' | ')' | '(') | None => {
+                    self.pos = start;
+                    (None, self.parse_type()?)
+                }
+                Some(_) => (Some(name), self.parse_type()?),
+            };
+            items.push(item);
+
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.expect(')')?;
+
+        Ok(items)
+    }
+}
+
+fn point() -> ColumnType {
+    ColumnType::Tuple(vec![
+        (None, ColumnType::Float64),
+        (None, ColumnType::Float64),
+    ])
+}
+
+#[test]
+fn it_parses_simple_types() {
+    assert_eq!(ColumnType::parse("UInt64").unwrap(), ColumnType::UInt64);
+    assert_eq!(ColumnType::parse("String").unwrap(), ColumnType::String);
+    assert_eq!(
+        ColumnType::parse("FixedString(16)").unwrap(),
+        ColumnType::FixedString(16)
+    );
+    assert_eq!(
+        ColumnType::parse("DateTime('Europe/London')").unwrap(),
+        ColumnType::DateTime
+    );
+    assert_eq!(
+        ColumnType::parse("DateTime64(3, 'Asia/Tokyo')").unwrap(),
+        ColumnType::DateTime64(3)
+    );
+    assert_eq!(
+        ColumnType::parse("Decimal(18, 4)").unwrap(),
+        ColumnType::Decimal {
+            precision: 18,
+            scale: 4
+        }
+    );
+    assert_eq!(
+        ColumnType::parse("Decimal32(2)").unwrap(),
+        ColumnType::Decimal {
+            precision: 9,
+            scale: 2
+        }
+    );
+}
+
+#[test]
+fn it_parses_compound_types() {
+    use ColumnType::*;
+
+    assert_eq!(
+        ColumnType::parse("Array(Nullable(String))").unwrap(),
+        Array(Box::new(Nullable(Box::new(String))))
+    );
+    assert_eq!(
+        ColumnType::parse("LowCardinality(Nullable(String))").unwrap(),
+        Nullable(Box::new(String))
+    );
+    assert_eq!(
+        ColumnType::parse("Map(String, Array(UInt8))").unwrap(),
+        Map(Box::new(String), Box::new(Array(Box::new(UInt8))))
+    );
+    assert_eq!(
+        ColumnType::parse("Tuple(UInt8, String)").unwrap(),
+        Tuple(vec![(None, UInt8), (None, String)])
+    );
+    assert_eq!(
+        ColumnType::parse("Tuple(a UInt8, `b c` Array(String))").unwrap(),
+        Tuple(vec![
+            (Some("a".into()), UInt8),
+            (Some("b c".into()), Array(Box::new(String)))
+        ])
+    );
+    assert_eq!(
+        ColumnType::parse("SimpleAggregateFunction(quantiles(0.5, 0.9), UInt64)").unwrap(),
+        UInt64
+    );
+}
+
+#[test]
+fn it_parses_enums() {
+    assert_eq!(
+        ColumnType::parse("Enum8('a' = 1, 'b\\'c' = -2)").unwrap(),
+        ColumnType::Enum8(vec![("a".into(), 1), ("b'c".into(), -2)])
+    );
+    assert_eq!(
+        ColumnType::parse("Enum16('x' = 1000)").unwrap(),
+        ColumnType::Enum16(vec![("x".into(), 1000)])
+    );
+}
+
+#[test]
+fn it_rejects_unsupported_types() {
+    assert!(ColumnType::parse("AggregateFunction(uniq, String)").is_err());
+    assert!(ColumnType::parse("Array(UInt8").is_err());
+    assert!(ColumnType::parse("UInt8 garbage").is_err());
+}
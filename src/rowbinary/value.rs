@@ -0,0 +1,472 @@
+use std::{
+    convert::TryFrom,
+    fmt::Write,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use bytes::Buf;
+use serde_json::{Map, Value};
+
+use super::{
+    de::{ensure_size, get_unsigned_leb128},
+    types::ColumnType,
+};
+use crate::error::{Error, Result};
+
+/// A column described by the `RowBinaryWithNamesAndTypes` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Column {
+    pub(crate) name: String,
+    pub(crate) ty: ColumnType,
+}
+
+/// Reads the `RowBinaryWithNamesAndTypes` header: the number of columns,
+/// then all names and then all types.
+pub(crate) fn read_header(input: &mut impl Buf) -> Result<Vec<Column>> {
+    let count = read_size(input)?;
+
+    let names = (0..count)
+        .map(|_| read_string(input))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Columns are identified by names, so duplicates are ambiguous.
+    for (idx, name) in names.iter().enumerate() {
+        if names[..idx].contains(name) {
+            return Err(Error::DuplicateColumn(name.clone()));
+        }
+    }
+
+    names
+        .into_iter()
+        .map(|name| {
+            let ty = ColumnType::parse(&read_string(input)?)?;
+            Ok(Column { name, ty })
+        })
+        .collect()
+}
+
+/// Reads the `RowBinaryWithNamesAndTypes` header, returning names and types,
+/// which are `None` if unsupported by [`ColumnType`].
+pub(crate) fn read_column_names_and_types(
+    input: &mut impl Buf,
+) -> Result<(Vec<String>, Vec<Option<ColumnType>>)> {
+    let count = read_size(input)?;
+
+    let names = (0..count)
+        .map(|_| read_string(input))
+        .collect::<Result<Vec<_>>>()?;
+
+    let types = (0..count)
+        .map(|_| Ok(ColumnType::parse(&read_string(input)?).ok()))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((names, types))
+}
+
+/// Reads the `RowBinaryWithNamesAndTypes` header, but returns only names.
+///
+/// Types aren't parsed, because they're irrelevant if rows are decoded by serde.
+pub(crate) fn read_column_names(input: &mut impl Buf) -> Result<Vec<String>> {
+    let count = read_size(input)?;
+
+    let names = (0..count)
+        .map(|_| read_string(input))
+        .collect::<Result<Vec<_>>>()?;
+
+    for _ in 0..count {
+        read_string(input)?;
+    }
+
+    Ok(names)
+}
+
+/// Reads one row, pairing values with names of `columns`.
+pub(crate) fn read_row(input: &mut impl Buf, columns: &[Column]) -> Result<Vec<(String, Value)>> {
+    columns
+        .iter()
+        .map(|column| Ok((column.name.clone(), read_value(input, &column.ty)?)))
+        .collect()
+}
+
+macro_rules! get {
+    ($input:expr, $method:ident, $size:expr) => {{
+        ensure_size(&mut *$input, $size)?;
+        $input.$method()
+    }};
+}
+
+fn read_value(input: &mut impl Buf, ty: &ColumnType) -> Result<Value> {
+    Ok(match ty {
+        ColumnType::UInt8 => get!(input, get_u8, 1).into(),
+        ColumnType::UInt16 => get!(input, get_u16_le, 2).into(),
+        ColumnType::UInt32 => get!(input, get_u32_le, 4).into(),
+        ColumnType::UInt64 => get!(input, get_u64_le, 8).into(),
+        ColumnType::UInt128 => get!(input, get_u128_le, 16).to_string().into(),
+        ColumnType::UInt256 => int256_to_string(read_limbs(input)?, false).into(),
+        ColumnType::Int8 => get!(input, get_i8, 1).into(),
+        ColumnType::Int16 => get!(input, get_i16_le, 2).into(),
+        ColumnType::Int32 => get!(input, get_i32_le, 4).into(),
+        ColumnType::Int64 => get!(input, get_i64_le, 8).into(),
+        ColumnType::Int128 => get!(input, get_i128_le, 16).to_string().into(),
+        ColumnType::Int256 => int256_to_string(read_limbs(input)?, true).into(),
+        // Go through the shortest representation to avoid `0.1` becoming `0.10000000149`.
+        ColumnType::Float32 => match get!(input, get_f32_le, 4).to_string().parse::<f64>() {
+            Ok(value) => value.into(),
+            Err(_) => Value::Null,
+        },
+        ColumnType::Float64 => get!(input, get_f64_le, 8).into(),
+        ColumnType::Bool => (get!(input, get_u8, 1) != 0).into(),
+        ColumnType::String => read_string(input)?.into(),
+        ColumnType::FixedString(size) => String::from_utf8_lossy(&read_bytes(input, *size)?).into(),
+        ColumnType::Uuid => {
+            let high = get!(input, get_u64_le, 8);
+            let low = get!(input, get_u64_le, 8);
+            let hex = format!("{high:016x}{low:016x}");
+            format!(
+                "{}-{}-{}-{}-{}",
+                &hex[..8],
+                &hex[8..12],
+                &hex[12..16],
+                &hex[16..20],
+                &hex[20..]
+            )
+            .into()
+        }
+        ColumnType::Date => format_date(i64::from(get!(input, get_u16_le, 2))).into(),
+        ColumnType::Date32 => format_date(i64::from(get!(input, get_i32_le, 4))).into(),
+        ColumnType::DateTime => format_datetime(i64::from(get!(input, get_u32_le, 4)), 0, 0).into(),
+        ColumnType::DateTime64(precision) => {
+            let ticks = get!(input, get_i64_le, 8);
+            let scale = 10i64.pow(*precision);
+            let secs = ticks.div_euclid(scale);
+            let frac = ticks.rem_euclid(scale);
+            format_datetime(secs, frac, *precision as usize).into()
+        }
+        ColumnType::Decimal { precision, scale } => {
+            let digits = match precision {
+                0..=9 => get!(input, get_i32_le, 4).to_string(),
+                10..=18 => get!(input, get_i64_le, 8).to_string(),
+                19..=38 => get!(input, get_i128_le, 16).to_string(),
+                _ => int256_to_string(read_limbs(input)?, true),
+            };
+            format_decimal(digits, *scale as usize).into()
+        }
+        ColumnType::Ipv4 => Ipv4Addr::from(get!(input, get_u32_le, 4))
+            .to_string()
+            .into(),
+        ColumnType::Ipv6 => Ipv6Addr::from(get!(input, get_u128, 16)).to_string().into(),
+        ColumnType::Enum8(items) => enum_label(items, get!(input, get_i8, 1))?,
+        ColumnType::Enum16(items) => enum_label(items, get!(input, get_i16_le, 2))?,
+        ColumnType::Nullable(inner) => match get!(input, get_u8, 1) {
+            0 => read_value(input, inner)?,
+            _ => Value::Null,
+        },
+        ColumnType::Array(inner) => {
+            let len = read_size(input)?;
+            (0..len)
+                .map(|_| read_value(input, inner))
+                .collect::<Result<Vec<_>>>()?
+                .into()
+        }
+        ColumnType::Tuple(items) if items.iter().all(|(name, _)| name.is_some()) => items
+            .iter()
+            .map(|(name, ty)| Ok((name.clone().unwrap(), read_value(input, ty)?)))
+            .collect::<Result<Map<_, _>>>()?
+            .into(),
+        ColumnType::Tuple(items) => items
+            .iter()
+            .map(|(_, ty)| read_value(input, ty))
+            .collect::<Result<Vec<_>>>()?
+            .into(),
+        ColumnType::Map(key_ty, value_ty) => {
+            let len = read_size(input)?;
+            (0..len)
+                .map(|_| {
+                    let key = match read_value(input, key_ty)? {
+                        Value::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    Ok((key, read_value(input, value_ty)?))
+                })
+                .collect::<Result<Map<_, _>>>()?
+                .into()
+        }
+    })
+}
+
+fn read_size(input: &mut impl Buf) -> Result<usize> {
+    let size = get_unsigned_leb128(&mut *input)?;
+    usize::try_from(size).map_err(|_| Error::NotEnoughData)
+}
+
+fn read_bytes(input: &mut impl Buf, size: usize) -> Result<Vec<u8>> {
+    ensure_size(&mut *input, size)?;
+    let mut bytes = vec![0; size];
+    input.copy_to_slice(&mut bytes);
+    Ok(bytes)
+}
+
+fn read_string(input: &mut impl Buf) -> Result<String> {
+    let size = read_size(input)?;
+    let bytes = read_bytes(input, size)?;
+    Ok(String::from_utf8(bytes)
+        .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned()))
+}
+
+// Little-endian 64-bit limbs.
+fn read_limbs(input: &mut impl Buf) -> Result<[u64; 4]> {
+    ensure_size(&mut *input, 32)?;
+    Ok([0; 4].map(|_| input.get_u64_le()))
+}
+
+pub(crate) fn int256_to_string(mut limbs: [u64; 4], signed: bool) -> String {
+    const CHUNK: u128 = 10_000_000_000_000_000_000; // 10^19, fits into `u64`
+
+    let negative = signed && limbs[3] >> 63 == 1;
+    if negative {
+        // Two's complement negation.
+        let mut carry = true;
+        for limb in &mut limbs {
+            let (value, overflow) = (!*limb).overflowing_add(u64::from(carry));
+            *limb = value;
+            carry = carry && overflow;
+        }
+    }
+
+    let mut chunks = Vec::new();
+    while limbs.iter().any(|&limb| limb != 0) {
+        let mut rem = 0u128;
+        for limb in limbs.iter_mut().rev() {
+            let cur = (rem << 64) | u128::from(*limb);
+            *limb = (cur / CHUNK) as u64;
+            rem = cur % CHUNK;
+        }
+        chunks.push(rem as u64);
+    }
+
+    let mut out = String::from(if negative { "-" } else { "" });
+    match chunks.split_last() {
+        Some((first, rest)) => {
+            write!(out, "{first}").unwrap();
+            for chunk in rest.iter().rev() {
+                write!(out, "{chunk:019}").unwrap();
+            }
+        }
+        None => out.push('0'),
+    }
+    out
+}
+
+fn format_decimal(digits: String, scale: usize) -> String {
+    if scale == 0 {
+        return digits;
+    }
+
+    let (sign, digits) = match digits.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", &digits[..]),
+    };
+
+    let digits = format!("{digits:0>width$}", width = scale + 1);
+    let (int, frac) = digits.split_at(digits.len() - scale);
+    format!("{sign}{int}.{frac}")
+}
+
+fn enum_label<T: PartialEq + std::fmt::Display>(items: &[(String, T)], value: T) -> Result<Value> {
+    items
+        .iter()
+        .find(|(_, v)| *v == value)
+        .map(|(name, _)| name.clone().into())
+        .ok_or_else(|| Error::BadResponse(format!("unknown enum value: {value}")))
+}
+
+// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn format_date(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+fn format_datetime(secs: i64, frac: i64, precision: usize) -> String {
+    let date = format_date(secs.div_euclid(86_400));
+    let time = secs.rem_euclid(86_400);
+    let (hours, minutes, seconds) = (time / 3600, time / 60 % 60, time % 60);
+
+    if precision == 0 {
+        format!("{date} {hours:02}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{date} {hours:02}:{minutes:02}:{seconds:02}.{frac:0precision$}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn column(name: &str, ty: &str) -> Column {
+        Column {
+            name: name.into(),
+            ty: ColumnType::parse(ty).unwrap(),
+        }
+    }
+
+    fn decode(ty: &str, mut input: &[u8]) -> Value {
+        let value = read_value(&mut input, &ColumnType::parse(ty).unwrap()).unwrap();
+        assert!(input.is_empty(), "trailing bytes");
+        value
+    }
+
+    #[test]
+    fn it_reads_header() {
+        let mut input = &b"\x02\x02no\x04name\x06UInt32\x16LowCardinality(String)"[..];
+        assert_eq!(
+            read_header(&mut input).unwrap(),
+            vec![column("no", "UInt32"), column("name", "String")]
+        );
+        assert!(input.is_empty());
+
+        let mut input = &b"\x02\x02no\x04name\x06UInt32"[..];
+        assert!(matches!(read_header(&mut input), Err(Error::NotEnoughData)));
+
+        let mut input = &b"\x02\x02no\x02no\x06UInt32\x06UInt32"[..];
+        assert!(matches!(
+            read_header(&mut input),
+            Err(Error::DuplicateColumn(name)) if name == "no"
+        ));
+    }
+
+    #[test]
+    fn it_reads_column_names() {
+        let mut input = &b"\x02\x02no\x04name\x06UInt32\x1fAggregateFunction(uniq, String)"[..];
+        assert_eq!(read_column_names(&mut input).unwrap(), vec!["no", "name"]);
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn it_reads_rows() {
+        let columns = [column("no", "UInt32"), column("name", "Nullable(String)")];
+        let row = read_row(&mut &b"\x2a\x00\x00\x00\x00\x03foo"[..], &columns).unwrap();
+        assert_eq!(
+            row,
+            vec![("no".into(), json!(42)), ("name".into(), json!("foo"))]
+        );
+
+        let row = read_row(&mut &b"\x2a\x00\x00\x00\x01"[..], &columns).unwrap();
+        assert_eq!(row[1], ("name".into(), Value::Null));
+
+        let result = read_row(&mut &b"\x2a\x00\x00\x00\x00\x03fo"[..], &columns);
+        assert!(matches!(result, Err(Error::NotEnoughData)));
+    }
+
+    #[test]
+    fn it_decodes_numbers() {
+        assert_eq!(decode("UInt8", &[255]), json!(255));
+        assert_eq!(decode("Int16", &[0xfe, 0xff]), json!(-2));
+        assert_eq!(decode("UInt64", &[0xff; 8]), json!(u64::MAX));
+        assert_eq!(decode("Int128", &[0xff; 16]), json!("-1"));
+        assert_eq!(decode("UInt256", &[0xff; 32]), json!(U256_MAX));
+        assert_eq!(decode("Int256", &[0xff; 32]), json!("-1"));
+        assert_eq!(decode("Float32", &0.1f32.to_le_bytes()), json!(0.1));
+        assert_eq!(decode("Float64", &f64::NAN.to_le_bytes()), Value::Null);
+        assert_eq!(decode("Bool", &[1]), json!(true));
+    }
+
+    const U256_MAX: &str =
+        "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+
+    #[test]
+    fn it_decodes_decimals() {
+        assert_eq!(
+            decode("Decimal(9, 2)", &12345i32.to_le_bytes()),
+            json!("123.45")
+        );
+        assert_eq!(
+            decode("Decimal64(4)", &(-5i64).to_le_bytes()),
+            json!("-0.0005")
+        );
+        assert_eq!(decode("Decimal128(0)", &42i128.to_le_bytes()), json!("42"));
+
+        let mut input = (-12345i128).to_le_bytes().to_vec();
+        input.extend([0xff; 16]);
+        assert_eq!(decode("Decimal256(3)", &input), json!("-12.345"));
+    }
+
+    #[test]
+    fn it_decodes_dates() {
+        assert_eq!(
+            decode("Date", &19_000u16.to_le_bytes()),
+            json!("2022-01-08")
+        );
+        assert_eq!(
+            decode("Date32", &(-1i32).to_le_bytes()),
+            json!("1969-12-31")
+        );
+        assert_eq!(
+            decode("DateTime('UTC')", &1_650_000_000u32.to_le_bytes()),
+            json!("2022-04-15 05:20:00")
+        );
+        assert_eq!(
+            decode("DateTime64(3)", &(-1i64).to_le_bytes()),
+            json!("1969-12-31 23:59:59.999")
+        );
+    }
+
+    #[test]
+    fn it_decodes_special_types() {
+        let mut uuid = 0x6151_4d76_0f16_4747u64.to_le_bytes().to_vec();
+        uuid.extend(0xa3b2_9d3a_bdcb_64a9u64.to_le_bytes());
+        assert_eq!(
+            decode("UUID", &uuid),
+            json!("61514d76-0f16-4747-a3b2-9d3abdcb64a9")
+        );
+
+        assert_eq!(decode("IPv4", &[1, 0, 0, 127]), json!("127.0.0.1"));
+        let mut ipv6 = [0; 16];
+        ipv6[15] = 1;
+        assert_eq!(decode("IPv6", &ipv6), json!("::1"));
+
+        assert_eq!(decode("FixedString(3)", b"abc"), json!("abc"));
+        assert_eq!(decode("Enum8('a' = 1, 'b' = 2)", &[2]), json!("b"));
+
+        let result = read_value(
+            &mut &[3][..],
+            &ColumnType::parse("Enum8('a' = 1, 'b' = 2)").unwrap(),
+        );
+        assert!(matches!(result, Err(Error::BadResponse(_))));
+    }
+
+    #[test]
+    fn it_decodes_compound_types() {
+        assert_eq!(decode("Array(UInt8)", &[3, 1, 2, 3]), json!([1, 2, 3]));
+        assert_eq!(
+            decode("Tuple(UInt8, String)", b"\x01\x01a"),
+            json!([1, "a"])
+        );
+        assert_eq!(
+            decode("Tuple(a UInt8, b String)", b"\x01\x01a"),
+            json!({"a": 1, "b": "a"})
+        );
+        assert_eq!(
+            decode("Map(String, UInt8)", b"\x02\x01a\x01\x01b\x02"),
+            json!({"a": 1, "b": 2})
+        );
+        assert_eq!(
+            decode("Map(UInt8, UInt8)", b"\x01\x07\x08"),
+            json!({"7": 8})
+        );
+    }
+}
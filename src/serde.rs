@@ -5,6 +5,30 @@ pub use serde::{
     ser::{Serialize, Serializer},
 };
 
+// Used by `derive(Enum8)` and `derive(Enum16)`.
+#[doc(hidden)]
+pub mod __private {
+    use std::fmt::Write;
+
+    pub fn unknown_discriminant<E: serde::de::Error>(
+        type_name: &str,
+        ch_type: &str,
+        value: i64,
+        expected: &[i64],
+    ) -> E {
+        let mut msg =
+            format!("unknown discriminant {value} of `{type_name}` ({ch_type}), expected");
+        if expected.is_empty() {
+            msg.push_str(" nothing");
+        }
+        for (idx, value) in expected.iter().enumerate() {
+            let sep = if idx > 0 { "," } else { " one of:" };
+            write!(msg, "{sep} {value}").expect("impossible");
+        }
+        E::custom(msg)
+    }
+}
+
 macro_rules! option {
     ($name:ident, $doc:literal) => {
         #[doc = $doc]
@@ -44,6 +68,11 @@ macro_rules! option {
 }
 
 /// Ser/de [`std::net::Ipv4Addr`] to/from `IPv4`.
+///
+/// ClickHouse stores `IPv4` as `UInt32`, which is little-endian in RowBinary,
+/// so octets go in reverse order on the wire, e.g. `192.168.0.1` is `01 00 a8 c0`.
+/// It's why `Ipv4Addr` requires this module, unlike [`std::net::Ipv6Addr`],
+/// which is serialized as is, in network byte order.
 pub mod ipv4 {
     use std::net::Ipv4Addr;
 
@@ -70,6 +99,66 @@ pub mod ipv4 {
     }
 }
 
+/// Ser/de `Vec<T>` to/from a `Nested(..)` column, where `T` is a struct deriving [`Row`].
+///
+/// ClickHouse flattens `Nested` into parallel arrays, one per subcolumn,
+/// so items are transposed while serializing and the field is expanded
+/// into `field.subcolumn` names in `INSERT`. Empty vectors are supported.
+///
+/// While deserializing, the arrays are zipped back into items. Subcolumns aren't
+/// expanded by `?fields`, so they must be selected explicitly and in the order of
+/// fields of `T`, e.g. `SELECT no, items.name, items.count`. Arrays of different
+/// lengths are reported as an error. Values are buffered to be transposed, so it's
+/// slower than reading subcolumns as separate arrays.
+///
+/// ```
+/// # use clickhouse::Row;
+/// # use serde::{Deserialize, Serialize};
+/// // CREATE TABLE test (no Int32, items Nested(name String, count UInt32)) ...
+/// #[derive(Row, Serialize, Deserialize)]
+/// struct Item {
+///     name: String,
+///     count: u32,
+/// }
+///
+/// // SELECT no, items.name, items.count FROM test
+/// #[derive(Row, Serialize, Deserialize)]
+/// struct MyRow {
+///     no: i32,
+///     #[serde(with = "clickhouse::serde::nested")]
+///     items: Vec<Item>,
+/// }
+/// ```
+///
+/// [`Row`]: crate::Row
+pub mod nested {
+    use serde::{de::DeserializeOwned, ser::SerializeStruct};
+
+    use super::*;
+    use crate::{nested::Column, nested::MARKER, row::DbRow};
+
+    pub fn serialize<T, S>(items: &[T], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: DbRow + Serialize,
+        S: Serializer,
+    {
+        let names = T::COLUMN_NAMES;
+        let mut columns = serializer.serialize_struct(MARKER, names.len())?;
+        for (idx, name) in names.iter().enumerate() {
+            columns.serialize_field(name, &Column { items, idx })?;
+        }
+        columns.end()
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where
+        T: DbRow + DeserializeOwned,
+        D: Deserializer<'de>,
+    {
+        crate::nested::deserialize(deserializer)
+    }
+}
+
 /// Ser/de [`::uuid::Uuid`] to/from `UUID`.
 #[cfg(feature = "uuid")]
 pub mod uuid {
@@ -149,9 +238,51 @@ pub mod time {
     }
 
     /// Contains modules to ser/de `OffsetDateTime` to/from `DateTime64(_)`.
+    ///
+    /// `DateTime64(P, 'zone')` is always sent as ticks since the Unix epoch in UTC,
+    /// the zone of a column affects only its rendering by ClickHouse. Thus values
+    /// are deserialized in UTC by default. Use `deserialize_in` with a [`TimeZone`]
+    /// to get them with the offset of the zone instead, which doesn't change the
+    /// point in time:
+    ///
+    /// ```
+    /// # use clickhouse::{serde::time::datetime64::{self, TimeZone}, Row};
+    /// # use serde::{Deserialize, Serialize};
+    /// # use time::{macros::offset, OffsetDateTime, UtcOffset};
+    /// struct Tokyo;
+    ///
+    /// impl TimeZone for Tokyo {
+    ///     fn offset_at(_utc: OffsetDateTime) -> UtcOffset {
+    ///         offset!(+9)
+    ///     }
+    /// }
+    ///
+    /// // CREATE TABLE events (at DateTime64(3, 'Asia/Tokyo')) ...
+    /// #[derive(Row, Serialize, Deserialize)]
+    /// struct Event {
+    ///     #[serde(
+    ///         serialize_with = "datetime64::millis::serialize",
+    ///         deserialize_with = "datetime64::millis::deserialize_in::<Tokyo, _>"
+    ///     )]
+    ///     at: OffsetDateTime,
+    /// }
+    /// ```
+    ///
+    /// Any offset can be serialized, only the point in time is sent.
     pub mod datetime64 {
+        use ::time::UtcOffset;
+
         use super::*;
 
+        /// A time zone to deserialize `DateTime64(_)` values in.
+        ///
+        /// The `time` crate has no database of time zones, so it's up to
+        /// implementors to provide offsets, e.g. using `tz-rs` for zones with DST.
+        pub trait TimeZone {
+            /// Returns the offset of the zone at the `utc` point in time.
+            fn offset_at(utc: OffsetDateTime) -> UtcOffset;
+        }
+
         /// Ser/de `OffsetDateTime` to/from `DateTime64(0)`.
         pub mod secs {
             use super::*;
@@ -174,6 +305,15 @@ pub mod time {
             {
                 do_deserialize(deserializer, 1_000_000_000)
             }
+
+            /// Like `deserialize`, but returns values with the offset of `Z`.
+            pub fn deserialize_in<'de, Z, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+            where
+                Z: TimeZone,
+                D: Deserializer<'de>,
+            {
+                do_deserialize(deserializer, 1_000_000_000).map(in_zone::<Z>)
+            }
         }
 
         /// Ser/de `OffsetDateTime` to/from `DateTime64(3)`.
@@ -198,6 +338,15 @@ pub mod time {
             {
                 do_deserialize(deserializer, 1_000_000)
             }
+
+            /// Like `deserialize`, but returns values with the offset of `Z`.
+            pub fn deserialize_in<'de, Z, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+            where
+                Z: TimeZone,
+                D: Deserializer<'de>,
+            {
+                do_deserialize(deserializer, 1_000_000).map(in_zone::<Z>)
+            }
         }
 
         /// Ser/de `OffsetDateTime` to/from `DateTime64(6)`.
@@ -222,6 +371,15 @@ pub mod time {
             {
                 do_deserialize(deserializer, 1_000)
             }
+
+            /// Like `deserialize`, but returns values with the offset of `Z`.
+            pub fn deserialize_in<'de, Z, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+            where
+                Z: TimeZone,
+                D: Deserializer<'de>,
+            {
+                do_deserialize(deserializer, 1_000).map(in_zone::<Z>)
+            }
         }
 
         /// Ser/de `OffsetDateTime` to/from `DateTime64(9)`.
@@ -246,6 +404,15 @@ pub mod time {
             {
                 do_deserialize(deserializer, 1)
             }
+
+            /// Like `deserialize`, but returns values with the offset of `Z`.
+            pub fn deserialize_in<'de, Z, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+            where
+                Z: TimeZone,
+                D: Deserializer<'de>,
+            {
+                do_deserialize(deserializer, 1).map(in_zone::<Z>)
+            }
         }
 
         fn do_serialize<S>(dt: &OffsetDateTime, div: i128, serializer: S) -> Result<S::Ok, S::Error>
@@ -267,6 +434,10 @@ pub mod time {
             let ts = i128::from(ts) * mul; // cannot overflow: `mul` fits in `i64`
             OffsetDateTime::from_unix_timestamp_nanos(ts).map_err(D::Error::custom)
         }
+
+        fn in_zone<Z: TimeZone>(dt: OffsetDateTime) -> OffsetDateTime {
+            dt.to_offset(Z::offset_at(dt))
+        }
     }
 
     /// Ser/de `time::Date` to/from `Date`.
@@ -351,3 +522,108 @@ pub mod time {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use clickhouse::{Enum16, Enum8};
+
+    use super::*;
+    use crate::{error::Error, rowbinary};
+
+    #[derive(Debug, PartialEq, Enum8)]
+    enum Level {
+        Debug = 1,
+        Info = 2,
+        Error = -3,
+    }
+
+    #[derive(Debug, PartialEq, Enum16)]
+    #[repr(i16)]
+    enum Code {
+        Ok = 200,
+        NotFound = 404,
+        Internal = 500,
+    }
+
+    fn roundtrip<T>(value: T, expected: &[u8])
+    where
+        T: std::fmt::Debug + PartialEq + Serialize + for<'b> Deserialize<'b>,
+    {
+        let mut buffer = Vec::new();
+        rowbinary::serialize_into(&mut buffer, &value).unwrap();
+        assert_eq!(buffer, expected);
+
+        let mut input = &buffer[..];
+        let actual: T = rowbinary::deserialize_from(&mut input, &mut []).unwrap();
+        assert_eq!(actual, value);
+    }
+
+    fn unknown<T: for<'b> Deserialize<'b>>(mut input: &[u8]) -> String {
+        match rowbinary::deserialize_from::<T>(&mut input, &mut []) {
+            Err(Error::Custom(msg)) => msg,
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn it_supports_enum8() {
+        roundtrip(Level::Info, b"\x02");
+        roundtrip(Level::Error, b"\xfd");
+        assert_eq!(
+            unknown::<Level>(b"\x07"),
+            "unknown discriminant 7 of `Level` (Enum8), expected one of: 1, 2, -3"
+        );
+    }
+
+    #[test]
+    fn it_supports_enum16() {
+        roundtrip(Code::NotFound, b"\x94\x01");
+        roundtrip(Code::Internal, b"\xf4\x01");
+        assert_eq!(
+            unknown::<Code>(b"\xf5\x01"),
+            "unknown discriminant 501 of `Code` (Enum16), expected one of: 200, 404, 500"
+        );
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn it_deserializes_datetime64_in_zones() {
+        use ::time::{macros::datetime, OffsetDateTime, UtcOffset};
+
+        use self::time::datetime64::{self, TimeZone};
+
+        struct Tokyo;
+
+        impl TimeZone for Tokyo {
+            fn offset_at(_utc: OffsetDateTime) -> UtcOffset {
+                UtcOffset::from_hms(9, 0, 0).unwrap()
+            }
+        }
+
+        #[derive(Debug, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
+        struct Event {
+            #[serde(
+                serialize_with = "datetime64::millis::serialize",
+                deserialize_with = "datetime64::millis::deserialize_in::<Tokyo, _>"
+            )]
+            at: OffsetDateTime,
+        }
+
+        // `DateTime64(3, 'Asia/Tokyo')` is sent as millis since the epoch in UTC.
+        let utc = datetime!(2024-01-01 00:00:00.123 UTC);
+        let wire = (utc.unix_timestamp_nanos() / 1_000_000) as i64;
+        let mut buffer = Vec::new();
+        rowbinary::serialize_into(&mut buffer, &wire).unwrap();
+
+        let mut input = &buffer[..];
+        let event: Event = rowbinary::deserialize_from(&mut input, &mut []).unwrap();
+        assert_eq!(event.at.offset(), UtcOffset::from_hms(9, 0, 0).unwrap());
+        assert_eq!(event.at, datetime!(2024-01-01 09:00:00.123 +9));
+        assert_eq!((event.at.hour(), event.at.millisecond()), (9, 123));
+
+        // The offset doesn't affect the wire value.
+        let mut actual = Vec::new();
+        rowbinary::serialize_into(&mut actual, &event).unwrap();
+        assert_eq!(actual, buffer);
+    }
+}
@@ -21,6 +21,10 @@ impl<S: Serialize> Bind for S {
 
 /// Bound the provided string as an identifier.
 /// It can be used for table names, for instance.
+///
+/// The value is wrapped in backticks with embedded backticks doubled, so it's
+/// always a single identifier: `Identifier("db.table")` is a table named `db.table`.
+/// Use `?.?` to bind a qualified name.
 pub struct Identifier<'a>(pub &'a str);
 
 #[sealed]
@@ -30,3 +34,41 @@ impl<'a> Bind for Identifier<'a> {
         escape::identifier(self.0, dst).map_err(|err| err.to_string())
     }
 }
+
+/// Bound the provided slice as a parenthesized list of values, e.g. `(1,2,3)`.
+/// It's intended for `x IN ?`, while a slice bound directly becomes an array, e.g. `[1,2,3]`.
+///
+/// Elements are escaped as any other bound values. An empty slice is bound
+/// as `(NULL)`, hence `x IN ?` never matches and `x NOT IN ?` always does
+/// (unless the `transform_null_in` setting is enabled).
+pub struct InList<'a, T>(pub &'a [T]);
+
+#[sealed]
+impl<'a, T: Serialize> Bind for InList<'a, T> {
+    fn write(&self, mut dst: impl fmt::Write) -> Result<(), String> {
+        if self.0.is_empty() {
+            return dst.write_str("(NULL)").map_err(|err| err.to_string());
+        }
+
+        dst.write_char('(').map_err(|err| err.to_string())?;
+        for (idx, value) in self.0.iter().enumerate() {
+            if idx > 0 {
+                dst.write_char(',').map_err(|err| err.to_string())?;
+            }
+            ser::write_arg(&mut dst, value)?;
+        }
+        dst.write_char(')').map_err(|err| err.to_string())
+    }
+}
+
+/// A pre-escaped SQL fragment, which is substituted as is.
+/// Used by [`Query::bind_raw`](crate::query::Query::bind_raw) only.
+pub(crate) struct Raw(pub(crate) String);
+
+#[sealed]
+impl Bind for Raw {
+    #[inline]
+    fn write(&self, mut dst: impl fmt::Write) -> Result<(), String> {
+        dst.write_str(&self.0).map_err(|err| err.to_string())
+    }
+}
@@ -2,34 +2,42 @@ use std::fmt;
 
 // See https://clickhouse.tech/docs/en/sql-reference/syntax/#syntax-string-literal
 pub(crate) fn string(src: &str, dst: impl fmt::Write) -> fmt::Result {
-    escape(src, dst, '\'')
+    escape(src, dst, '\'', "\\'")
 }
 
 // See https://clickhouse.tech/docs/en/sql-reference/syntax/#syntax-identifiers
+// Backticks are doubled, which is the standard SQL way, unlike string literals.
+// The whole value is quoted, so `db.table` is a single identifier, not a qualified name.
 pub(crate) fn identifier(src: &str, dst: impl fmt::Write) -> fmt::Result {
-    escape(src, dst, '`')
+    escape(src, dst, '`', "``")
 }
 
-fn escape(src: &str, mut dst: impl fmt::Write, ch: char) -> fmt::Result {
-    dst.write_char(ch)?;
+fn escape(src: &str, mut dst: impl fmt::Write, quote: char, escaped_quote: &str) -> fmt::Result {
+    dst.write_char(quote)?;
 
-    // TODO: escape newlines?
-    for (idx, part) in src.split(ch).enumerate() {
-        if idx > 0 {
-            dst.write_char('\\')?;
-            dst.write_char(ch)?;
-        }
+    // Literals are parsed with C-style escapes, so control characters are escaped too:
+    // they are valid as is, but a raw `\0` or `\r` is easily mangled on the way.
+    let is_special = |ch| ch == quote || matches!(ch, '\\' | '\0' | '\n' | '\r' | '\t');
 
-        for (idx, part) in part.split('\\').enumerate() {
-            if idx > 0 {
-                dst.write_str("\\\\")?;
-            }
+    let mut rest = src;
+    while let Some(idx) = rest.find(is_special) {
+        dst.write_str(&rest[..idx])?;
+
+        let ch = rest[idx..].chars().next().expect("found by find()");
+        dst.write_str(match ch {
+            '\\' => "\\\\",
+            '\0' => "\\0",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            _ => escaped_quote,
+        })?;
 
-            dst.write_str(part)?;
-        }
+        rest = &rest[idx + ch.len_utf8()..];
     }
 
-    dst.write_char(ch)
+    dst.write_str(rest)?;
+    dst.write_char(quote)
 }
 
 #[test]
@@ -43,5 +51,79 @@ fn it_escapes_string() {
 fn it_escapes_identifier() {
     let mut actual = String::new();
     identifier(r#"f\o`o `` b\`ar`"#, &mut actual).unwrap();
-    assert_eq!(actual, r#"`f\\o\`o \`\` b\\\`ar\``"#);
+    assert_eq!(actual, r#"`f\\o``o ```` b\\``ar```"#);
+
+    let mut actual = String::new();
+    identifier("db.table", &mut actual).unwrap();
+    assert_eq!(actual, "`db.table`");
+}
+
+// Parses a literal back as ClickHouse does (see `parseComplexEscapeSequence`):
+// `\\` followed by `0`, `b`, `f`, `n`, `r`, `t` or `xHH` is a special char,
+// by any other char is that char, and a doubled quote is the quote.
+#[cfg(test)]
+fn unescape(literal: &str, quote: char) -> String {
+    let inner = literal
+        .strip_prefix(quote)
+        .and_then(|rest| rest.strip_suffix(quote))
+        .expect("not quoted");
+
+    let mut result = String::new();
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        let ch = match ch {
+            '\\' => match chars.next().expect("dangling backslash") {
+                '0' => '\0',
+                'b' => '\x08',
+                'f' => '\x0c',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'x' => {
+                    let hex = chars.by_ref().take(2).collect::<String>();
+                    char::from(u8::from_str_radix(&hex, 16).unwrap())
+                }
+                ch => ch,
+            },
+            ch if ch == quote => {
+                assert_eq!(chars.next(), Some(quote), "unescaped quote in {literal:?}");
+                quote
+            }
+            ch => ch,
+        };
+        result.push(ch);
+    }
+    result
+}
+
+#[test]
+fn it_escapes_tricky_strings() {
+    const CASES: &[(&str, &str)] = &[
+        ("", r"''"),
+        ("'", r"'\''"),
+        ("''", r"'\'\''"),
+        ("\\", r"'\\'"),
+        ("\\'", r"'\\\''"),
+        ("a\\", r"'a\\'"),
+        ("'; DROP TABLE test; --", r"'\'; DROP TABLE test; --'"),
+        ("\\'; DROP TABLE test; --", r"'\\\'; DROP TABLE test; --'"),
+        ("nul\0byte", r"'nul\0byte'"),
+        ("new\nline\r\n", r"'new\nline\r\n'"),
+        ("tab\tbed", r"'tab\tbed'"),
+        ("\\n is not a newline", r"'\\n is not a newline'"),
+        ("`\"?", r#"'`"?'"#),
+        ("unicode: ü, 日本, 🦀", "'unicode: ü, 日本, 🦀'"),
+        ("\u{7f}\u{1b}", "'\u{7f}\u{1b}'"),
+    ];
+
+    for &(value, expected) in CASES {
+        let mut actual = String::new();
+        string(value, &mut actual).unwrap();
+        assert_eq!(actual, expected, "{value:?}");
+        assert_eq!(unescape(&actual, '\''), value, "{actual:?}");
+
+        let mut actual = String::new();
+        identifier(value, &mut actual).unwrap();
+        assert_eq!(unescape(&actual, '`'), value, "{actual:?}");
+    }
 }
@@ -0,0 +1,133 @@
+/// Returns `n` of the outermost `LIMIT n` (or `LIMIT m, n`) clause.
+///
+/// String literals, quoted identifiers, comments and subqueries are skipped.
+/// `LIMIT n BY` is ignored because it doesn't restrict the total number of rows.
+/// Returns `None` if the limit is unknown, e.g. for `UNION` of several queries.
+pub(crate) fn outer_limit(sql: &str) -> Option<u64> {
+    let tokens = tokenize(sql);
+    let mut limit = None;
+
+    for (idx, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Word(word) if ["UNION", "INTERSECT", "EXCEPT"].contains(&&word[..]) => {
+                return None
+            }
+            Token::Word(word) if word == "LIMIT" => {
+                let (value, rest) = match &tokens[idx + 1..] {
+                    [Token::Number(_), Token::Comma, Token::Number(n), rest @ ..] => (*n, rest),
+                    [Token::Number(n), rest @ ..] => (*n, rest),
+                    _ => return None,
+                };
+
+                if !matches!(rest.first(), Some(Token::Word(word)) if word == "BY") {
+                    limit = Some(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    limit
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Number(u64),
+    Comma,
+    Other,
+}
+
+// Produces tokens of the top level only.
+fn tokenize(sql: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut depth = 0usize;
+    let mut chars = sql.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\'' | '"' | '`' => {
+                while let Some(next) = chars.next() {
+                    match next {
+                        '\\' => {
+                            chars.next();
+                        }
+                        next if next == ch => break,
+                        _ => {}
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                chars.by_ref().find(|&c| {
+                    let end = prev == '*' && c == '/';
+                    prev = c;
+                    end
+                });
+            }
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ if depth > 0 => {}
+            ',' => tokens.push(Token::Comma),
+            ch if ch.is_alphanumeric() || ch == '_' => {
+                let mut word = String::from(ch);
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '_') {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+
+                tokens.push(if word.bytes().all(|b| b.is_ascii_digit()) {
+                    word.parse().map_or(Token::Other, Token::Number)
+                } else {
+                    Token::Word(word.to_ascii_uppercase())
+                });
+            }
+            ch if ch.is_whitespace() => {}
+            _ => tokens.push(Token::Other),
+        }
+    }
+
+    tokens
+}
+
+#[test]
+fn it_finds_outer_limit() {
+    assert_eq!(outer_limit("SELECT 1"), None);
+    assert_eq!(outer_limit("SELECT * FROM t LIMIT 10"), Some(10));
+    assert_eq!(outer_limit("select * from t limit 5, 10"), Some(10));
+    assert_eq!(outer_limit("SELECT * FROM t LIMIT 10 OFFSET 5"), Some(10));
+    assert_eq!(
+        outer_limit("SELECT * FROM t LIMIT 3 SETTINGS max_threads = 1 FORMAT RowBinary"),
+        Some(3)
+    );
+    assert_eq!(
+        outer_limit("SELECT * FROM t LIMIT 1 BY a LIMIT 100"),
+        Some(100)
+    );
+}
+
+#[test]
+fn it_ignores_nested_limits() {
+    assert_eq!(outer_limit("SELECT * FROM t LIMIT 1 BY a"), None);
+    assert_eq!(outer_limit("SELECT * FROM (SELECT * FROM t LIMIT 1)"), None);
+    assert_eq!(
+        outer_limit("SELECT 'LIMIT 1', `LIMIT 2` FROM t -- LIMIT 3\n /* LIMIT 4 */"),
+        None
+    );
+    assert_eq!(
+        outer_limit("SELECT 'it''s \\' LIMIT 1' FROM t LIMIT 2"),
+        Some(2)
+    );
+    assert_eq!(
+        outer_limit("SELECT 1 LIMIT 1 UNION ALL SELECT 2 LIMIT 1"),
+        None
+    );
+    assert_eq!(outer_limit("SELECT * FROM t LIMIT 1e3"), None);
+}
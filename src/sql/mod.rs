@@ -3,15 +3,25 @@ use crate::{
     row::{self, DbRow},
 };
 
-pub use bind::{Bind, Identifier};
+pub(crate) use bind::Raw;
+pub use bind::{Bind, Identifier, InList};
 
 mod bind;
 pub(crate) mod escape;
+pub(crate) mod limit;
 mod ser;
 
 #[derive(Clone)]
 pub(crate) enum SqlBuilder {
-    InProgress { parts: Vec<Part>, size: usize },
+    InProgress {
+        parts: Vec<Part>,
+        size: usize,
+        // The number of `?` placeholders (excluding `?fields`) and provided arguments.
+        args: usize,
+        bound: usize,
+        // Values bound to `{name}` placeholders, which are substituted by `finish()`.
+        named: Vec<(String, String)>,
+    },
     Failed(String),
 }
 
@@ -19,7 +29,10 @@ pub(crate) enum SqlBuilder {
 pub(crate) enum Part {
     Arg,
     Fields,
+    // A part of the template.
     Text(String),
+    // A bound value or expanded fields, which are never parsed for `{name}`.
+    Value(String),
 }
 
 impl SqlBuilder {
@@ -28,6 +41,7 @@ impl SqlBuilder {
         let prefix = String::from(iter.next().unwrap());
         let mut size = prefix.len();
         let mut parts = vec![Part::Text(prefix)];
+        let mut args = 0;
 
         for s in iter {
             let text = if let Some(text) = s.strip_prefix("fields") {
@@ -35,6 +49,7 @@ impl SqlBuilder {
                 text
             } else {
                 parts.push(Part::Arg);
+                args += 1;
                 s
             };
 
@@ -42,11 +57,39 @@ impl SqlBuilder {
             parts.push(Part::Text(text.into()));
         }
 
-        SqlBuilder::InProgress { parts, size }
+        SqlBuilder::InProgress {
+            parts,
+            size,
+            args,
+            bound: 0,
+            named: Vec::new(),
+        }
+    }
+
+    /// Wraps ready SQL, which is never parsed for `?` or `{name}` placeholders.
+    pub(crate) fn literal(sql: String) -> Self {
+        SqlBuilder::InProgress {
+            size: sql.len(),
+            // Empty texts around allow `prepend()` and `append()`.
+            parts: vec![
+                Part::Text(String::new()),
+                Part::Value(sql),
+                Part::Text(String::new()),
+            ],
+            args: 0,
+            bound: 0,
+            named: Vec::new(),
+        }
     }
 
+    // Extra arguments are only counted to be reported by `finish()`.
     pub(crate) fn bind_arg(&mut self, value: impl Bind) {
-        if let Self::InProgress { parts, size } = self {
+        if let Self::InProgress {
+            parts, size, bound, ..
+        } = self
+        {
+            *bound += 1;
+
             if let Some(part) = parts.iter_mut().find(|p| matches!(p, Part::Arg)) {
                 let mut s = String::new();
 
@@ -56,26 +99,61 @@ impl SqlBuilder {
                 }
 
                 *size += s.len();
-                *part = Part::Text(s);
-            } else {
-                panic!("all query arguments are already bound");
+                *part = Part::Value(s);
             }
         }
     }
 
+    /// Binds `value` to all `{name}` placeholders, replacing a previously bound value.
+    pub(crate) fn bind_named(&mut self, name: &str, value: impl Bind) {
+        if let Self::InProgress { named, .. } = self {
+            let mut s = String::new();
+
+            if let Err(err) = value.write(&mut s) {
+                *self = SqlBuilder::Failed(err);
+                return;
+            }
+
+            match named.iter_mut().find(|(n, _)| n == name) {
+                Some((_, v)) => *v = s,
+                None => named.push((name.into(), s)),
+            }
+        }
+    }
+
+    /// Returns the number of `?` placeholders without bound arguments,
+    /// or the error of a failed binding.
+    pub(crate) fn unbound_args(&self) -> Result<usize> {
+        match self {
+            Self::InProgress { args, bound, .. } => Ok(args.saturating_sub(*bound)),
+            Self::Failed(err) => Err(invalid_params(err.clone())),
+        }
+    }
+
     pub(crate) fn bind_fields<T: DbRow>(&mut self) {
-        if let Self::InProgress { parts, size } = self {
+        if let Self::InProgress { parts, size, .. } = self {
             if let Some(fields) = row::join_column_names::<T>() {
                 for part in parts.iter_mut().filter(|p| matches!(p, Part::Fields)) {
                     *size += fields.len();
-                    *part = Part::Text(fields.clone());
+                    *part = Part::Value(fields.clone());
                 }
             }
         }
     }
 
+    pub(crate) fn prepend(&mut self, prefix: &str) {
+        if let Self::InProgress { parts, size, .. } = self {
+            if let Some(Part::Text(text)) = parts.first_mut() {
+                *size += prefix.len();
+                text.insert_str(0, prefix);
+            } else {
+                unreachable!();
+            }
+        }
+    }
+
     pub(crate) fn append(&mut self, suffix: &str) {
-        if let Self::InProgress { parts, size } = self {
+        if let Self::InProgress { parts, size, .. } = self {
             if let Some(Part::Text(text)) = parts.last_mut() {
                 *size += suffix.len();
                 text.push_str(suffix);
@@ -87,31 +165,121 @@ impl SqlBuilder {
 
     pub(crate) fn finish(self) -> Result<String> {
         match self {
-            Self::InProgress { parts, size } => {
-                Ok(parts
-                    .into_iter()
-                    .fold(String::with_capacity(size), |mut res, part| {
-                        if let Part::Text(text) = part {
-                            debug_assert!(res.len() + text.len() <= res.capacity());
-                            res.push_str(&text);
-                            res
-                        } else {
-                            panic!("unbound query argument ? or ?fields");
+            Self::InProgress {
+                parts,
+                size,
+                args,
+                bound,
+                named,
+            } => {
+                if args != bound {
+                    return Err(invalid_params(format!(
+                        "the query has {}, but {} bound",
+                        plural(args, "`?` placeholder"),
+                        match bound {
+                            1 => "1 argument is".into(),
+                            _ => format!("{bound} arguments are"),
+                        },
+                    )));
+                }
+
+                let mut used = vec![false; named.len()];
+                let mut res = String::with_capacity(size);
+                for part in parts {
+                    match part {
+                        // Named placeholders are recognized even if nothing is bound by name,
+                        // so unbound ones are reported and `{{` always means `{`.
+                        Part::Text(text) if text.contains('{') => {
+                            substitute(&text, &named, &mut used, &mut res)
+                                .map_err(invalid_params)?;
+                        }
+                        Part::Text(text) | Part::Value(text) => res.push_str(&text),
+                        Part::Fields => {
+                            return Err(invalid_params(
+                                "`?fields` can be expanded only when fetching structs".into(),
+                            ));
                         }
-                    }))
+                        Part::Arg => unreachable!("all arguments are bound"),
+                    }
+                }
+
+                if let Some(idx) = used.iter().position(|used| !used) {
+                    return Err(invalid_params(format!(
+                        "the query has no `{{{}}}` placeholder",
+                        named[idx].0
+                    )));
+                }
+
+                Ok(res)
             }
-            Self::Failed(err) => Err(Error::InvalidParams(err.into())),
+            Self::Failed(err) => Err(invalid_params(err)),
         }
     }
 }
 
+/// Replaces `{name}` in `text` with values of `named`, and `{{` with `{`.
+/// Other braces, e.g. of `{name:Type}` server-side parameters, are kept as is.
+fn substitute(
+    text: &str,
+    named: &[(String, String)],
+    used: &mut [bool],
+    res: &mut String,
+) -> Result<(), String> {
+    let mut rest = text;
+
+    while let Some(pos) = rest.find('{') {
+        res.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+
+        if let Some(after) = rest.strip_prefix('{') {
+            res.push('{');
+            rest = after;
+            continue;
+        }
+
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let name = &rest[..len];
+
+        let is_placeholder = rest[len..].starts_with('}')
+            && name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+
+        if !is_placeholder {
+            res.push('{');
+            continue;
+        }
+
+        let idx = named
+            .iter()
+            .position(|(n, _)| n == name)
+            .ok_or_else(|| format!("no value is bound to the `{{{name}}}` placeholder"))?;
+
+        used[idx] = true;
+        res.push_str(&named[idx].1);
+        rest = &rest[len + 1..];
+    }
+
+    res.push_str(rest);
+    Ok(())
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {noun}"),
+        _ => format!("{count} {noun}s"),
+    }
+}
+
+fn invalid_params(reason: String) -> Error {
+    Error::InvalidParams(reason.into())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    // XXX: need for `derive(Row)`. Provide `row(crate = ..)` instead.
-    use crate as clickhouse;
-    use clickhouse_derive::DbRow;
+    use clickhouse_derive::Row;
 
     #[allow(unused)]
     #[derive(Row)]
@@ -132,6 +300,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_builds_sql_with_nulls() {
+        let mut sql = SqlBuilder::new("SELECT ?fields FROM test WHERE a = ? AND b = ?");
+        sql.bind_arg(None::<i64>);
+        sql.bind_arg(Some("foo"));
+        sql.bind_fields::<Row>();
+        assert_eq!(
+            sql.finish().unwrap(),
+            r"SELECT `a`,`b` FROM test WHERE a = NULL AND b = 'foo'"
+        );
+
+        let mut sql = SqlBuilder::new("SELECT 1 WHERE a = ? AND b = ?");
+        sql.bind_arg(Some(5));
+        sql.bind_arg(None::<String>);
+        assert_eq!(sql.finish().unwrap(), r"SELECT 1 WHERE a = 5 AND b = NULL");
+    }
+
     #[test]
     fn it_builds_sql_with_in_clause() {
         fn t(arg: &[&str], expected: &str) {
@@ -154,6 +339,84 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_builds_sql_with_in_list() {
+        let mut sql = SqlBuilder::new("SELECT 1 FROM test WHERE a IN ? AND b NOT IN ?");
+        sql.bind_arg(InList(&[1, 2, 3]));
+        sql.bind_arg(InList(&["it's", "a \\ b", "c?"]));
+        assert_eq!(
+            sql.finish().unwrap(),
+            r"SELECT 1 FROM test WHERE a IN (1,2,3) AND b NOT IN ('it\'s','a \\ b','c?')"
+        );
+
+        let mut sql = SqlBuilder::new("SELECT 1 FROM test WHERE a IN ?");
+        sql.bind_arg(InList::<u32>(&[]));
+        assert_eq!(
+            sql.finish().unwrap(),
+            "SELECT 1 FROM test WHERE a IN (NULL)"
+        );
+    }
+
+    #[test]
+    fn it_builds_sql_with_identifiers() {
+        let mut sql = SqlBuilder::new("SELECT ? FROM ?.? WHERE a = ?");
+        sql.bind_arg(Identifier("we`ird"));
+        sql.bind_arg(Identifier("db"));
+        sql.bind_arg(Identifier("my.table"));
+        sql.bind_arg("it's `x`");
+        assert_eq!(
+            sql.finish().unwrap(),
+            r"SELECT `we``ird` FROM `db`.`my.table` WHERE a = 'it\'s `x`'"
+        );
+    }
+
+    #[test]
+    fn it_checks_the_number_of_bound_args() {
+        let template = "SELECT 1 FROM test WHERE a = ? AND b < ?";
+
+        let mut sql = SqlBuilder::new(template);
+        sql.bind_arg("foo");
+        let err = sql.finish().unwrap_err();
+        assert!(matches!(err, Error::InvalidParams(_)));
+        assert!(err
+            .to_string()
+            .ends_with("the query has 2 `?` placeholders, but 1 argument is bound"));
+
+        let mut sql = SqlBuilder::new(template);
+        sql.bind_arg("foo");
+        sql.bind_arg(42);
+        sql.bind_arg(43);
+        let err = sql.finish().unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("the query has 2 `?` placeholders, but 3 arguments are bound"));
+
+        let mut sql = SqlBuilder::new(template);
+        sql.bind_arg("foo");
+        sql.bind_arg(42);
+        assert_eq!(
+            sql.finish().unwrap(),
+            "SELECT 1 FROM test WHERE a = 'foo' AND b < 42"
+        );
+    }
+
+    #[test]
+    fn it_counts_fields_separately() {
+        // `?fields` isn't an argument.
+        let mut sql = SqlBuilder::new("SELECT ?fields FROM test WHERE a = ?");
+        sql.bind_fields::<Row>();
+        let err = sql.finish().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("1 `?` placeholder, but 0 arguments"));
+
+        let mut sql = SqlBuilder::new("SELECT ?fields FROM test WHERE a = ?");
+        sql.bind_arg(1);
+        let err = sql.finish().unwrap_err();
+        assert!(matches!(err, Error::InvalidParams(_)));
+        assert!(err.to_string().contains("`?fields`"));
+    }
+
     // See #18.
     #[test]
     fn it_builds_sql_with_question_marks_inside() {
@@ -165,4 +428,69 @@ mod tests {
             r"SELECT 1 FROM test WHERE a IN ['a?b','c?'] AND b = 'a?'"
         );
     }
+
+    #[test]
+    fn it_keeps_literals_as_is() {
+        let mut sql = SqlBuilder::literal("SELECT 1 AS `a?`, 2 AS `?fields{id}`".into());
+        sql.prepend("EXPLAIN ");
+        sql.append(" FORMAT RowBinary");
+        assert_eq!(sql.unbound_args().unwrap(), 0);
+        assert_eq!(
+            sql.finish().unwrap(),
+            "EXPLAIN SELECT 1 AS `a?`, 2 AS `?fields{id}` FORMAT RowBinary"
+        );
+    }
+
+    #[test]
+    fn it_builds_sql_with_named_args() {
+        let mut sql =
+            SqlBuilder::new("SELECT ?fields FROM test WHERE a = {id} OR b = {id} AND c = ?");
+        sql.bind_fields::<Row>();
+        sql.bind_named("id", "it's {id}");
+        sql.bind_arg("{id}");
+        assert_eq!(
+            sql.finish().unwrap(),
+            r"SELECT `a`,`b` FROM test WHERE a = 'it\'s {id}' OR b = 'it\'s {id}' AND c = '{id}'"
+        );
+
+        // The last value is bound.
+        let mut sql = SqlBuilder::new("SELECT {a}, {b_2}, {a}");
+        sql.bind_named("a", 1);
+        sql.bind_named("b_2", 2);
+        sql.bind_named("a", 3);
+        assert_eq!(sql.finish().unwrap(), "SELECT 3, 2, 3");
+
+        // Escaped and unrelated braces.
+        let mut sql =
+            SqlBuilder::new("SELECT '{{id}', {{}, {'k':{id}}, {p:UInt8}, {1}, { id }, {id");
+        sql.bind_named("id", 1);
+        assert_eq!(
+            sql.finish().unwrap(),
+            "SELECT '{id}', {}, {'k':1}, {p:UInt8}, {1}, { id }, {id"
+        );
+
+        // Escaping doesn't depend on named arguments.
+        let sql = SqlBuilder::new("SELECT '{{id}', {p:UInt8}, {'k':1}");
+        assert_eq!(sql.finish().unwrap(), "SELECT '{id}', {p:UInt8}, {'k':1}");
+    }
+
+    #[test]
+    fn it_fails_on_missing_named_args() {
+        let mut sql = SqlBuilder::new("SELECT {a}, {b}");
+        sql.bind_named("a", 1);
+        let err = sql.finish().unwrap_err();
+        assert!(err.to_string().contains("`{b}`"), "{}", err);
+
+        // Even if nothing is bound by name.
+        let sql = SqlBuilder::new("SELECT {id}");
+        let err = sql.finish().unwrap_err();
+        assert!(matches!(err, Error::InvalidParams(_)));
+        assert!(err.to_string().contains("`{id}`"), "{}", err);
+
+        let mut sql = SqlBuilder::new("SELECT {a}");
+        sql.bind_named("a", 1);
+        sql.bind_named("c", 1);
+        let err = sql.finish().unwrap_err();
+        assert!(err.to_string().contains("no `{c}` placeholder"), "{}", err);
+    }
 }
@@ -86,7 +86,6 @@ impl<'a, W: Write> Serializer for SqlSerializer<'a, W> {
     unsupported!(
         serialize_map(Option<usize>) -> Result<Impossible>,
         serialize_bytes(&[u8]),
-        serialize_none,
         serialize_unit,
         serialize_unit_struct(&'static str),
     );
@@ -140,8 +139,14 @@ impl<'a, W: Write> Serializer for SqlSerializer<'a, W> {
     }
 
     #[inline]
-    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result {
-        Err(SqlSerializerError::Unsupported("serialize_some"))
+    fn serialize_none(self) -> Result {
+        self.writer.write_str("NULL")?;
+        Ok(())
+    }
+
+    #[inline]
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result {
+        value.serialize(self)
     }
 
     #[inline]
@@ -336,12 +341,20 @@ mod tests {
         assert_eq!(check((42, 43)), "(42,43)");
     }
 
+    #[test]
+    fn it_writes_options() {
+        assert_eq!(check(None::<i64>), "NULL");
+        assert_eq!(check(Some(5)), "5");
+        assert_eq!(check(None::<&str>), "NULL");
+        assert_eq!(check(Some("a'b")), "'a\\'b'");
+        assert_eq!(check([Some(1), None]), "(1,NULL)");
+        assert_eq!(check(vec![None, Some("a")]), "[NULL,'a']");
+    }
+
     #[test]
     fn it_fails_on_unsupported() {
         let mut out = String::new();
         assert!(write_arg(&mut out, &std::collections::HashMap::<u32, u32>::new()).is_err());
-        assert!(write_arg(&mut out, &None::<u32>).is_err());
-        assert!(write_arg(&mut out, &Some(42)).is_err());
         assert!(write_arg(&mut out, &()).is_err());
 
         #[derive(Serialize)]
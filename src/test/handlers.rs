@@ -12,32 +12,52 @@ use serde::{Deserialize, Serialize};
 use super::{Handler, HandlerFn};
 use crate::{error::Result, rowbinary};
 
-// === raw ===
+// === respond ===
 
-struct RawHandler<F>(Option<F>);
+struct RespondHandler<F>(Option<F>);
 
 #[sealed]
-impl<F> super::Handler for RawHandler<F>
+impl<F> super::Handler for RespondHandler<F>
 where
     F: FnOnce(Request<Body>) -> Response<Body> + Send + 'static,
 {
     type Control = ();
 
     fn make(&mut self) -> (HandlerFn, Self::Control) {
-        let h = Box::new(self.0.take().expect("raw handler must be called only once"));
+        let h = Box::new(
+            self.0
+                .take()
+                .expect("respond handler must be called only once"),
+        );
         (h, ())
     }
 }
 
-fn raw(f: impl FnOnce(Request<Body>) -> Response<Body> + Send + 'static) -> impl Handler {
-    RawHandler(Some(f))
+pub(crate) fn respond(
+    f: impl FnOnce(Request<Body>) -> Response<Body> + Send + 'static,
+) -> impl Handler {
+    RespondHandler(Some(f))
+}
+
+// === raw ===
+
+/// Responds with `body` as is, in one chunk.
+pub fn raw(body: impl Into<Bytes>) -> impl Handler {
+    let body = body.into();
+    respond(move |_req| Response::new(Body::from(body)))
+}
+
+/// Responds with `chunks` as is, each one sent separately.
+pub fn raw_chunks(chunks: impl Stream<Item = Bytes> + Send + 'static) -> impl Handler {
+    let s = chunks.map(Ok::<_, Infallible>);
+    respond(move |_req| Response::new(Body::wrap_stream(s)))
 }
 
 // === failure ===
 
 pub fn failure(status: StatusCode) -> impl Handler {
     let reason = status.canonical_reason().unwrap_or("<unknown status code>");
-    raw(move |_req| {
+    respond(move |_req| {
         Response::builder()
             .status(status)
             .body(Body::from(reason))
@@ -45,6 +65,39 @@ pub fn failure(status: StatusCode) -> impl Handler {
     })
 }
 
+// === exception ===
+
+/// Responds with an exception as ClickHouse does before sending any data:
+/// `500 Internal Server Error` and the `X-ClickHouse-Exception-Code` header.
+pub fn exception(code: u32, message: impl Into<String>) -> impl Handler {
+    let message = message.into();
+    respond(move |_req| {
+        Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .header("X-ClickHouse-Exception-Code", code.to_string())
+            .body(Body::from(format!(
+                "Code: {code}. DB::Exception: {message}\n"
+            )))
+            .expect("invalid builder")
+    })
+}
+
+// === redirect ===
+
+/// Responds with `307 Temporary Redirect` to `prefix` followed by the path and
+/// the query of the request, like proxies in front of replicas usually do.
+pub fn redirect(prefix: impl Into<String>) -> impl Handler {
+    let prefix = prefix.into();
+    respond(move |req| {
+        let rest = req.uri().path_and_query().map_or("/", |rest| rest.as_str());
+        Response::builder()
+            .status(StatusCode::TEMPORARY_REDIRECT)
+            .header("Location", format!("{prefix}{rest}"))
+            .body(Body::empty())
+            .expect("invalid builder")
+    })
+}
+
 // === provide ===
 
 pub fn provide<T>(rows: impl Stream<Item = T> + Send + 'static) -> impl Handler
@@ -56,7 +109,7 @@ where
         rowbinary::serialize_into(&mut buffer, &row)?;
         Ok(buffer.freeze())
     });
-    raw(move |_req| Response::new(Body::wrap_stream(s)))
+    respond(move |_req| Response::new(Body::wrap_stream(s)))
 }
 
 // === record ===
@@ -183,7 +236,7 @@ where
         json.push('\n');
         Ok(json.into())
     });
-    raw(move |_req| Response::new(Body::wrap_stream(s)))
+    respond(move |_req| Response::new(Body::wrap_stream(s)))
 }
 
 #[cfg(feature = "watch")]
@@ -200,5 +253,5 @@ pub fn watch_only_events(rows: impl Stream<Item = u64> + Send + 'static) -> impl
         json.push('\n');
         Ok(json.into())
     });
-    raw(move |_req| Response::new(Body::wrap_stream(s)))
+    respond(move |_req| Response::new(Body::wrap_stream(s)))
 }
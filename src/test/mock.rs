@@ -1,5 +1,6 @@
 use std::{
     convert::Infallible,
+    mem,
     net::SocketAddr,
     sync::{
         atomic::{AtomicUsize, Ordering},
@@ -9,13 +10,15 @@ use std::{
     time::Duration,
 };
 
+use bytes::{Bytes, BytesMut};
 use futures::{
-    channel::{self, mpsc::UnboundedSender},
+    channel::{self, mpsc::UnboundedSender, oneshot},
+    future,
     lock::Mutex,
-    StreamExt,
+    stream, StreamExt,
 };
 use hyper::service::{make_service_fn, service_fn};
-use hyper::Server;
+use hyper::{body::HttpBody, Body, HeaderMap, Method, Request, Response, Server, Uri};
 use tokio::time::timeout;
 
 use super::{Handler, HandlerFn};
@@ -26,9 +29,37 @@ pub struct Mock {
     url: String,
     tx: UnboundedSender<HandlerFn>,
     responses_left: Arc<AtomicUsize>,
+    // Requests are recorded once their bodies are read, numbered in order of arrival.
+    requests: Arc<std::sync::Mutex<Vec<(usize, RecordedRequest)>>>,
     non_exhaustive: bool,
 }
 
+/// A request received by [`Mock`].
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl RecordedRequest {
+    /// Returns a decoded URL parameter, e.g. `database` or a setting.
+    pub fn param(&self, name: &str) -> Option<String> {
+        let query = self.uri.query()?;
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    /// Returns SQL of the request with all bound arguments, which is sent
+    /// either as the `query` parameter (`INSERT`s and short `SELECT`s) or as a body.
+    pub fn sql(&self) -> String {
+        self.param("query")
+            .unwrap_or_else(|| String::from_utf8_lossy(&self.body).into_owned())
+    }
+}
+
 impl Mock {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
@@ -38,18 +69,37 @@ impl Mock {
         let rx = Arc::new(Mutex::new(rx));
         let responses_left = Arc::new(AtomicUsize::new(0));
         let responses_left_0 = responses_left.clone();
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let requests_0 = requests.clone();
+        let arrived = Arc::new(AtomicUsize::new(0));
 
         // Hm, here is one of the ugliest code that I've written ever.
         let make_service = make_service_fn(move |_conn| {
             let rx1 = rx.clone();
             let responses_left_1 = responses_left.clone();
+            let requests_1 = requests.clone();
+            let arrived_1 = arrived.clone();
             async move {
                 let rx2 = rx1.clone();
                 let responses_left_2 = responses_left_1.clone();
-                Ok::<_, Infallible>(service_fn(move |req| {
+                let requests_2 = requests_1.clone();
+                let arrived_2 = arrived_1.clone();
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
                     let rx3 = rx2.clone();
                     let responses_left = responses_left_2.clone();
+                    let requests = requests_2.clone();
+                    let no = arrived_2.fetch_add(1, Ordering::Relaxed);
                     async move {
+                        let (parts, body) = req.into_parts();
+                        let recorded = RecordedRequest {
+                            method: parts.method.clone(),
+                            uri: parts.uri.clone(),
+                            headers: parts.headers.clone(),
+                            body: Bytes::new(),
+                        };
+                        let (body, is_recorded) = record(body, recorded, no, requests);
+                        let req = Request::from_parts(parts, body);
+
                         let handler_fn = {
                             let mut rx = rx3.lock().await;
 
@@ -62,7 +112,7 @@ impl Mock {
                                 _ => panic!("unexpected request, no predefined responses left"),
                             }
                         };
-                        Ok::<_, Infallible>(handler_fn(req))
+                        Ok::<_, Infallible>(finish_after(handler_fn(req), is_recorded))
                     }
                 }))
             }
@@ -77,6 +127,7 @@ impl Mock {
             url: format!("http://{addr}"),
             tx,
             responses_left: responses_left_0,
+            requests: requests_0,
             non_exhaustive: false,
         }
     }
@@ -94,11 +145,54 @@ impl Mock {
         control
     }
 
+    /// Takes all requests received since the last call, in order of arrival.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        let mut requests = mem::take(&mut *self.requests.lock().unwrap());
+        requests.sort_by_key(|(no, _)| *no);
+        requests.into_iter().map(|(_, request)| request).collect()
+    }
+
     pub fn non_exhaustive(&mut self) {
         self.non_exhaustive = true;
     }
 }
 
+/// Passes `body` to the handler as it's received, while the whole body is recorded.
+/// It's read to the end even if the handler doesn't need it.
+fn record(
+    mut body: Body,
+    mut recorded: RecordedRequest,
+    no: usize,
+    requests: Arc<std::sync::Mutex<Vec<(usize, RecordedRequest)>>>,
+) -> (Body, oneshot::Receiver<()>) {
+    let (mut tx, streamed) = Body::channel();
+    let (is_recorded_tx, is_recorded) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let mut content = BytesMut::new();
+        let mut is_passed = true;
+
+        while let Some(Ok(chunk)) = body.data().await {
+            content.extend_from_slice(&chunk);
+            is_passed = is_passed && tx.send_data(chunk).await.is_ok();
+        }
+
+        recorded.body = content.freeze();
+        requests.lock().unwrap().push((no, recorded));
+        let _ = is_recorded_tx.send(());
+    });
+
+    (streamed, is_recorded)
+}
+
+/// Ends the response only after the request is recorded, so it's already
+/// returned by [`Mock::requests`] once a client has read the response.
+fn finish_after(response: Response<Body>, is_recorded: oneshot::Receiver<()>) -> Response<Body> {
+    let (parts, body) = response.into_parts();
+    let tail = stream::once(is_recorded).filter_map(|_| future::ready(None));
+    Response::from_parts(parts, Body::wrap_stream(body.chain(tail)))
+}
+
 impl Drop for Mock {
     fn drop(&mut self) {
         if !self.non_exhaustive
@@ -1,7 +1,7 @@
 use hyper::{Body, Request, Response, StatusCode};
 use sealed::sealed;
 
-pub use self::mock::Mock;
+pub use self::mock::{Mock, RecordedRequest};
 
 pub mod handlers;
 mod mock;
@@ -26,6 +26,7 @@ pub mod status {
     pub const NOT_FOUND: StatusCode = StatusCode::NOT_FOUND;
     pub const PAYLOAD_TOO_LARGE: StatusCode = StatusCode::PAYLOAD_TOO_LARGE;
     pub const NOT_IMPLEMENTED: StatusCode = StatusCode::NOT_IMPLEMENTED;
+    pub const BAD_GATEWAY: StatusCode = StatusCode::BAD_GATEWAY;
     pub const SERVICE_UNAVAILABLE: StatusCode = StatusCode::SERVICE_UNAVAILABLE;
     pub const LENGTH_REQUIRED: StatusCode = StatusCode::LENGTH_REQUIRED;
     pub const INTERNAL_SERVER_ERROR: StatusCode = StatusCode::INTERNAL_SERVER_ERROR;
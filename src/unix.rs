@@ -0,0 +1,135 @@
+//! A connector dialing a Unix domain socket, which is enabled by the `unix` feature.
+
+use std::{
+    future::Future,
+    io,
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use hyper::{
+    client::connect::{Connected, Connection},
+    service::Service,
+    Uri,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::UnixStream,
+};
+
+/// Connects to the same socket for any URI, so the URI is used only
+/// for the request line and the `Host` header.
+#[derive(Clone)]
+pub(crate) struct UnixConnector {
+    path: Arc<PathBuf>,
+}
+
+impl UnixConnector {
+    pub(crate) fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Arc::new(path.into()),
+        }
+    }
+}
+
+impl Service<Uri> for UnixConnector {
+    type Response = UnixConnection;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<UnixConnection>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let path = self.path.clone();
+        Box::pin(async move { UnixStream::connect(&*path).await.map(UnixConnection) })
+    }
+}
+
+pub(crate) struct UnixConnection(UnixStream);
+
+impl Connection for UnixConnection {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for UnixConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UnixConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use hyper::{server::conn::Http, service::service_fn, Body, Request, Response};
+    use tokio::net::UnixListener;
+
+    use crate::Client;
+
+    #[tokio::test]
+    async fn it_queries_through_unix_sockets() {
+        let dir = std::env::temp_dir().join(format!("clickhouse-unix-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("http.sock");
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        // Serves exactly one request and records its URI and `Host`.
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (tx, rx) = futures::channel::oneshot::channel();
+            let mut tx = Some(tx);
+            let service = service_fn(move |req: Request<Body>| {
+                let host = req.headers()["host"].to_str().unwrap().to_owned();
+                let _ = tx.take().unwrap().send((req.uri().clone(), host));
+                // `'hello'` as `String` in RowBinary.
+                async { Ok::<_, Infallible>(Response::new(Body::from(&b"\x05hello"[..]))) }
+            });
+            Http::new().serve_connection(stream, service).await.unwrap();
+            rx.await.unwrap()
+        });
+
+        let client = Client::with_unix_socket(path.clone(), "http://clickhouse:8123/prefix")
+            .with_compression(crate::Compression::None);
+        let value = client
+            .query("SELECT 'hello'")
+            .fetch_one::<String>()
+            .await
+            .unwrap();
+        assert_eq!(value, "hello");
+
+        drop(client);
+        let (uri, host) = server.await.unwrap();
+        assert_eq!(uri.path(), "/prefix");
+        assert_eq!(host, "clickhouse:8123");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
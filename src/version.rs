@@ -0,0 +1,143 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use crate::error::Error;
+
+/// A version of ClickHouse, e.g. returned by [`Client::server_version`].
+///
+/// Versions are ordered by components, so they can be compared with each other
+/// and with tuples, e.g. `version >= (22, 0, 0, 0)`.
+///
+/// Missing components of non-standard versions are zeros, and suffixes
+/// like `.altinitystable` are ignored.
+///
+/// [`Client::server_version`]: crate::Client::server_version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ServerVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub build: u32,
+}
+
+impl ServerVersion {
+    pub fn new(major: u32, minor: u32, patch: u32, build: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            build,
+        }
+    }
+}
+
+impl From<(u32, u32, u32, u32)> for ServerVersion {
+    fn from((major, minor, patch, build): (u32, u32, u32, u32)) -> Self {
+        Self::new(major, minor, patch, build)
+    }
+}
+
+impl PartialEq<(u32, u32, u32, u32)> for ServerVersion {
+    fn eq(&self, other: &(u32, u32, u32, u32)) -> bool {
+        *self == Self::from(*other)
+    }
+}
+
+impl PartialOrd<(u32, u32, u32, u32)> for ServerVersion {
+    fn partial_cmp(&self, other: &(u32, u32, u32, u32)) -> Option<Ordering> {
+        self.partial_cmp(&Self::from(*other))
+    }
+}
+
+impl FromStr for ServerVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut components = [0; 4];
+        let mut count = 0;
+
+        for (component, part) in components.iter_mut().zip(s.trim().split('.')) {
+            // Take leading digits only, e.g. `23.8.1.2992-lts`.
+            let len = part.bytes().take_while(u8::is_ascii_digit).count();
+            match part[..len].parse() {
+                Ok(value) => *component = value,
+                Err(_) => break,
+            }
+
+            count += 1;
+
+            if len < part.len() {
+                break;
+            }
+        }
+
+        if count == 0 {
+            return Err(Error::BadResponse(format!(
+                "unexpected server version: {s:?}"
+            )));
+        }
+
+        let [major, minor, patch, build] = components;
+        Ok(Self::new(major, minor, patch, build))
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.{}",
+            self.major, self.minor, self.patch, self.build
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_versions() {
+        let cases = [
+            ("24.3.2.23", (24, 3, 2, 23)),
+            ("23.8.11.29.altinitystable", (23, 8, 11, 29)),
+            ("22.8.5.29-lts", (22, 8, 5, 29)),
+            ("21.8", (21, 8, 0, 0)),
+            ("23.3.1-testing", (23, 3, 1, 0)),
+            ("1.1.54390", (1, 1, 54390, 0)),
+            (" 24.1.1.2048\n", (24, 1, 1, 2048)),
+        ];
+
+        for (src, expected) in cases {
+            let version = src.parse::<ServerVersion>().unwrap();
+            assert_eq!(version, expected, "{}", src);
+        }
+
+        for src in ["", "unknown", "v24.3", ".1"] {
+            assert!(src.parse::<ServerVersion>().is_err(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn it_compares_versions() {
+        let parse = |s: &str| s.parse::<ServerVersion>().unwrap();
+
+        assert!(parse("23.10.1.1") > parse("23.9.5.100"));
+        assert!(parse("22.0.0.1") > parse("22"));
+        assert!(parse("21.12.3.32") < (22, 0, 0, 0));
+        assert!(parse("22.1.3.7") >= (22, 0, 0, 0));
+        assert_eq!(parse("24.3").to_string(), "24.3.0.0");
+
+        let mut versions = ["24.1.1.2048", "1.1.54390", "23.8.11.29", "23.12"].map(parse);
+        versions.sort();
+        assert_eq!(
+            versions,
+            [
+                (1, 1, 54390, 0),
+                (23, 8, 11, 29),
+                (23, 12, 0, 0),
+                (24, 1, 1, 2048)
+            ]
+            .map(ServerVersion::from)
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use std::{fmt::Write, time::Duration};
 
+use futures::{stream, Stream};
 use serde::Deserialize;
 use sha1::{Digest, Sha1};
 
@@ -47,7 +48,9 @@ impl<V> Watch<V> {
     fn cursor<T: DbRow>(mut self, only_events: bool) -> Result<CursorWithInit<T>> {
         self.sql.bind_fields::<T>();
         let sql = self.sql.finish()?;
-        let (sql, view) = if is_table_name(&sql) {
+        let (sql, view) = if let Some(view) = strip_watch(&sql) {
+            (None, view.to_string())
+        } else if is_table_name(&sql) {
             (None, sql)
         } else {
             let view = make_live_view_name(&sql);
@@ -109,6 +112,61 @@ impl Watch<Rows> {
         Ok(RowCursor(self.cursor(false)?))
     }
 
+    /// Subscribes to updates, returning a stream of `(Version, T)`.
+    ///
+    /// The HTTP connection is kept open and rows are yielded as soon as they arrive.
+    /// After the initial result, every change of the live view produces a new batch
+    /// of rows with an incremented version. Progress rows sent by the server to keep
+    /// the connection alive are skipped.
+    ///
+    /// Both a query (a live view is created for it) and an existing live view
+    /// (`lv` or `WATCH lv`) can be watched.
+    ///
+    /// The stream ends after the first error. Reconnection isn't performed,
+    /// call `subscribe()` again to resume watching.
+    ///
+    /// # Panics
+    /// Panics if `T` are rows without specified names.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> clickhouse::error::Result<()> {
+    /// use futures::TryStreamExt;
+    ///
+    /// #[derive(clickhouse::Row, serde::Deserialize)]
+    /// struct MyRow {
+    ///     total: u64,
+    /// }
+    ///
+    /// let mut updates = clickhouse::Client::default()
+    ///     .watch("WATCH lv")
+    ///     .subscribe::<MyRow>()?;
+    ///
+    /// while let Some((version, row)) = updates.try_next().await? {
+    ///     println!("{version}: {}", row.total);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    #[track_caller]
+    pub fn subscribe<T>(self) -> Result<impl Stream<Item = Result<(Version, T)>> + Unpin>
+    where
+        T: DbRow + for<'b> Deserialize<'b>,
+    {
+        let cursor = self.fetch::<T>()?;
+
+        let stream = stream::unfold(Some(cursor), |cursor| async move {
+            let mut cursor = cursor?;
+            match cursor.next().await {
+                Ok(Some(row)) => Some((Ok(row), Some(cursor))),
+                Ok(None) => None,
+                Err(err) => Some((Err(err), None)),
+            }
+        });
+
+        Ok(Box::pin(stream))
+    }
+
     pub async fn fetch_one<T>(self) -> Result<(Version, T)>
     where
         T: DbRow + for<'b> Deserialize<'b>,
@@ -249,6 +307,15 @@ async fn init_cursor<T>(client: &Client, params: &WatchParams) -> Result<JsonCur
     Ok(JsonCursor::new(response))
 }
 
+// Extracts `lv` from `WATCH lv`.
+fn strip_watch(sql: &str) -> Option<&str> {
+    let mut words = sql.split_ascii_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some(watch), Some(view), None) if watch.eq_ignore_ascii_case("WATCH") => Some(view),
+        _ => None,
+    }
+}
+
 fn is_table_name(sql: &str) -> bool {
     // TODO: support quoted identifiers.
     sql.split_ascii_whitespace().take(2).count() == 1
@@ -276,3 +343,12 @@ fn it_makes_live_view_name() {
     assert_eq!(a.len(), 3 + 40);
     assert_eq!(b.len(), 3 + 40);
 }
+
+#[test]
+fn it_strips_watch() {
+    assert_eq!(strip_watch("WATCH lv"), Some("lv"));
+    assert_eq!(strip_watch(" watch  db.lv\n"), Some("db.lv"));
+    assert_eq!(strip_watch("lv"), None);
+    assert_eq!(strip_watch("WATCH lv EVENTS"), None);
+    assert_eq!(strip_watch("SELECT 1"), None);
+}
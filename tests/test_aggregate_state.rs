@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+use clickhouse::{aggregate_state::AggregateState, Row};
+
+mod common;
+
+#[common::named]
+#[tokio::test]
+async fn roundtrip() {
+    let client = common::prepare_database!();
+
+    #[derive(Debug, Row, Serialize, Deserialize)]
+    struct MyRow {
+        key: String,
+        users: AggregateState,
+    }
+
+    client
+        .query(
+            "CREATE TABLE test(key String, users AggregateFunction(uniq, String))
+             ENGINE = AggregatingMergeTree ORDER BY key",
+        )
+        .execute()
+        .await
+        .unwrap();
+
+    let original = client
+        .query(
+            "SELECT toString(number % 3) AS key, toString(uniqState(toString(number))) AS users
+             FROM (SELECT number FROM system.numbers LIMIT 1000)
+             GROUP BY key ORDER BY key",
+        )
+        .fetch_all::<MyRow>()
+        .await
+        .unwrap();
+    assert_eq!(original.len(), 3);
+
+    let mut insert = client.insert("test").unwrap();
+    for row in &original {
+        insert.write(row).await.unwrap();
+    }
+    insert.end().await.unwrap();
+
+    let stored = client
+        .query("SELECT key, toString(users) AS users FROM test ORDER BY key")
+        .fetch_all::<MyRow>()
+        .await
+        .unwrap();
+
+    assert_eq!(stored.len(), original.len());
+    for (stored, original) in stored.iter().zip(&original) {
+        assert_eq!(stored.key, original.key);
+        assert_eq!(stored.users, original.users);
+    }
+
+    let total = client
+        .query("SELECT uniqMerge(users) FROM test")
+        .fetch_one::<u64>()
+        .await
+        .unwrap();
+    assert_eq!(total, 1000);
+}
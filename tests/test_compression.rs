@@ -23,7 +23,7 @@ async fn check(client: Client) {
         .await
         .unwrap();
 
-    let mut inserter = client.inserter().unwrap();
+    let mut inserter = client.inserter("test").unwrap();
 
     for i in 0..200_000 {
         inserter.write(&MyRow { no: i, name: "foo" }).await.unwrap();
@@ -66,3 +66,49 @@ async fn lz4_hc() {
     let client = common::prepare_database!().with_compression(Compression::Lz4Hc(4));
     check(client).await;
 }
+
+#[cfg(feature = "lz4")]
+#[common::named]
+#[tokio::test]
+async fn lz4_with_threshold() {
+    let client = common::prepare_database!()
+        .with_compression(Compression::Lz4)
+        .with_compression_threshold(100);
+
+    // Below the threshold, the response isn't compressed.
+    let numbers = client
+        .query("SELECT number FROM system.numbers LIMIT 10")
+        .fetch_all::<u64>()
+        .await
+        .unwrap();
+    assert_eq!(numbers, (0..10).collect::<Vec<_>>());
+
+    // Above the threshold, the response is compressed as usual.
+    let numbers = client
+        .query("SELECT number FROM system.numbers LIMIT 1000")
+        .fetch_all::<u64>()
+        .await
+        .unwrap();
+    assert_eq!(numbers, (0..1000).collect::<Vec<_>>());
+
+    check(client).await;
+}
+
+#[cfg(feature = "lz4")]
+#[common::named]
+#[tokio::test]
+async fn lz4_raw_bytes() {
+    let client = common::prepare_database!().with_compression(Compression::Lz4);
+
+    let tsv = client
+        .query("SELECT number, toString(number) FROM system.numbers LIMIT 100000 FORMAT TSV")
+        .fetch_raw_bytes()
+        .await
+        .unwrap();
+
+    let tsv = String::from_utf8(tsv).unwrap();
+    let lines = tsv.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 100_000);
+    assert_eq!(lines[0], "0\t0");
+    assert_eq!(lines[99_999], "99999\t99999");
+}
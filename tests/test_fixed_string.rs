@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+use clickhouse::{fixed_string::FixedString, Row};
+
+mod common;
+
+#[common::named]
+#[tokio::test]
+async fn smoke() {
+    let client = common::prepare_database!();
+
+    #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Row)]
+    struct MyRow {
+        no: u32,
+        fs: FixedString<16>,
+    }
+
+    client
+        .query(
+            "
+            CREATE TABLE test(
+                no UInt32,
+                fs FixedString(16)
+            ) ENGINE = MergeTree ORDER BY no
+        ",
+        )
+        .execute()
+        .await
+        .unwrap();
+
+    let original_rows = vec![
+        MyRow {
+            no: 0,
+            fs: "short".into(),
+        },
+        MyRow {
+            no: 1,
+            fs: "exactly 16 bytes".into(),
+        },
+        MyRow {
+            no: 2,
+            fs: "".into(),
+        },
+    ];
+
+    let mut insert = client.insert("test").unwrap();
+    for row in &original_rows {
+        insert.write(row).await.unwrap();
+    }
+    insert.end().await.unwrap();
+
+    let rows = client
+        .query("SELECT ?fields FROM test ORDER BY no")
+        .fetch_all::<MyRow>()
+        .await
+        .unwrap();
+
+    assert_eq!(rows, original_rows);
+
+    // Short strings are padded with zeros.
+    let (len, hex) = client
+        .query("SELECT length(fs), hex(fs) FROM test WHERE no = 0")
+        .fetch_one::<(u64, String)>()
+        .await
+        .unwrap();
+
+    assert_eq!(len, 16);
+    assert_eq!(hex, format!("{}{}", hex_of("short"), "00".repeat(11)));
+}
+
+#[common::named]
+#[tokio::test]
+async fn too_long() {
+    let client = common::prepare_database!();
+
+    #[derive(Debug, Serialize, Row)]
+    struct MyRow {
+        fs: FixedString<16>,
+    }
+
+    client
+        .query("CREATE TABLE test(fs FixedString(16)) ENGINE = MergeTree ORDER BY fs")
+        .execute()
+        .await
+        .unwrap();
+
+    let mut insert = client.insert("test").unwrap();
+    let row = MyRow {
+        fs: "more than 16 bytes".into(),
+    };
+    assert!(insert.write(&row).await.is_err());
+}
+
+fn hex_of(s: &str) -> String {
+    s.bytes().map(|b| format!("{b:02X}")).collect()
+}
@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+
+use clickhouse::{
+    geo::{MultiPolygon, Point, Polygon, Ring},
+    Row,
+};
+
+mod common;
+
+#[common::named]
+#[tokio::test]
+async fn roundtrip() {
+    let client = common::prepare_database!().with_option("allow_experimental_geo_types", "1");
+
+    #[derive(Debug, PartialEq, Row, Serialize, Deserialize)]
+    struct MyRow {
+        name: String,
+        center: Point,
+        area: Polygon,
+        parts: MultiPolygon,
+    }
+
+    client
+        .query(
+            "CREATE TABLE test(name String, center Point, area Polygon, parts MultiPolygon)
+             ENGINE = MergeTree ORDER BY name",
+        )
+        .execute()
+        .await
+        .unwrap();
+
+    let square = |from: f64, to: f64| Ring::new([(from, from), (from, to), (to, to), (to, from)]);
+    let original = vec![
+        MyRow {
+            name: "donut".into(),
+            center: Point::new(5., 5.),
+            area: Polygon::new(square(0., 10.), [square(4., 6.)]),
+            parts: MultiPolygon::new([Polygon::new(square(0., 1.), [])]),
+        },
+        MyRow {
+            name: "empty".into(),
+            center: Point::default(),
+            area: Polygon::default(),
+            parts: MultiPolygon::default(),
+        },
+    ];
+
+    let mut insert = client.insert("test").unwrap();
+    for row in &original {
+        insert.write(row).await.unwrap();
+    }
+    insert.end().await.unwrap();
+
+    let rows = client
+        .query("SELECT ?fields FROM test ORDER BY name")
+        .fetch_all::<MyRow>()
+        .await
+        .unwrap();
+    assert_eq!(rows, original);
+
+    // Check that the server agrees on the layout.
+    let (exterior_len, hole_x) = client
+        .query("SELECT length(area[1]), area[2][1].1 FROM test WHERE name = 'donut'")
+        .fetch_one::<(u64, f64)>()
+        .await
+        .unwrap();
+    assert_eq!(exterior_len, 4);
+    assert_eq!(hole_x, 4.);
+}
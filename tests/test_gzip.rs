@@ -0,0 +1,167 @@
+#![cfg(feature = "gzip")]
+
+use std::{
+    convert::Infallible,
+    io::Read,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use flate2::read::GzDecoder;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use serde::{Deserialize, Serialize};
+
+use clickhouse::{Client, Compression, Row};
+
+mod common;
+
+#[derive(Debug, Row, Serialize, Deserialize)]
+struct MyRow {
+    no: u32,
+    name: String,
+}
+
+#[common::named]
+#[tokio::test]
+async fn insert_and_query() {
+    let client = common::prepare_database!()
+        .with_compression(Compression::None)
+        .with_gzip_requests(true);
+
+    client
+        .query("CREATE TABLE test(no UInt32, name String) ENGINE = MergeTree ORDER BY no")
+        .execute()
+        .await
+        .unwrap();
+
+    let mut insert = client.insert("test").unwrap();
+    for no in 0..100_000 {
+        let name = format!("name-{no}");
+        insert.write(&MyRow { no, name }).await.unwrap();
+    }
+    insert.end().await.unwrap();
+
+    // Long enough to be sent by POST and compressed.
+    let sql = format!(
+        "SELECT count() FROM test WHERE no NOT IN ({})",
+        (0..3000)
+            .map(|no| no.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    let count = client.query(&sql).fetch_one::<u64>().await.unwrap();
+    assert_eq!(count, 97_000);
+}
+
+// `Content-Encoding` and a decoded body.
+type Recorded = (Option<String>, Vec<u8>);
+
+// Records all requests, responding with `200 OK`.
+#[derive(Default, Clone)]
+struct Recorder(Arc<Mutex<Vec<Recorded>>>);
+
+impl Recorder {
+    async fn start(&self) -> String {
+        let recorder = self.clone();
+        let make_service = make_service_fn(move |_conn| {
+            let recorder = recorder.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let recorder = recorder.clone();
+                    async move {
+                        let encoding = req
+                            .headers()
+                            .get("content-encoding")
+                            .map(|v| v.to_str().unwrap().to_string());
+                        let raw = hyper::body::to_bytes(req.into_body()).await.unwrap();
+
+                        let mut body = Vec::new();
+                        if encoding.is_some() {
+                            GzDecoder::new(&raw[..]).read_to_end(&mut body).unwrap();
+                        } else {
+                            body.extend_from_slice(&raw);
+                        }
+
+                        recorder.0.lock().unwrap().push((encoding, body));
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        url
+    }
+
+    fn take(&self) -> Vec<Recorded> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+#[tokio::test]
+async fn content_encoding() {
+    let recorder = Recorder::default();
+    let client = Client::default()
+        .with_url(recorder.start().await)
+        .with_compression(Compression::None)
+        .with_gzip_requests(true);
+
+    // Short GET queries have no body.
+    client.query("SELECT 1").execute().await.unwrap();
+    // Short POST queries aren't compressed.
+    client.query("DROP TABLE t").execute().await.unwrap();
+    // Long ones are.
+    let long = format!("INSERT INTO t VALUES {}", "(1),".repeat(1000));
+    client.query(&long).execute().await.unwrap();
+
+    let requests = recorder.take();
+    assert_eq!(requests[0], (None, b"SELECT 1".to_vec()));
+    assert_eq!(requests[1], (None, b"DROP TABLE t".to_vec()));
+    assert_eq!(requests[2], (Some("gzip".into()), long.into_bytes()));
+
+    // Inserts are compressed as a single stream of several chunks.
+    let mut insert = client.insert("t").unwrap();
+    let mut expected = Vec::new();
+    for no in 0..50_000 {
+        let row = MyRow {
+            no,
+            name: "foo".into(),
+        };
+        expected.extend_from_slice(&no.to_le_bytes());
+        expected.extend_from_slice(b"\x03foo");
+        insert.write(&row).await.unwrap();
+    }
+    insert.end().await.unwrap();
+
+    let requests = recorder.take();
+    assert_eq!(requests, vec![(Some("gzip".into()), expected)]);
+}
+
+#[cfg(feature = "lz4")]
+#[tokio::test]
+async fn not_with_lz4() {
+    let recorder = Recorder::default();
+    let client = Client::default()
+        .with_url(recorder.start().await)
+        .with_compression(Compression::Lz4)
+        .with_gzip_requests(true);
+
+    let mut insert = client.insert("t").unwrap();
+    insert
+        .write(&MyRow {
+            no: 1,
+            name: "foo".into(),
+        })
+        .await
+        .unwrap();
+    insert.end().await.unwrap();
+
+    let requests = recorder.take();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].0, None);
+}
@@ -0,0 +1,404 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::StreamExt;
+use hyper::{
+    header::{HeaderName, CONTENT_LENGTH, TRANSFER_ENCODING},
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use serde::Serialize;
+
+use clickhouse::{error::Error, Client, Compression, Row};
+
+mod common;
+
+#[derive(Row, Serialize)]
+struct MyRow {
+    no: u32,
+}
+
+#[common::named]
+#[tokio::test]
+async fn quorum_settings() {
+    let client = common::prepare_database!();
+
+    client
+        .query("CREATE TABLE test(no UInt32) ENGINE = MergeTree ORDER BY no")
+        .execute()
+        .await
+        .unwrap();
+
+    // Settings are ignored for non-replicated tables, but must be sent anyway.
+    let mut insert = client
+        .insert("test")
+        .unwrap()
+        .with_insert_quorum(2)
+        .with_insert_quorum_timeout(Duration::from_secs(5))
+        .with_insert_quorum_parallel(false);
+    insert.write(&MyRow { no: 1 }).await.unwrap();
+    insert.end().await.unwrap();
+
+    client.query("SYSTEM FLUSH LOGS").execute().await.unwrap();
+
+    let settings = client
+        .query(
+            "SELECT Settings['insert_quorum'], Settings['insert_quorum_timeout'],
+                    Settings['insert_quorum_parallel']
+             FROM system.query_log
+             WHERE current_database = currentDatabase()
+               AND query_kind = 'Insert' AND type = 'QueryFinish'",
+        )
+        .fetch_one::<(String, String, String)>()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        settings,
+        ("2".to_string(), "5000".to_string(), "0".to_string())
+    );
+}
+
+#[tokio::test]
+async fn chunked_transfer() {
+    // (`Transfer-Encoding`, `Content-Length`, body size) of every request.
+    type Recorded = (Option<String>, Option<String>, usize);
+
+    let recorded = Arc::new(Mutex::new(Vec::<Recorded>::new()));
+    let recorded2 = recorded.clone();
+    let make_service = make_service_fn(move |_conn| {
+        let recorded = recorded2.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let recorded = recorded.clone();
+                async move {
+                    let header = |name: HeaderName| {
+                        let value = req.headers().get(name)?;
+                        Some(value.to_str().unwrap().to_string())
+                    };
+                    let encoding = header(TRANSFER_ENCODING);
+                    let length = header(CONTENT_LENGTH);
+                    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+
+                    recorded
+                        .lock()
+                        .unwrap()
+                        .push((encoding, length, body.len()));
+                    Ok::<_, Infallible>(Response::new(Body::empty()))
+                }
+            }))
+        }
+    });
+
+    let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+
+    let client = Client::default()
+        .with_url(url)
+        .with_compression(Compression::None);
+
+    // Enough rows to be sent in several chunks, before the total size is known.
+    let mut insert = client.insert("test").unwrap();
+    for no in 0..100_000 {
+        insert.write(&MyRow { no }).await.unwrap();
+    }
+    insert.end().await.unwrap();
+
+    let recorded = recorded.lock().unwrap().clone();
+    assert_eq!(recorded, vec![(Some("chunked".into()), None, 400_000)]);
+}
+
+#[common::named]
+#[tokio::test]
+async fn async_insert() {
+    let client = common::prepare_database!();
+
+    client
+        .query("CREATE TABLE test(no UInt32) ENGINE = MergeTree ORDER BY no")
+        .execute()
+        .await
+        .unwrap();
+
+    for no in 0..10 {
+        let mut insert = client.insert("test").unwrap().with_async(true);
+        insert.write(&MyRow { no }).await.unwrap();
+        insert.end().await.unwrap();
+    }
+
+    // Rows are flushed before `end()` returns.
+    let (count, sum) = client
+        .query("SELECT count(), sum(no) FROM test")
+        .fetch_one::<(u64, u64)>()
+        .await
+        .unwrap();
+    assert_eq!(count, 10);
+    assert_eq!(sum, 45);
+
+    client.query("SYSTEM FLUSH LOGS").execute().await.unwrap();
+
+    let settings = client
+        .query(
+            "SELECT DISTINCT Settings['async_insert'], Settings['wait_for_async_insert']
+             FROM system.query_log
+             WHERE current_database = currentDatabase()
+               AND query_kind = 'Insert' AND type = 'QueryFinish'",
+        )
+        .fetch_all::<(String, String)>()
+        .await
+        .unwrap();
+    assert_eq!(settings, [("1".to_string(), "1".to_string())]);
+}
+
+#[common::named]
+#[tokio::test]
+async fn pipe_into() {
+    let client = common::prepare_database!();
+
+    for table in ["src", "dst"] {
+        client
+            .query("CREATE TABLE ?(no UInt32) ENGINE = MergeTree ORDER BY no")
+            .bind(clickhouse::sql::Identifier(table))
+            .execute()
+            .await
+            .unwrap();
+    }
+
+    let mut insert = client.insert("src").unwrap();
+    for no in 0..100_000 {
+        insert.write(&MyRow { no }).await.unwrap();
+    }
+    insert.end().await.unwrap();
+
+    let insert = client.insert::<MyRow>("dst").unwrap();
+    client
+        .query("SELECT ?fields FROM src WHERE no % 2 = 0")
+        .pipe_into(insert)
+        .await
+        .unwrap();
+
+    let (count, sum) = client
+        .query("SELECT count(), sum(no) FROM dst")
+        .fetch_one::<(u64, u64)>()
+        .await
+        .unwrap();
+    assert_eq!(count, 50_000);
+    assert_eq!(sum, (0..100_000u64).step_by(2).sum::<u64>());
+}
+
+#[common::named]
+#[tokio::test]
+async fn stream() {
+    let client = common::prepare_database!();
+
+    client
+        .query(
+            "CREATE TABLE some(no UInt32, CONSTRAINT small CHECK no < 150000)
+             ENGINE = MergeTree ORDER BY no",
+        )
+        .execute()
+        .await
+        .unwrap();
+
+    let rows = futures::stream::iter(0..100_000).map(|no| MyRow { no });
+    client.insert("some").unwrap().stream(rows).await.unwrap();
+
+    let (count, sum) = client
+        .query("SELECT count(), sum(no) FROM some")
+        .fetch_one::<(u64, u64)>()
+        .await
+        .unwrap();
+    assert_eq!(count, 100_000);
+    assert_eq!(sum, (0..100_000u64).sum::<u64>());
+
+    // The server rejects rows in the middle of the stream.
+    let rows = futures::stream::iter(100_000..1_100_000).map(|no| MyRow { no });
+    let result = client.insert("some").unwrap().stream(rows).await;
+    assert!(matches!(result, Err(Error::ServerException { .. })));
+
+    let count = client
+        .query("SELECT count() FROM some")
+        .fetch_one::<u64>()
+        .await
+        .unwrap();
+    assert_eq!(count, 100_000);
+}
+
+#[common::named]
+#[tokio::test]
+async fn stream_formatted_body() {
+    let client = common::prepare_database!();
+
+    client
+        .query("CREATE TABLE some(no UInt32, name String) ENGINE = MergeTree ORDER BY no")
+        .execute()
+        .await
+        .unwrap();
+
+    // The length is never known, so the body is sent chunked.
+    let lines = futures::stream::unfold(0u32, |no| async move {
+        (no < 100_000).then(|| (Ok::<_, Error>(format!("{no},name-{no}\n")), no + 1))
+    });
+    client
+        .query("INSERT INTO some(no, name) FORMAT CSV")
+        .execute_with_body(lines)
+        .await
+        .unwrap();
+
+    let (count, sum) = client
+        .query("SELECT count(), sum(no) FROM some")
+        .fetch_one::<(u64, u64)>()
+        .await
+        .unwrap();
+    assert_eq!(count, 100_000);
+    assert_eq!(sum, (0..100_000u64).sum::<u64>());
+}
+
+#[common::named]
+#[tokio::test]
+async fn insert_json() {
+    let client = common::prepare_database!();
+
+    client
+        .query("CREATE TABLE some(no UInt32, name String) ENGINE = MergeTree ORDER BY no")
+        .execute()
+        .await
+        .unwrap();
+
+    let rows = futures::stream::iter(0..1000)
+        .map(|no| serde_json::json!({"no": no, "name": format!("it's\n{no}")}));
+    client.insert_json("some", rows).await.unwrap();
+
+    let (count, name) = client
+        .query("SELECT count(), anyIf(name, no = 42) FROM some")
+        .fetch_one::<(u64, String)>()
+        .await
+        .unwrap();
+    assert_eq!(count, 1000);
+    assert_eq!(name, "it's\n42");
+
+    // Omitted columns get default values.
+    let rows = futures::stream::iter([serde_json::json!({"no": 1000})]);
+    client.insert_json("some", rows).await.unwrap();
+
+    let name = client
+        .query("SELECT name FROM some WHERE no = 1000")
+        .fetch_one::<String>()
+        .await
+        .unwrap();
+    assert_eq!(name, "");
+}
+
+#[common::named]
+#[tokio::test]
+async fn with_columns() {
+    let client = common::prepare_database!();
+
+    client
+        .query(
+            "CREATE TABLE wide(
+                c0 UInt32, c1 String DEFAULT 'one', c2 UInt32 DEFAULT 2, c3 UInt32 DEFAULT 3,
+                c4 String, c5 UInt32 DEFAULT 5, c6 UInt32 DEFAULT 6, c7 UInt32 DEFAULT 7,
+                c8 UInt32, c9 UInt32 DEFAULT c0 + 9
+            ) ENGINE = MergeTree ORDER BY c0",
+        )
+        .execute()
+        .await
+        .unwrap();
+
+    // Fields are matched by position, not by name.
+    #[derive(Row, Serialize)]
+    struct Narrow {
+        a: u32,
+        b: String,
+        c: u32,
+    }
+
+    let mut insert = client
+        .insert::<Narrow>("wide")
+        .unwrap()
+        .with_columns(&["c0", "c4", "c8"]);
+    for no in 0..10 {
+        let row = Narrow {
+            a: no,
+            b: format!("four-{no}"),
+            c: no * 8,
+        };
+        insert.write(&row).await.unwrap();
+    }
+    insert.end().await.unwrap();
+
+    let rows = client
+        .query("SELECT c0, c1, c2, c4, c8, c9 FROM wide ORDER BY c0")
+        .fetch_all::<(u32, String, u32, String, u32, u32)>()
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 10);
+    assert_eq!(rows[3], (3, "one".into(), 2, "four-3".into(), 24, 12));
+
+    // The number of columns must match the number of fields.
+    let mut insert = client
+        .insert::<Narrow>("wide")
+        .unwrap()
+        .with_columns(&["c0", "c4"]);
+    let row = Narrow {
+        a: 1,
+        b: "".into(),
+        c: 2,
+    };
+    let result = insert.write(&row).await;
+    assert!(matches!(result, Err(Error::InvalidParams(_))));
+}
+
+#[common::named]
+#[tokio::test]
+async fn deduplication_token() {
+    let client = common::prepare_database!();
+
+    // Non-replicated tables deduplicate only with the window set.
+    client
+        .query(
+            "CREATE TABLE test(no UInt32) ENGINE = MergeTree ORDER BY no
+             SETTINGS non_replicated_deduplication_window = 100",
+        )
+        .execute()
+        .await
+        .unwrap();
+
+    let insert_batch = |token: &'static str| {
+        let client = client.clone();
+        async move {
+            let mut insert = client
+                .insert("test")
+                .unwrap()
+                .with_deduplication_token(token);
+            for no in 0..10 {
+                insert.write(&MyRow { no }).await.unwrap();
+            }
+            insert.end().await.unwrap();
+        }
+    };
+
+    let count = || async {
+        client
+            .query("SELECT count() FROM test")
+            .fetch_one::<u64>()
+            .await
+            .unwrap()
+    };
+
+    insert_batch("batch-1").await;
+    assert_eq!(count().await, 10);
+
+    // A retry is ignored.
+    insert_batch("batch-1").await;
+    assert_eq!(count().await, 10);
+
+    // The same data with another token isn't.
+    insert_batch("batch-2").await;
+    assert_eq!(count().await, 20);
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+use clickhouse::{
+    int256::{I256, U256},
+    Row,
+};
+
+mod common;
+
+#[common::named]
+#[tokio::test]
+async fn roundtrip() {
+    let client = common::prepare_database!();
+
+    #[derive(Debug, PartialEq, Row, Serialize, Deserialize)]
+    struct MyRow {
+        no: u32,
+        i128: i128,
+        u128: u128,
+        i256: I256,
+        u256: U256,
+    }
+
+    client
+        .query(
+            "CREATE TABLE test(no UInt32, i128 Int128, u128 UInt128, i256 Int256, u256 UInt256)
+             ENGINE = MergeTree ORDER BY no",
+        )
+        .execute()
+        .await
+        .unwrap();
+
+    let original = vec![
+        MyRow {
+            no: 0,
+            i128: i128::MIN,
+            u128: u128::MIN,
+            i256: I256::from(i128::MIN),
+            u256: U256::default(),
+        },
+        MyRow {
+            no: 1,
+            i128: i128::MAX,
+            u128: u128::MAX,
+            i256: I256::from(-1),
+            u256: U256([0xff; 32]),
+        },
+    ];
+
+    let mut insert = client.insert("test").unwrap();
+    for row in &original {
+        insert.write(row).await.unwrap();
+    }
+    insert.end().await.unwrap();
+
+    let rows = client
+        .query("SELECT ?fields FROM test ORDER BY no")
+        .fetch_all::<MyRow>()
+        .await
+        .unwrap();
+    assert_eq!(rows, original);
+
+    // Check that the server agrees on the byte order.
+    let value = client
+        .query("SELECT toUInt256('340282366920938463463374607431768211714')")
+        .fetch_one::<U256>()
+        .await
+        .unwrap();
+    let mut expected = [0; 32];
+    expected[0] = 0x02;
+    expected[1] = 0x01;
+    expected[16] = 0x01;
+    assert_eq!(value.to_le_bytes(), expected);
+
+    let strings = client
+        .query("SELECT toString(i256), toString(u256) FROM test ORDER BY no")
+        .fetch_all::<(String, String)>()
+        .await
+        .unwrap();
+    let expected = original
+        .iter()
+        .map(|row| (row.i256.to_string(), row.u256.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(strings, expected);
+}
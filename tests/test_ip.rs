@@ -43,7 +43,7 @@ async fn smoke() {
         ipv6_opt: Some(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0xafc8, 0x10, 0x1)),
     };
 
-    let mut insert = client.insert().unwrap();
+    let mut insert = client.insert("test").unwrap();
     insert.write(&original_row).await.unwrap();
     insert.end().await.unwrap();
 
@@ -57,3 +57,60 @@ async fn smoke() {
     assert_eq!(row_ipv4_str, original_row.ipv4.to_string());
     assert_eq!(row_ipv6_str, original_row.ipv6.to_string());
 }
+
+#[common::named]
+#[tokio::test]
+async fn byte_order() {
+    let client = common::prepare_database!();
+
+    #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Row)]
+    struct MyRow {
+        #[serde(with = "clickhouse::serde::ipv4")]
+        ipv4: Ipv4Addr,
+        ipv6: Ipv6Addr,
+    }
+
+    client
+        .query("CREATE TABLE test(ipv4 IPv4, ipv6 IPv6) ENGINE = MergeTree ORDER BY ipv4")
+        .execute()
+        .await
+        .unwrap();
+
+    let original_row = MyRow {
+        ipv4: Ipv4Addr::new(10, 20, 30, 40),
+        ipv6: Ipv6Addr::new(0xfe80, 0, 0, 0, 0x1, 0x2, 0x3, 0x4),
+    };
+
+    let mut insert = client.insert("test").unwrap();
+    insert.write(&original_row).await.unwrap();
+    insert.end().await.unwrap();
+
+    // The server interprets the address in the same way as `std`.
+    let (ipv4_num, ipv6_hex) = client
+        .query("SELECT toUInt32(ipv4), hex(ipv6) FROM test")
+        .fetch_one::<(u32, String)>()
+        .await
+        .unwrap();
+
+    assert_eq!(ipv4_num, u32::from(original_row.ipv4));
+    assert_eq!(ipv6_hex, "FE800000000000000001000200030004");
+
+    // On the wire, `IPv4` is a little-endian `UInt32`, `IPv6` is in network byte order.
+    let raw = client
+        .query("SELECT ?fields FROM test")
+        .fetch_raw::<MyRow>()
+        .await
+        .unwrap();
+
+    let mut expected = vec![40, 30, 20, 10];
+    expected.extend(original_row.ipv6.octets());
+    assert_eq!(raw, expected);
+
+    let row = client
+        .query("SELECT ?fields FROM test")
+        .fetch_one::<MyRow>()
+        .await
+        .unwrap();
+
+    assert_eq!(row, original_row);
+}
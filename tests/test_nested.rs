@@ -41,7 +41,7 @@ async fn smoke() {
         items_count: vec![1, 5],
     };
 
-    let mut insert = client.insert().unwrap();
+    let mut insert = client.insert("test").unwrap();
     insert.write(&original_row).await.unwrap();
     insert.end().await.unwrap();
 
@@ -53,3 +53,193 @@ async fn smoke() {
 
     assert_eq!(row, original_row);
 }
+
+#[common::named]
+#[tokio::test]
+async fn insert_from_structs() {
+    let client = common::prepare_database!();
+
+    #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Row)]
+    struct Item {
+        name: String,
+        count: u32,
+    }
+
+    #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Row)]
+    struct MyRow {
+        no: i32,
+        #[serde(serialize_with = "clickhouse::serde::nested::serialize")]
+        items: Vec<Item>,
+    }
+
+    client
+        .query(
+            "
+        CREATE TABLE test(
+            no      Int32,
+            items   Nested(
+                name    String,
+                count   UInt32
+            )
+        )
+        ENGINE = MergeTree ORDER BY no
+    ",
+        )
+        .execute()
+        .await
+        .unwrap();
+
+    let original_rows = vec![
+        MyRow {
+            no: 1,
+            items: vec![
+                Item {
+                    name: "foo".into(),
+                    count: 1,
+                },
+                Item {
+                    name: "bar".into(),
+                    count: 5,
+                },
+            ],
+        },
+        MyRow {
+            no: 2,
+            items: Vec::new(),
+        },
+    ];
+
+    let mut insert = client.insert("test").unwrap();
+    for row in &original_rows {
+        insert.write(row).await.unwrap();
+    }
+    insert.end().await.unwrap();
+
+    let rows = client
+        .query("SELECT no, arrayZip(items.name, items.count) FROM test ORDER BY no")
+        .fetch_all::<MyRow>()
+        .await
+        .unwrap();
+
+    assert_eq!(rows, original_rows);
+}
+
+#[common::named]
+#[tokio::test]
+async fn fetch_into_structs() {
+    let client = common::prepare_database!();
+
+    #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Row)]
+    struct Item {
+        name: String,
+        count: u64,
+    }
+
+    #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Row)]
+    struct MyRow {
+        no: i32,
+        #[serde(with = "clickhouse::serde::nested")]
+        items: Vec<Item>,
+    }
+
+    client
+        .query(
+            "
+        CREATE TABLE test(
+            no      Int32,
+            items   Nested(
+                name    String,
+                count   UInt64
+            )
+        )
+        ENGINE = MergeTree ORDER BY no
+    ",
+        )
+        .execute()
+        .await
+        .unwrap();
+
+    client
+        .query("INSERT INTO test VALUES (1, ['foo', 'bar'], [1, 5]), (2, [], [])")
+        .execute()
+        .await
+        .unwrap();
+
+    let rows = client
+        .query("SELECT no, items.name, items.count FROM test ORDER BY no")
+        .fetch_all::<MyRow>()
+        .await
+        .unwrap();
+
+    let expected = vec![
+        MyRow {
+            no: 1,
+            items: vec![
+                Item {
+                    name: "foo".into(),
+                    count: 1,
+                },
+                Item {
+                    name: "bar".into(),
+                    count: 5,
+                },
+            ],
+        },
+        MyRow {
+            no: 2,
+            items: Vec::new(),
+        },
+    ];
+    assert_eq!(rows, expected);
+
+    // Written by the same struct.
+    let mut insert = client.insert("test").unwrap();
+    insert.write(&expected[0]).await.unwrap();
+    insert.end().await.unwrap();
+
+    let rows = client
+        .query("SELECT no, items.name, items.count FROM test WHERE no = 1")
+        .fetch_all::<MyRow>()
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+    assert!(rows.iter().all(|row| *row == expected[0]));
+}
+
+#[common::named]
+#[tokio::test]
+async fn fetch_ragged_arrays() {
+    let client = common::prepare_database!();
+
+    #[derive(Debug, Deserialize, Row)]
+    #[allow(dead_code)]
+    struct Item {
+        name: String,
+        count: u64,
+    }
+
+    #[derive(Debug, Deserialize, Row)]
+    #[allow(dead_code)]
+    struct MyRow {
+        no: i32,
+        #[serde(with = "clickhouse::serde::nested")]
+        items: Vec<Item>,
+    }
+
+    // Plain arrays can have any lengths, unlike subcolumns of `Nested`.
+    let err = client
+        .query(
+            "SELECT toInt32(1) AS no, ['foo', 'bar'] AS `items.name`, \
+             [toUInt64(1)] AS `items.count`",
+        )
+        .fetch_all::<MyRow>()
+        .await
+        .unwrap_err();
+
+    let message = err.to_string();
+    assert!(
+        message.contains("`count` has 1 values, but `name` has 2"),
+        "{}",
+        message
+    );
+}
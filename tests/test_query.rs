@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use clickhouse::{error::Error, Row};
+use clickhouse::{error::Error, query::ExplainKind, Row};
 
 mod common;
 
@@ -29,7 +29,7 @@ async fn smoke() {
         .unwrap();
 
     // Write to the table.
-    let mut insert = client.insert().unwrap();
+    let mut insert = client.insert("test").unwrap();
     for i in 0..1000 {
         insert.write(&MyRow { no: i, name: "foo" }).await.unwrap();
     }
@@ -183,3 +183,1065 @@ async fn all_floats() {
 
     assert_eq!(vec, &[42.5, 43.5]);
 }
+
+#[common::named]
+#[tokio::test]
+async fn response_headers() {
+    let client = common::prepare_database!();
+
+    let mut cursor = client
+        .query("SELECT number FROM system.numbers LIMIT 3")
+        .fetch::<u64>()
+        .unwrap();
+
+    let query_id = cursor
+        .headers()
+        .await
+        .unwrap()
+        .get("X-ClickHouse-Query-Id")
+        .expect("no query id")
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    assert!(!query_id.is_empty());
+
+    // Reading headers doesn't consume the body.
+    let mut rows = Vec::new();
+    while let Some(no) = cursor.next().await.unwrap() {
+        rows.push(no);
+    }
+
+    assert_eq!(rows, [0, 1, 2]);
+}
+
+#[common::named]
+#[tokio::test]
+async fn fetch_named_rows() {
+    let client = common::prepare_database!();
+
+    let mut cursor = client
+        .query(
+            "SELECT number AS no, toString(number) AS name, [number, NULL] AS arr
+             FROM system.numbers LIMIT 2",
+        )
+        .fetch_named_rows()
+        .unwrap();
+
+    let mut rows = Vec::new();
+    while let Some(row) = cursor.next().await.unwrap() {
+        rows.push(row);
+    }
+
+    assert_eq!(
+        rows,
+        vec![
+            vec![
+                ("no".to_string(), serde_json::json!(0)),
+                ("name".to_string(), serde_json::json!("0")),
+                ("arr".to_string(), serde_json::json!([0, null])),
+            ],
+            vec![
+                ("no".to_string(), serde_json::json!(1)),
+                ("name".to_string(), serde_json::json!("1")),
+                ("arr".to_string(), serde_json::json!([1, null])),
+            ],
+        ]
+    );
+
+    // The header is sent even if there are no rows.
+    let mut cursor = client
+        .query("SELECT number FROM system.numbers LIMIT 0")
+        .fetch_named_rows()
+        .unwrap();
+
+    assert_eq!(cursor.next().await.unwrap(), None);
+}
+
+#[common::named]
+#[tokio::test]
+async fn generate() {
+    let client = common::prepare_database!();
+
+    #[derive(Debug, PartialEq, Row, Deserialize)]
+    struct MyRow {
+        no: u32,
+        name: String,
+        opt: Option<i8>,
+        arr: Vec<u64>,
+    }
+
+    let mut cursor = client.generate::<MyRow>(300).unwrap();
+
+    let mut i = 0u64;
+    while let Some(row) = cursor.next().await.unwrap() {
+        assert_eq!(
+            row,
+            MyRow {
+                no: i as u32,
+                name: i.to_string(),
+                opt: Some(i as i8),
+                arr: vec![i],
+            }
+        );
+        i += 1;
+    }
+
+    assert_eq!(i, 300);
+}
+
+#[common::named]
+#[tokio::test]
+async fn fetch_with_summary() {
+    let client = common::prepare_database!();
+
+    let (rows, summary) = client
+        .query("SELECT number FROM system.numbers LIMIT 3")
+        .fetch_all_with_summary::<u64>()
+        .await
+        .unwrap();
+
+    assert_eq!(rows, vec![0, 1, 2]);
+    assert!(summary.read_rows >= 3);
+    assert!(summary.read_bytes >= 3 * 8);
+
+    let (row, summary) = client
+        .query("SELECT number FROM system.numbers LIMIT 1")
+        .fetch_optional_with_summary::<u64>()
+        .await
+        .unwrap();
+
+    assert_eq!(row, Some(0));
+    assert!(summary.read_rows >= 1);
+
+    let (row, summary) = client
+        .query("SELECT number FROM system.numbers LIMIT 0")
+        .fetch_optional_with_summary::<u64>()
+        .await
+        .unwrap();
+
+    assert_eq!(row, None);
+    assert_eq!(summary.result_rows, 0);
+}
+
+#[common::named]
+#[tokio::test]
+async fn session() {
+    let client = common::prepare_database!()
+        .with_session(function_name!(), std::time::Duration::from_secs(60));
+
+    #[derive(Debug, PartialEq, Row, Serialize, Deserialize)]
+    struct MyRow {
+        no: u32,
+    }
+
+    // Temporary tables live only in the session.
+    client
+        .query("CREATE TEMPORARY TABLE test(no UInt32)")
+        .execute()
+        .await
+        .unwrap();
+
+    let mut insert = client.insert("test").unwrap();
+    insert.write(&MyRow { no: 42 }).await.unwrap();
+    insert.end().await.unwrap();
+
+    let rows = client
+        .query("SELECT ?fields FROM test")
+        .fetch_all::<MyRow>()
+        .await
+        .unwrap();
+
+    assert_eq!(rows, vec![MyRow { no: 42 }]);
+}
+
+#[common::named]
+#[tokio::test]
+async fn duplicate_columns() {
+    let client = common::prepare_database!();
+
+    // Positional decoding doesn't care about names.
+    let row = client
+        .query("SELECT number, number FROM system.numbers LIMIT 1")
+        .fetch_one::<(u64, u64)>()
+        .await
+        .unwrap();
+    assert_eq!(row, (0, 0));
+
+    // Name-based decoding is ambiguous.
+    let err = client
+        .query("SELECT number, number FROM system.numbers LIMIT 1")
+        .fetch_named_rows()
+        .unwrap()
+        .next()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::DuplicateColumn(name) if name == "number"));
+}
+
+#[common::named]
+#[tokio::test]
+async fn execute_batch() {
+    use clickhouse::query::OnError;
+
+    let client = common::prepare_database!();
+
+    let statements = (0..10)
+        .map(|i| format!("CREATE TABLE test_{i}(no UInt32) ENGINE = MergeTree ORDER BY no"))
+        .collect::<Vec<_>>();
+    let statements = statements.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let results = client.execute_batch(&statements, OnError::Stop).await;
+    assert_eq!(results.len(), 10);
+    assert!(results.iter().all(Result::is_ok));
+
+    let count = client
+        .query("SELECT count() FROM system.tables WHERE database = currentDatabase()")
+        .fetch_one::<u64>()
+        .await
+        .unwrap();
+    assert_eq!(count, 10);
+
+    // Tables already exist, so the first and the last statements fail.
+    let batch = [
+        statements[0],
+        "CREATE TABLE test_ok(no UInt32) ENGINE = MergeTree ORDER BY no",
+        statements[9],
+    ];
+
+    let results = client.execute_batch(&batch, OnError::Continue).await;
+    assert_eq!(results.len(), 3);
+    assert!(matches!(results[0], Err(Error::ServerException { .. })));
+    assert!(results[1].is_ok());
+    assert!(matches!(results[2], Err(Error::ServerException { .. })));
+
+    let results = client.execute_batch(&batch, OnError::Stop).await;
+    assert_eq!(results.len(), 1);
+    assert!(matches!(results[0], Err(Error::ServerException { .. })));
+}
+
+#[common::named]
+#[tokio::test]
+async fn fetch_row_count() {
+    let client = common::prepare_database!();
+
+    #[derive(Row, Serialize)]
+    struct MyRow<'a> {
+        no: u32,
+        name: &'a str,
+    }
+
+    client
+        .query("CREATE TABLE test(no UInt32, name String) ENGINE = MergeTree ORDER BY no")
+        .execute()
+        .await
+        .unwrap();
+
+    let mut insert = client.insert("test").unwrap();
+    for no in 0..10 {
+        let name = if no % 2 == 0 { "even" } else { "odd" };
+        insert.write(&MyRow { no, name }).await.unwrap();
+    }
+    insert.end().await.unwrap();
+
+    let count = client.query_row_count("SELECT * FROM test").await.unwrap();
+    assert_eq!(count, 10);
+
+    let count = client
+        .query("SELECT no FROM test WHERE name = ? AND no > ?")
+        .bind("odd")
+        .bind(4)
+        .fetch_row_count()
+        .await
+        .unwrap();
+    assert_eq!(count, 3);
+
+    // The query isn't simply prefixed with `count()`: grouping is preserved.
+    let count = client
+        .query_row_count("SELECT name FROM test GROUP BY name -- names")
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+}
+
+#[common::named]
+#[tokio::test]
+async fn fetch_dynamic() {
+    let client = common::prepare_database!();
+
+    let mut cursor = client
+        .query(
+            "SELECT
+                toUInt64(18446744073709551615) AS big,
+                toInt8(-5) AS small,
+                'foo' AS name,
+                1.5 AS ratio,
+                toDecimal64(1.23, 2) AS price,
+                [1, 2]::Array(UInt8) AS tags,
+                NULL::Nullable(UInt8) AS none,
+                map('a', 1) AS attrs",
+        )
+        .fetch_dynamic()
+        .unwrap();
+
+    let row = cursor.next().await.unwrap().unwrap();
+    assert_eq!(row["big"], "18446744073709551615");
+    assert_eq!(row["small"], -5);
+    assert_eq!(row["name"], "foo");
+    assert_eq!(row["ratio"], 1.5);
+    assert_eq!(row["price"], "1.23");
+    assert_eq!(row["tags"], serde_json::json!([1, 2]));
+    assert!(row["none"].is_null());
+    assert_eq!(row["attrs"]["a"], 1);
+    assert!(cursor.next().await.unwrap().is_none());
+}
+
+#[common::named]
+#[tokio::test]
+async fn fetch_scalar() {
+    let client = common::prepare_database!();
+
+    let count = client
+        .query("SELECT count() FROM system.numbers_mt WHERE number < 1000")
+        .fetch_scalar::<u64>()
+        .await
+        .unwrap();
+    assert_eq!(count, 1000);
+
+    let name = client
+        .query("SELECT ?")
+        .bind("foo")
+        .fetch_scalar::<String>()
+        .await
+        .unwrap();
+    assert_eq!(name, "foo");
+
+    let result = client
+        .query("SELECT number FROM system.numbers LIMIT 0")
+        .fetch_scalar::<u64>()
+        .await;
+    assert!(matches!(result, Err(Error::RowNotFound)));
+
+    let result = client
+        .query("SELECT number FROM system.numbers LIMIT 2")
+        .fetch_scalar::<u64>()
+        .await;
+    assert!(matches!(result, Err(Error::TooManyRows)));
+
+    // The column count is checked even if rows could be decoded as `T`.
+    let result = client
+        .query("SELECT 1::UInt64, 2::UInt64")
+        .fetch_scalar::<u64>()
+        .await;
+    assert!(matches!(result, Err(Error::UnexpectedColumnCount(2))));
+}
+
+#[common::named]
+#[tokio::test]
+async fn enum_labels() {
+    use clickhouse::enum_labels::EnumLabels;
+
+    let client = common::prepare_database!();
+
+    let (value, ty) = client
+        .query("SELECT CAST('b', 'Enum8(\\'a\\' = 1, \\'b\\' = 2)') AS e, toTypeName(e)")
+        .fetch_one::<(i8, String)>()
+        .await
+        .unwrap();
+
+    let labels = EnumLabels::parse(&ty).unwrap();
+    assert_eq!(labels.label(value.into()), Some("b"));
+}
+
+#[common::named]
+#[tokio::test]
+async fn in_list() {
+    use clickhouse::sql::InList;
+
+    let client = common::prepare_database!();
+
+    let ids: Vec<u64> = vec![1, 5, 7];
+    let numbers = client
+        .query("SELECT number FROM system.numbers WHERE number IN ? LIMIT 10")
+        .bind(InList(&ids))
+        .fetch_all::<u64>()
+        .await
+        .unwrap();
+    assert_eq!(numbers, ids);
+
+    let names = ["it's", "a \\ b", "c?"];
+    let count = client
+        .query("SELECT count() FROM (SELECT arrayJoin(?) AS name) WHERE name IN ?")
+        .bind(&names[..])
+        .bind(InList(&names[..2]))
+        .fetch_scalar::<u64>()
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+
+    // An empty list never matches.
+    let count = client
+        .query("SELECT count() FROM (SELECT 1 AS no) WHERE no IN ?")
+        .bind(InList::<u64>(&[]))
+        .fetch_scalar::<u64>()
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[common::named]
+#[tokio::test]
+async fn last_row_bytes() {
+    let client = common::prepare_database!();
+
+    #[derive(Debug, Row, Deserialize)]
+    struct MyRow {
+        no: u64,
+        name: String,
+    }
+
+    let sql = "SELECT number AS no, repeat('x', number % 100) AS name
+               FROM system.numbers LIMIT 10000";
+
+    let mut cursor = client.query(sql).fetch::<MyRow>().unwrap();
+    assert_eq!(cursor.last_row_bytes(), 0);
+
+    let mut total = 0;
+    while let Some(row) = cursor.next().await.unwrap() {
+        assert_eq!(row.name.len() as u64, row.no % 100);
+        // 8 bytes of `no`, LEB128 length and bytes of `name`.
+        assert_eq!(cursor.last_row_bytes(), 8 + 1 + row.name.len());
+        total += cursor.last_row_bytes();
+    }
+
+    let raw = client.query(sql).fetch_raw::<MyRow>().await.unwrap();
+    assert_eq!(total, raw.len());
+}
+
+#[common::named]
+#[tokio::test]
+async fn with_database() {
+    let client = common::prepare_database!();
+
+    let current = || client.query("SELECT currentDatabase()");
+
+    let db = current().fetch_scalar::<String>().await.unwrap();
+    assert_eq!(db, "test_query__with_database");
+
+    let db = current()
+        .with_database("system")
+        .fetch_scalar::<String>()
+        .await
+        .unwrap();
+    assert_eq!(db, "system");
+
+    // An empty name means the server's default.
+    let db = current()
+        .with_database("")
+        .fetch_scalar::<String>()
+        .await
+        .unwrap();
+    assert_eq!(db, "default");
+
+    // The client's database isn't changed.
+    let db = current().fetch_scalar::<String>().await.unwrap();
+    assert_eq!(db, "test_query__with_database");
+}
+
+#[common::named]
+#[tokio::test]
+async fn cursor_as_stream() {
+    use futures::{StreamExt, TryStreamExt};
+
+    let client = common::prepare_database!();
+
+    #[derive(Debug, Row, Deserialize)]
+    struct MyRow {
+        no: u64,
+        name: String,
+    }
+
+    let query = || client.query("SELECT number, toString(number) FROM system.numbers LIMIT 2500");
+
+    let rows = query()
+        .fetch::<MyRow>()
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 2500);
+    assert!(rows.iter().enumerate().all(|(i, row)| row.no == i as u64));
+
+    let chunks = query()
+        .fetch::<MyRow>()
+        .unwrap()
+        .map(|row| row.unwrap().name)
+        .chunks(1000)
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(
+        chunks.iter().map(Vec::len).collect::<Vec<_>>(),
+        vec![1000, 1000, 500]
+    );
+    assert_eq!(chunks[2][499], "2499");
+
+    // Errors are emitted as items.
+    let result = client
+        .query("SELECT throwIf(number = 10) FROM system.numbers")
+        .fetch::<u8>()
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await;
+    assert!(matches!(result, Err(Error::ServerException { .. })));
+}
+
+#[common::named]
+#[tokio::test]
+async fn fetch_raw_bytes() {
+    let client = common::prepare_database!();
+
+    let bytes = client
+        .query("SELECT number AS no FROM system.numbers LIMIT 3 FORMAT JSON")
+        .fetch_raw_bytes()
+        .await
+        .unwrap();
+
+    let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(json["rows"], 3);
+    assert_eq!(
+        json["data"],
+        serde_json::json!([{"no": "0"}, {"no": "1"}, {"no": "2"}])
+    );
+
+    // Errors are detected even in other formats.
+    let result = client
+        .query("SELECT unknown_column FORMAT JSON")
+        .fetch_raw_bytes()
+        .await;
+    assert!(matches!(result, Err(Error::ServerException { .. })));
+}
+
+#[common::named]
+#[tokio::test]
+async fn kill_query() {
+    let client = common::prepare_database!();
+    let query_id = format!("{}-{}", function_name!(), std::process::id());
+
+    // Lasts for 30s unless cancelled.
+    let query = client
+        .query("SELECT sleepEachRow(0.5) FROM numbers(60) SETTINGS max_block_size = 1")
+        .with_query_id(&query_id);
+
+    let started_at = std::time::Instant::now();
+    let fetching = tokio::spawn(async move { query.fetch_all::<u8>().await });
+
+    // Wait for the query to reach the server.
+    let mut found = false;
+    while !found && started_at.elapsed() < std::time::Duration::from_secs(10) {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        found = client.kill_query(&query_id).await.unwrap();
+    }
+    assert!(found);
+
+    let result = fetching.await.unwrap();
+    assert!(matches!(result, Err(Error::ServerException { .. })));
+    assert!(started_at.elapsed() < std::time::Duration::from_secs(15));
+
+    // The query is already gone.
+    assert!(!client.kill_query(&query_id).await.unwrap());
+}
+
+#[common::named]
+#[tokio::test]
+async fn cancel_cursor() {
+    let client = common::prepare_database!();
+
+    let started_at = std::time::Instant::now();
+    let mut cursor = client
+        .query("SELECT sleepEachRow(0.5) FROM numbers(60) SETTINGS max_block_size = 1")
+        .fetch::<u8>()
+        .unwrap();
+
+    // The query id is taken from response headers.
+    assert_eq!(cursor.next().await.unwrap(), Some(0));
+    assert!(cursor.cancel().await.unwrap());
+    assert!(started_at.elapsed() < std::time::Duration::from_secs(15));
+}
+
+#[tokio::test]
+async fn ping() {
+    // The database isn't created, because it's not required.
+    let client = clickhouse::Client::default()
+        .with_url("http://localhost:8123")
+        .with_database("nonexistent_database");
+    client.ping().await.unwrap();
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let client = clickhouse::Client::default().with_url(format!("http://{addr}"));
+    let err = client.ping().await.unwrap_err();
+    assert!(matches!(err, Error::Network(_)));
+}
+
+#[common::named]
+#[tokio::test]
+async fn odd_identifiers() {
+    let client = common::prepare_database!();
+    let table = "we`ird.na\\me";
+
+    client
+        .query("CREATE TABLE ?(no UInt32) ENGINE = MergeTree ORDER BY no")
+        .bind(clickhouse::sql::Identifier(table))
+        .execute()
+        .await
+        .unwrap();
+
+    let name = client
+        .query("SELECT name FROM system.tables WHERE database = currentDatabase()")
+        .fetch_one::<String>()
+        .await
+        .unwrap();
+    assert_eq!(name, table);
+
+    let count = client
+        .query("SELECT count() FROM ?")
+        .bind(clickhouse::sql::Identifier(table))
+        .fetch_one::<u64>()
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[common::named]
+#[tokio::test]
+async fn tricky_strings() {
+    let client = common::prepare_database!();
+
+    let values = [
+        "'",
+        "\\",
+        "\\'",
+        "'; SELECT 1; --",
+        "nul\0byte",
+        "new\nline\r\n",
+        "tab\tbed",
+        "\\n is not a newline",
+        "`\"?",
+        "unicode: ü, 日本, 🦀",
+    ];
+
+    for value in values {
+        let (actual, length) = client
+            .query("SELECT ? AS s, length(s)")
+            .bind(value)
+            .fetch_one::<(String, u64)>()
+            .await
+            .unwrap();
+        assert_eq!(actual, value);
+        assert_eq!(length, value.len() as u64);
+    }
+}
+
+#[common::named]
+#[tokio::test]
+async fn totals_and_extremes() {
+    let client = common::prepare_database!();
+
+    // RowBinary has no totals and extremes, so only rows are sent.
+    let rows = client
+        .query(
+            "SELECT number % 2 AS parity, count()
+             FROM numbers(10) GROUP BY parity WITH TOTALS ORDER BY parity",
+        )
+        .with_option("extremes", "1")
+        .fetch_all::<(u64, u64)>()
+        .await
+        .unwrap();
+    assert_eq!(rows, [(0, 5), (1, 5)]);
+
+    // Totals are ordinary rows with `WITH ROLLUP`.
+    let rows = client
+        .query(
+            "SELECT number % 2 AS parity, count() AS count
+             FROM numbers(10) GROUP BY parity WITH ROLLUP ORDER BY count, parity",
+        )
+        .fetch_all::<(u64, u64)>()
+        .await
+        .unwrap();
+    assert_eq!(rows, [(0, 5), (1, 5), (0, 10)]);
+}
+
+#[common::named]
+#[tokio::test]
+async fn settings_in_query() {
+    let comment = "it's `odd`\n".repeat(1000);
+    let client = common::prepare_database!()
+        .with_option("log_comment", &comment)
+        .with_option("max_threads", "3")
+        .with_settings_in_query(true);
+
+    let (actual_comment, max_threads) = client
+        .query("SELECT getSetting('log_comment'), toUInt64(getSetting('max_threads'))")
+        .fetch_one::<(String, u64)>()
+        .await
+        .unwrap();
+    assert_eq!(actual_comment, comment);
+    assert_eq!(max_threads, 3);
+}
+
+#[common::named]
+#[tokio::test]
+async fn booleans() {
+    let client = common::prepare_database!();
+
+    let flags = client
+        .query("SELECT true, false, toUInt8(2), toUInt8(0)")
+        .fetch_one::<(bool, bool, bool, bool)>()
+        .await
+        .unwrap();
+    assert_eq!(flags, (true, false, true, false));
+}
+
+#[common::named]
+#[tokio::test]
+async fn shutdown() {
+    let client = common::prepare_database!();
+    let other = client.clone();
+
+    let value = client.query("SELECT 42").fetch_one::<u8>().await.unwrap();
+    assert_eq!(value, 42);
+
+    client
+        .shutdown(std::time::Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    let err = other.query("SELECT 42").execute().await.unwrap_err();
+    assert!(matches!(err, Error::Shutdown));
+}
+
+#[common::named]
+#[tokio::test]
+async fn fetch_all_split() {
+    use std::collections::HashSet;
+
+    let client = common::prepare_database!();
+
+    let sql = "SELECT number FROM system.numbers WHERE number % ? != 0 AND number BETWEEN ? AND ? LIMIT 1000";
+    let expected = client
+        .query(sql)
+        .bind(3)
+        .bind(10)
+        .bind(999)
+        .fetch_all::<u64>()
+        .await
+        .unwrap()
+        .into_iter()
+        .collect::<HashSet<_>>();
+    assert_eq!(expected.len(), 660);
+
+    // Including a non-divisible split and more shards than numbers.
+    for shards in [1, 7, 10_000] {
+        let actual = client
+            .query(sql)
+            .bind(3)
+            .fetch_all_split::<u64>(10..1000, shards)
+            .await
+            .unwrap();
+        assert_eq!(actual.len(), expected.len());
+        assert_eq!(actual.into_iter().collect::<HashSet<_>>(), expected);
+    }
+}
+
+#[common::named]
+#[tokio::test]
+async fn empty_result() {
+    let client = common::prepare_database!();
+
+    let sql = "SELECT number FROM system.numbers LIMIT 0";
+
+    let err = client.query(sql).fetch_one::<u64>().await.unwrap_err();
+    assert!(matches!(err, Error::RowNotFound));
+
+    let row = client.query(sql).fetch_optional::<u64>().await.unwrap();
+    assert_eq!(row, None);
+
+    // The last row is cut, because `UInt32` is wider than `UInt8`.
+    let err = client
+        .query("SELECT toUInt8(1)")
+        .fetch_one::<u32>()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::NotEnoughData));
+}
+
+#[common::named]
+#[tokio::test]
+async fn fetch_csv() {
+    use futures::TryStreamExt;
+
+    // Counts rows, skipping line breaks inside quoted fields.
+    fn count_rows(csv: &[u8]) -> usize {
+        let mut quoted = false;
+        let mut rows = 0;
+        for &byte in csv {
+            match byte {
+                b'"' => quoted = !quoted,
+                b'\n' if !quoted => rows += 1,
+                _ => {}
+            }
+        }
+        rows
+    }
+
+    let client = common::prepare_database!();
+
+    let sql = "SELECT number AS no, concat('a\nb,', toString(number)) AS name \
+               FROM system.numbers LIMIT 1000";
+
+    let rows = client
+        .query(sql)
+        .fetch_all::<(u64, String)>()
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1000);
+
+    let mut csv = Vec::new();
+    let mut chunks = client.query(sql).fetch_csv(false).unwrap();
+    while let Some(chunk) = chunks.try_next().await.unwrap() {
+        csv.extend_from_slice(&chunk);
+    }
+    assert_eq!(count_rows(&csv), rows.len());
+    assert!(csv.starts_with(b"0,\"a\nb,0\"\n"));
+
+    let csv = client
+        .query(sql)
+        .fetch_csv(true)
+        .unwrap()
+        .try_fold(Vec::new(), |mut acc, chunk| async move {
+            acc.extend_from_slice(&chunk);
+            Ok(acc)
+        })
+        .await
+        .unwrap();
+    assert_eq!(count_rows(&csv), rows.len() + 1);
+    assert!(csv.starts_with(b"\"no\",\"name\"\n"));
+
+    // Errors are detected even in CSV.
+    let result = client
+        .query("SELECT unknown_column")
+        .fetch_csv(false)
+        .unwrap()
+        .try_next()
+        .await;
+    assert!(matches!(result, Err(Error::ServerException { .. })));
+}
+
+#[common::named]
+#[tokio::test]
+async fn max_result_bytes() {
+    let client = common::prepare_database!().with_max_result_bytes(1024);
+
+    let err = client
+        .query("SELECT number FROM system.numbers LIMIT 1000000")
+        .fetch_all::<u64>()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::ResultTooLarge(1024)), "{:?}", err);
+
+    let rows = client
+        .query("SELECT number FROM system.numbers LIMIT 100")
+        .fetch_all::<u64>()
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 100);
+}
+
+#[common::named]
+#[tokio::test]
+async fn fetch_all_with_count() {
+    let client = common::prepare_database!();
+
+    let (rows, count) = client
+        .query("SELECT number FROM system.numbers LIMIT 5")
+        .fetch_all_with_count::<u64>()
+        .await
+        .unwrap();
+
+    assert_eq!(rows, vec![0, 1, 2, 3, 4]);
+    assert_eq!(count, rows.len() as u64);
+}
+
+#[common::named]
+#[tokio::test]
+async fn execute_all() {
+    use clickhouse::query::OnError;
+
+    let client = common::prepare_database!();
+
+    // The `ALTER` depends on the `CREATE`, so they must be executed in order.
+    let statements = [
+        "CREATE TABLE test(no UInt32) ENGINE = MergeTree ORDER BY no",
+        "ALTER TABLE test ADD COLUMN name String",
+        "ALTER TABLE unknown ADD COLUMN name String",
+        "ALTER TABLE test ADD COLUMN size UInt64",
+    ];
+
+    let results = client.execute_all(&statements, OnError::Stop).await;
+    assert_eq!(results.len(), 3);
+    assert!(results[0].is_ok());
+    assert!(results[1].is_ok());
+    assert!(matches!(results[2], Err(Error::ServerException { .. })));
+
+    let columns = client
+        .query("SELECT count() FROM system.columns WHERE database = currentDatabase()")
+        .fetch_one::<u64>()
+        .await
+        .unwrap();
+    assert_eq!(columns, 2);
+
+    let statements = [
+        "ALTER TABLE test DROP COLUMN name",
+        "ALTER TABLE unknown ADD COLUMN name String",
+        "ALTER TABLE test ADD COLUMN size UInt64",
+    ];
+
+    let results = client.execute_all(&statements, OnError::Continue).await;
+    assert_eq!(results.len(), 3);
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(Error::ServerException { .. })));
+    assert!(results[2].is_ok());
+
+    let columns = client
+        .query(
+            "SELECT name FROM system.columns WHERE database = currentDatabase() ORDER BY position",
+        )
+        .fetch_all::<String>()
+        .await
+        .unwrap();
+    assert_eq!(columns, ["no", "size"]);
+}
+
+#[common::named]
+#[tokio::test]
+async fn next_block() {
+    let client = common::prepare_database!();
+
+    let sql = "SELECT number FROM system.numbers LIMIT 10000";
+    let mut cursor = client
+        .query(sql)
+        .with_max_block_size(100)
+        .fetch::<u64>()
+        .unwrap();
+
+    let mut blocks = Vec::new();
+    while let Some(block) = cursor.next_block().await.unwrap() {
+        assert!(!block.is_empty());
+        blocks.push(block);
+    }
+
+    let rows = client.query(sql).fetch_all::<u64>().await.unwrap();
+    assert!(blocks.len() > 1);
+    assert_eq!(blocks.concat(), rows);
+}
+
+#[cfg(feature = "arrow")]
+#[common::named]
+#[tokio::test]
+async fn fetch_arrow() {
+    use arrow_array::{Array, StringArray, UInt64Array};
+    use futures::TryStreamExt;
+
+    let client = common::prepare_database!();
+
+    let batches = client
+        .query("SELECT number, toString(number) AS name FROM system.numbers LIMIT 100000")
+        .with_option("output_format_arrow_string_as_string", "1")
+        .with_max_block_size(30000)
+        .fetch_arrow()
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+
+    assert!(batches.len() > 1);
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 100_000);
+
+    let schema = batches[0].schema();
+    assert_eq!(schema.field(0).name(), "number");
+    assert_eq!(schema.field(1).name(), "name");
+
+    let numbers = batches[0]
+        .column(0)
+        .as_any()
+        .downcast_ref::<UInt64Array>()
+        .unwrap();
+    let names = batches[0]
+        .column(1)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    assert_eq!(numbers.value(1), 1);
+    assert_eq!(names.value(1), "1");
+    assert!(!numbers.is_empty());
+}
+
+#[common::named]
+#[tokio::test]
+async fn explain() {
+    let client = common::prepare_database!();
+
+    client
+        .query("CREATE TABLE test(no UInt32, name String) ENGINE = MergeTree ORDER BY no")
+        .execute()
+        .await
+        .unwrap();
+
+    let plan = client
+        .query("SELECT name FROM test WHERE no > ?")
+        .bind(5)
+        .explain(ExplainKind::Plan)
+        .await
+        .unwrap();
+    assert!(plan.contains("Expression"), "{}", plan);
+    assert!(plan.contains("ReadFromMergeTree"), "{}", plan);
+
+    let syntax = client
+        .query("SELECT name FROM test WHERE no > ?")
+        .bind(5)
+        .explain(ExplainKind::Syntax)
+        .await
+        .unwrap();
+    assert!(syntax.contains("no > 5"), "{}", syntax);
+}
+
+#[common::named]
+#[tokio::test]
+async fn schema_validation() {
+    let client = common::prepare_database!();
+
+    #[derive(Debug, Row, Deserialize)]
+    #[allow(dead_code)]
+    struct MyRow {
+        no: u32,
+        name: String,
+    }
+
+    let err = client
+        .query("SELECT 'foo' AS name, 42::UInt32 AS no")
+        .with_schema_validation(true)
+        .fetch_one::<MyRow>()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::SchemaMismatch { .. }), "{:?}", err);
+
+    let row = client
+        .query("SELECT 42::UInt32 AS no, 'foo' AS name")
+        .with_schema_validation(true)
+        .fetch_one::<MyRow>()
+        .await
+        .unwrap();
+    assert_eq!((row.no, row.name.as_str()), (42, "foo"));
+}
@@ -116,6 +116,53 @@ async fn datetime() {
     assert_eq!(row_str.dt64ns, &original_row.dt64ns.to_string()[..29]);
 }
 
+#[common::named]
+#[tokio::test]
+async fn datetime64_in_zone() {
+    use clickhouse::serde::time::datetime64::{self, TimeZone};
+    use time::{macros::offset, UtcOffset};
+
+    let client = common::prepare_database!();
+
+    struct Tokyo;
+
+    impl TimeZone for Tokyo {
+        fn offset_at(_utc: OffsetDateTime) -> UtcOffset {
+            offset!(+9)
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Row)]
+    struct MyRow {
+        #[serde(
+            serialize_with = "datetime64::millis::serialize",
+            deserialize_with = "datetime64::millis::deserialize_in::<Tokyo, _>"
+        )]
+        dt: OffsetDateTime,
+    }
+
+    client
+        .query("CREATE TABLE test(dt DateTime64(3, 'Asia/Tokyo')) ENGINE = MergeTree ORDER BY dt")
+        .execute()
+        .await
+        .unwrap();
+
+    let mut insert = client.insert("test").unwrap();
+    let dt = datetime!(2024-01-01 00:00:00.123 UTC);
+    insert.write(&MyRow { dt }).await.unwrap();
+    insert.end().await.unwrap();
+
+    let (row, rendered) = client
+        .query("SELECT ?fields, toString(dt) FROM test")
+        .fetch_one::<(MyRow, String)>()
+        .await
+        .unwrap();
+
+    assert_eq!(row.dt, dt);
+    assert_eq!(row.dt.offset(), offset!(+9));
+    assert_eq!(rendered, "2024-01-01 09:00:00.123");
+}
+
 #[common::named]
 #[tokio::test]
 async fn date() {
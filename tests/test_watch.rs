@@ -106,3 +106,41 @@ async fn events() {
     assert_eq!(cursor1.next().await.unwrap(), None);
     assert_eq!(cursor2.next().await.unwrap(), Some(3));
 }
+
+#[common::named]
+#[tokio::test]
+async fn subscribe() {
+    use futures::TryStreamExt;
+
+    let client = common::prepare_database!();
+
+    create_table(&client).await;
+
+    client
+        .clone()
+        .with_option("allow_experimental_live_view", "1")
+        .query("CREATE LIVE VIEW lv WITH TIMEOUT AS SELECT sum(num) AS num FROM test")
+        .execute()
+        .await
+        .unwrap();
+
+    let mut updates = client.watch("WATCH lv").subscribe::<MyRow>().unwrap();
+
+    // The initial result.
+    assert_eq!(
+        updates.try_next().await.unwrap(),
+        Some((1, MyRow { num: 0 }))
+    );
+
+    insert_into_table(&client, &[MyRow { num: 1 }, MyRow { num: 2 }]).await;
+    assert_eq!(
+        updates.try_next().await.unwrap(),
+        Some((2, MyRow { num: 3 }))
+    );
+
+    insert_into_table(&client, &[MyRow { num: 3 }]).await;
+    assert_eq!(
+        updates.try_next().await.unwrap(),
+        Some((3, MyRow { num: 6 }))
+    );
+}